@@ -1,432 +1,5107 @@
-use anyhow::Result;
+use crate::config::{Config, Shortcuts};
+use crate::diagnostics::{mask_home, Diagnostics, StreamFormat};
+use crate::dsp::channels::{
+    remix, ChannelMap, ChannelMapper, MonoToStereoMode, DEFAULT_HAAS_DELAY_MS, DEFAULT_STEREO_WIDTH, MAX_HAAS_DELAY_MS,
+    MAX_STEREO_WIDTH, MIN_HAAS_DELAY_MS,
+};
+use crate::dsp::backend::ProcessingBackend;
+use crate::dsp::alignment::{DelayCalibration, CALIBRATION_SECS, DELAY_MARGIN, MAX_REFERENCE_DELAY_MS};
+use crate::dsp::calibration::{CalibrationResult, CalibrationStep, MicCalibration, SILENCE_MS};
+use crate::dsp::comfort::MIN_COMFORT_NOISE_LEVEL_DB;
+use crate::dsp::compressor::{
+    MAX_COMPRESSOR_ATTACK_MS, MAX_COMPRESSOR_KNEE_DB, MAX_COMPRESSOR_MAKEUP_DB, MAX_COMPRESSOR_RATIO, MAX_COMPRESSOR_RELEASE_MS,
+};
+use crate::dsp::deesser::{MAX_DEESSER_RANGE_DB, MIN_DEESSER_THRESHOLD_DB};
+use crate::dsp::expander::{MAX_EXPANDER_KNEE_DB, MAX_EXPANDER_RATIO};
+use crate::dsp::gain::{Gain, MAX_GAIN_DB, MIN_GAIN_DB};
+use crate::dsp::noise::NoiseProfile;
+use crate::dsp::loudness::{LoudnessMeter, LoudnessReading, MAX_LOUDNESS_TARGET_LUFS, MIN_LOUDNESS_TARGET_LUFS};
+use crate::dsp::resample::{LinearResampler, PullResampler};
+use crate::dsp::spectral::{
+    floor_for_max_reduction_db, fft_size_for, max_reduction_db, process_spectral, NoiseReductionMode, SpectralState,
+    WindowType, MAX_NOISE_REDUCTION_STRENGTH, MAX_SPECTRAL_FLOOR, MIN_NOISE_REDUCTION_STRENGTH,
+};
+use crate::dsp::tone::TestTone;
+use crate::dsp::weighting::{MeterWeighting, WeightingFilter};
+use crate::dsp::delay::DelayLine;
+use crate::dsp::{
+    apply_mute, clamp_to_ceiling, crossfade, fade_in, mix_into, DspState, DEFAULT_SAFETY_CEILING_DB, MAX_POWER_SAVE_HOLD_MS, MIN_SAFETY_CEILING_DB,
+    MUTE_FADE_MS,
+};
+use crate::errors::{describe_stream_error, AudioError, ErrorLog, Result};
+use crate::events::{EventSender, ProcessorEvent, Signal};
+use crate::metrics::{MetricsExport, MetricsRecord};
+use crate::playout::{Playout, DEFAULT_PLAYOUT_TARGET_MS, MAX_PLAYOUT_TARGET_MS};
+use crate::recording::{create_wav, tee_to_recording, write_samples, Recording, RecordingTap};
+use crate::replay::{validate_replay_secs, ReplayBuffer, DEFAULT_REPLAY_SECS};
+use crate::scope::ScopeRing;
+use crate::settings::{fit_hop_size, validate_fft_size, validate_framing, ProcessorSettings, QualityProfile, DEFAULT_FFT_SIZE, MAX_FFT_SIZE, MIN_FFT_SIZE};
+use crate::stream::{self, PlayingStream};
+use crate::virtual_device::{self, is_virtual_cable_name};
 use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    Device, Host, Stream, StreamConfig, SupportedStreamConfig,
+    traits::{DeviceTrait, HostTrait},
+    Device, Host, HostId,
 };
-use ringbuf::{HeapRb, Rb};
-use rustfft::{num_complex::Complex, FftPlanner};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::path::Path;
+use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
-use tracing::{error, info};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
 
 #[derive(Debug, Clone)]
 pub struct DeviceInfo {
     pub name: String,
     pub is_default: bool,
+    pub is_loopback: bool,
+    // Stream configurations the device supports. Querying them can open the
+    // device, so this stays None until first asked for through
+    // `AudioProcessor::input_capabilities` / `output_capabilities`.
+    pub capabilities: Option<Vec<DeviceCapability>>,
 }
 
 impl DeviceInfo {
-    pub fn new(name: String, is_default: bool) -> Self {
-        Self { name, is_default }
+    pub fn new(name: String, is_default: bool, is_loopback: bool) -> Self {
+        Self { name, is_default, is_loopback, capabilities: None }
     }
 }
 
-pub struct AudioProcessor {
-    host: Host,
-    input_devices: Vec<Device>,
-    output_devices: Vec<Device>,
-    input_device_info: Vec<DeviceInfo>,
-    output_device_info: Vec<DeviceInfo>,
-    selected_input_device: Option<Device>,
-    selected_output_device: Option<Device>,
-    selected_input_index: usize,
-    selected_output_index: usize,
-    loopback_device: Option<Device>,
-    input_stream: Option<Stream>,
-    output_stream: Option<Stream>,
-    loopback_stream: Option<Stream>,
-    mic_buffer: Arc<Mutex<HeapRb<f32>>>,
-    app_buffer: Arc<Mutex<HeapRb<f32>>>,
-    processed_buffer: Arc<Mutex<HeapRb<f32>>>,
-    sample_rate: u32,
-    channels: u16,
-    is_processing: bool,
-    noise_reduction_enabled: bool,
-    echo_cancellation_enabled: bool,
+// One supported stream configuration range of a device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceCapability {
+    pub channels: u16,
+    pub sample_format: cpal::SampleFormat,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
 }
 
-impl AudioProcessor {
-    pub fn new() -> Result<Self> {
-        let host = cpal::default_host();
-        
-        // Enumerate input devices
-        let mut input_devices = Vec::new();
-        let mut input_device_info = Vec::new();
-        let default_input = host.default_input_device();
-        let default_input_name = default_input.as_ref()
-            .and_then(|d| d.name().ok())
-            .unwrap_or_else(|| "Unknown".to_string());
-        
-        for device in host.input_devices()? {
-            let device_name = device.name().unwrap_or_else(|_| "Unknown Device".to_string());
-            let is_default = device_name == default_input_name;
-            input_devices.push(device);
-            input_device_info.push(DeviceInfo::new(device_name, is_default));
+impl DeviceCapability {
+    fn from_range(range: &cpal::SupportedStreamConfigRange) -> Self {
+        Self {
+            channels: range.channels(),
+            sample_format: range.sample_format(),
+            min_sample_rate: range.min_sample_rate().0,
+            max_sample_rate: range.max_sample_rate().0,
         }
-        
-        // Enumerate output devices
-        let mut output_devices = Vec::new();
-        let mut output_device_info = Vec::new();
-        let default_output = host.default_output_device();
-        let default_output_name = default_output.as_ref()
-            .and_then(|d| d.name().ok())
-            .unwrap_or_else(|| "Unknown".to_string());
-        
-        for device in host.output_devices()? {
-            let device_name = device.name().unwrap_or_else(|_| "Unknown Device".to_string());
-            let is_default = device_name == default_output_name;
-            output_devices.push(device);
-            output_device_info.push(DeviceInfo::new(device_name, is_default));
+    }
+
+    // e.g. "2 ch, f32, 44100-48000 Hz"
+    pub fn summary(&self) -> String {
+        let rates = if self.min_sample_rate == self.max_sample_rate {
+            self.min_sample_rate.to_string()
+        } else {
+            format!("{}-{}", self.min_sample_rate, self.max_sample_rate)
+        };
+        format!("{} ch, {}, {} Hz", self.channels, self.sample_format, rates)
+    }
+}
+
+// Capabilities from a device's config query. A device that can't be queried
+// (busy, or gone since enumeration) reports none rather than failing.
+fn query_capabilities(
+    configs: std::result::Result<impl Iterator<Item = cpal::SupportedStreamConfigRange>, cpal::SupportedStreamConfigsError>,
+    name: &str,
+) -> Vec<DeviceCapability> {
+    match configs {
+        Ok(configs) => configs.map(|range| DeviceCapability::from_range(&range)).collect(),
+        Err(e) => {
+            warn!("Failed to query the configurations of {}: {}", name, e);
+            Vec::new()
         }
-        
-        // Find default device indices
-        let selected_input_index = input_device_info.iter()
-            .position(|info| info.is_default)
-            .unwrap_or(0);
-        let selected_output_index = output_device_info.iter()
-            .position(|info| info.is_default)
-            .unwrap_or(0);
-        
-        let selected_input_device = input_devices.get(selected_input_index).cloned();
-        let selected_output_device = output_devices.get(selected_output_index).cloned();
-        
-        if let Some(ref device) = selected_input_device {
-            info!("Selected input device: {}", device.name().unwrap_or_else(|_| "Unknown".to_string()));
+    }
+}
+
+// Virtual drivers commonly used to route system audio back into an input
+const VIRTUAL_LOOPBACK_NAMES: &[&str] = &["BlackHole", "Soundflower", "Loopback Audio"];
+
+// Capture devices that carry system audio rather than a microphone
+fn is_loopback_name(name: &str) -> bool {
+    name.ends_with(".monitor") || VIRTUAL_LOOPBACK_NAMES.iter().any(|v| name.contains(v))
+}
+
+// Lock-free f32 cell for values published by the processing thread
+#[derive(Default)]
+struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+// How mono processing is spread over a stereo output, read by the output
+// callback on every period so changes apply without a restart
+struct StereoOutput {
+    wide: AtomicBool,
+    width: AtomicF32,
+    haas_delay_ms: AtomicF32,
+}
+
+impl StereoOutput {
+    fn mode(&self) -> MonoToStereoMode {
+        if self.wide.load(Ordering::Relaxed) { MonoToStereoMode::Wide } else { MonoToStereoMode::Center }
+    }
+}
+
+// Delay added by each stage of the live pipeline, in milliseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyBreakdown {
+    // Capture to input callback, as reported by the driver
+    pub input_device_ms: f32,
+    // Mic samples waiting for the processing task
+    pub input_buffer_ms: f32,
+    // Block accumulation plus the overlap-add delay of noise reduction
+    pub processing_ms: f32,
+    // Processed samples waiting for the output callback
+    pub output_buffer_ms: f32,
+    // Output callback to playback, as reported by the driver
+    pub output_device_ms: f32,
+    // Frames per callback of the input and output devices, i.e. the buffer
+    // sizes they settled on; 0 until their first callback
+    pub input_period_frames: u32,
+    pub output_period_frames: u32,
+}
+
+impl LatencyBreakdown {
+    pub fn total_ms(&self) -> f32 {
+        self.input_device_ms
+            + self.input_buffer_ms
+            + self.processing_ms
+            + self.output_buffer_ms
+            + self.output_device_ms
+    }
+}
+
+// Latest per-stage latencies, written from the stream callbacks and the
+// processing task
+#[derive(Default)]
+struct LatencyMeters {
+    input_device: AtomicF32,
+    input_buffer: AtomicF32,
+    processing: AtomicF32,
+    output_buffer: AtomicF32,
+    output_device: AtomicF32,
+    input_period: AtomicU32,
+    output_period: AtomicU32,
+}
+
+impl LatencyMeters {
+    fn snapshot(&self) -> LatencyBreakdown {
+        LatencyBreakdown {
+            input_device_ms: self.input_device.load(),
+            input_buffer_ms: self.input_buffer.load(),
+            processing_ms: self.processing.load(),
+            output_buffer_ms: self.output_buffer.load(),
+            output_device_ms: self.output_device.load(),
+            input_period_frames: self.input_period.load(Ordering::Relaxed),
+            output_period_frames: self.output_period.load(Ordering::Relaxed),
         }
-        if let Some(ref device) = selected_output_device {
-            info!("Selected output device: {}", device.name().unwrap_or_else(|_| "Unknown".to_string()));
+    }
+
+    fn reset(&self) {
+        for meter in [
+            &self.input_device,
+            &self.input_buffer,
+            &self.processing,
+            &self.output_buffer,
+            &self.output_device,
+        ] {
+            meter.store(0.0);
         }
+        self.input_period.store(0, Ordering::Relaxed);
+        self.output_period.store(0, Ordering::Relaxed);
+    }
+}
 
-        let buffer_size = 48000; // 1 second at 48kHz
-        let mic_buffer = Arc::new(Mutex::new(HeapRb::<f32>::new(buffer_size)));
-        let app_buffer = Arc::new(Mutex::new(HeapRb::<f32>::new(buffer_size)));
-        let processed_buffer = Arc::new(Mutex::new(HeapRb::<f32>::new(buffer_size)));
+// Learned noise profile and its learning, shared with the processing task,
+// which owns the spectral state that does the learning
+#[derive(Default)]
+struct NoiseProfileSlot {
+    // Frames per channel to learn from, until the task starts the learning
+    learn_frames: Option<usize>,
+    // Progress of the learning under way, 0 to 1
+    progress: Option<f32>,
+    profile: Option<NoiseProfile>,
+    // `profile` was replaced from outside the task
+    changed: bool,
+}
 
-        Ok(Self {
-            host,
-            input_devices,
-            output_devices,
-            input_device_info,
-            output_device_info,
-            selected_input_device,
-            selected_output_device,
-            selected_input_index,
-            selected_output_index,
-            loopback_device: None,
-            input_stream: None,
-            output_stream: None,
-            loopback_stream: None,
-            mic_buffer,
-            app_buffer,
-            processed_buffer,
-            sample_rate: 48000,
-            channels: 2,
-            is_processing: false,
-            noise_reduction_enabled: true,
-            echo_cancellation_enabled: true,
-        })
+// Loudness of the output and the normalizing gain, published by the
+// processing task
+#[derive(Default)]
+struct LoudnessMeters {
+    momentary: AtomicF32,
+    short_term: AtomicF32,
+    integrated: AtomicF32,
+    normalization_gain_db: AtomicF32,
+    // Asks the task to start the integrated measurement over
+    reset_integrated: AtomicBool,
+}
+
+impl LoudnessMeters {
+    fn new() -> Self {
+        let meters = Self::default();
+        meters.reset();
+        meters
     }
 
-    pub fn start_input_capture(&mut self) -> Result<()> {
-        if let Some(device) = &self.selected_input_device {
-            let config = device.default_input_config()?;
-            info!("Input config: {:?}", config);
-            
-            let sample_rate = config.sample_rate().0;
-            let channels = config.channels();
-            
-            self.sample_rate = sample_rate;
-            self.channels = channels;
-
-            let mic_buffer = Arc::clone(&self.mic_buffer);
-            
-            let stream = device.build_input_stream(
-                &config.into(),
-                move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                    if let Ok(mut buffer) = mic_buffer.lock() {
-                        for &sample in data {
-                            let _ = buffer.push(sample);
-                        }
-                    }
-                },
-                |err| error!("Input stream error: {}", err),
-                None,
-            )?;
+    fn publish(&self, reading: LoudnessReading) {
+        self.momentary.store(reading.momentary_lufs);
+        self.short_term.store(reading.short_term_lufs);
+        self.integrated.store(reading.integrated_lufs);
+    }
 
-            stream.play()?;
-            self.input_stream = Some(stream);
-            info!("Input capture started");
+    fn snapshot(&self) -> LoudnessReading {
+        LoudnessReading {
+            momentary_lufs: self.momentary.load(),
+            short_term_lufs: self.short_term.load(),
+            integrated_lufs: self.integrated.load(),
         }
-        Ok(())
     }
 
-    pub fn start_loopback_capture(&mut self) -> Result<()> {
-        // This is a simplified implementation
-        // In a real application, you'd need platform-specific code to capture system audio
-        info!("Loopback capture would be implemented here");
-        Ok(())
+    fn reset(&self) {
+        self.publish(LoudnessReading::default());
+        self.normalization_gain_db.store(0.0);
+        self.reset_integrated.store(false, Ordering::Relaxed);
     }
+}
 
-    pub fn start_processing(&mut self) -> Result<()> {
-        self.is_processing = true;
-        
-        // Spawn processing thread
-        let mic_buffer = Arc::clone(&self.mic_buffer);
-        let app_buffer = Arc::clone(&self.app_buffer);
-        let processed_buffer = Arc::clone(&self.processed_buffer);
-        let echo_cancellation = self.echo_cancellation_enabled;
-        let noise_reduction = self.noise_reduction_enabled;
-
-        tokio::spawn(async move {
-            let mut planner = FftPlanner::new();
-            let fft = planner.plan_fft_forward(1024);
-            let ifft = planner.plan_fft_inverse(1024);
-            
-            loop {
-                // Process audio in chunks
-                let mut mic_samples = Vec::new();
-                let mut app_samples = Vec::new();
-                
-                // Extract samples from buffers
-                if let (Ok(mut mic_buf), Ok(mut app_buf)) = 
-                    (mic_buffer.lock(), app_buffer.lock()) {
-                    
-                    for _ in 0..1024 {
-                        if let Some(sample) = mic_buf.pop() {
-                            mic_samples.push(sample);
-                        } else {
-                            mic_samples.push(0.0);
-                        }
-                        
-                        if let Some(sample) = app_buf.pop() {
-                            app_samples.push(sample);
-                        } else {
-                            app_samples.push(0.0);
-                        }
-                    }
-                }
-
-                if mic_samples.len() == 1024 {
-                    let processed = Self::process_audio_chunk(
-                        &mic_samples,
-                        &app_samples,
-                        echo_cancellation,
-                        noise_reduction,
-                        fft.as_ref(),
-                        ifft.as_ref(),
-                    );
+/// Recap of one run of the pipeline, from `start` to `stop`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionStats {
+    // Audio processed, not counting stretches where the mic delivered nothing
+    pub duration: Duration,
+    // Highest sample peak of the output, dBFS
+    pub peak_db: f32,
+    // Integrated loudness of the output, LUFS; -inf if the session was too
+    // short or quiet to measure
+    pub integrated_lufs: f32,
+    // Times the output reached full scale; consecutive clipped blocks count once
+    pub clips: u64,
+    pub underruns: u64,
+    // Share of the duration classified as speech, 0 to 1
+    pub speech_ratio: f32,
+    // Share of the block duration spent processing, averaged over the blocks
+    // that went through the chain
+    pub average_load: f32,
+}
 
-                    // Store processed samples
-                    if let Ok(mut proc_buf) = processed_buffer.lock() {
-                        for sample in processed {
-                            let _ = proc_buf.push(sample);
-                        }
-                    }
-                }
+// Totals the processing task accumulates over a session, summarized into
+// SessionStats on stop
+#[derive(Default)]
+struct SessionTally {
+    frames: AtomicU64,
+    voiced_frames: AtomicU64,
+    peak: AtomicF32,
+    clips: AtomicU64,
+    // The last block clipped, so a clip lasting several blocks counts once
+    clipping: AtomicBool,
+    processed_blocks: AtomicU64,
+    average_load: AtomicF32,
+}
 
-                tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
-            }
-        });
+impl SessionTally {
+    fn record_block(&self, output: &[f32], channels: u16, voice: bool) {
+        let frames = (output.len() / channels.max(1) as usize) as u64;
+        self.frames.fetch_add(frames, Ordering::Relaxed);
+        if voice {
+            self.voiced_frames.fetch_add(frames, Ordering::Relaxed);
+        }
+        let peak = output.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        self.peak.store(self.peak.load().max(peak));
+        let clipping = peak >= 1.0;
+        if clipping && !self.clipping.swap(true, Ordering::Relaxed) {
+            self.clips.fetch_add(1, Ordering::Relaxed);
+        }
+        self.clipping.store(clipping, Ordering::Relaxed);
+    }
 
-        info!("Audio processing started");
-        Ok(())
+    fn record_load(&self, load: f32) {
+        let blocks = self.processed_blocks.fetch_add(1, Ordering::Relaxed) + 1;
+        let average = self.average_load.load();
+        self.average_load.store(average + (load - average) / blocks as f32);
     }
 
-    fn process_audio_chunk(
-        mic_samples: &[f32],
-        app_samples: &[f32],
-        echo_cancellation: bool,
-        noise_reduction: bool,
-        fft: &dyn rustfft::Fft<f32>,
-        ifft: &dyn rustfft::Fft<f32>,
-    ) -> Vec<f32> {
-        let mut processed = mic_samples.to_vec();
-        
-        if echo_cancellation {
-            // Phase inversion for echo cancellation
-            for (i, &app_sample) in app_samples.iter().enumerate() {
-                if i < processed.len() {
-                    processed[i] -= app_sample; // Subtract inverted app audio
-                }
-            }
+    fn summary(&self, sample_rate: u32, integrated_lufs: f32, underruns: u64) -> SessionStats {
+        let frames = self.frames.load(Ordering::Relaxed);
+        let voiced_frames = self.voiced_frames.load(Ordering::Relaxed);
+        SessionStats {
+            duration: Duration::from_secs_f64(frames as f64 / sample_rate.max(1) as f64),
+            peak_db: to_dbfs(self.peak.load()),
+            integrated_lufs,
+            clips: self.clips.load(Ordering::Relaxed),
+            underruns,
+            speech_ratio: if frames == 0 { 0.0 } else { voiced_frames as f32 / frames as f32 },
+            average_load: self.average_load.load(),
         }
+    }
 
-        if noise_reduction {
-            // Simple spectral subtraction for noise reduction
-            processed = Self::spectral_subtraction(&processed, fft, ifft);
+    fn reset(&self) {
+        for counter in [&self.frames, &self.voiced_frames, &self.clips, &self.processed_blocks] {
+            counter.store(0, Ordering::Relaxed);
         }
+        self.peak.store(0.0);
+        self.average_load.store(0.0);
+        self.clipping.store(false, Ordering::Relaxed);
+    }
+}
 
-        processed
+// Latest input and processed spectra, published for display
+#[derive(Debug, Clone, Default)]
+pub struct SpectrumSnapshot {
+    pub sample_rate: u32,
+    // Counts up with every block, so a display can tell a new snapshot from
+    // one it has already seen
+    pub sequence: u64,
+    // dBFS per bin from DC to Nyquist
+    pub input_db: Vec<f32>,
+    pub output_db: Vec<f32>,
+}
+
+// The last second of mic and processed audio, downmixed to mono
+#[derive(Debug, Clone, Default)]
+pub struct Waveform {
+    pub sample_rate: u32,
+    pub input: Vec<f32>,
+    pub output: Vec<f32>,
+}
+
+// Scope history shared with the processing task; replaced on every start so
+// its length follows the stream's sample rate
+struct ScopeRings {
+    sample_rate: u32,
+    input: ScopeRing,
+    output: ScopeRing,
+}
+
+impl ScopeRings {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            input: ScopeRing::new(sample_rate as usize),
+            output: ScopeRing::new(sample_rate as usize),
+        }
     }
+}
 
-    fn spectral_subtraction(
-        samples: &[f32],
-        fft: &dyn rustfft::Fft<f32>,
-        ifft: &dyn rustfft::Fft<f32>,
-    ) -> Vec<f32> {
-        let mut buffer: Vec<Complex<f32>> = samples
-            .iter()
-            .map(|&x| Complex::new(x, 0.0))
-            .collect();
-        
-        // Pad to FFT size if needed
-        buffer.resize(fft.len(), Complex::new(0.0, 0.0));
-        
-        // Forward FFT
-        fft.process(&mut buffer);
-        
-        // Apply spectral subtraction (simplified)
-        for sample in &mut buffer {
-            let magnitude = sample.norm();
-            let noise_floor = 0.1; // Estimated noise floor
-            let alpha = 2.0; // Over-subtraction factor
-            
-            if magnitude > noise_floor {
-                let new_magnitude = magnitude - alpha * noise_floor;
-                let new_magnitude = new_magnitude.max(0.1 * magnitude); // Don't over-subtract
-                *sample = *sample * (new_magnitude / magnitude);
-            }
+// Ring buffer halves are handed to the processing thread through these slots
+// whenever a stream is (re)started, so the realtime callbacks only ever own
+// their half of an SPSC buffer and never take a lock
+type Handoff<T> = Arc<Mutex<Option<T>>>;
+
+fn take_handoff<T>(slot: &Handoff<T>, current: &mut Option<T>) {
+    if let Ok(mut slot) = slot.try_lock() {
+        if let Some(half) = slot.take() {
+            *current = Some(half);
         }
-        
-        // Inverse FFT
-        ifft.process(&mut buffer);
-        
-        buffer.iter().map(|c| c.re / buffer.len() as f32).collect()
     }
+}
 
-    pub fn start_loopback_output(&mut self) -> Result<()> {
-        if let Some(device) = &self.selected_output_device {
-            let config = device.default_output_config()?;
-            let processed_buffer = Arc::clone(&self.processed_buffer);
-            
-            let stream = device.build_output_stream(
-                &config.into(),
-                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                    if let Ok(mut buffer) = processed_buffer.lock() {
-                        for sample in data.iter_mut() {
-                            *sample = buffer.pop().unwrap_or(0.0);
-                        }
-                    }
-                },
-                |err| error!("Output stream error: {}", err),
-                None,
-            )?;
+// Reading end of a capture buffer inside the processing task. A restarted
+// stream hands over a new buffer; whatever the old one still holds is read
+// out first, so restarting a capture stream doesn't drop audio.
+#[derive(Default)]
+struct CaptureBuffer {
+    current: Option<HeapConsumer<f32>>,
+    previous: Option<HeapConsumer<f32>>,
+}
 
-            stream.play()?;
-            self.loopback_stream = Some(stream);
-            info!("Loopback output started");
+impl CaptureBuffer {
+    fn take_handoff(&mut self, slot: &Handoff<HeapConsumer<f32>>) {
+        let mut current = None;
+        take_handoff(slot, &mut current);
+        if let Some(consumer) = current {
+            self.previous = self.current.replace(consumer).filter(|old| !old.is_empty());
         }
-        Ok(())
     }
 
-    pub fn stop(&mut self) {
-        self.is_processing = false;
-        
-        if let Some(stream) = self.input_stream.take() {
-            drop(stream);
+    // Drops everything buffered so far, e.g. audio from a device that was
+    // switched away from
+    fn clear(&mut self) {
+        self.previous = None;
+        if let Some(current) = self.current.as_mut() {
+            current.clear();
         }
-        if let Some(stream) = self.output_stream.take() {
-            drop(stream);
+    }
+
+    fn len(&self) -> usize {
+        self.previous.as_ref().map_or(0, |c| c.len()) + self.current.as_ref().map_or(0, |c| c.len())
+    }
+
+    fn pop_slice(&mut self, samples: &mut [f32]) -> usize {
+        let mut read = 0;
+        if let Some(previous) = self.previous.as_mut() {
+            read = previous.pop_slice(samples);
+            if previous.is_empty() {
+                self.previous = None;
+            }
         }
-        if let Some(stream) = self.loopback_stream.take() {
-            drop(stream);
+        if let Some(current) = self.current.as_mut() {
+            read += current.pop_slice(&mut samples[read..]);
         }
-        
-        info!("Audio processing stopped");
+        read
     }
+}
 
-    pub fn set_echo_cancellation(&mut self, enabled: bool) {
-        self.echo_cancellation_enabled = enabled;
+// Buffer halves of streams opened on top of the main ones, i.e. extra
+// loopback sources and extra outputs, passed to the processing task. Clearing
+// and adding go through the same lock, so the task never drops a stream
+// added right after a clear.
+struct ExtraBuffers<T> {
+    clear: bool,
+    added: Vec<T>,
+}
+
+impl<T> Default for ExtraBuffers<T> {
+    fn default() -> Self {
+        Self { clear: false, added: Vec::new() }
     }
+}
 
-    pub fn set_noise_reduction(&mut self, enabled: bool) {
-        self.noise_reduction_enabled = enabled;
+impl<T> ExtraBuffers<T> {
+    // Brings the task's `buffers` up to date with what was handed over
+    fn take_into(slot: &Mutex<Self>, buffers: &mut Vec<T>) {
+        if let Ok(mut slot) = slot.try_lock() {
+            if std::mem::take(&mut slot.clear) {
+                buffers.clear();
+            }
+            buffers.append(&mut slot.added);
+        }
     }
+}
 
-    pub fn is_processing(&self) -> bool {
-        self.is_processing
+// Pushes a block of interleaved samples, dropping whole frames when the
+// buffer is full so its output callback, which remixes frame by frame, never
+// falls out of step. Returns the samples pushed.
+fn push_frames(producer: &mut HeapProducer<f32>, samples: &[f32], channels: usize) -> usize {
+    let channels = channels.max(1);
+    let room = producer.free_len() / channels * channels;
+    producer.push_slice(&samples[..samples.len().min(room)])
+}
+
+// Wakes the processing task once the mic buffer holds a full block, so it
+// neither polls nor waits longer than the audio takes to arrive
+struct BlockSignal {
+    notify: Notify,
+    block_size: AtomicUsize,
+    // Passes of the processing loop, to check that an idle task sleeps
+    wakeups: AtomicU64,
+}
+
+impl BlockSignal {
+    fn new(block_size: usize) -> Self {
+        Self { notify: Notify::new(), block_size: AtomicUsize::new(block_size), wakeups: AtomicU64::new(0) }
     }
 
-    pub fn get_input_level(&self) -> f32 {
-        if let Ok(buffer) = self.mic_buffer.lock() {
-            let samples: Vec<f32> = buffer.iter().copied().collect();
-            if !samples.is_empty() {
-                let rms = (samples.iter().map(|&x| x * x).sum::<f32>() / samples.len() as f32).sqrt();
-                return rms;
-            }
+    // Called from the input callback with the number of samples now buffered
+    fn samples_buffered(&self, buffered: usize) {
+        if buffered >= self.block_size.load(Ordering::Relaxed) {
+            self.notify.notify_one();
         }
-        0.0
+    }
+}
+
+// What the input callback does with each buffer after noting its timing:
+// maps the device channels, resamples to the processing rate and hands the
+// samples to processing. Nothing here waits on the reader; samples that don't
+// fit in the buffer are dropped.
+struct InputCapture {
+    mapper: ChannelMapper,
+    resampler: Option<LinearResampler>,
+    producer: HeapProducer<f32>,
+    block_signal: Arc<BlockSignal>,
+}
+
+impl InputCapture {
+    // Returns the samples dropped because the buffer was full
+    fn push(&mut self, data: &[f32]) -> usize {
+        let data = self.mapper.map(data);
+        let producer = &mut self.producer;
+        let dropped = match self.resampler.as_mut() {
+            Some(resampler) => {
+                let mut dropped = 0;
+                resampler.process(data, |sample| {
+                    if producer.push(sample).is_err() {
+                        dropped += 1;
+                    }
+                });
+                dropped
+            }
+            None => data.len() - producer.push_slice(data),
+        };
+        self.block_signal.samples_buffered(producer.len());
+        dropped
+    }
+}
+
+// Handle to a spawned processing task. Clearing `running` makes the task exit
+// at its next block boundary.
+struct ProcessingTask {
+    running: Arc<AtomicBool>,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+// Time the meters' RMS is measured over, independent of the block size
+const METER_WINDOW_MS: f32 = 300.0;
+
+// Running RMS over the most recent fixed number of samples, kept by the
+// processing thread and published through a LevelMeter
+struct RmsWindow {
+    squares: VecDeque<f32>,
+    capacity: usize,
+    sum: f64,
+}
+
+impl RmsWindow {
+    fn new(sample_rate: u32, channels: u16) -> Self {
+        let capacity = ((METER_WINDOW_MS / 1000.0 * sample_rate as f32) as usize * channels.max(1) as usize).max(1);
+        Self { squares: VecDeque::with_capacity(capacity), capacity, sum: 0.0 }
     }
 
-    pub fn get_output_level(&self) -> f32 {
-        if let Ok(buffer) = self.processed_buffer.lock() {
-            let samples: Vec<f32> = buffer.iter().copied().collect();
-            if !samples.is_empty() {
-                let rms = (samples.iter().map(|&x| x * x).sum::<f32>() / samples.len() as f32).sqrt();
-                return rms;
+    // Adds `samples` and returns the RMS of the window. Until the window has
+    // filled, only the samples seen so far count.
+    fn push<'a>(&mut self, samples: impl IntoIterator<Item = &'a f32>) -> f32 {
+        for &sample in samples {
+            if self.squares.len() == self.capacity {
+                self.sum -= self.squares.pop_front().unwrap_or(0.0) as f64;
             }
+            let square = sample * sample;
+            self.squares.push_back(square);
+            self.sum += square as f64;
+        }
+        if self.squares.is_empty() {
+            return 0.0;
         }
-        0.0
+        // Rounding in the running sum can leave it slightly negative
+        (self.sum.max(0.0) / self.squares.len() as f64).sqrt() as f32
     }
+}
 
-    pub fn get_input_devices(&self) -> &Vec<DeviceInfo> {
-        &self.input_device_info
+// Level reported for silence instead of minus infinity
+pub const MIN_LEVEL_DB: f32 = -120.0;
+
+pub fn to_dbfs(linear: f32) -> f32 {
+    (20.0 * linear.log10()).max(MIN_LEVEL_DB)
+}
+
+// One meter's view of the signal: RMS over the last METER_WINDOW_MS, peak
+// of the latest processed block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeterReading {
+    pub rms_db: f32,
+    // Largest absolute sample, in dBFS
+    pub peak_db: f32,
+    // A sample reached full scale since the indicator was last reset
+    pub clipped: bool,
+}
+
+impl Default for MeterReading {
+    fn default() -> Self {
+        Self { rms_db: MIN_LEVEL_DB, peak_db: MIN_LEVEL_DB, clipped: false }
     }
+}
 
-    pub fn get_output_devices(&self) -> &Vec<DeviceInfo> {
-        &self.output_device_info
+// Levels published by the processing thread for one signal
+#[derive(Default)]
+struct LevelMeter {
+    rms: AtomicF32,
+    peak: AtomicF32,
+    clipped: AtomicBool,
+}
+
+impl LevelMeter {
+    // Publishes the windowed `rms` and the peak of the latest block. Returns
+    // true when this block set the clip indicator.
+    fn update<'a>(&self, samples: impl IntoIterator<Item = &'a f32>, rms: f32) -> bool {
+        let peak = samples.into_iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        self.rms.store(rms);
+        self.peak.store(peak);
+        peak >= 1.0 && !self.clipped.swap(true, Ordering::Relaxed)
     }
 
-    pub fn get_selected_input_index(&self) -> usize {
-        self.selected_input_index
+    fn reading(&self) -> MeterReading {
+        MeterReading {
+            rms_db: to_dbfs(self.rms.load()),
+            peak_db: to_dbfs(self.peak.load()),
+            clipped: self.clipped.load(Ordering::Relaxed),
+        }
     }
 
-    pub fn get_selected_output_index(&self) -> usize {
-        self.selected_output_index
+    fn reset(&self) {
+        self.rms.store(0.0);
+        self.peak.store(0.0);
+        self.clipped.store(false, Ordering::Relaxed);
     }
+}
 
-    pub fn set_input_device(&mut self, index: usize) -> Result<()> {
-        if index < self.input_devices.len() {
-            self.selected_input_index = index;
-            self.selected_input_device = self.input_devices.get(index).cloned();
-            
-            if self.is_processing {
-                // Stop current input stream if running
-                if let Some(stream) = self.input_stream.take() {
-                    drop(stream);
-                }
-                // Restart with new device
-                self.start_input_capture()?;
-            }
-            
-            info!("Input device changed to: {}", 
-                  self.input_device_info[index].name);
+// Channels metered individually; further channels go unmetered
+pub const MAX_METERED_CHANNELS: usize = 32;
+
+// Levels of each channel of one signal, to spot a dead or hot channel.
+// Meters past the channel count in use are left idle.
+struct ChannelLevels {
+    channels: AtomicUsize,
+    meters: [LevelMeter; MAX_METERED_CHANNELS],
+}
+
+impl Default for ChannelLevels {
+    fn default() -> Self {
+        Self { channels: AtomicUsize::new(0), meters: std::array::from_fn(|_| LevelMeter::default()) }
+    }
+}
+
+impl ChannelLevels {
+    // Meters each channel of interleaved `samples`, the RMS taken of their
+    // `weighted` version, with `windows` holding one RMS window per channel
+    fn update(&self, samples: &[f32], weighted: &[f32], windows: &mut [RmsWindow]) {
+        let channels = windows.len();
+        self.channels.store(channels.min(MAX_METERED_CHANNELS), Ordering::Relaxed);
+        for (channel, (meter, window)) in self.meters.iter().zip(windows).enumerate() {
+            let channel_samples = samples.iter().skip(channel).step_by(channels);
+            let rms = window.push(weighted.iter().skip(channel).step_by(channels));
+            meter.update(channel_samples, rms);
         }
-        Ok(())
     }
 
-    pub fn set_output_device(&mut self, index: usize) -> Result<()> {
-        if index < self.output_devices.len() {
-            self.selected_output_index = index;
-            self.selected_output_device = self.output_devices.get(index).cloned();
-            
-            if self.is_processing {
-                // Stop current output stream if running
-                if let Some(stream) = self.loopback_stream.take() {
-                    drop(stream);
-                }
-                // Restart with new device
-                self.start_loopback_output()?;
-            }
-            
-            info!("Output device changed to: {}", 
-                  self.output_device_info[index].name);
+    fn readings(&self) -> Vec<MeterReading> {
+        let channels = self.channels.load(Ordering::Relaxed);
+        self.meters[..channels].iter().map(LevelMeter::reading).collect()
+    }
+
+    fn reset_clipped(&self) {
+        for meter in &self.meters {
+            meter.clipped.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn reset(&self) {
+        self.channels.store(0, Ordering::Relaxed);
+        for meter in &self.meters {
+            meter.reset();
         }
-        Ok(())
     }
 }
 
-impl Drop for AudioProcessor {
-    fn drop(&mut self) {
-        self.stop();
+// Lists the host's capture devices, flagging the default and system-audio sources
+fn enumerate_input_devices(host: &Host) -> Result<(Vec<Device>, Vec<DeviceInfo>)> {
+    let default_name = host.default_input_device()
+        .and_then(|d| d.name().ok())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let mut devices = Vec::new();
+    let mut infos = Vec::new();
+    for device in host.input_devices()? {
+        let device_name = device.name().unwrap_or_else(|_| "Unknown Device".to_string());
+        let is_default = device_name == default_name;
+        let is_loopback = is_loopback_name(&device_name);
+        devices.push(device);
+        infos.push(DeviceInfo::new(device_name, is_default, is_loopback));
+    }
+    Ok((devices, infos))
+}
+
+fn enumerate_output_devices(host: &Host) -> Result<(Vec<Device>, Vec<DeviceInfo>)> {
+    let default_name = host.default_output_device()
+        .and_then(|d| d.name().ok())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let mut devices = Vec::new();
+    let mut infos = Vec::new();
+    for device in host.output_devices()? {
+        let device_name = device.name().unwrap_or_else(|_| "Unknown Device".to_string());
+        let is_default = device_name == default_name;
+        devices.push(device);
+        infos.push(DeviceInfo::new(device_name, is_default, false));
     }
-}
\ No newline at end of file
+    Ok((devices, infos))
+}
+
+// Device with the given name, else the system default
+fn find_by_name_or_default(infos: &[DeviceInfo], name: Option<&str>) -> Option<usize> {
+    name.and_then(|name| infos.iter().position(|info| info.name == name))
+        .or_else(|| infos.iter().position(|info| info.is_default))
+}
+
+// Index of the system default among `infos` if it isn't `last`, which is
+// updated to it. None while the system has no default or it is unchanged.
+fn changed_default(infos: &[DeviceInfo], last: &mut Option<String>) -> Option<usize> {
+    let index = infos.iter().position(|info| info.is_default)?;
+    if last.as_deref() == Some(infos[index].name.as_str()) {
+        return None;
+    }
+    *last = Some(infos[index].name.clone());
+    Some(index)
+}
+
+// Gain reduction beyond which the limiter counts as active for events; rounding
+// in its gain smoothing leaves tiny reductions after a peak has passed
+const LIMITER_ACTIVE_DB: f32 = -0.01;
+
+// Time constant of the running average behind `get_processing_load`
+const PROCESSING_LOAD_AVERAGE_SECS: f32 = 1.0;
+
+// Length of the fade when bypass is switched on or off
+const BYPASS_CROSSFADE_MS: f32 = 5.0;
+
+// Audio the monitor buffer holds before new samples are dropped; kept short
+// since the monitor exists to be heard with little delay
+const MONITOR_BUFFER_MS: f32 = 100.0;
+
+/// What the monitor (sidetone) output plays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MonitorSource {
+    /// The processed signal, as the main output gets it
+    #[default]
+    Processed,
+    /// The microphone after the input gain, without the processing latency
+    Raw,
+}
+
+// Length of the fade-in where output resumes on a new stream
+pub(crate) const STREAM_FADE_IN_MS: f32 = 20.0;
+
+// Default rate the DSP chain, recording and visualizations run at. Devices at
+// other rates are resampled to it on capture and from it on playback.
+pub const PROCESSING_SAMPLE_RATE: u32 = 48_000;
+// Largest rate the processing chain is offered at; device rates beyond this
+// gain nothing for voice
+pub const MAX_PROCESSING_SAMPLE_RATE: u32 = 192_000;
+// Processing rates offered in the UI: 16 kHz covers the voice band, 48 kHz
+// keeps music intact
+pub const INTERNAL_SAMPLE_RATES: &[u32] = &[16_000, 24_000, 48_000];
+
+// How much audio each ring buffer between the streams and the processing task
+// can hold. Smaller buffers bound the worst-case latency more tightly, larger
+// ones ride out longer scheduling hiccups.
+pub const DEFAULT_BUFFER_MS: f32 = 1000.0;
+
+// The device's default config moved to the processing rate when the device
+// supports that with the same channels and format, so no resampling is needed
+fn at_processing_rate(
+    mut configs: impl Iterator<Item = cpal::SupportedStreamConfigRange>,
+    default_config: cpal::SupportedStreamConfig,
+    sample_rate: u32,
+) -> cpal::SupportedStreamConfig {
+    configs
+        .find(|range| {
+            range.channels() == default_config.channels()
+                && range.sample_format() == default_config.sample_format()
+                && range.min_sample_rate().0 <= sample_rate
+                && range.max_sample_rate().0 >= sample_rate
+        })
+        .map(|range| range.with_sample_rate(cpal::SampleRate(sample_rate)))
+        .unwrap_or(default_config)
+}
+
+pub struct AudioProcessor {
+    host: Host,
+    input_devices: Vec<Device>,
+    output_devices: Vec<Device>,
+    input_device_info: Vec<DeviceInfo>,
+    output_device_info: Vec<DeviceInfo>,
+    selected_input_device: Option<Device>,
+    selected_output_device: Option<Device>,
+    selected_input_index: usize,
+    selected_output_index: usize,
+    // Switch devices when the system default changes, see `apply_system_defaults`
+    follow_system_default: bool,
+    // System defaults as last seen, so only a change of default switches
+    last_default_input: Option<String>,
+    last_default_output: Option<String>,
+    loopback_device: Option<Device>,
+    loopback_device_name: Option<String>,
+    // Further sources mixed into the echo reference, e.g. the monitors of
+    // other sinks apps play to, and their streams while processing
+    extra_loopback_names: Vec<String>,
+    extra_app_streams: Vec<PlayingStream>,
+    extra_app_consumers: Arc<Mutex<ExtraBuffers<HeapConsumer<f32>>>>,
+    // Further outputs playing the processed audio alongside the main one,
+    // e.g. headphones next to a virtual microphone, kept by name like the
+    // monitor, and their streams while processing
+    extra_output_names: Vec<String>,
+    extra_output_streams: Vec<PlayingStream>,
+    extra_output_producers: Arc<Mutex<ExtraBuffers<HeapProducer<f32>>>>,
+    // Processed audio goes to a virtual microphone rather than a speaker
+    virtual_output: bool,
+    #[cfg(target_os = "linux")]
+    virtual_microphone: Option<virtual_device::VirtualMicrophone>,
+    input_stream: Option<PlayingStream>,
+    output_stream: Option<PlayingStream>,
+    loopback_stream: Option<PlayingStream>,
+    // Third output, for hearing yourself on headphones; the device is kept
+    // by name since indices shift when the device lists are refreshed
+    monitor_stream: Option<PlayingStream>,
+    monitor_device_name: Option<String>,
+    monitor_producer: Handoff<HeapProducer<f32>>,
+    monitor_raw: Arc<AtomicBool>,
+    monitor_volume_db: Arc<AtomicF32>,
+    app_stream: Option<PlayingStream>,
+    // Formats the input and output streams were opened with, for diagnostics
+    input_format: Option<StreamFormat>,
+    output_format: Option<StreamFormat>,
+    // Capacity of each ring buffer, as time at the processing format
+    buffer_ms: f32,
+    // Audio the output keeps buffered beyond one block, see `Playout`
+    playout_target_ms: f32,
+    // Times the output device found the processed buffer short
+    underruns: Arc<AtomicU64>,
+    mic_consumer: Handoff<HeapConsumer<f32>>,
+    app_consumer: Handoff<HeapConsumer<f32>>,
+    processed_producer: Handoff<HeapProducer<f32>>,
+    input_level: Arc<LevelMeter>,
+    input_channel_levels: Arc<ChannelLevels>,
+    output_level: Arc<LevelMeter>,
+    // Weighting of the meters' RMS, as an index into MeterWeighting::ALL
+    meter_weighting: Arc<AtomicUsize>,
+    voice_active: Arc<AtomicBool>,
+    agc_gain_db: Arc<AtomicF32>,
+    // Raw mic goes to the output instead of the processed signal
+    bypass: Arc<AtomicBool>,
+    // Silence goes to the output (and recording) instead of either signal
+    mute: Arc<AtomicBool>,
+    // The output (and recording) is negated, to undo an inversion downstream
+    output_inverted: Arc<AtomicBool>,
+    stereo_output: Arc<StereoOutput>,
+    // Hard clamp of every output sample, whatever else is enabled, and the
+    // samples it clamped since processing started
+    safety_enabled: Arc<AtomicBool>,
+    safety_ceiling_db: Arc<AtomicF32>,
+    safety_clamps: Arc<AtomicU64>,
+    // Plays in place of the processed signal until it ends
+    test_tone: Arc<Mutex<Option<TestTone>>>,
+    limiter_reduction_db: Arc<AtomicF32>,
+    compressor_reduction_db: Arc<AtomicF32>,
+    // Non-finite samples the processing task replaced with silence
+    sanitized_samples: Arc<AtomicU64>,
+    // The gate is enabled and passing audio
+    gate_open: Arc<AtomicBool>,
+    // Power saving has the spectral frames skipping their transforms
+    power_saving: Arc<AtomicBool>,
+    // Averaged share of each block's duration spent processing it
+    processing_load: Arc<AtomicF32>,
+    loudness: Arc<LoudnessMeters>,
+    session: Arc<SessionTally>,
+    // Recap of the last session, set by `stop` and cleared by `start`
+    last_session: Option<SessionStats>,
+    latency: Arc<LatencyMeters>,
+    block_signal: Arc<BlockSignal>,
+    processing_task: Option<ProcessingTask>,
+    errors: ErrorLog,
+    events: EventSender,
+    // Set by the stream error callbacks when a device disappears
+    input_lost: Arc<AtomicBool>,
+    output_lost: Arc<AtomicBool>,
+    // Set when the user picks another input device, for the processing
+    // task to drop the old device's audio and start the DSP state over
+    input_switched: Arc<AtomicBool>,
+    // Set by `reset_dsp_state` for the processing task to start its DSP
+    // state over at the next block
+    dsp_reset: Arc<AtomicBool>,
+    spectrum: Arc<Mutex<Option<SpectrumSnapshot>>>,
+    // Set by `get_spectrum` for the processing task to take the next
+    // snapshot, so nothing is transformed for display while no one looks
+    spectrum_wanted: Arc<AtomicBool>,
+    // Filled by the processing task while an echo delay calibration runs
+    delay_calibration: Arc<Mutex<Option<DelayCalibration>>>,
+    // Filled with the raw microphone while a mic calibration runs, along
+    // with the input gain the noise profile is being learned at
+    mic_calibration: Arc<Mutex<Option<MicCalibration>>>,
+    mic_calibration_gain_db: f32,
+    noise_profile: Arc<Mutex<NoiseProfileSlot>>,
+    scope: Arc<ScopeRings>,
+    recording_tap: RecordingTap,
+    recording: Option<Recording>,
+    // Seconds of processed audio the replay buffer holds
+    replay_secs: u32,
+    replay: Arc<ReplayBuffer>,
+    // A resized replay buffer for the running task to switch to
+    replay_handoff: Handoff<Arc<ReplayBuffer>>,
+    metrics: Option<MetricsExport>,
+    // Processing runs at the input's channel count, or the input map's
+    // output count; output is remixed to its own
+    channels: u16,
+    output_channels: u16,
+    // Custom routing from the input device to processing and from
+    // processing to the output device; None uses the default mapping
+    input_channel_map: Option<ChannelMap>,
+    output_channel_map: Option<ChannelMap>,
+    // Single input device channel processed as mono, counted from 0; takes
+    // precedence over the input map
+    input_channel: Option<u16>,
+    // Rate the processing chain runs at; streams are resampled to and from it
+    sample_rate: u32,
+    // Format to open the input and output devices with, where the user chose one
+    requested_sample_rate: Option<u32>,
+    requested_channels: Option<u16>,
+    // Frames per device callback, where the user chose a size
+    buffer_frames: Option<u32>,
+    // Profile the block and buffer sizes come from; None once one of them
+    // is set on its own
+    quality_profile: Option<QualityProfile>,
+    is_processing: bool,
+    settings: Arc<Mutex<ProcessorSettings>>,
+    // State before the last wholesale change, for `undo_settings`
+    undo: Option<SettingsSnapshot>,
+}
+
+// DSP settings and devices at one moment. Devices are kept by name, as
+// their indices change when devices come and go.
+#[derive(Debug, Clone)]
+struct SettingsSnapshot {
+    settings: ProcessorSettings,
+    input_device: Option<String>,
+    output_device: Option<String>,
+}
+
+// Stream config for a device: the requested rate and channel count where set,
+// otherwise the default config moved to the processing rate if supported. A
+// request the device can't meet exactly falls back, with a warning, to the
+// closest channel count and then the closest rate it supports.
+fn choose_stream_config(
+    configs: impl Iterator<Item = cpal::SupportedStreamConfigRange>,
+    default_config: cpal::SupportedStreamConfig,
+    processing_rate: u32,
+    requested_rate: Option<u32>,
+    requested_channels: Option<u16>,
+) -> cpal::SupportedStreamConfig {
+    if requested_rate.is_none() && requested_channels.is_none() {
+        return at_processing_rate(configs, default_config, processing_rate);
+    }
+
+    let channels = requested_channels.unwrap_or(default_config.channels());
+    let rate = requested_rate.unwrap_or(processing_rate);
+    let nearest_rate = |range: &cpal::SupportedStreamConfigRange| {
+        rate.clamp(range.min_sample_rate().0, range.max_sample_rate().0)
+    };
+    let best = configs.min_by_key(|range| {
+        (
+            range.channels().abs_diff(channels),
+            nearest_rate(range).abs_diff(rate),
+            // Keep the default format when it can do the rest
+            range.sample_format() != default_config.sample_format(),
+        )
+    });
+
+    match best {
+        Some(range) => {
+            let chosen_rate = nearest_rate(&range);
+            if requested_channels.is_some_and(|c| c != range.channels())
+                || requested_rate.is_some_and(|r| r != chosen_rate)
+            {
+                warn!(
+                    "Device does not support {} ch at {} Hz, using {} ch at {} Hz",
+                    channels, rate, range.channels(), chosen_rate
+                );
+            }
+            range.with_sample_rate(cpal::SampleRate(chosen_rate))
+        }
+        None => {
+            warn!("Device reports no supported configurations, using its default");
+            default_config
+        }
+    }
+}
+
+// FFT size at `to_rate` covering the time `fft_size` covers at `from_rate`,
+// to the nearest power of two
+fn fft_size_at_rate(fft_size: usize, from_rate: u32, to_rate: u32) -> usize {
+    let exponent = (fft_size as f64 * to_rate as f64 / from_rate.max(1) as f64).log2().round();
+    2usize.pow(exponent.max(0.0) as u32).clamp(MIN_FFT_SIZE, MAX_FFT_SIZE)
+}
+
+// Builds a stream with `config`, and if the device refuses its fixed buffer
+// size, again with the device's default
+fn with_buffer_fallback<T>(
+    label: &str,
+    config: cpal::StreamConfig,
+    mut build: impl FnMut(&cpal::StreamConfig) -> Result<T>,
+) -> Result<T> {
+    match build(&config) {
+        Err(e) if config.buffer_size != cpal::BufferSize::Default => {
+            warn!("{} device refused a buffer of {:?} ({}), using its default", label, config.buffer_size, e);
+            build(&cpal::StreamConfig { buffer_size: cpal::BufferSize::Default, ..config })
+        }
+        result => result,
+    }
+}
+
+/// Configures an [`AudioProcessor`] before any device is opened.
+///
+/// Preferred devices are looked up by name when building and fall back to
+/// the system defaults if absent. Invalid parameters make [`build`] fail
+/// with a description of the problem.
+///
+/// [`build`]: AudioProcessorBuilder::build
+#[derive(Debug, Clone)]
+pub struct AudioProcessorBuilder {
+    host: Option<HostId>,
+    sample_rate: u32,
+    requested_sample_rate: Option<u32>,
+    requested_channels: Option<u16>,
+    input_channel: Option<u16>,
+    buffer_frames: Option<u32>,
+    quality_profile: Option<QualityProfile>,
+    buffer_ms: f32,
+    playout_target_ms: f32,
+    replay_secs: u32,
+    output_polarity_inverted: bool,
+    output_stereo_mode: MonoToStereoMode,
+    stereo_width: f32,
+    haas_delay_ms: f32,
+    safety_ceiling_enabled: bool,
+    safety_ceiling_db: f32,
+    meter_weighting: MeterWeighting,
+    follow_system_default: bool,
+    preferred_input: Option<String>,
+    preferred_output: Option<String>,
+    settings: ProcessorSettings,
+}
+
+impl Default for AudioProcessorBuilder {
+    fn default() -> Self {
+        Self {
+            host: None,
+            sample_rate: PROCESSING_SAMPLE_RATE,
+            requested_sample_rate: None,
+            requested_channels: None,
+            input_channel: None,
+            buffer_frames: None,
+            quality_profile: None,
+            buffer_ms: DEFAULT_BUFFER_MS,
+            playout_target_ms: DEFAULT_PLAYOUT_TARGET_MS,
+            replay_secs: DEFAULT_REPLAY_SECS,
+            output_polarity_inverted: false,
+            output_stereo_mode: MonoToStereoMode::default(),
+            stereo_width: DEFAULT_STEREO_WIDTH,
+            haas_delay_ms: DEFAULT_HAAS_DELAY_MS,
+            safety_ceiling_enabled: true,
+            safety_ceiling_db: DEFAULT_SAFETY_CEILING_DB,
+            meter_weighting: MeterWeighting::default(),
+            follow_system_default: false,
+            preferred_input: None,
+            preferred_output: None,
+            settings: ProcessorSettings::default(),
+        }
+    }
+}
+
+impl AudioProcessorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Starts from saved devices and settings
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            preferred_input: config.input_device.clone(),
+            preferred_output: config.output_device.clone(),
+            sample_rate: config.processing_sample_rate,
+            requested_sample_rate: config.sample_rate,
+            requested_channels: config.channels,
+            input_channel: config.input_channel,
+            buffer_frames: config.buffer_frames,
+            quality_profile: config.quality_profile,
+            replay_secs: config.replay_secs,
+            output_polarity_inverted: config.output_polarity_inverted,
+            output_stereo_mode: config.output_stereo_mode,
+            stereo_width: config.stereo_width,
+            haas_delay_ms: config.haas_delay_ms,
+            safety_ceiling_enabled: config.safety_ceiling_enabled,
+            safety_ceiling_db: config.safety_ceiling_db,
+            meter_weighting: config.meter_weighting,
+            follow_system_default: config.follow_system_default,
+            settings: ProcessorSettings {
+                input_gain_db: config.input_gain_db,
+                output_gain_db: config.output_gain_db,
+                backend: config.processing_backend,
+                echo_cancellation: config.echo_cancellation,
+                noise_reduction: config.noise_reduction,
+                noise_reduction_strength: config.noise_reduction_strength,
+                spectral_floor: config.spectral_floor,
+                // The default covers the same time at any processing rate
+                fft_size: fft_size_at_rate(DEFAULT_FFT_SIZE, PROCESSING_SAMPLE_RATE, config.processing_sample_rate),
+                ..ProcessorSettings::default()
+            },
+            ..Self::default()
+        }
+    }
+
+    // Audio backend; the platform default when not set
+    pub fn host(mut self, host: HostId) -> Self {
+        self.host = Some(host);
+        self
+    }
+
+    // Rate the DSP runs at; devices running at other rates are resampled
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    // Rate and channel count to open the devices with instead of their
+    // defaults; see `AudioProcessor::set_requested_sample_rate`
+    pub fn requested_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.requested_sample_rate = Some(sample_rate);
+        self
+    }
+
+    pub fn requested_channels(mut self, channels: u16) -> Self {
+        self.requested_channels = Some(channels);
+        self
+    }
+
+    // Input device channel to process alone; see `AudioProcessor::set_input_channel`
+    pub fn input_channel(mut self, channel: u16) -> Self {
+        self.input_channel = Some(channel);
+        self
+    }
+
+    pub fn buffer_frames(mut self, frames: u32) -> Self {
+        self.buffer_frames = Some(frames);
+        self
+    }
+
+    // Overrides the FFT, buffer and playout sizes with the profile's
+    pub fn quality_profile(mut self, profile: QualityProfile) -> Self {
+        self.quality_profile = Some(profile);
+        self
+    }
+
+    pub fn output_polarity_inverted(mut self, inverted: bool) -> Self {
+        self.output_polarity_inverted = inverted;
+        self
+    }
+
+    // See `AudioProcessor::set_output_stereo_mode`
+    pub fn output_stereo_mode(mut self, mode: MonoToStereoMode) -> Self {
+        self.output_stereo_mode = mode;
+        self
+    }
+
+    pub fn stereo_width(mut self, width: f32) -> Self {
+        self.stereo_width = width;
+        self
+    }
+
+    pub fn haas_delay_ms(mut self, ms: f32) -> Self {
+        self.haas_delay_ms = ms;
+        self
+    }
+
+    // See `AudioProcessor::set_safety_ceiling_db`; on by default
+    pub fn safety_ceiling_enabled(mut self, enabled: bool) -> Self {
+        self.safety_ceiling_enabled = enabled;
+        self
+    }
+
+    pub fn safety_ceiling_db(mut self, ceiling_db: f32) -> Self {
+        self.safety_ceiling_db = ceiling_db;
+        self
+    }
+
+    // See `AudioProcessor::set_meter_weighting`
+    pub fn meter_weighting(mut self, weighting: MeterWeighting) -> Self {
+        self.meter_weighting = weighting;
+        self
+    }
+
+    pub fn fft_size(mut self, fft_size: usize) -> Self {
+        self.settings.fft_size = fft_size;
+        self
+    }
+
+    pub fn buffer_ms(mut self, buffer_ms: f32) -> Self {
+        self.buffer_ms = buffer_ms;
+        self
+    }
+
+    pub fn playout_target_ms(mut self, playout_target_ms: f32) -> Self {
+        self.playout_target_ms = playout_target_ms;
+        self
+    }
+
+    // See `AudioProcessor::set_replay_secs`
+    pub fn replay_secs(mut self, secs: u32) -> Self {
+        self.replay_secs = secs;
+        self
+    }
+
+    pub fn preferred_input_name(mut self, name: impl Into<String>) -> Self {
+        self.preferred_input = Some(name.into());
+        self
+    }
+
+    pub fn preferred_output_name(mut self, name: impl Into<String>) -> Self {
+        self.preferred_output = Some(name.into());
+        self
+    }
+
+    // Starts on the system default devices, overriding the preferred names,
+    // and follows them; see `AudioProcessor::set_follow_system_default`
+    pub fn follow_system_default(mut self, follow: bool) -> Self {
+        self.follow_system_default = follow;
+        self
+    }
+
+    // Initial DSP settings; a later `fft_size` call overrides theirs
+    pub fn settings(mut self, settings: ProcessorSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    // Checks the parameters without touching any device
+    pub fn validate(&self) -> Result<()> {
+        if self.sample_rate == 0 || self.sample_rate > MAX_PROCESSING_SAMPLE_RATE {
+            return Err(AudioError::InvalidSetting(format!(
+                "Sample rate must be between 1 and {} Hz, got {}",
+                MAX_PROCESSING_SAMPLE_RATE, self.sample_rate
+            )));
+        }
+        if self.requested_sample_rate == Some(0) || self.requested_channels == Some(0) || self.buffer_frames == Some(0) {
+            return Err(AudioError::InvalidSetting("Requested sample rate, channel count and buffer size must be positive".to_string()));
+        }
+        validate_fft_size(self.settings.fft_size)?;
+        validate_framing(self.settings.fft_window, self.settings.hop_size, self.settings.fft_size)?;
+        // The FFT size counts samples per channel, so a block lasts as long
+        // whatever channel count the input opens with
+        let block_ms = self.settings.fft_size as f32 * 1000.0 / self.sample_rate as f32;
+        if self.buffer_ms.is_nan() || self.buffer_ms < block_ms {
+            return Err(AudioError::InvalidSetting(format!(
+                "Buffer must hold at least one FFT block ({:.1} ms), got {} ms",
+                block_ms, self.buffer_ms
+            )));
+        }
+        if !(0.0..=MAX_PLAYOUT_TARGET_MS).contains(&self.playout_target_ms) {
+            return Err(AudioError::InvalidSetting(format!(
+                "Playout target must be between 0 and {} ms, got {} ms",
+                MAX_PLAYOUT_TARGET_MS, self.playout_target_ms
+            )));
+        }
+        validate_replay_secs(self.replay_secs)?;
+        Ok(())
+    }
+
+    // Validates the parameters, enumerates devices and selects the preferred ones
+    pub fn build(self) -> Result<AudioProcessor> {
+        self.validate()?;
+
+        let events = EventSender::default();
+        let host = match self.host {
+            Some(id) => cpal::host_from_id(id)?,
+            None => cpal::default_host(),
+        };
+        let (input_devices, input_device_info) = enumerate_input_devices(&host)?;
+        let (output_devices, output_device_info) = enumerate_output_devices(&host)?;
+        
+        // Prefer the saved devices by name, then the system defaults
+        let selected_input_index = find_by_name_or_default(&input_device_info, self.preferred_input.as_deref())
+            .unwrap_or(0);
+        let selected_output_index = find_by_name_or_default(&output_device_info, self.preferred_output.as_deref())
+            .unwrap_or(0);
+        
+        let selected_input_device = input_devices.get(selected_input_index).cloned();
+        let selected_output_device = output_devices.get(selected_output_index).cloned();
+        
+        // Not fatal: devices may be plugged in later and picked up by a refresh
+        match selected_input_device {
+            Some(ref device) => info!("Selected input device: {}", device.name().unwrap_or_else(|_| "Unknown".to_string())),
+            None => warn!("No input device available"),
+        }
+        match selected_output_device {
+            Some(ref device) => info!("Selected output device: {}", device.name().unwrap_or_else(|_| "Unknown".to_string())),
+            None => warn!("No output device available"),
+        }
+
+        let mut processor = AudioProcessor {
+            host,
+            input_devices,
+            output_devices,
+            input_device_info,
+            output_device_info,
+            selected_input_device,
+            selected_output_device,
+            selected_input_index,
+            selected_output_index,
+            loopback_device: None,
+            loopback_device_name: None,
+            extra_loopback_names: Vec::new(),
+            extra_app_streams: Vec::new(),
+            extra_app_consumers: Arc::default(),
+            extra_output_names: Vec::new(),
+            extra_output_streams: Vec::new(),
+            extra_output_producers: Arc::default(),
+            virtual_output: false,
+            follow_system_default: self.follow_system_default,
+            last_default_input: None,
+            last_default_output: None,
+            #[cfg(target_os = "linux")]
+            virtual_microphone: None,
+            input_stream: None,
+            output_stream: None,
+            loopback_stream: None,
+            app_stream: None,
+            input_format: None,
+            output_format: None,
+            monitor_stream: None,
+            monitor_device_name: None,
+            monitor_producer: Arc::new(Mutex::new(None)),
+            monitor_raw: Arc::new(AtomicBool::new(false)),
+            monitor_volume_db: Arc::new(AtomicF32::default()),
+            buffer_ms: self.buffer_ms,
+            playout_target_ms: self.playout_target_ms,
+            underruns: Arc::new(AtomicU64::new(0)),
+            mic_consumer: Arc::new(Mutex::new(None)),
+            app_consumer: Arc::new(Mutex::new(None)),
+            processed_producer: Arc::new(Mutex::new(None)),
+            input_level: Arc::new(LevelMeter::default()),
+            input_channel_levels: Arc::new(ChannelLevels::default()),
+            output_level: Arc::new(LevelMeter::default()),
+            meter_weighting: Arc::new(AtomicUsize::new(self.meter_weighting as usize)),
+            voice_active: Arc::new(AtomicBool::new(false)),
+            agc_gain_db: Arc::new(AtomicF32::default()),
+            bypass: Arc::new(AtomicBool::new(false)),
+            mute: Arc::new(AtomicBool::new(false)),
+            output_inverted: Arc::new(AtomicBool::new(self.output_polarity_inverted)),
+            safety_enabled: Arc::new(AtomicBool::new(self.safety_ceiling_enabled)),
+            safety_ceiling_db: Arc::new(AtomicF32::new(self.safety_ceiling_db.clamp(MIN_SAFETY_CEILING_DB, 0.0))),
+            safety_clamps: Arc::new(AtomicU64::new(0)),
+            stereo_output: Arc::new(StereoOutput {
+                wide: AtomicBool::new(self.output_stereo_mode == MonoToStereoMode::Wide),
+                width: AtomicF32::new(self.stereo_width.clamp(0.0, MAX_STEREO_WIDTH)),
+                haas_delay_ms: AtomicF32::new(self.haas_delay_ms.clamp(MIN_HAAS_DELAY_MS, MAX_HAAS_DELAY_MS)),
+            }),
+            test_tone: Arc::new(Mutex::new(None)),
+            limiter_reduction_db: Arc::new(AtomicF32::default()),
+            compressor_reduction_db: Arc::new(AtomicF32::default()),
+            sanitized_samples: Arc::new(AtomicU64::new(0)),
+            gate_open: Arc::new(AtomicBool::new(false)),
+            power_saving: Arc::new(AtomicBool::new(false)),
+            processing_load: Arc::new(AtomicF32::default()),
+            loudness: Arc::new(LoudnessMeters::new()),
+            session: Arc::default(),
+            last_session: None,
+            latency: Arc::new(LatencyMeters::default()),
+            block_signal: Arc::new(BlockSignal::new(self.settings.fft_size)),
+            processing_task: None,
+            errors: ErrorLog::new(events.clone()),
+            events,
+            input_lost: Arc::new(AtomicBool::new(false)),
+            output_lost: Arc::new(AtomicBool::new(false)),
+            input_switched: Arc::new(AtomicBool::new(false)),
+            dsp_reset: Arc::new(AtomicBool::new(false)),
+            spectrum: Arc::new(Mutex::new(None)),
+            spectrum_wanted: Arc::new(AtomicBool::new(false)),
+            delay_calibration: Arc::new(Mutex::new(None)),
+            mic_calibration: Arc::new(Mutex::new(None)),
+            mic_calibration_gain_db: 0.0,
+            noise_profile: Arc::new(Mutex::new(NoiseProfileSlot::default())),
+            scope: Arc::new(ScopeRings::new(self.sample_rate)),
+            recording_tap: Arc::new(Mutex::new(None)),
+            recording: None,
+            replay_secs: self.replay_secs,
+            replay: Arc::new(ReplayBuffer::new(self.replay_secs, self.sample_rate, 2)),
+            replay_handoff: Arc::new(Mutex::new(None)),
+            metrics: None,
+            channels: 2,
+            output_channels: 2,
+            input_channel_map: None,
+            output_channel_map: None,
+            input_channel: self.input_channel,
+            is_processing: false,
+            sample_rate: self.sample_rate,
+            requested_sample_rate: self.requested_sample_rate,
+            requested_channels: self.requested_channels,
+            buffer_frames: self.buffer_frames,
+            quality_profile: None,
+            settings: Arc::new(Mutex::new(self.settings)),
+            undo: None,
+        };
+        if let Some(profile) = self.quality_profile {
+            processor.apply_quality_profile(profile)?;
+        }
+        processor.apply_system_defaults()?;
+        Ok(processor)
+    }
+}
+
+impl AudioProcessor {
+    // Default configuration with the saved devices and settings
+    pub fn new() -> Result<Self> {
+        Self::with_config(&Config::load())
+    }
+
+    pub fn with_config(config: &Config) -> Result<Self> {
+        AudioProcessorBuilder::from_config(config).build()
+    }
+
+    pub fn builder() -> AudioProcessorBuilder {
+        AudioProcessorBuilder::new()
+    }
+
+    // Starts the full pipeline: mic capture, loopback capture, processing and output
+    pub fn start(&mut self) -> Result<()> {
+        // Fail before anything starts rather than leave half a pipeline running
+        if !self.has_input_device() {
+            return Err(AudioError::NoDevice("input"));
+        }
+        if !self.has_output_device() {
+            return Err(AudioError::NoDevice("output"));
+        }
+
+        // A previous task that has not yet seen its stop flag must not pick
+        // up the buffers of the streams about to start, so give it none
+        self.stop_processing_task();
+        self.mic_consumer = Arc::new(Mutex::new(None));
+        self.app_consumer = Arc::new(Mutex::new(None));
+        self.extra_app_consumers = Arc::default();
+        self.extra_output_producers = Arc::default();
+        self.processed_producer = Arc::new(Mutex::new(None));
+        self.monitor_producer = Arc::new(Mutex::new(None));
+        self.underruns.store(0, Ordering::Relaxed);
+        self.session.reset();
+        self.last_session = None;
+
+        self.start_input_capture()?;
+        // The profile sets a block duration, which in samples depends on the
+        // rate the input came up with
+        if let Some(profile) = self.quality_profile {
+            let fft_size = profile.fft_size(self.sample_rate);
+            self.update_settings(|s| {
+                s.fft_size = fft_size;
+                s.hop_size = fit_hop_size(s.fft_window, 0, fft_size);
+            });
+        }
+        self.start_loopback_capture()?;
+        self.start_extra_loopback_captures();
+        self.start_processing()?;
+        self.start_loopback_output()?;
+        // The monitor is a convenience; the main path runs without it
+        if let Err(e) = self.start_monitor_output() {
+            self.errors.push(format!("Monitor output failed: {}", e));
+        }
+        self.start_extra_outputs();
+        self.events.emit(ProcessorEvent::Started);
+        Ok(())
+    }
+
+    // Whether a device is selected for each direction. Without both,
+    // `start` fails; the lists may fill up again on the next refresh.
+    pub fn has_input_device(&self) -> bool {
+        self.selected_input_device.is_some()
+    }
+
+    pub fn has_output_device(&self) -> bool {
+        self.selected_output_device.is_some()
+    }
+
+    pub fn start_input_capture(&mut self) -> Result<()> {
+        let Some(device) = &self.selected_input_device else {
+            return Err(AudioError::NoDevice("input"));
+        };
+        let config = choose_stream_config(
+            device.supported_input_configs()?,
+            device.default_input_config()?,
+            self.sample_rate,
+            self.requested_sample_rate,
+            self.requested_channels,
+        );
+        info!("Input config: {:?}", config);
+        self.input_format = Some(StreamFormat::from(&config));
+        let device_channels = config.channels();
+        let input_map = self.input_map(device_channels);
+        self.channels = input_map.as_ref().map_or(device_channels, |map| map.outputs() as u16);
+
+        // Everything the callback owns is made per attempt, since a refused
+        // buffer size means building the stream again
+        let (stream, consumer) = with_buffer_fallback("Input", self.stream_config(&config), |stream_config| {
+            let mapper = match &input_map {
+                Some(map) => ChannelMapper::with_map(map.clone()),
+                None => ChannelMapper::new(device_channels, device_channels),
+            };
+
+            // Resampling happens after mapping, at the processing channel count
+            let resampler = (config.sample_rate().0 != self.sample_rate).then(|| {
+                info!("Resampling input from {} Hz to {} Hz", config.sample_rate().0, self.sample_rate);
+                LinearResampler::new(config.sample_rate().0, self.sample_rate, self.channels as usize)
+            });
+            let (producer, consumer) = HeapRb::<f32>::new(self.buffer_capacity()).split();
+            let mut capture = InputCapture { mapper, resampler, producer, block_signal: Arc::clone(&self.block_signal) };
+            let latency = Arc::clone(&self.latency);
+            let errors = self.errors.clone();
+            let lost = Arc::clone(&self.input_lost);
+
+            let stream = stream::build_input_stream(
+                device,
+                stream_config,
+                config.sample_format(),
+                move |data: &[f32], info: &cpal::InputCallbackInfo| {
+                    let timestamp = info.timestamp();
+                    if let Some(delay) = timestamp.callback.duration_since(&timestamp.capture) {
+                        latency.input_device.store(delay.as_secs_f32() * 1000.0);
+                    }
+                    latency.input_period.store((data.len() / device_channels.max(1) as usize) as u32, Ordering::Relaxed);
+                    capture.push(data);
+                },
+                move |err| {
+                    if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+                        lost.store(true, Ordering::Relaxed);
+                    }
+                    errors.push(describe_stream_error("Input", &err));
+                },
+            )?;
+            Ok((stream, consumer))
+        })?;
+
+        self.input_stream = Some(stream::play("Input", stream)?);
+        if let Ok(mut slot) = self.mic_consumer.lock() {
+            *slot = Some(consumer);
+        }
+        info!("Input capture started");
+        Ok(())
+    }
+
+    // Routing from an input device with `device_channels` channels into
+    // processing: the selected input channel, else the custom map; None for
+    // the default of processing every channel
+    fn input_map(&self, device_channels: u16) -> Option<ChannelMap> {
+        if let Some(channel) = self.input_channel {
+            if channel < device_channels {
+                info!("Processing input channel {} of {} as mono", channel + 1, device_channels);
+                return ChannelMap::select(device_channels as usize, &[channel as usize]).ok();
+            }
+            warn!(
+                "Input channel {} is selected but the device has {} channels, processing all of them",
+                channel + 1, device_channels
+            );
+            return None;
+        }
+        match &self.input_channel_map {
+            Some(map) if map.inputs() == device_channels as usize => {
+                info!("Mapping {} input device channels to {} processing channels", map.inputs(), map.outputs());
+                Some(map.clone())
+            }
+            Some(map) => {
+                warn!(
+                    "Input channel map expects {} channels but the device has {}, ignoring it",
+                    map.inputs(), device_channels
+                );
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn start_loopback_capture(&mut self) -> Result<()> {
+        #[cfg(target_os = "windows")]
+        {
+            self.start_wasapi_loopback()
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            self.start_monitor_loopback()
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            self.start_virtual_device_loopback()
+        }
+
+        #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+        {
+            info!("Loopback capture is not implemented on this platform");
+            Ok(())
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    fn start_wasapi_loopback(&mut self) -> Result<()> {
+        // WASAPI switches to loopback mode when an input stream is built on a
+        // render endpoint, so we capture whatever is mixed into the default one
+        let default_name = self.host.default_output_device()
+            .and_then(|d| d.name().ok());
+        let mut render_devices: Vec<Device> = self.host.output_devices()?.collect();
+
+        if render_devices.is_empty() {
+            // Nothing is playing anywhere; the processing loop substitutes
+            // silence for the missing reference, so echo cancellation is a no-op
+            warn!("No active render device, echo cancellation reference will be silent");
+            return Ok(());
+        }
+
+        let wanted_name = self.loopback_device_name.clone().or(default_name);
+        let index = render_devices.iter()
+            .position(|d| d.name().ok() == wanted_name)
+            .unwrap_or(0);
+        let device = render_devices.swap_remove(index);
+        let config = self.reference_stream_config(
+            device.supported_output_configs()?,
+            device.default_output_config()?,
+        );
+
+        info!("Loopback device: {}", device.name().unwrap_or_else(|_| "Unknown".to_string()));
+        self.start_app_capture(device, config)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn start_monitor_loopback(&mut self) -> Result<()> {
+        // PulseAudio, and PipeWire through pipewire-pulse, expose the mix of
+        // every sink as a capture source named "<sink>.monitor".
+        if let Some(name) = self.loopback_device_name.clone() {
+            return self.start_named_loopback(&name);
+        }
+
+        let default_sink = self.host.default_output_device()
+            .and_then(|d| d.name().ok());
+        let mut monitors: Vec<Device> = self.host.input_devices()?
+            .filter(|d| {
+                d.name()
+                    .map(|name| is_loopback_name(&name) && !virtual_device::is_own_monitor(&name))
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        if monitors.is_empty() {
+            return Err(AudioError::Unavailable(
+                "No PulseAudio/PipeWire monitor source found; \
+                 make sure a sound server is running and exposes \"<sink>.monitor\" sources"
+                    .to_string(),
+            ));
+        }
+
+        let index = monitors.iter()
+            .position(|d| match (d.name().ok(), default_sink.as_ref()) {
+                (Some(name), Some(sink)) => name == format!("{}.monitor", sink),
+                _ => false,
+            })
+            .unwrap_or(0);
+        let device = monitors.swap_remove(index);
+        let config = self.reference_stream_config(
+            device.supported_input_configs()?,
+            device.default_input_config()?,
+        );
+
+        info!("Loopback device: {}", device.name().unwrap_or_else(|_| "Unknown".to_string()));
+        self.start_app_capture(device, config)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn start_virtual_device_loopback(&mut self) -> Result<()> {
+        // CoreAudio has no loopback mode, so system audio has to be routed
+        // through a virtual device such as BlackHole that shows up as an input
+        if let Some(name) = self.loopback_device_name.clone() {
+            return self.start_named_loopback(&name);
+        }
+
+        let device = self.host.input_devices()?
+            .find(|d| d.name().map(|name| is_loopback_name(&name)).unwrap_or(false))
+            .ok_or_else(|| AudioError::Unavailable(
+                "No virtual loopback device found; install BlackHole (or Soundflower), \
+                 set it as the system output or part of a multi-output device, and try again"
+                    .to_string(),
+            ))?;
+        let config = self.reference_stream_config(
+            device.supported_input_configs()?,
+            device.default_input_config()?,
+        );
+
+        info!("Loopback device: {}", device.name().unwrap_or_else(|_| "Unknown".to_string()));
+        self.start_app_capture(device, config)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    fn start_named_loopback(&mut self, name: &str) -> Result<()> {
+        let device = self.host.input_devices()?
+            .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+            .ok_or_else(|| AudioError::DeviceNotFound { kind: "Loopback", name: name.to_string() })?;
+        let config = self.reference_stream_config(
+            device.supported_input_configs()?,
+            device.default_input_config()?,
+        );
+
+        info!("Loopback device: {}", name);
+        self.start_app_capture(device, config)
+    }
+
+    // Picks a stream config matching the microphone format so that the
+    // reference lines up sample-for-sample with the mic in process_audio_chunk
+    #[cfg_attr(
+        not(any(target_os = "windows", target_os = "linux", target_os = "macos")),
+        allow(dead_code)
+    )]
+    fn reference_stream_config(
+        &self,
+        configs: impl Iterator<Item = cpal::SupportedStreamConfigRange>,
+        default_config: cpal::SupportedStreamConfig,
+    ) -> cpal::SupportedStreamConfig {
+        // Any sample format is converted in the callback, but f32 avoids the
+        // conversion, so prefer it when the device offers the mic's format in it
+        let matching: Vec<_> = configs
+            .filter(|range| {
+                range.channels() == self.channels
+                    && range.min_sample_rate().0 <= self.sample_rate
+                    && range.max_sample_rate().0 >= self.sample_rate
+            })
+            .collect();
+        let preferred = matching.iter()
+            .find(|range| range.sample_format() == cpal::SampleFormat::F32)
+            .or_else(|| matching.first());
+
+        match preferred {
+            Some(range) => (*range).with_sample_rate(cpal::SampleRate(self.sample_rate)),
+            None => {
+                warn!(
+                    "Loopback device does not support {} Hz / {} ch, capturing at {} Hz / {} ch",
+                    self.sample_rate,
+                    self.channels,
+                    default_config.sample_rate().0,
+                    default_config.channels()
+                );
+                default_config
+            }
+        }
+    }
+
+    #[cfg_attr(
+        not(any(target_os = "windows", target_os = "linux", target_os = "macos")),
+        allow(dead_code)
+    )]
+    fn start_app_capture(&mut self, device: Device, supported: cpal::SupportedStreamConfig) -> Result<()> {
+        let (stream, consumer) = self.build_app_capture(&device, supported)?;
+        self.app_stream = Some(stream);
+        if let Ok(mut slot) = self.app_consumer.lock() {
+            *slot = Some(consumer);
+        }
+        self.loopback_device = Some(device);
+        info!("Loopback capture started");
+        Ok(())
+    }
+
+    // Finds a loopback source by name: a render endpoint on Windows, where
+    // WASAPI captures it in loopback mode, a capture device elsewhere
+    fn find_loopback_source(&self, name: &str) -> Result<(Device, cpal::SupportedStreamConfig)> {
+        #[cfg(target_os = "windows")]
+        {
+            let device = self.host.output_devices()?
+                .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+                .ok_or_else(|| AudioError::DeviceNotFound { kind: "Loopback", name: name.to_string() })?;
+            let config = self.reference_stream_config(
+                device.supported_output_configs()?,
+                device.default_output_config()?,
+            );
+            Ok((device, config))
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            let device = self.host.input_devices()?
+                .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+                .ok_or_else(|| AudioError::DeviceNotFound { kind: "Loopback", name: name.to_string() })?;
+            let config = self.reference_stream_config(
+                device.supported_input_configs()?,
+                device.default_input_config()?,
+            );
+            Ok((device, config))
+        }
+
+        #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+        {
+            Err(AudioError::Unavailable(format!("Loopback capture is not implemented on this platform (\"{}\")", name)))
+        }
+    }
+
+    // (Re)starts a capture for every extra loopback source. A source that
+    // fails is reported and left out, the others and the main one still run.
+    fn start_extra_loopback_captures(&mut self) {
+        self.extra_app_streams.clear();
+        let mut started = Vec::new();
+        for name in &self.extra_loopback_names {
+            let capture = self.find_loopback_source(name)
+                .and_then(|(device, config)| self.build_app_capture(&device, config));
+            match capture {
+                Ok((stream, consumer)) => {
+                    info!("Extra loopback capture started: {}", name);
+                    self.extra_app_streams.push(stream);
+                    started.push(consumer);
+                }
+                Err(e) => self.errors.push(format!("Loopback source \"{}\" failed: {}", name, e)),
+            }
+        }
+        if let Ok(mut slot) = self.extra_app_consumers.lock() {
+            slot.clear = true;
+            slot.added = started;
+        }
+    }
+
+    // Opens a capture of `device` at the processing format, returning the
+    // started stream and the reading end of its buffer
+    #[cfg_attr(
+        not(any(target_os = "windows", target_os = "linux", target_os = "macos")),
+        allow(dead_code)
+    )]
+    fn build_app_capture(&self, device: &Device, supported: cpal::SupportedStreamConfig) -> Result<(PlayingStream, HeapConsumer<f32>)> {
+        let (mut producer, consumer) = HeapRb::<f32>::new(self.buffer_capacity()).split();
+        let config = supported.config();
+
+        // Virtual devices often run at 44.1kHz while the mic runs at 48kHz;
+        // bring the reference to the processing rate before it is buffered
+        let mut resampler = (config.sample_rate.0 != self.sample_rate).then(|| {
+            info!("Resampling loopback from {} Hz to {} Hz", config.sample_rate.0, self.sample_rate);
+            LinearResampler::new(config.sample_rate.0, self.sample_rate, config.channels as usize)
+        });
+        // The echo canceller pairs each mic channel with the same channel of
+        // the reference, so the reference takes on the mic's layout
+        let (from, to) = (config.channels as usize, self.channels as usize);
+        if from != to {
+            info!("Mapping {} loopback channels to {} processing channels", from, to);
+        }
+        // Reused across callbacks so they don't allocate once warmed up
+        let mut resampled = Vec::new();
+        let mut remixed = Vec::new();
+
+        let errors = self.errors.clone();
+        let stream = stream::build_input_stream(
+            device,
+            &config,
+            supported.sample_format(),
+            move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                let data = match resampler.as_mut() {
+                    Some(resampler) => {
+                        resampled.clear();
+                        resampler.process(data, |sample| resampled.push(sample));
+                        &resampled[..]
+                    }
+                    None => data,
+                };
+                if from == to {
+                    producer.push_slice(data);
+                } else {
+                    remixed.resize(data.len() / from * to, 0.0);
+                    let frames = remix(data, from, &mut remixed, to);
+                    producer.push_slice(&remixed[..frames * to]);
+                }
+            },
+            move |err| errors.push(describe_stream_error("Loopback", &err)),
+        )?;
+
+        Ok((stream::play("Loopback", stream)?, consumer))
+    }
+
+    /// Spawns the processing task that moves audio from the capture buffers,
+    /// through [`Self::process_audio_chunk`], into the output buffer.
+    ///
+    /// Threading contract:
+    /// - Must be called from within a Tokio runtime; the task is started with
+    ///   `tokio::spawn` and awaits the next block rather than blocking a thread.
+    /// - The task owns all DSP state and the consumer/producer halves of the
+    ///   ring buffers. Audio callbacks never lock; the input callback only
+    ///   signals once a full block is buffered. Streams started before or
+    ///   after this call hand their buffer half over through a slot the task
+    ///   checks once per block.
+    /// - Only whole blocks go through the DSP chain; a block the mic is still
+    ///   filling is waited for, never padded. Without a running mic stream,
+    ///   or while it stalls, the task produces a block of silence every two
+    ///   block durations that skips the chain, so output keeps flowing and
+    ///   the noise estimate and echo filter never see the gap.
+    /// - Any previous task is told to stop, and [`Self::stop`] stops this one.
+    ///   A stopped task processes the block the mic had started and the
+    ///   latency tail, padded with silence, so a running recording keeps the
+    ///   end of the audio, then exits without waiting for more input.
+    /// - Settings are snapshotted at every block boundary, so setters apply
+    ///   to the next block. A new FFT size rebuilds the FFT plans and starts
+    ///   from fresh overlap and noise-estimate state.
+    pub fn start_processing(&mut self) -> Result<()> {
+        self.stop_processing_task();
+        self.is_processing = true;
+        let running = Arc::new(AtomicBool::new(true));
+        
+        // Spawn processing thread
+        let mic_handoff = Arc::clone(&self.mic_consumer);
+        let app_handoff = Arc::clone(&self.app_consumer);
+        let extra_app_handoff = Arc::clone(&self.extra_app_consumers);
+        let processed_handoff = Arc::clone(&self.processed_producer);
+        let monitor_handoff = Arc::clone(&self.monitor_producer);
+        let extra_output_handoff = Arc::clone(&self.extra_output_producers);
+        let monitor_raw = Arc::clone(&self.monitor_raw);
+        let input_level = Arc::clone(&self.input_level);
+        let input_channel_levels = Arc::clone(&self.input_channel_levels);
+        let output_level = Arc::clone(&self.output_level);
+        let meter_weighting = Arc::clone(&self.meter_weighting);
+        let voice_active = Arc::clone(&self.voice_active);
+        let agc_gain_db = Arc::clone(&self.agc_gain_db);
+        let bypass = Arc::clone(&self.bypass);
+        let mute = Arc::clone(&self.mute);
+        let output_inverted = Arc::clone(&self.output_inverted);
+        let test_tone = Arc::clone(&self.test_tone);
+        let limiter_reduction_db = Arc::clone(&self.limiter_reduction_db);
+        let compressor_reduction_db = Arc::clone(&self.compressor_reduction_db);
+        let gate_open = Arc::clone(&self.gate_open);
+        let power_saving = Arc::clone(&self.power_saving);
+        let processing_load = Arc::clone(&self.processing_load);
+        let sanitized_samples = Arc::clone(&self.sanitized_samples);
+        sanitized_samples.store(0, Ordering::Relaxed);
+        let safety_enabled = Arc::clone(&self.safety_enabled);
+        let safety_ceiling_db = Arc::clone(&self.safety_ceiling_db);
+        let safety_clamps = Arc::clone(&self.safety_clamps);
+        safety_clamps.store(0, Ordering::Relaxed);
+        let loudness = Arc::clone(&self.loudness);
+        let session = Arc::clone(&self.session);
+        let events = self.events.clone();
+        let latency = Arc::clone(&self.latency);
+        let spectrum = Arc::clone(&self.spectrum);
+        let spectrum_wanted = Arc::clone(&self.spectrum_wanted);
+        let delay_calibration = Arc::clone(&self.delay_calibration);
+        let mic_calibration = Arc::clone(&self.mic_calibration);
+        let noise_profile = Arc::clone(&self.noise_profile);
+        let input_switched = Arc::clone(&self.input_switched);
+        let dsp_reset = Arc::clone(&self.dsp_reset);
+        self.scope = Arc::new(ScopeRings::new(self.sample_rate));
+        let scope = Arc::clone(&self.scope);
+        let recording_tap = Arc::clone(&self.recording_tap);
+        // Sized for this run's rate and channel count; a resize from before
+        // it started is superseded
+        self.replay = Arc::new(ReplayBuffer::new(self.replay_secs, self.sample_rate, self.channels));
+        self.replay_handoff = Arc::new(Mutex::new(None));
+        let mut replay = Arc::clone(&self.replay);
+        let replay_handoff = Arc::clone(&self.replay_handoff);
+        let shared_settings = Arc::clone(&self.settings);
+        let mut settings = self.settings();
+        let sample_rate = self.sample_rate;
+        let channels = self.channels;
+        let block_signal = Arc::clone(&self.block_signal);
+        block_signal.block_size.store(settings.block_size(channels), Ordering::Relaxed);
+
+        let task_running = Arc::clone(&running);
+        let handle = tokio::spawn(async move {
+            let running = task_running;
+            let mut dsp = DspState::new(settings.fft_size, settings.hop_size, sample_rate, channels);
+            let mut spectrum_sequence = 0;
+            let mut loudness_meter = LoudnessMeter::new(sample_rate, channels);
+
+            let mut mic_buffer = CaptureBuffer::default();
+            let mut app_buffer = CaptureBuffer::default();
+            let mut extra_app_buffers: Vec<HeapConsumer<f32>> = Vec::new();
+            let mut extra_app_samples = vec![0.0; settings.block_size(channels)];
+            let mut processed_producer = None;
+            let mut monitor_producer: Option<HeapProducer<f32>> = None;
+            let mut extra_outputs: Vec<HeapProducer<f32>> = Vec::new();
+            let mut mic_samples = vec![0.0; settings.block_size(channels)];
+            let mut app_samples = vec![0.0; settings.block_size(channels)];
+            // Raw mic delayed like the processed path, so toggling bypass
+            // compares the same moment of audio
+            let mut monitor = DelayLine::new(0);
+            let mut input_window = RmsWindow::new(sample_rate, channels);
+            let mut input_channel_windows: Vec<_> = (0..channels)
+                .map(|_| RmsWindow::new(sample_rate, 1))
+                .collect();
+            // Last reported states, so events only go out on changes
+            let mut was_voice_active = false;
+            let mut was_limiting = false;
+            let mut output_window = RmsWindow::new(sample_rate, channels);
+            let weighting = MeterWeighting::ALL[meter_weighting.load(Ordering::Relaxed)];
+            let mut input_weighting = WeightingFilter::new(weighting, sample_rate, channels);
+            let mut output_weighting = WeightingFilter::new(weighting, sample_rate, channels);
+            let mut weighted = Vec::with_capacity(settings.block_size(channels));
+            let mut was_bypassed = bypass.load(Ordering::Relaxed);
+            let mut was_muted = mute.load(Ordering::Relaxed);
+            let crossfade_samples = (BYPASS_CROSSFADE_MS / 1000.0 * sample_rate as f32) as usize * channels as usize;
+            let fade_in_samples = (STREAM_FADE_IN_MS / 1000.0 * sample_rate as f32) as usize * channels as usize;
+            // The next block goes to a stream that starts from silence
+            let mut fade_next = false;
+            let mute_fade_samples = (MUTE_FADE_MS / 1000.0 * sample_rate as f32) as usize * channels as usize;
+            // The spectral state is new and lacks the noise profile
+            let mut profile_lost = true;
+            // Samples still to push through the chain once stopped
+            let mut flush_remaining: Option<usize> = None;
+
+            loop {
+                block_signal.wakeups.fetch_add(1, Ordering::Relaxed);
+                // Never wait on the UI thread; keep the last snapshot if the
+                // settings are being written right now
+                if let Ok(current) = shared_settings.try_lock() {
+                    if current.fft_size != settings.fft_size || current.hop_size != settings.hop_size {
+                        dsp.spectral = SpectralState::with_hop(current.fft_size, current.hop_size, channels);
+                        profile_lost = true;
+                        let block_size = current.block_size(channels);
+                        mic_samples.resize(block_size, 0.0);
+                        app_samples.resize(block_size, 0.0);
+                        extra_app_samples.resize(block_size, 0.0);
+                        block_signal.block_size.store(block_size, Ordering::Relaxed);
+                        info!("FFT size changed to {}, blocks of {}", current.fft_size, block_size);
+                    }
+                    settings.clone_from(&current);
+                }
+                dsp.apply_settings(&settings);
+
+                // Checked before the handoffs, so everything buffered at this
+                // point came from the old device: the new stream's buffer is
+                // either still in its slot or not created yet. The echo
+                // filter and noise estimate describe the old device too.
+                if input_switched.swap(false, Ordering::Relaxed) {
+                    mic_buffer.clear();
+                    app_buffer.clear();
+                    for buffer in &mut extra_app_buffers {
+                        buffer.clear();
+                    }
+                    dsp.reset(settings.fft_size, settings.hop_size);
+                    dsp.apply_settings(&settings);
+                    monitor = DelayLine::new(0);
+                    fade_next = true;
+                    profile_lost = true;
+                } else if dsp_reset.swap(false, Ordering::Relaxed) {
+                    // The same fresh start, keeping the audio in flight
+                    dsp.reset(settings.fft_size, settings.hop_size);
+                    dsp.apply_settings(&settings);
+                    fade_next = true;
+                    profile_lost = true;
+                    info!("DSP state reset");
+                }
+
+                if let Ok(mut slot) = noise_profile.try_lock() {
+                    // A learning that finished during the last block
+                    let progress = dsp.spectral.noise_learning_progress();
+                    if slot.progress.is_some() && progress.is_none() {
+                        if let Some(profile) = dsp.spectral.noise_profile() {
+                            slot.profile = Some(profile.clone());
+                            info!("Noise profile learned");
+                        }
+                    }
+                    slot.progress = progress;
+                    if let Some(frames) = slot.learn_frames.take() {
+                        dsp.spectral.start_noise_learning(frames);
+                        slot.progress = Some(0.0);
+                    }
+                    let reapply = std::mem::take(&mut slot.changed) | std::mem::take(&mut profile_lost);
+                    if reapply && !dsp.spectral.set_noise_profile(slot.profile.clone()) {
+                        warn!("Noise profile was learned at another FFT size, using the adaptive estimate");
+                    }
+                }
+
+                // Pick up buffer halves from streams that were (re)started
+                mic_buffer.take_handoff(&mic_handoff);
+                app_buffer.take_handoff(&app_handoff);
+                ExtraBuffers::take_into(&extra_app_handoff, &mut extra_app_buffers);
+                let mut new_producer = None;
+                take_handoff(&processed_handoff, &mut new_producer);
+                if new_producer.is_some() {
+                    processed_producer = new_producer;
+                    fade_next = true;
+                }
+                take_handoff(&monitor_handoff, &mut monitor_producer);
+                let mut new_replay = None;
+                take_handoff(&replay_handoff, &mut new_replay);
+                if let Some(new_replay) = new_replay {
+                    replay = new_replay;
+                }
+                ExtraBuffers::take_into(&extra_output_handoff, &mut extra_outputs);
+
+                // Wait until the mic has delivered a whole block. A wakeup can
+                // be stale (left over from a block already taken), so re-check
+                // until the deadline.
+                let block_size = settings.block_size(channels);
+                let samples_per_second = sample_rate as f32 * channels as f32;
+                let block_duration = Duration::from_secs_f32(block_size as f32 / samples_per_second);
+                let deadline = tokio::time::Instant::now() + block_duration * 2;
+                let filled_before = mic_buffer.len();
+                while running.load(Ordering::Relaxed) && mic_buffer.len() < block_size {
+                    if tokio::time::timeout_at(deadline, block_signal.notify.notified()).await.is_err() {
+                        break;
+                    }
+                }
+
+                // Once stopped, what is in flight (the block the mic started
+                // and the latency tail) goes through with silence after it
+                let stopping = !running.load(Ordering::Relaxed);
+                if stopping {
+                    let remaining = flush_remaining.get_or_insert(mic_buffer.len() + dsp.latency(&settings));
+                    if *remaining == 0 {
+                        break;
+                    }
+                    *remaining = remaining.saturating_sub(block_size);
+                }
+                // A short block padded with silence would feed the noise
+                // estimate and the echo filter a gap that isn't in the audio.
+                // While the mic is still filling the block, keep waiting;
+                // when nothing more came in (no stream, or a stalled one),
+                // a block of silence skips the chain to keep output flowing.
+                let filled = mic_buffer.len();
+                if !stopping && filled < block_size && filled > filled_before {
+                    continue;
+                }
+                let filler = !stopping && filled < block_size;
+
+                // Extract samples from buffers; only the last block of a
+                // flush is padded with silence. The reference moves on during
+                // filler blocks too, so it doesn't back up.
+                let read = if filler { 0 } else { mic_buffer.pop_slice(&mut mic_samples) };
+                mic_samples[read..].fill(0.0);
+                let read = app_buffer.pop_slice(&mut app_samples);
+                app_samples[read..].fill(0.0);
+                // Everything the speakers play is echo, so the reference is
+                // the sum of all sources; a short one adds its silence
+                for buffer in &mut extra_app_buffers {
+                    let read = buffer.pop_slice(&mut extra_app_samples);
+                    mix_into(&mut app_samples, &extra_app_samples[..read]);
+                }
+                // Before anything else sees them: bypass, calibration and
+                // the meters use these blocks directly
+                dsp.sanitize(&mut mic_samples);
+                dsp.sanitize(&mut app_samples);
+                sanitized_samples.store(dsp.sanitized_samples(), Ordering::Relaxed);
+
+                if let (false, Ok(mut calibration)) = (filler, delay_calibration.try_lock()) {
+                    if let Some(calibration) = calibration.as_mut() {
+                        calibration.push(&mic_samples, &app_samples, channels);
+                    }
+                }
+                if let (false, Ok(mut calibration)) = (filler, mic_calibration.try_lock()) {
+                    if let Some(calibration) = calibration.as_mut() {
+                        calibration.push(&mic_samples, channels);
+                    }
+                }
+
+                // A filler block stays silent even while bypassed, rather
+                // than playing the raw mic still in the delay line
+                monitor.set_delay(dsp.latency(&settings));
+                let raw = if filler { vec![0.0; mic_samples.len()] } else { monitor.process(&mic_samples) };
+                let bypassed = bypass.load(Ordering::Relaxed);
+                let chain_ran = !(filler || bypassed && was_bypassed);
+                let processed = if !chain_ran {
+                    raw
+                } else {
+                    let started = Instant::now();
+                    let mut processed = Self::process_audio_chunk(
+                        &mic_samples,
+                        &app_samples,
+                        &settings,
+                        &mut dsp,
+                    );
+                    // Exponential average over about a second of blocks
+                    let block_secs = block_duration.as_secs_f32();
+                    let load = started.elapsed().as_secs_f32() / block_secs;
+                    let weight = 1.0 - (-block_secs / PROCESSING_LOAD_AVERAGE_SECS).exp();
+                    let average = processing_load.load();
+                    processing_load.store(average + (load - average) * weight);
+                    session.record_load(load);
+                    // The block where bypass flips fades between the two
+                    if bypassed {
+                        let mut raw = raw;
+                        crossfade(&processed, &mut raw, crossfade_samples);
+                        processed = raw;
+                    } else if was_bypassed {
+                        crossfade(&raw, &mut processed, crossfade_samples);
+                    }
+                    processed
+                };
+                was_bypassed = bypassed;
+
+                let mut processed = processed;
+                // A test tone stands in for the processed signal at the
+                // output gain; if the slot is busy it resumes next block
+                if let Ok(mut tone) = test_tone.try_lock() {
+                    if let Some(generator) = tone.as_mut() {
+                        generator.fill(&mut processed);
+                        let gain = 10f32.powf(settings.output_gain_db / 20.0);
+                        processed.iter_mut().for_each(|sample| *sample *= gain);
+                        if generator.is_finished() {
+                            tone.take();
+                        }
+                    }
+                }
+                let muted = mute.load(Ordering::Relaxed);
+                apply_mute(&mut processed, muted, was_muted, mute_fade_samples);
+                was_muted = muted;
+                if fade_next {
+                    fade_in(&mut processed, fade_in_samples);
+                    fade_next = false;
+                }
+                // Audio resuming after a gap of filler fades in
+                fade_next |= filler;
+                if output_inverted.load(Ordering::Relaxed) {
+                    processed.iter_mut().for_each(|sample| *sample = -*sample);
+                }
+                // Last of all, so nothing above, bypass and the test tone
+                // included, can get past it
+                if safety_enabled.load(Ordering::Relaxed) {
+                    let clamped = clamp_to_ceiling(&mut processed, safety_ceiling_db.load());
+                    safety_clamps.fetch_add(clamped as u64, Ordering::Relaxed);
+                }
+
+                // Input displays show the mic after the input gain, as the
+                // chain saw it; while bypassed nothing applies the gain
+                let input = if chain_ran { &dsp.input } else { &mic_samples };
+                let weighting = MeterWeighting::ALL[meter_weighting.load(Ordering::Relaxed)];
+                if weighting != input_weighting.weighting() {
+                    input_weighting = WeightingFilter::new(weighting, sample_rate, channels);
+                    output_weighting = WeightingFilter::new(weighting, sample_rate, channels);
+                }
+                input_weighting.process(input, &mut weighted);
+                if input_level.update(input, input_window.push(&weighted)) {
+                    events.emit(ProcessorEvent::Clipped(Signal::Input));
+                }
+                input_channel_levels.update(input, &weighted, &mut input_channel_windows);
+                output_weighting.process(&processed, &mut weighted);
+                if output_level.update(&processed, output_window.push(&weighted)) {
+                    events.emit(ProcessorEvent::Clipped(Signal::Output));
+                }
+                let voice = dsp.vad.is_active();
+                if !filler {
+                    session.record_block(&processed, channels, voice);
+                }
+                voice_active.store(voice, Ordering::Relaxed);
+                if voice != was_voice_active {
+                    events.emit(ProcessorEvent::VoiceActivity(voice));
+                    was_voice_active = voice;
+                }
+                agc_gain_db.store(if settings.agc_enabled { dsp.agc.gain_db() } else { 0.0 });
+                compressor_reduction_db.store(if settings.compressor_enabled { dsp.compressor.gain_reduction_db() } else { 0.0 });
+                let reduction_db = if settings.limiter_enabled { dsp.limiter.gain_reduction_db() } else { 0.0 };
+                limiter_reduction_db.store(reduction_db);
+                let limiting = reduction_db < LIMITER_ACTIVE_DB;
+                if limiting != was_limiting {
+                    events.emit(ProcessorEvent::LimiterActive(limiting));
+                    was_limiting = limiting;
+                }
+                gate_open.store(settings.gate_enabled && dsp.gate.is_open(), Ordering::Relaxed);
+                power_saving.store(dsp.is_power_saving(), Ordering::Relaxed);
+                if loudness.reset_integrated.swap(false, Ordering::Relaxed) {
+                    loudness_meter.reset_integrated();
+                }
+                if loudness_meter.process(&processed) {
+                    loudness.publish(loudness_meter.reading());
+                }
+                let normalization_gain_db = if settings.loudness_normalization { dsp.normalizer.gain_db() } else { 0.0 };
+                loudness.normalization_gain_db.store(normalization_gain_db);
+
+                scope.input.push_frames(input, channels as usize);
+                scope.output.push_frames(&processed, channels as usize);
+
+                // Only while the UI asks for it and power save isn't on; skip
+                // the update rather than wait if the UI is reading
+                spectrum_sequence += 1;
+                if spectrum_wanted.load(Ordering::Relaxed) && !dsp.is_power_saving() {
+                    if let Ok(mut spectrum) = spectrum.try_lock() {
+                        *spectrum = Some(SpectrumSnapshot {
+                            sample_rate,
+                            sequence: spectrum_sequence,
+                            input_db: dsp.analyzer.magnitudes_db(input),
+                            output_db: dsp.analyzer.magnitudes_db(&processed),
+                        });
+                        spectrum_wanted.store(false, Ordering::Relaxed);
+                    }
+                }
+
+                // Samples still queued on either side of the task; these grow
+                // when the task falls behind the streams
+                let samples_per_ms = (sample_rate as usize * channels as usize) as f32 / 1000.0;
+                let processing_samples = settings.block_size(channels) + dsp.latency(&settings);
+                latency.processing.store(processing_samples as f32 / samples_per_ms);
+                latency.input_buffer.store(mic_buffer.len() as f32 / samples_per_ms);
+
+                // Store processed samples
+                if let Some(producer) = processed_producer.as_mut() {
+                    push_frames(producer, &processed, channels as usize);
+                    latency.output_buffer.store(producer.len() as f32 / samples_per_ms);
+                }
+                for producer in &mut extra_outputs {
+                    push_frames(producer, &processed, channels as usize);
+                }
+                tee_to_recording(&recording_tap, &processed);
+                replay.push(&processed);
+
+                if let Some(producer) = monitor_producer.as_mut() {
+                    let monitored = if monitor_raw.load(Ordering::Relaxed) { input } else { &processed };
+                    push_frames(producer, monitored, channels as usize);
+                }
+
+                // Catching up on a backlog never awaits above; let other tasks run
+                tokio::task::yield_now().await;
+            }
+            info!("Processing task exited");
+        });
+        self.processing_task = Some(ProcessingTask { running, handle });
+
+        info!("Audio processing started");
+        Ok(())
+    }
+
+    /// Runs a WAV file through the same DSP chain as live audio, with the
+    /// current settings, and writes the result to `output`. No devices or
+    /// streams are involved; the FFT size follows the input file's sample
+    /// rate and channel count, and the output is aligned with the input.
+    pub fn process_file(&self, input: &Path, output: &Path) -> Result<()> {
+        let settings = self.settings();
+        let (spec, mut samples) = Self::read_wav(input)?;
+        let fft_size = fft_size_for(spec.sample_rate);
+        let block_size = fft_size * spec.channels as usize;
+        info!(
+            "Processing {} ({} Hz, {} ch) with {}-sample blocks",
+            input.display(), spec.sample_rate, spec.channels, block_size
+        );
+
+        let mut dsp = DspState::new(fft_size, 0, spec.sample_rate, spec.channels);
+        dsp.apply_settings(&settings);
+        let latency = dsp.latency(&settings);
+
+        // Pad the end so the delayed tail is flushed out, then drop the
+        // leading delay so output sample n lines up with input sample n
+        let len = samples.len();
+        samples.resize((len + latency).div_ceil(block_size) * block_size, 0.0);
+        let reference = vec![0.0; block_size];
+
+        let mut processed = Vec::with_capacity(samples.len());
+        for block in samples.chunks(block_size) {
+            processed.extend(Self::process_audio_chunk(
+                block,
+                &reference,
+                &settings,
+                &mut dsp,
+            ));
+        }
+
+        let mut writer = create_wav(output, spec.sample_rate, spec.channels)?;
+        write_samples(&mut writer, &processed[latency..latency + len])?;
+        writer.finalize().map_err(anyhow::Error::from)?;
+        Ok(())
+    }
+
+    // Reads an integer or float WAV file as interleaved f32 samples in [-1, 1]
+    fn read_wav(path: &Path) -> anyhow::Result<(hound::WavSpec, Vec<f32>)> {
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        let samples = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+                reader.samples::<i32>().map(|sample| sample.map(|s| s as f32 * scale)).collect::<Result<_, _>>()?
+            }
+        };
+        Ok((spec, samples))
+    }
+
+    /// Processes one block of interleaved microphone samples against the
+    /// matching block of application (reference) audio. Safe to call on any
+    /// slices without starting streams; `dsp` carries the echo filter,
+    /// overlap-add and noise-estimate state between consecutive blocks.
+    ///
+    /// Echo cancellation subtracts the reference sample for sample, so
+    /// `app_samples` must have the mic's sample rate and channel layout.
+    /// The streams bring both captures to the processing rate before
+    /// buffering them; offline, see
+    /// [`OfflinePipeline::push_reference_samples_at`](crate::OfflinePipeline::push_reference_samples_at).
+    pub fn process_audio_chunk(
+        mic_samples: &[f32],
+        app_samples: &[f32],
+        settings: &ProcessorSettings,
+        dsp: &mut DspState,
+    ) -> Vec<f32> {
+        // Switching echo cancellation or noise reduction fades over from
+        // the chain as it was, which runs alongside until then
+        let previous = dsp.run_previous(settings, |settings, previous| {
+            Self::process_audio_chunk(mic_samples, app_samples, settings, previous)
+        });
+
+        let mut input = std::mem::take(&mut dsp.input);
+        input.clear();
+        input.extend_from_slice(mic_samples);
+        // Devices can deliver garbage; nothing non-finite may reach the
+        // filters and estimators below, whose state it would poison
+        dsp.sanitize(&mut input);
+        dsp.input_gain.process(&mut input);
+        let mic_samples = &input[..];
+
+        // Always delayed, so switching echo cancellation on finds the
+        // reference history already in place
+        let mut reference = dsp.reference.process(app_samples);
+        dsp.sanitize(&mut reference);
+
+        // The WebRTC module stands in for everything up to the mix
+        #[cfg(feature = "webrtc-apm")]
+        if let Some(apm) = dsp.webrtc.0.as_mut().filter(|_| settings.backend == ProcessingBackend::WebRtc) {
+            let mut processed = apm.process(mic_samples, &reference);
+            let latency = apm.latency();
+            dsp.sanitize(&mut processed);
+            let voice = dsp.vad.update(&processed);
+            dsp.spectral.set_voice_active(voice);
+            return Self::finish_chunk(input, processed, latency, previous, settings, dsp);
+        }
+
+        let mut processed = if settings.echo_cancellation {
+            // Adaptive filter estimates the echo of the app audio and removes it
+            dsp.echo.process(mic_samples, &reference)
+        } else {
+            mic_samples.to_vec()
+        };
+
+        // Rumble would otherwise dominate the VAD energy and the noise
+        // estimate, so it goes before both
+        if settings.highpass_enabled {
+            dsp.highpass.process(&mut processed);
+        }
+
+        // Runs even with noise reduction off so voice activity stays observable
+        let voice = dsp.vad.update(&processed);
+        dsp.spectral.set_voice_active(voice);
+        dsp.update_power_save(settings, voice, processed.len());
+
+        // RNNoise works on the samples; the spectral frames that follow still
+        // track the noise for comfort noise and run the stages below
+        #[cfg(feature = "rnnoise")]
+        if let Some(rnnoise) = dsp.rnnoise.as_mut().filter(|_| settings.noise_reduction) {
+            processed = rnnoise.process(&processed);
+            dsp.sanitize(&mut processed);
+        }
+
+        if settings.spectral_enabled() {
+            // Click suppression and the de-esser share noise reduction's FFT
+            // frames; clicks go first so they don't trigger the de-esser
+            let click = &mut dsp.click;
+            let deesser = &mut dsp.deesser;
+            processed = process_spectral(&processed, &mut dsp.spectral, |channel, spectrum| {
+                if settings.click_suppression {
+                    click.process(channel, spectrum);
+                }
+                if settings.deesser_enabled {
+                    deesser.process(channel, spectrum);
+                }
+            });
+            dsp.sanitize(&mut processed);
+        }
+
+        // Works on what noise reduction left of the room tone
+        if settings.expander_enabled {
+            dsp.expander.process(&mut processed);
+        }
+
+        // Comfort noise follows the noise estimate, so it reflects the
+        // background as of the last time noise reduction ran
+        if settings.gate_enabled {
+            if settings.comfort_noise_enabled {
+                let comfort = dsp.comfort.generate(dsp.spectral.noise_power(), processed.len());
+                dsp.gate.process_with_fill(&mut processed, &comfort);
+            } else {
+                dsp.gate.process(&mut processed);
+            }
+        } else if settings.comfort_noise_enabled {
+            dsp.comfort.fill_silence(&mut processed, dsp.spectral.noise_power(), !voice);
+        }
+
+        // Evens out the voice once the noise is gone, which it would
+        // otherwise bring up along with quiet speech; the AGC then sets the
+        // overall level
+        if settings.compressor_enabled {
+            dsp.compressor.process(&mut processed);
+        }
+
+        // Last, so it levels what is actually sent; gated pauses read as
+        // silence and leave its gain alone
+        if settings.agc_enabled {
+            dsp.agc.process(&mut processed);
+        }
+
+        // RNNoise and the spectral stages are the only ones before this point
+        // with latency
+        let spectral_latency = if settings.spectral_enabled() { dsp.spectral.latency() } else { 0 };
+        let latency = spectral_latency + dsp.rnnoise_latency(settings);
+        Self::finish_chunk(input, processed, latency, previous, settings, dsp)
+    }
+
+    // Mixes `processed`, `latency` samples behind the (gained) `input`, with
+    // the dry signal and runs the output stages every backend shares
+    fn finish_chunk(
+        input: Vec<f32>,
+        mut processed: Vec<f32>,
+        latency: usize,
+        previous: Option<Vec<f32>>,
+        settings: &ProcessorSettings,
+        dsp: &mut DspState,
+    ) -> Vec<f32> {
+        // Delaying the dry signal as much as the wet one avoids comb
+        // filtering. The dry path always runs so changing the mix never plays
+        // stale audio.
+        dsp.dry.set_delay(latency);
+        let dry = dsp.dry.process(&input);
+        if settings.mix < 1.0 {
+            let wet = settings.mix.max(0.0);
+            for (sample, dry) in processed.iter_mut().zip(&dry) {
+                *sample = wet * *sample + (1.0 - wet) * dry;
+            }
+        }
+
+        // After the mix so the target holds whatever the blend; the limiter
+        // still follows to catch the peaks raising the level creates
+        if settings.loudness_normalization {
+            dsp.normalizer.process(&mut processed);
+        }
+
+        dsp.output_gain.process(&mut processed);
+
+        // Catches whatever echo subtraction and gain pushed past the ceiling;
+        // must stay the final stage
+        if settings.limiter_enabled {
+            dsp.limiter.process(&mut processed);
+        }
+        dsp.sanitize(&mut processed);
+        if let Some(previous) = previous {
+            dsp.blend_previous(&previous, &mut processed);
+        }
+
+        dsp.input = input;
+        processed
+    }
+
+    pub fn start_loopback_output(&mut self) -> Result<()> {
+        let Some(device) = &self.selected_output_device else {
+            return Err(AudioError::NoDevice("output"));
+        };
+        let config = choose_stream_config(
+            device.supported_output_configs()?,
+            device.default_output_config()?,
+            self.sample_rate,
+            self.requested_sample_rate,
+            self.requested_channels,
+        );
+        info!("Output config: {:?}", config);
+        self.output_format = Some(StreamFormat::from(&config));
+        self.output_channels = config.channels();
+        if self.output_channels != self.channels {
+            info!("Mapping {} input channels to {} output channels", self.channels, self.output_channels);
+        }
+        let output_channels = self.output_channels;
+        let (stream, producer) = with_buffer_fallback("Output", self.stream_config(&config), |stream_config| {
+            let mut mapper = match &self.output_channel_map {
+                Some(map) if map.inputs() == self.channels as usize && map.outputs() == output_channels as usize => {
+                    info!("Using the custom output channel map");
+                    ChannelMapper::with_map(map.clone())
+                }
+                Some(map) => {
+                    warn!(
+                        "Output channel map is {} to {} channels but the streams are {} to {}, ignoring it",
+                        map.inputs(), map.outputs(), self.channels, output_channels
+                    );
+                    ChannelMapper::new(self.channels, output_channels)
+                }
+                None => ChannelMapper::new(self.channels, output_channels),
+            };
+            // Resampling happens before remixing, at the processing channel count
+            let mut resampler = (config.sample_rate().0 != self.sample_rate).then(|| {
+                info!("Resampling output from {} Hz to {} Hz", self.sample_rate, config.sample_rate().0);
+                PullResampler::new(self.sample_rate, config.sample_rate().0, self.channels as usize)
+            });
+            let (producer, mut consumer) = HeapRb::<f32>::new(self.buffer_capacity()).split();
+            let mut playout = Playout::new(self.playout_target(), Arc::clone(&self.underruns));
+            let latency = Arc::clone(&self.latency);
+            let errors = self.errors.clone();
+            let lost = Arc::clone(&self.output_lost);
+            let stereo_output = Arc::clone(&self.stereo_output);
+            let output_rate = config.sample_rate().0 as f32;
+
+            let stream = stream::build_output_stream(
+                device,
+                stream_config,
+                config.sample_format(),
+                move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+                    let timestamp = info.timestamp();
+                    if let Some(delay) = timestamp.playback.duration_since(&timestamp.callback) {
+                        latency.output_device.store(delay.as_secs_f32() * 1000.0);
+                    }
+                    latency.output_period.store((data.len() / output_channels.max(1) as usize) as u32, Ordering::Relaxed);
+                    let delay_frames = (stereo_output.haas_delay_ms.load() / 1000.0 * output_rate).round() as usize;
+                    mapper.set_stereo(stereo_output.mode(), stereo_output.width.load(), delay_frames);
+                    mapper.fill(data, |buffer| {
+                        let wanted = buffer.len();
+                        playout.read(consumer.len(), wanted, || match resampler.as_mut() {
+                            Some(resampler) => resampler.read(buffer, |input| consumer.pop_slice(input)),
+                            None => consumer.pop_slice(buffer),
+                        })
+                    });
+                },
+                move |err| {
+                    if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+                        lost.store(true, Ordering::Relaxed);
+                    }
+                    errors.push(describe_stream_error("Output", &err));
+                },
+            )?;
+            Ok((stream, producer))
+        })?;
+
+        self.loopback_stream = Some(stream::play("Output", stream)?);
+        if let Ok(mut slot) = self.processed_producer.lock() {
+            *slot = Some(producer);
+        }
+        info!("Loopback output started");
+        Ok(())
+    }
+
+    // Asks the running processing task, if any, to exit. Returns immediately;
+    // the task finishes the block it is on and then stops.
+    fn stop_processing_task(&mut self) {
+        if let Some(task) = &self.processing_task {
+            task.running.store(false, Ordering::Relaxed);
+            // Don't leave the task waiting out its block timeout
+            self.block_signal.notify.notify_one();
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if self.is_processing {
+            let integrated_lufs = self.loudness.snapshot().integrated_lufs;
+            let stats = self.session.summary(self.sample_rate, integrated_lufs, self.get_underrun_count());
+            info!(
+                "Session: {:.1} s, peak {:.1} dBFS, integrated {:.1} LUFS, {} clips, {} underruns, {:.0}% speech, {:.0}% average load",
+                stats.duration.as_secs_f32(),
+                stats.peak_db,
+                stats.integrated_lufs,
+                stats.clips,
+                stats.underruns,
+                stats.speech_ratio * 100.0,
+                stats.average_load * 100.0,
+            );
+            self.last_session = Some(stats);
+        }
+        self.is_processing = false;
+        self.stop_processing_task();
+        self.latency.reset();
+        self.input_lost.store(false, Ordering::Relaxed);
+        self.output_lost.store(false, Ordering::Relaxed);
+        self.input_switched.store(false, Ordering::Relaxed);
+        self.dsp_reset.store(false, Ordering::Relaxed);
+        self.input_level.reset();
+        self.input_channel_levels.reset();
+        self.output_level.reset();
+        self.agc_gain_db.store(0.0);
+        self.limiter_reduction_db.store(0.0);
+        self.compressor_reduction_db.store(0.0);
+        self.gate_open.store(false, Ordering::Relaxed);
+        self.power_saving.store(false, Ordering::Relaxed);
+        self.processing_load.store(0.0);
+        self.loudness.reset();
+        if let Ok(mut spectrum) = self.spectrum.lock() {
+            spectrum.take();
+        }
+        if let Ok(mut calibration) = self.delay_calibration.lock() {
+            calibration.take();
+        }
+        if let Ok(mut calibration) = self.mic_calibration.lock() {
+            calibration.take();
+        }
+        if let Ok(mut slot) = self.noise_profile.lock() {
+            slot.learn_frames = None;
+            slot.progress = None;
+        }
+        if let Ok(mut tone) = self.test_tone.lock() {
+            tone.take();
+        }
+        
+        if let Some(stream) = self.input_stream.take() {
+            drop(stream);
+        }
+        if let Some(stream) = self.output_stream.take() {
+            drop(stream);
+        }
+        if let Some(stream) = self.loopback_stream.take() {
+            drop(stream);
+        }
+        if let Some(stream) = self.app_stream.take() {
+            drop(stream);
+        }
+        self.extra_app_streams.clear();
+        self.extra_output_streams.clear();
+        self.monitor_stream = None;
+        self.input_format = None;
+        self.output_format = None;
+        
+        info!("Audio processing stopped");
+        self.events.emit(ProcessorEvent::Stopped);
+    }
+
+    // Snapshot of the current settings in persistable form
+    pub fn config(&self) -> Config {
+        let settings = self.settings();
+        Config {
+            input_device: self.input_device_info.get(self.selected_input_index)
+                .map(|info| info.name.clone()),
+            output_device: self.output_device_info.get(self.selected_output_index)
+                .map(|info| info.name.clone()),
+            processing_sample_rate: self.sample_rate,
+            sample_rate: self.requested_sample_rate,
+            channels: self.requested_channels,
+            input_channel: self.input_channel,
+            buffer_frames: self.buffer_frames,
+            quality_profile: self.quality_profile,
+            replay_secs: self.replay_secs,
+            output_polarity_inverted: self.is_output_polarity_inverted(),
+            output_stereo_mode: self.get_output_stereo_mode(),
+            stereo_width: self.get_stereo_width(),
+            haas_delay_ms: self.get_haas_delay_ms(),
+            safety_ceiling_enabled: self.is_safety_ceiling_enabled(),
+            safety_ceiling_db: self.get_safety_ceiling_db(),
+            meter_weighting: self.get_meter_weighting(),
+            follow_system_default: self.follow_system_default,
+            input_gain_db: settings.input_gain_db,
+            output_gain_db: settings.output_gain_db,
+            processing_backend: settings.backend,
+            echo_cancellation: settings.echo_cancellation,
+            noise_reduction: settings.noise_reduction,
+            noise_reduction_strength: settings.noise_reduction_strength,
+            spectral_floor: settings.spectral_floor,
+            autostart: false,
+            shortcuts: Shortcuts::default(),
+        }
+    }
+
+    // Records processed audio to a WAV file at the current sample rate and
+    // channel count, whether or not the output stream is running
+    pub fn start_recording(&mut self, path: &Path) -> Result<()> {
+        if self.recording.is_some() {
+            return Err(AudioError::InvalidState("A recording is already in progress".to_string()));
+        }
+        self.recording = Some(Recording::start(path, self.sample_rate, self.channels, &self.recording_tap)?);
+        info!("Recording to {}", path.display());
+        Ok(())
+    }
+
+    // Finalizes the WAV file; returns the error that ended the recording early, if any
+    pub fn stop_recording(&mut self) -> Result<()> {
+        match self.recording.take() {
+            Some(recording) => {
+                let result = recording.stop(&self.recording_tap);
+                info!("Recording stopped");
+                Ok(result?)
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.as_ref().is_some_and(|recording| !recording.is_finished())
+    }
+
+    pub fn recording_elapsed(&self) -> Option<Duration> {
+        self.recording.as_ref().map(|recording| recording.elapsed())
+    }
+
+    /// Writes a [`MetricsRecord`] as a line of JSON every `interval` until
+    /// [`Self::stop_metrics`], appending to the file at `path` or, without
+    /// one, to stdout. Records are sampled and written on a thread of their
+    /// own, never on the audio path.
+    pub fn start_metrics(&mut self, path: Option<&Path>, interval: Duration) -> Result<()> {
+        if self.metrics.is_some() {
+            return Err(AudioError::InvalidState("Metrics export is already running".to_string()));
+        }
+        let input_level = Arc::clone(&self.input_level);
+        let output_level = Arc::clone(&self.output_level);
+        let processing_load = Arc::clone(&self.processing_load);
+        let latency = Arc::clone(&self.latency);
+        let voice_active = Arc::clone(&self.voice_active);
+        let gate_open = Arc::clone(&self.gate_open);
+        let limiter_reduction_db = Arc::clone(&self.limiter_reduction_db);
+        self.metrics = Some(MetricsExport::start(path, interval, move || MetricsRecord {
+            timestamp: SystemTime::now(),
+            input: input_level.reading(),
+            output: output_level.reading(),
+            processing_load: processing_load.load(),
+            latency_ms: latency.snapshot().total_ms(),
+            voice_active: voice_active.load(Ordering::Relaxed),
+            gate_open: gate_open.load(Ordering::Relaxed),
+            limiter_reduction_db: limiter_reduction_db.load(),
+        })?);
+        match path {
+            Some(path) => info!("Exporting metrics to {}", path.display()),
+            None => info!("Exporting metrics to stdout"),
+        }
+        Ok(())
+    }
+
+    // Stops the metrics export; returns the write error that ended it early, if any
+    pub fn stop_metrics(&mut self) -> Result<()> {
+        match self.metrics.take() {
+            Some(metrics) => {
+                let result = metrics.stop();
+                info!("Metrics export stopped");
+                Ok(result?)
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_exporting_metrics(&self) -> bool {
+        self.metrics.as_ref().is_some_and(|metrics| !metrics.is_finished())
+    }
+
+    // Current DSP settings, as the processing task will see them on its next block
+    pub fn settings(&self) -> ProcessorSettings {
+        self.settings.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    fn update_settings(&mut self, update: impl FnOnce(&mut ProcessorSettings)) {
+        if let Ok(mut settings) = self.settings.lock() {
+            update(&mut settings);
+        }
+    }
+
+    // Replaces all DSP settings at once, so the processing task never sees a
+    // mix of old and new values (e.g. when applying a preset). The settings
+    // replaced can be restored with `undo_settings`.
+    pub fn apply_settings(&mut self, settings: ProcessorSettings) -> Result<()> {
+        validate_fft_size(settings.fft_size)?;
+        validate_framing(settings.fft_window, settings.hop_size, settings.fft_size)?;
+        let previous = self.snapshot();
+        self.update_settings(|s| *s = settings);
+        self.undo = Some(previous);
+        Ok(())
+    }
+
+    fn snapshot(&self) -> SettingsSnapshot {
+        SettingsSnapshot {
+            settings: self.settings(),
+            input_device: self.input_device_info.get(self.selected_input_index).map(|info| info.name.clone()),
+            output_device: self.output_device_info.get(self.selected_output_index).map(|info| info.name.clone()),
+        }
+    }
+
+    /// Restores every DSP setting to its default and selects the system
+    /// default input and output devices, keeping the output while the
+    /// virtual microphone is on. The device format, buffer sizes and output
+    /// polarity stay as they are. [`Self::undo_settings`] brings back what
+    /// was replaced.
+    pub fn reset_to_defaults(&mut self) -> Result<()> {
+        let previous = self.snapshot();
+        self.apply_settings(ProcessorSettings::default())?;
+        self.quality_profile = None;
+
+        if let Some(index) = find_by_name_or_default(&self.input_device_info, None) {
+            if index != self.selected_input_index {
+                self.set_input_device(index)?;
+            }
+        }
+        if !self.virtual_output {
+            if let Some(index) = find_by_name_or_default(&self.output_device_info, None) {
+                if index != self.selected_output_index {
+                    self.set_output_device(index)?;
+                }
+            }
+        }
+
+        self.undo = Some(previous);
+        info!("Settings reset to defaults");
+        Ok(())
+    }
+
+    /// Goes back to the settings and devices in use before the last
+    /// [`Self::apply_settings`], [`Self::reset_to_defaults`] or undo, so a
+    /// second undo redoes. Devices that are gone since stay as they are.
+    /// Returns false when there is nothing to undo.
+    pub fn undo_settings(&mut self) -> Result<bool> {
+        let Some(snapshot) = self.undo.take() else {
+            return Ok(false);
+        };
+        let current = self.snapshot();
+        self.apply_settings(snapshot.settings)?;
+        self.quality_profile = None;
+
+        let input = snapshot.input_device.as_deref().and_then(|name| self.find_input_device(name));
+        if let Some(index) = input.filter(|&index| index != self.selected_input_index) {
+            self.set_input_device(index)?;
+        }
+        let output = snapshot.output_device.as_deref().and_then(|name| self.find_output_device(name));
+        if let Some(index) = output.filter(|&index| index != self.selected_output_index) {
+            self.set_output_device(index)?;
+        }
+
+        self.undo = Some(current);
+        info!("Settings change undone");
+        Ok(true)
+    }
+
+    pub fn can_undo_settings(&self) -> bool {
+        self.undo.is_some()
+    }
+
+    pub fn set_echo_cancellation(&mut self, enabled: bool) {
+        self.update_settings(|s| s.echo_cancellation = enabled);
+    }
+
+    // Which implementation does echo cancellation, noise reduction and gain
+    // control; the switches above still turn each on and off. Fails for a
+    // backend this build doesn't include, or one that can't run at the
+    // processing rate.
+    pub fn set_processing_backend(&mut self, backend: ProcessingBackend) -> Result<()> {
+        if !backend.is_available() {
+            return Err(AudioError::InvalidSetting(format!(
+                "The {} backend isn't included in this build", backend.label()
+            )));
+        }
+        if !backend.supports_sample_rate(self.sample_rate) {
+            return Err(AudioError::InvalidSetting(format!(
+                "The {} backend can't process at {} Hz", backend.label(), self.sample_rate
+            )));
+        }
+        self.update_settings(|s| s.backend = backend);
+        Ok(())
+    }
+
+    // Trim applied to the microphone before processing, -24 to +24 dB
+    pub fn set_input_gain_db(&mut self, gain_db: f32) {
+        self.update_settings(|s| s.input_gain_db = gain_db.clamp(MIN_GAIN_DB, MAX_GAIN_DB));
+    }
+
+    // Trim applied to the processed audio ahead of the limiter, -24 to +24 dB
+    pub fn set_output_gain_db(&mut self, gain_db: f32) {
+        self.update_settings(|s| s.output_gain_db = gain_db.clamp(MIN_GAIN_DB, MAX_GAIN_DB));
+    }
+
+    pub fn set_echo_step_size(&mut self, step_size: f32) {
+        self.update_settings(|s| s.echo_step_size = step_size.clamp(f32::EPSILON, 1.0));
+    }
+
+    fn max_reference_delay_samples(&self) -> usize {
+        (MAX_REFERENCE_DELAY_MS / 1000.0 * self.sample_rate as f32) as usize
+    }
+
+    /// Delays the echo reference by `samples` per channel at the processing
+    /// rate before cancellation, up to 500 ms. Set it to the time the
+    /// application audio takes to come back through the microphone, or let
+    /// [`Self::start_delay_calibration`] measure it.
+    pub fn set_reference_delay_samples(&mut self, samples: usize) {
+        let samples = samples.min(self.max_reference_delay_samples());
+        self.update_settings(|s| s.reference_delay_samples = samples);
+    }
+
+    pub fn get_reference_delay_ms(&self) -> f32 {
+        self.settings().reference_delay_samples as f32 * 1000.0 / self.sample_rate as f32
+    }
+
+    /// Starts measuring the echo delay from the next two seconds of audio,
+    /// which should have the application playing through the speakers.
+    /// Poll [`Self::poll_delay_calibration`] for the result.
+    pub fn start_delay_calibration(&mut self) -> Result<()> {
+        if !self.is_processing {
+            return Err(AudioError::InvalidState("Start processing before calibrating the echo delay".to_string()));
+        }
+        let length = (CALIBRATION_SECS * self.sample_rate as f32) as usize;
+        if let Ok(mut calibration) = self.delay_calibration.lock() {
+            *calibration = Some(DelayCalibration::new(length));
+        }
+        info!("Echo delay calibration started");
+        Ok(())
+    }
+
+    pub fn is_calibrating_delay(&self) -> bool {
+        self.delay_calibration.lock().map(|c| c.is_some()).unwrap_or(false)
+    }
+
+    /// Result of a finished calibration, once: the delay in samples per
+    /// channel, which is applied as the reference delay, or an error if no
+    /// echo was found. None while it is still collecting audio or when none
+    /// was started.
+    pub fn poll_delay_calibration(&mut self) -> Option<Result<usize>> {
+        let calibration = {
+            let mut slot = self.delay_calibration.lock().ok()?;
+            if !slot.as_ref()?.is_complete() {
+                return None;
+            }
+            slot.take()?
+        };
+
+        let Some(delay) = calibration.estimate(self.max_reference_delay_samples()) else {
+            return Some(Err(AudioError::NoEcho));
+        };
+        self.set_reference_delay_samples(delay.saturating_sub(DELAY_MARGIN));
+        info!("Echo delay measured at {} samples, reference delayed by {:.1} ms", delay, self.get_reference_delay_ms());
+        Some(Ok(delay))
+    }
+
+    /// Starts the guided mic calibration: the next two seconds of input
+    /// should be silence, from which the noise is learned as with
+    /// [`Self::start_noise_learning`], and the five after that normal
+    /// speech. Poll [`Self::mic_calibration_progress`] to follow it and
+    /// [`Self::poll_mic_calibration`] for the result.
+    pub fn start_mic_calibration(&mut self) -> Result<()> {
+        if !self.is_processing {
+            return Err(AudioError::InvalidState("Start processing before calibrating the microphone".to_string()));
+        }
+        self.set_noise_profile(None);
+        self.start_noise_learning(SILENCE_MS)?;
+        self.mic_calibration_gain_db = self.settings().input_gain_db;
+        if let Ok(mut calibration) = self.mic_calibration.lock() {
+            *calibration = Some(MicCalibration::new(self.sample_rate));
+        }
+        info!("Mic calibration started");
+        Ok(())
+    }
+
+    // Step under way and the share of it done, 0 to 1, while a mic
+    // calibration runs
+    pub fn mic_calibration_progress(&self) -> Option<(CalibrationStep, f32)> {
+        let calibration = self.mic_calibration.lock().ok()?;
+        let calibration = calibration.as_ref()?;
+        Some((calibration.step(), calibration.progress()))
+    }
+
+    pub fn is_calibrating_mic(&self) -> bool {
+        self.mic_calibration_progress().is_some()
+    }
+
+    /// Result of a finished mic calibration, once, for the caller to show and
+    /// apply with [`Self::apply_mic_calibration`]. None while it is still
+    /// collecting audio or when none was started.
+    pub fn poll_mic_calibration(&mut self) -> Option<Result<CalibrationResult>> {
+        let calibration = {
+            let mut slot = self.mic_calibration.lock().ok()?;
+            if !slot.as_ref()?.is_complete() {
+                return None;
+            }
+            slot.take()?
+        };
+        let result = calibration.result();
+        match &result {
+            Ok(result) => info!(
+                "Mic calibration measured a noise floor of {:.1} dBFS and speech peaks of {:.1} dBFS",
+                result.noise_floor_db, result.speech_peak_db,
+            ),
+            Err(e) => warn!("Mic calibration failed: {}", e),
+        }
+        Some(result)
+    }
+
+    // Sets the input gain and gate threshold a calibration chose, and keeps
+    // the noise profile it learned matching the new gain
+    pub fn apply_mic_calibration(&mut self, result: &CalibrationResult) {
+        let gain_change = result.input_gain_db - self.mic_calibration_gain_db;
+        self.set_input_gain_db(result.input_gain_db);
+        self.set_gate_threshold_db(result.gate_threshold_db);
+        if let Some(profile) = self.get_noise_profile() {
+            self.set_noise_profile(Some(profile.scaled(gain_change)));
+        }
+        self.mic_calibration_gain_db = result.input_gain_db;
+        info!(
+            "Mic calibration applied: input gain {:+.1} dB, gate threshold {:.1} dBFS",
+            result.input_gain_db, result.gate_threshold_db,
+        );
+    }
+
+    /// Learns the noise from the next `duration_ms` of input, during which
+    /// the user should stay quiet, and from then on has noise reduction
+    /// subtract that profile instead of its running estimate. Poll
+    /// [`Self::noise_learning_progress`] to follow it.
+    pub fn start_noise_learning(&mut self, duration_ms: u32) -> Result<()> {
+        if !self.is_processing {
+            return Err(AudioError::InvalidState("Start processing before learning the noise".to_string()));
+        }
+        let settings = self.settings();
+        let hop_size = match settings.hop_size {
+            0 => settings.fft_size / 2,
+            hop => hop,
+        };
+        let frames = (duration_ms as usize * self.sample_rate as usize / 1000 / hop_size).max(1);
+        if let Ok(mut slot) = self.noise_profile.lock() {
+            slot.learn_frames = Some(frames);
+            slot.progress = Some(0.0);
+        }
+        info!("Noise learning started for {} ms", duration_ms);
+        Ok(())
+    }
+
+    // Share of the noise learning done, 0 to 1, while one is under way
+    pub fn noise_learning_progress(&self) -> Option<f32> {
+        self.noise_profile.lock().ok()?.progress
+    }
+
+    pub fn is_learning_noise(&self) -> bool {
+        self.noise_learning_progress().is_some()
+    }
+
+    // The profile noise reduction subtracts, if one was learned or set
+    pub fn get_noise_profile(&self) -> Option<NoiseProfile> {
+        self.noise_profile.lock().ok()?.profile.clone()
+    }
+
+    /// Has noise reduction subtract `profile`, e.g. one saved from an
+    /// earlier session, or go back to the adaptive estimate with None. A
+    /// profile learned at another FFT size is ignored with a warning.
+    pub fn set_noise_profile(&mut self, profile: Option<NoiseProfile>) {
+        if let Ok(mut slot) = self.noise_profile.lock() {
+            slot.profile = profile;
+            slot.changed = true;
+        }
+    }
+
+    pub fn set_noise_reduction(&mut self, enabled: bool) {
+        self.update_settings(|s| s.noise_reduction = enabled);
+    }
+
+    // Fails for a mode this build doesn't include, or one that can't run at
+    // the processing rate
+    pub fn set_noise_reduction_mode(&mut self, mode: NoiseReductionMode) -> Result<()> {
+        if !mode.is_available() {
+            return Err(AudioError::InvalidSetting(format!("{} isn't included in this build", mode.label())));
+        }
+        if !mode.supports_sample_rate(self.sample_rate) {
+            return Err(AudioError::InvalidSetting(format!(
+                "{} can't process at {} Hz", mode.label(), self.sample_rate
+            )));
+        }
+        self.update_settings(|s| s.noise_reduction_mode = mode);
+        Ok(())
+    }
+
+    // Analysis window of the spectral frames; takes effect on the next frame
+    // without a restart. Fails for a window that doesn't overlap-add to a
+    // constant at the current hop, e.g. Blackman at 50% overlap.
+    pub fn set_window(&mut self, window: WindowType) -> Result<()> {
+        let settings = self.settings();
+        validate_framing(window, settings.hop_size, settings.fft_size)?;
+        self.update_settings(|s| s.fft_window = window);
+        Ok(())
+    }
+
+    // Over-subtraction factor of spectral subtraction, 1.0 to 4.0. Higher
+    // removes more noise at the cost of more artifacts.
+    pub fn set_noise_reduction_strength(&mut self, strength: f32) {
+        self.update_settings(|s| {
+            s.noise_reduction_strength = strength.clamp(MIN_NOISE_REDUCTION_STRENGTH, MAX_NOISE_REDUCTION_STRENGTH)
+        });
+    }
+
+    // Strengths of the multi-band mode's bands, lowest band first, each 1.0
+    // to 4.0. Bands beyond the end of `strengths` are left unchanged.
+    pub fn set_band_strengths(&mut self, strengths: &[f32]) {
+        self.update_settings(|s| {
+            for (band, &strength) in s.band_strengths.iter_mut().zip(strengths) {
+                *band = strength.clamp(MIN_NOISE_REDUCTION_STRENGTH, MAX_NOISE_REDUCTION_STRENGTH);
+            }
+        });
+    }
+
+    // Lowest gain noise reduction applies to a bin, 0.0 to 0.5 of its
+    // magnitude. Higher leaves more noise but sounds more natural.
+    pub fn set_spectral_floor(&mut self, floor: f32) {
+        self.update_settings(|s| s.spectral_floor = floor.clamp(0.0, MAX_SPECTRAL_FLOOR));
+    }
+
+    // The spectral floor as the most noise reduction turns any bin down, e.g.
+    // 12 to reduce noise by at most 12 dB. 6 to 60 dB.
+    pub fn set_max_reduction_db(&mut self, db: f32) {
+        self.update_settings(|s| s.spectral_floor = floor_for_max_reduction_db(db));
+    }
+
+    // Infinite when the floor is 0 and bins may be removed entirely
+    pub fn get_max_reduction_db(&self) -> f32 {
+        max_reduction_db(self.settings().spectral_floor)
+    }
+
+    pub fn set_noise_adaptation_rate(&mut self, rate: f32) {
+        self.update_settings(|s| s.noise_adaptation_rate = rate.clamp(f32::EPSILON, 1.0));
+    }
+
+    /// Saves CPU while nobody talks: once voice activity detection has heard
+    /// no voice for the hold time, the spectral stages stop transforming
+    /// their frames and pass the audio through at the same latency, and the
+    /// first block with voice gets full processing again. The noise estimate
+    /// holds meanwhile, so noise reduction resumes where it left off. The
+    /// gate and the other stages keep running throughout.
+    pub fn set_power_save_enabled(&mut self, enabled: bool) {
+        self.update_settings(|s| s.power_save = enabled);
+    }
+
+    // Silence before power saving sets in, up to 10 seconds
+    pub fn set_power_save_hold_ms(&mut self, ms: f32) {
+        self.update_settings(|s| s.power_save_hold_ms = ms.clamp(0.0, MAX_POWER_SAVE_HOLD_MS));
+    }
+
+    pub fn set_gate_enabled(&mut self, enabled: bool) {
+        self.update_settings(|s| s.gate_enabled = enabled);
+    }
+
+    pub fn set_expander_enabled(&mut self, enabled: bool) {
+        self.update_settings(|s| s.expander_enabled = enabled);
+    }
+
+    // Level in dBFS below which the expander turns the signal down
+    pub fn set_expander_threshold_db(&mut self, threshold_db: f32) {
+        self.update_settings(|s| s.expander_threshold_db = threshold_db.min(0.0));
+    }
+
+    // Output dB per input dB below the threshold, 1 to 10
+    pub fn set_expander_ratio(&mut self, ratio: f32) {
+        self.update_settings(|s| s.expander_ratio = ratio.clamp(1.0, MAX_EXPANDER_RATIO));
+    }
+
+    // Width of the soft knee around the threshold, 0 to 24 dB
+    pub fn set_expander_knee_db(&mut self, knee_db: f32) {
+        self.update_settings(|s| s.expander_knee_db = knee_db.clamp(0.0, MAX_EXPANDER_KNEE_DB));
+    }
+
+    // Level in dBFS below which the noise gate silences the output
+    pub fn set_gate_threshold_db(&mut self, threshold_db: f32) {
+        self.update_settings(|s| s.gate_threshold_db = threshold_db.min(0.0));
+    }
+
+    pub fn set_gate_attack_ms(&mut self, ms: f32) {
+        self.update_settings(|s| s.gate_attack_ms = ms.max(0.0));
+    }
+
+    pub fn set_gate_hold_ms(&mut self, ms: f32) {
+        self.update_settings(|s| s.gate_hold_ms = ms.max(0.0));
+    }
+
+    pub fn set_gate_release_ms(&mut self, ms: f32) {
+        self.update_settings(|s| s.gate_release_ms = ms.max(0.0));
+    }
+
+    pub fn set_compressor_enabled(&mut self, enabled: bool) {
+        self.update_settings(|s| s.compressor_enabled = enabled);
+    }
+
+    // Level in dBFS above which the compressor turns the signal down
+    pub fn set_compressor_threshold_db(&mut self, threshold_db: f32) {
+        self.update_settings(|s| s.compressor_threshold_db = threshold_db.min(0.0));
+    }
+
+    // Input dB per output dB above the threshold, 1 to 20
+    pub fn set_compressor_ratio(&mut self, ratio: f32) {
+        self.update_settings(|s| s.compressor_ratio = ratio.clamp(1.0, MAX_COMPRESSOR_RATIO));
+    }
+
+    // Time to react to a rise in level, 0 to 200 ms
+    pub fn set_compressor_attack_ms(&mut self, ms: f32) {
+        self.update_settings(|s| s.compressor_attack_ms = ms.clamp(0.0, MAX_COMPRESSOR_ATTACK_MS));
+    }
+
+    // Time to recover after the level falls, 0 to 2000 ms
+    pub fn set_compressor_release_ms(&mut self, ms: f32) {
+        self.update_settings(|s| s.compressor_release_ms = ms.clamp(0.0, MAX_COMPRESSOR_RELEASE_MS));
+    }
+
+    // Gain after compression, 0 to 24 dB
+    pub fn set_compressor_makeup_db(&mut self, makeup_db: f32) {
+        self.update_settings(|s| s.compressor_makeup_db = makeup_db.clamp(0.0, MAX_COMPRESSOR_MAKEUP_DB));
+    }
+
+    // Width of the soft knee around the threshold, 0 to 24 dB
+    pub fn set_compressor_knee_db(&mut self, knee_db: f32) {
+        self.update_settings(|s| s.compressor_knee_db = knee_db.clamp(0.0, MAX_COMPRESSOR_KNEE_DB));
+    }
+
+    pub fn set_comfort_noise_enabled(&mut self, enabled: bool) {
+        self.update_settings(|s| s.comfort_noise_enabled = enabled);
+    }
+
+    // Comfort noise level in dB relative to the estimated background noise,
+    // -60 to 0
+    pub fn set_comfort_noise_level_db(&mut self, level_db: f32) {
+        self.update_settings(|s| s.comfort_noise_level_db = level_db.clamp(MIN_COMFORT_NOISE_LEVEL_DB, 0.0));
+    }
+
+    pub fn set_deesser_enabled(&mut self, enabled: bool) {
+        self.update_settings(|s| s.deesser_enabled = enabled);
+    }
+
+    // Share of the frame energy in the sibilance band, in dB, above which the
+    // de-esser attenuates it; -40 to 0
+    pub fn set_deesser_threshold_db(&mut self, threshold_db: f32) {
+        self.update_settings(|s| s.deesser_threshold_db = threshold_db.clamp(MIN_DEESSER_THRESHOLD_DB, 0.0));
+    }
+
+    // Most the de-esser attenuates the sibilance band by, 0 to 24 dB
+    pub fn set_deesser_range_db(&mut self, range_db: f32) {
+        self.update_settings(|s| s.deesser_range_db = range_db.clamp(0.0, MAX_DEESSER_RANGE_DB));
+    }
+
+    pub fn set_click_suppression_enabled(&mut self, enabled: bool) {
+        self.update_settings(|s| s.click_suppression = enabled);
+    }
+
+    // How readily a sudden broadband jump counts as a click, 0 to 1
+    pub fn set_click_sensitivity(&mut self, sensitivity: f32) {
+        self.update_settings(|s| s.click_sensitivity = sensitivity.clamp(0.0, 1.0));
+    }
+
+    pub fn set_highpass_enabled(&mut self, enabled: bool) {
+        self.update_settings(|s| s.highpass_enabled = enabled);
+    }
+
+    // Frequency below which the high-pass filter attenuates rumble
+    pub fn set_highpass_cutoff_hz(&mut self, cutoff_hz: f32) {
+        self.update_settings(|s| s.highpass_cutoff_hz = cutoff_hz.max(1.0));
+    }
+
+    pub fn set_agc_enabled(&mut self, enabled: bool) {
+        self.update_settings(|s| s.agc_enabled = enabled);
+    }
+
+    // RMS level in dBFS the automatic gain control steers the output toward
+    pub fn set_agc_target_db(&mut self, target_db: f32) {
+        self.update_settings(|s| s.agc_target_db = target_db.min(0.0));
+    }
+
+    // Most the automatic gain control may amplify, in dB
+    pub fn set_agc_max_gain_db(&mut self, max_gain_db: f32) {
+        self.update_settings(|s| s.agc_max_gain_db = max_gain_db.max(0.0));
+    }
+
+    pub fn set_loudness_normalization(&mut self, enabled: bool) {
+        self.update_settings(|s| s.loudness_normalization = enabled);
+    }
+
+    // Short-term loudness the normalizer steers toward, -36 to -10 LUFS
+    pub fn set_loudness_target_lufs(&mut self, target_lufs: f32) {
+        self.update_settings(|s| {
+            s.loudness_target_lufs = target_lufs.clamp(MIN_LOUDNESS_TARGET_LUFS, MAX_LOUDNESS_TARGET_LUFS)
+        });
+    }
+
+    // Blend of processed and original audio, from 0.0 (original mic only)
+    // to 1.0 (processed only)
+    pub fn set_mix(&mut self, mix: f32) {
+        self.update_settings(|s| s.mix = mix.clamp(0.0, 1.0));
+    }
+
+    /// Routes the raw microphone signal to the output in place of the
+    /// processed one, for A/B comparison. Takes effect at the next block
+    /// with a short cross-fade; the DSP chain is idle while bypassed.
+    pub fn set_bypass(&self, bypass: bool) {
+        self.bypass.store(bypass, Ordering::Relaxed);
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.bypass.load(Ordering::Relaxed)
+    }
+
+    /// Silences the output, and any recording, whether processed or
+    /// bypassed. Capture and processing keep running, so unmuting resumes at
+    /// once. Takes effect at the next block with a short fade.
+    pub fn set_muted(&self, muted: bool) {
+        self.mute.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.mute.load(Ordering::Relaxed)
+    }
+
+    /// Negates every output sample, processed, bypassed or test tone, from
+    /// the next block on, to undo a polarity inversion further down the
+    /// routing. Adds no latency. Recordings and a processed monitor get the
+    /// inverted signal too.
+    pub fn set_output_polarity_inverted(&self, inverted: bool) {
+        self.output_inverted.store(inverted, Ordering::Relaxed);
+    }
+
+    pub fn is_output_polarity_inverted(&self) -> bool {
+        self.output_inverted.load(Ordering::Relaxed)
+    }
+
+    /// How mono processing fills a stereo output device: the same signal on
+    /// both sides, or spread with a Haas-style widener that still sums back
+    /// to the mono signal (see [`MonoToStereoMode`]). Applies from the next
+    /// output period, only with the default output channel mapping. Stereo
+    /// processing goes out as it is.
+    pub fn set_output_stereo_mode(&self, mode: MonoToStereoMode) {
+        self.stereo_output.wide.store(mode == MonoToStereoMode::Wide, Ordering::Relaxed);
+    }
+
+    pub fn get_output_stereo_mode(&self) -> MonoToStereoMode {
+        self.stereo_output.mode()
+    }
+
+    // Share of the delayed copy in the wide mode, 0 (centered) to 1
+    pub fn set_stereo_width(&self, width: f32) {
+        self.stereo_output.width.store(width.clamp(0.0, MAX_STEREO_WIDTH));
+    }
+
+    pub fn get_stereo_width(&self) -> f32 {
+        self.stereo_output.width.load()
+    }
+
+    // Delay of the copy in the wide mode, 1 to 30 ms; longer sounds wider
+    // until it starts to sound like an echo
+    pub fn set_haas_delay_ms(&self, ms: f32) {
+        self.stereo_output.haas_delay_ms.store(ms.clamp(MIN_HAAS_DELAY_MS, MAX_HAAS_DELAY_MS));
+    }
+
+    pub fn get_haas_delay_ms(&self) -> f32 {
+        self.stereo_output.haas_delay_ms.load()
+    }
+
+    // Channels of the processed signal and of the output device, as of the
+    // last start
+    pub fn get_channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn get_output_channels(&self) -> u16 {
+        self.output_channels
+    }
+
+    /// Plays a sine at `freq_hz` for `duration_ms` on every output channel
+    /// in place of the processed signal, to check routing and levels. The
+    /// output gain and mute apply to it as they do to processed audio.
+    /// Needs processing to be running; a tone already playing is replaced.
+    pub fn play_test_tone(&mut self, freq_hz: f32, duration_ms: u32) -> Result<()> {
+        if !self.is_processing {
+            return Err(AudioError::InvalidState("Start processing to play a test tone".to_string()));
+        }
+        let nyquist = self.sample_rate as f32 / 2.0;
+        if !(freq_hz > 0.0 && freq_hz < nyquist) {
+            return Err(AudioError::InvalidSetting(format!("Test tone frequency must be between 0 and {} Hz, got {}", nyquist, freq_hz)));
+        }
+        if let Ok(mut tone) = self.test_tone.lock() {
+            *tone = Some(TestTone::new(freq_hz, duration_ms, self.sample_rate, self.channels));
+        }
+        info!("Playing a {} Hz test tone for {} ms", freq_hz, duration_ms);
+        Ok(())
+    }
+
+    // Fades out the test tone, if one is playing
+    pub fn stop_test_tone(&self) {
+        if let Ok(mut tone) = self.test_tone.lock() {
+            if let Some(tone) = tone.as_mut() {
+                tone.stop();
+            }
+        }
+    }
+
+    pub fn is_playing_test_tone(&self) -> bool {
+        self.test_tone.lock().is_ok_and(|tone| tone.is_some())
+    }
+
+    pub fn set_limiter_enabled(&mut self, enabled: bool) {
+        self.update_settings(|s| s.limiter_enabled = enabled);
+    }
+
+    // Highest level in dBFS the limiter lets through
+    pub fn set_limiter_ceiling_db(&mut self, ceiling_db: f32) {
+        self.update_settings(|s| s.limiter_ceiling_db = ceiling_db.min(0.0));
+    }
+
+    // Block and FFT size per channel for live processing; must be a power
+    // of two. A hop size that doesn't fit the new size goes back to the
+    // default, or to the least overlap the window allows.
+    pub fn set_fft_size(&mut self, size: usize) -> Result<()> {
+        validate_fft_size(size)?;
+        self.update_settings(|s| {
+            if validate_framing(s.fft_window, s.hop_size, size).is_err() {
+                s.hop_size = fit_hop_size(s.fft_window, 0, size);
+            }
+            s.fft_size = size;
+        });
+        self.quality_profile = None;
+        Ok(())
+    }
+
+    pub fn get_fft_size(&self) -> usize {
+        self.settings().fft_size
+    }
+
+    /// Sets how far the FFT frames advance, in samples per channel like the
+    /// FFT size, which also becomes the processing block. Blocks of the FFT
+    /// size at 50% overlap (the default, 0) delay the audio by 1.5 FFT sizes;
+    /// with a hop it is one FFT size, and the output moves in steps of the
+    /// hop. A 2048 FFT with a hop of 512 thus keeps its frequency resolution
+    /// with a third less delay and finer blocks, for four times the FFT
+    /// work. Otherwise it must be a power of two from an eighth to half the
+    /// FFT size, at which the window must overlap-add to a constant (see
+    /// [`Self::set_window`]). Applies from the next block, restarting noise
+    /// reduction like a new FFT size.
+    pub fn set_hop_size(&mut self, hop_size: usize) -> Result<()> {
+        let settings = self.settings();
+        validate_framing(settings.fft_window, hop_size, settings.fft_size)?;
+        self.update_settings(|s| s.hop_size = hop_size);
+        self.quality_profile = None;
+        Ok(())
+    }
+
+    pub fn get_hop_size(&self) -> usize {
+        self.settings().hop_size
+    }
+
+    // Shortest buffer that still holds one FFT block at the current rate
+    fn min_buffer_ms(&self) -> f32 {
+        self.settings().fft_size as f32 * 1000.0 / self.sample_rate as f32
+    }
+
+    // Ring buffer capacity in samples for the current channel count, never
+    // less than one FFT block even if the FFT size grew after the buffer
+    // size was set
+    fn buffer_capacity(&self) -> usize {
+        let samples_per_ms = self.sample_rate as f32 * self.channels.max(1) as f32 / 1000.0;
+        ((self.buffer_ms * samples_per_ms) as usize).max(self.settings().fft_size * self.channels.max(1) as usize)
+    }
+
+    /// Sets how much audio, in milliseconds, each internal ring buffer holds.
+    /// Smaller buffers reduce the worst-case latency, larger ones tolerate
+    /// more scheduling jitter. Must hold at least one FFT block.
+    ///
+    /// While processing, the streams are restarted with the new buffers.
+    /// Audio already captured is still processed; audio queued for the
+    /// output device is dropped.
+    pub fn set_buffer_ms(&mut self, ms: f32) -> Result<()> {
+        let min_ms = self.min_buffer_ms();
+        if ms.is_nan() || ms < min_ms {
+            return Err(AudioError::InvalidSetting(format!("Buffer must hold at least one FFT block ({:.1} ms), got {} ms", min_ms, ms)));
+        }
+        self.buffer_ms = ms;
+        self.quality_profile = None;
+        info!("Buffer size set to {} ms", ms);
+
+        if self.is_processing {
+            self.input_stream.take();
+            self.app_stream.take();
+            self.start_input_capture()?;
+            self.start_loopback_capture()?;
+            self.start_extra_loopback_captures();
+            self.restart_output()?;
+            self.start_extra_outputs();
+        }
+        Ok(())
+    }
+
+    pub fn get_buffer_ms(&self) -> f32 {
+        self.buffer_ms
+    }
+
+    // Samples the output waits for before playing: one block, since blocks
+    // arrive whole, plus the playout target, within the buffer
+    fn playout_target(&self) -> usize {
+        let samples_per_ms = self.sample_rate as f32 * self.channels.max(1) as f32 / 1000.0;
+        let target = self.settings().block_size(self.channels) + (self.playout_target_ms * samples_per_ms) as usize;
+        target.min(self.buffer_capacity())
+    }
+
+    /// Sets how much audio, in milliseconds, the output keeps buffered ahead
+    /// of the device beyond one processing block (0 to 500 ms). More rides
+    /// out longer stalls of the processing thread without dropouts, at the
+    /// cost of that much latency. Takes effect by restarting the output.
+    pub fn set_playout_target_ms(&mut self, ms: f32) -> Result<()> {
+        if !(0.0..=MAX_PLAYOUT_TARGET_MS).contains(&ms) {
+            return Err(AudioError::InvalidSetting(format!("Playout target must be between 0 and {} ms, got {} ms", MAX_PLAYOUT_TARGET_MS, ms)));
+        }
+        self.playout_target_ms = ms;
+        self.quality_profile = None;
+        info!("Playout target set to {} ms", ms);
+        self.restart_output()
+    }
+
+    pub fn get_playout_target_ms(&self) -> f32 {
+        self.playout_target_ms
+    }
+
+    /// Sets how many seconds of processed audio the replay buffer keeps for
+    /// [`Self::save_replay`] (5 to 120). The buffer starts over empty.
+    pub fn set_replay_secs(&mut self, secs: u32) -> Result<()> {
+        validate_replay_secs(secs)?;
+        self.replay_secs = secs;
+        self.replay = Arc::new(ReplayBuffer::new(secs, self.sample_rate, self.channels));
+        if let Ok(mut slot) = self.replay_handoff.lock() {
+            *slot = Some(Arc::clone(&self.replay));
+        }
+        info!("Replay buffer set to {} s", secs);
+        Ok(())
+    }
+
+    pub fn get_replay_secs(&self) -> u32 {
+        self.replay_secs
+    }
+
+    /// Saves the last seconds of processed audio, as much as the replay
+    /// buffer holds, to a WAV file at the processing rate and channel count,
+    /// e.g. right after something worth keeping happened on a stream.
+    /// Processing carries on while the file is written; once stopped, the
+    /// buffer still holds the end of the session. Returns the length saved.
+    pub fn save_replay(&self, path: &Path) -> Result<Duration> {
+        if self.replay.is_empty() {
+            return Err(AudioError::InvalidState("No processed audio to save yet".to_string()));
+        }
+        let duration = self.replay.save(path)?;
+        info!("Saved {:.1} s of replay to {}", duration.as_secs_f32(), path.display());
+        Ok(duration)
+    }
+
+    /// Times since processing started that the output device found too
+    /// little processed audio and played silence, each heard as a dropout.
+    /// Rising counts call for a larger playout target or a smaller load.
+    pub fn get_underrun_count(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Runs the DSP chain at `sample_rate`, resampling capture down to it
+    /// and the output back up to the device rate. 16 kHz still covers the
+    /// voice band (up to 7 kHz) for about a third of the processing of
+    /// 48 kHz; 48 kHz keeps music intact.
+    ///
+    /// The FFT size and hop are scaled to the nearest power of two of the
+    /// same duration (or follow the quality profile in effect), and the echo
+    /// reference delay keeps its time. A learned noise profile no longer
+    /// matches the frequencies and is dropped. Running streams restart, and
+    /// the DSP state is rebuilt at the new rate. Fails while recording, as
+    /// the file's rate is fixed.
+    pub fn set_internal_sample_rate(&mut self, sample_rate: u32) -> Result<()> {
+        if sample_rate == 0 || sample_rate > MAX_PROCESSING_SAMPLE_RATE {
+            return Err(AudioError::InvalidSetting(format!(
+                "Sample rate must be between 1 and {} Hz, got {}",
+                MAX_PROCESSING_SAMPLE_RATE, sample_rate
+            )));
+        }
+        if self.recording.is_some() {
+            return Err(AudioError::InvalidState("Stop the recording before changing the processing rate".to_string()));
+        }
+        if sample_rate == self.sample_rate {
+            return Ok(());
+        }
+
+        let ratio = sample_rate as f64 / self.sample_rate as f64;
+        let settings = self.settings();
+        let fft_size = match self.quality_profile {
+            Some(profile) => profile.fft_size(sample_rate),
+            None => fft_size_at_rate(settings.fft_size, self.sample_rate, sample_rate),
+        };
+        let hop_size = match self.quality_profile {
+            Some(_) => 0,
+            None => settings.hop_size * fft_size / settings.fft_size,
+        };
+        let hop_size = fit_hop_size(settings.fft_window, hop_size, fft_size);
+        let reference_delay_samples = (settings.reference_delay_samples as f64 * ratio).round() as usize;
+
+        self.sample_rate = sample_rate;
+        self.update_settings(|s| {
+            s.fft_size = fft_size;
+            s.hop_size = hop_size;
+        });
+        self.set_reference_delay_samples(reference_delay_samples);
+        self.set_noise_profile(None);
+        // A block may have grown in the rounding to a power of two
+        self.buffer_ms = self.buffer_ms.max(self.min_buffer_ms());
+        info!("Processing at {} Hz: FFT size {}, hop {}", sample_rate, fft_size, hop_size);
+        self.restart()
+    }
+
+    /// Opens the input and output devices at `sample_rate` instead of their
+    /// default, or goes back to the default with None. A rate a device
+    /// doesn't support falls back to the nearest one it does, with a
+    /// warning. Processing still runs at the processing rate; the streams
+    /// are resampled. Running streams are restarted.
+    pub fn set_requested_sample_rate(&mut self, sample_rate: Option<u32>) -> Result<()> {
+        if sample_rate == Some(0) {
+            return Err(AudioError::InvalidSetting("Sample rate must be positive".to_string()));
+        }
+        self.requested_sample_rate = sample_rate;
+        self.restart()
+    }
+
+    /// Opens the devices with this many channels instead of their default,
+    /// falling back to the nearest supported count. The input's channel
+    /// count is also the processing channel count.
+    pub fn set_requested_channels(&mut self, channels: Option<u16>) -> Result<()> {
+        if channels == Some(0) {
+            return Err(AudioError::InvalidSetting("Channel count must be positive".to_string()));
+        }
+        self.requested_channels = channels;
+        self.restart()
+    }
+
+    pub fn get_requested_sample_rate(&self) -> Option<u32> {
+        self.requested_sample_rate
+    }
+
+    pub fn get_requested_channels(&self) -> Option<u16> {
+        self.requested_channels
+    }
+
+    /// Asks the input and output devices for `frames` per callback instead
+    /// of their default, or goes back to the default with None. Smaller
+    /// buffers cut latency but glitch sooner under load. A size outside what
+    /// a device supports is clamped to its range, and a device that refuses
+    /// a fixed size runs at its default, with a warning either way. Running
+    /// streams are restarted; see `LatencyBreakdown` for the sizes in use.
+    pub fn set_buffer_frames(&mut self, frames: Option<u32>) -> Result<()> {
+        if frames == Some(0) {
+            return Err(AudioError::InvalidSetting("Buffer size must be positive".to_string()));
+        }
+        self.buffer_frames = frames;
+        self.quality_profile = None;
+        self.restart()
+    }
+
+    pub fn get_buffer_frames(&self) -> Option<u32> {
+        self.buffer_frames
+    }
+
+    /// Sets the FFT size, ring buffer size, playout target and device buffer
+    /// size from `profile` in one go (see [`QualityProfile`] for the values),
+    /// restarting running streams once. The FFT size follows the profile's
+    /// block duration at the processing rate. Setting any of those sizes on
+    /// its own afterwards leaves the profile.
+    pub fn apply_quality_profile(&mut self, profile: QualityProfile) -> Result<()> {
+        let fft_size = profile.fft_size(self.sample_rate);
+        validate_fft_size(fft_size)?;
+        let block_ms = fft_size as f32 * 1000.0 / self.sample_rate as f32;
+        if profile.buffer_ms() < block_ms {
+            return Err(AudioError::InvalidSetting(format!("{} buffers can't hold a block of {:.1} ms", profile.label(), block_ms)));
+        }
+
+        self.update_settings(|s| {
+            s.fft_size = fft_size;
+            s.hop_size = fit_hop_size(s.fft_window, 0, fft_size);
+        });
+        self.buffer_ms = profile.buffer_ms();
+        self.playout_target_ms = profile.playout_target_ms();
+        self.buffer_frames = profile.buffer_frames();
+        self.quality_profile = Some(profile);
+        info!(
+            "Quality profile {}: FFT size {}, buffer {} ms, playout target {} ms, device buffer {:?}",
+            profile.label(), fft_size, self.buffer_ms, self.playout_target_ms, self.buffer_frames
+        );
+        self.restart()
+    }
+
+    // The profile in effect, or None when the sizes were tuned individually
+    pub fn get_quality_profile(&self) -> Option<QualityProfile> {
+        self.quality_profile
+    }
+
+    // Stream config for `supported` with the requested buffer size, clamped
+    // to the range the device reports. Devices that report none get the
+    // size as asked; building the stream tells whether they accept it.
+    fn stream_config(&self, supported: &cpal::SupportedStreamConfig) -> cpal::StreamConfig {
+        let mut config = supported.config();
+        if let Some(frames) = self.buffer_frames {
+            let frames = match supported.buffer_size() {
+                cpal::SupportedBufferSize::Range { min, max } if *min <= *max => {
+                    let clamped = frames.clamp(*min, *max);
+                    if clamped != frames {
+                        warn!("Device supports buffers of {} to {} frames, using {} instead of {}", min, max, clamped, frames);
+                    }
+                    clamped
+                }
+                _ => frames,
+            };
+            config.buffer_size = cpal::BufferSize::Fixed(frames);
+        }
+        config
+    }
+
+    // Restarts the whole pipeline if it is running, e.g. when the channel
+    // count may change, which the processing task can't follow
+    fn restart(&mut self) -> Result<()> {
+        if self.is_processing {
+            self.stop();
+            self.start()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_processing(&self) -> bool {
+        self.is_processing
+    }
+
+    // Whether a processing task is alive; false once a stopped task has exited
+    pub fn is_processing_task_running(&self) -> bool {
+        self.processing_task.as_ref().is_some_and(|task| !task.handle.is_finished())
+    }
+
+    pub fn set_loopback_device_name(&mut self, name: Option<String>) {
+        self.loopback_device_name = name;
+    }
+
+    pub fn get_loopback_device_name(&self) -> Option<String> {
+        self.loopback_device.as_ref().and_then(|d| d.name().ok())
+    }
+
+    /// Adds a loopback source, by device name, whose audio is summed with
+    /// the main one into the echo cancellation reference. Use it when apps
+    /// play to several endpoints that all reach the microphone, e.g. a game
+    /// on the speakers and a call on a second sink. Sources run at any rate
+    /// and channel count and are converted like the main one.
+    ///
+    /// While processing, the extra captures restart with the new source. A
+    /// device that doesn't exist is an error and isn't added; one that fails
+    /// to open is logged and tried again on the next start, while the other
+    /// sources keep running.
+    pub fn add_loopback_source(&mut self, name: String) -> Result<()> {
+        if self.extra_loopback_names.contains(&name) {
+            return Ok(());
+        }
+        if self.is_processing {
+            self.find_loopback_source(&name)?;
+        }
+        self.extra_loopback_names.push(name);
+        if self.is_processing {
+            self.start_extra_loopback_captures();
+        }
+        Ok(())
+    }
+
+    // Leaves only the main loopback source in the reference
+    pub fn clear_loopback_sources(&mut self) {
+        self.extra_loopback_names.clear();
+        self.extra_app_streams.clear();
+        if let Ok(mut slot) = self.extra_app_consumers.lock() {
+            slot.clear = true;
+            slot.added.clear();
+        }
+    }
+
+    pub fn get_loopback_sources(&self) -> &[String] {
+        &self.extra_loopback_names
+    }
+
+    pub fn get_input_meter(&self) -> MeterReading {
+        self.input_level.reading()
+    }
+
+    pub fn get_output_meter(&self) -> MeterReading {
+        self.output_level.reading()
+    }
+
+    // Input meter of each processed channel, in channel order; empty until
+    // processing has metered a block
+    pub fn get_input_channel_meters(&self) -> Vec<MeterReading> {
+        self.input_channel_levels.readings()
+    }
+
+    // RMS level of each input channel in dBFS, as in get_input_channel_meters
+    pub fn get_input_levels(&self) -> Vec<f32> {
+        self.get_input_channel_meters().iter().map(|meter| meter.rms_db).collect()
+    }
+
+    /// Weights the signal the meters take their RMS of, to follow perceived
+    /// loudness: A-weighting counts low frequencies for much less, as the
+    /// ear does, K-weighting (as in the loudness readings) for a little
+    /// less. A 1 kHz tone reads the same under all of them. Only the meters
+    /// change; peaks and clip indicators always see the unweighted signal.
+    pub fn set_meter_weighting(&self, weighting: MeterWeighting) {
+        self.meter_weighting.store(weighting as usize, Ordering::Relaxed);
+    }
+
+    pub fn get_meter_weighting(&self) -> MeterWeighting {
+        MeterWeighting::ALL[self.meter_weighting.load(Ordering::Relaxed)]
+    }
+
+    pub fn reset_clip_indicators(&self) {
+        self.input_level.clipped.store(false, Ordering::Relaxed);
+        self.input_channel_levels.reset_clipped();
+        self.output_level.clipped.store(false, Ordering::Relaxed);
+    }
+
+    // Total delay from microphone to output, in milliseconds
+    pub fn get_latency_ms(&self) -> f32 {
+        self.latency.snapshot().total_ms()
+    }
+
+    pub fn get_latency_breakdown(&self) -> LatencyBreakdown {
+        self.latency.snapshot()
+    }
+
+    // Spectra of the last block processed since the previous call, or of an
+    // earlier one. Snapshots are only taken while this is being called, and
+    // not during power save.
+    pub fn get_spectrum(&self) -> Option<SpectrumSnapshot> {
+        self.spectrum_wanted.store(true, Ordering::Relaxed);
+        self.spectrum.lock().ok().and_then(|spectrum| spectrum.clone())
+    }
+
+    pub fn get_waveform(&self) -> Waveform {
+        Waveform {
+            sample_rate: self.scope.sample_rate,
+            input: self.scope.input.snapshot(),
+            output: self.scope.output.snapshot(),
+        }
+    }
+
+    // Stream errors since the last call, oldest first, for display
+    /// Subscribes to [`ProcessorEvent`]s from now on. Events are never
+    /// waited on: a receiver more than [`EVENT_CAPACITY`] events behind
+    /// loses the oldest, which its next `recv` reports as lagged.
+    ///
+    /// [`EVENT_CAPACITY`]: crate::events::EVENT_CAPACITY
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<ProcessorEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn take_errors(&self) -> Vec<String> {
+        self.errors.take()
+    }
+
+    /// Setup and state for a bug report: devices, formats, settings,
+    /// measurements and recent errors. It carries no audio and no file
+    /// paths, and errors have the home directory replaced with "~".
+    pub fn diagnostics(&self) -> Diagnostics {
+        Diagnostics {
+            version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            host: self.host.id().name().to_string(),
+            input_device: self.input_device_info.get(self.selected_input_index).map(|info| info.name.clone()),
+            output_device: self.output_device_info.get(self.selected_output_index).map(|info| info.name.clone()),
+            loopback_device: self.get_loopback_device_name(),
+            monitor_device: self.monitor_device_name.clone(),
+            input_format: self.input_format,
+            output_format: self.output_format,
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            output_channels: self.output_channels,
+            requested_sample_rate: self.requested_sample_rate,
+            requested_channels: self.requested_channels,
+            input_channel: self.input_channel,
+            buffer_frames: self.buffer_frames,
+            buffer_ms: self.buffer_ms,
+            playout_target_ms: self.playout_target_ms,
+            quality_profile: self.quality_profile,
+            virtual_output: self.virtual_output,
+            settings: self.settings(),
+            is_processing: self.is_processing,
+            bypassed: self.is_bypassed(),
+            muted: self.is_muted(),
+            latency: self.get_latency_breakdown(),
+            processing_load: self.get_processing_load(),
+            underruns: self.get_underrun_count(),
+            sanitized_samples: self.get_sanitized_sample_count(),
+            safety_ceiling_db: self.is_safety_ceiling_enabled().then(|| self.get_safety_ceiling_db()),
+            safety_clamps: self.get_safety_clamp_count(),
+            recent_errors: self.errors.recent().iter().map(|error| mask_home(error)).collect(),
+        }
+    }
+
+    /// Recap of the last session: peak level, integrated loudness, clips,
+    /// underruns, share of speech and average processing load between the
+    /// last `start` and `stop`. `None` while processing and before the first
+    /// session. The integrated loudness starts over with
+    /// [`Self::reset_integrated_loudness`].
+    pub fn get_session_stats(&self) -> Option<SessionStats> {
+        self.last_session
+    }
+
+    // Whether the last processed block was classified as speech
+    pub fn is_voice_active(&self) -> bool {
+        self.voice_active.load(Ordering::Relaxed)
+    }
+
+    // Whether power saving is passing the audio through untransformed
+    // right now, as of the last block
+    pub fn is_power_saving(&self) -> bool {
+        self.power_saving.load(Ordering::Relaxed)
+    }
+
+    // Gain the automatic gain control applied to the last block, in dB;
+    // 0 while it is disabled
+    pub fn get_agc_gain_db(&self) -> f32 {
+        self.agc_gain_db.load()
+    }
+
+    // Gain reduction the limiter applied at the end of the last block, in dB
+    // (0 or less); 0 while it is disabled
+    pub fn get_limiter_reduction_db(&self) -> f32 {
+        self.limiter_reduction_db.load()
+    }
+
+    // Gain reduction the compressor applied at the end of the last block, in
+    // dB (0 or less, before makeup gain); 0 while it is disabled
+    pub fn get_compressor_reduction_db(&self) -> f32 {
+        self.compressor_reduction_db.load()
+    }
+
+    /// Clamps every output sample to `ceiling_db` dBFS (-24 to 0, default
+    /// -3) as the very last step, after the limiter, bypass, the test tone
+    /// and everything else, to protect hearing from an echo canceller gone
+    /// wrong. It distorts whatever it clamps, which is the point: see
+    /// [`Self::get_safety_clamp_count`]. Applies from the next block.
+    pub fn set_safety_ceiling_db(&self, ceiling_db: f32) {
+        self.safety_ceiling_db.store(ceiling_db.clamp(MIN_SAFETY_CEILING_DB, 0.0));
+    }
+
+    pub fn get_safety_ceiling_db(&self) -> f32 {
+        self.safety_ceiling_db.load()
+    }
+
+    // On by default; off leaves the output to the limiter, if enabled
+    pub fn set_safety_ceiling_enabled(&self, enabled: bool) {
+        self.safety_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_safety_ceiling_enabled(&self) -> bool {
+        self.safety_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Output samples the safety ceiling clamped since processing started.
+    /// With the limiter on at a lower ceiling this stays at 0; anything else
+    /// means the output got loud enough to be cut off.
+    pub fn get_safety_clamp_count(&self) -> u64 {
+        self.safety_clamps.load(Ordering::Relaxed)
+    }
+
+    /// NaN or infinite samples replaced with silence since processing
+    /// started, from the devices or from inside the DSP chain. Anything
+    /// above 0 points at a misbehaving driver or a numerical problem.
+    pub fn get_sanitized_sample_count(&self) -> u64 {
+        self.sanitized_samples.load(Ordering::Relaxed)
+    }
+
+    /// Time spent in [`Self::process_audio_chunk`] relative to the duration
+    /// of the audio it processed, averaged over about a second. Values near
+    /// or above 1.0 mean processing can't keep up and the output will glitch.
+    /// 0 while stopped; bypass leaves the last value in place.
+    pub fn get_processing_load(&self) -> f32 {
+        self.processing_load.load()
+    }
+
+    /// Loudness of the output after BS.1770 (EBU R128), in LUFS. Momentary
+    /// and short-term loudness cover the last 400 ms and 3 s; integrated
+    /// loudness covers everything since processing started or
+    /// [`Self::reset_integrated_loudness`]. -inf until measured.
+    pub fn get_loudness(&self) -> LoudnessReading {
+        self.loudness.snapshot()
+    }
+
+    /// Starts the DSP state over at the next block, as if processing had just
+    /// started: the echo filter taps, overlap buffers, noise estimate, AGC
+    /// gain, limiter and every other envelope. Settings, a learned noise
+    /// profile and the gain trims stay, and no audio is dropped; the output
+    /// fades back in. Use it when the echo canceller or the noise estimate
+    /// diverged, e.g. after a device glitch, into squealing or distortion
+    /// that doesn't go away. A noise learning in progress is abandoned.
+    /// Safe to call at any time; without processing it does nothing.
+    pub fn reset_dsp_state(&self) {
+        if self.is_processing {
+            self.dsp_reset.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // Starts the integrated loudness measurement over from the next block
+    pub fn reset_integrated_loudness(&self) {
+        self.loudness.reset_integrated.store(true, Ordering::Relaxed);
+    }
+
+    // Gain loudness normalization applied to the last block, in dB; 0 while
+    // it is disabled
+    pub fn get_loudness_normalization_gain_db(&self) -> f32 {
+        self.loudness.normalization_gain_db.load()
+    }
+
+    // Supported configurations of an input device, queried on first use and
+    // kept until the device lists are refreshed without it
+    pub fn input_capabilities(&mut self, index: usize) -> Option<&[DeviceCapability]> {
+        let (device, info) = (self.input_devices.get(index)?, self.input_device_info.get_mut(index)?);
+        if info.capabilities.is_none() {
+            info.capabilities = Some(query_capabilities(device.supported_input_configs(), &info.name));
+        }
+        info.capabilities.as_deref()
+    }
+
+    pub fn output_capabilities(&mut self, index: usize) -> Option<&[DeviceCapability]> {
+        let (device, info) = (self.output_devices.get(index)?, self.output_device_info.get_mut(index)?);
+        if info.capabilities.is_none() {
+            info.capabilities = Some(query_capabilities(device.supported_output_configs(), &info.name));
+        }
+        info.capabilities.as_deref()
+    }
+
+    pub fn get_input_devices(&self) -> &Vec<DeviceInfo> {
+        &self.input_device_info
+    }
+
+    pub fn get_output_devices(&self) -> &Vec<DeviceInfo> {
+        &self.output_device_info
+    }
+
+    pub fn get_selected_input_index(&self) -> usize {
+        self.selected_input_index
+    }
+
+    pub fn get_selected_output_index(&self) -> usize {
+        self.selected_output_index
+    }
+
+    pub fn find_input_device(&self, name: &str) -> Option<usize> {
+        self.input_device_info.iter().position(|info| info.name == name)
+    }
+
+    pub fn find_output_device(&self, name: &str) -> Option<usize> {
+        self.output_device_info.iter().position(|info| info.name == name)
+    }
+
+    /// Re-enumerates devices so newly connected ones can be selected,
+    /// keeping the current selections by name. Running streams are left
+    /// alone: a selected device that vanished keeps its stream until
+    /// [`Self::recover_lost_devices`] replaces it. Returns whether either
+    /// list changed.
+    /// Audio backends built into cpal that are usable on this system. Which
+    /// ones exist depends on cpal's features: JACK on Linux needs `jack`,
+    /// ASIO on Windows needs `asio`.
+    pub fn available_hosts() -> Vec<HostId> {
+        cpal::available_hosts()
+    }
+
+    pub fn get_host_id(&self) -> HostId {
+        self.host.id()
+    }
+
+    /// Switches to another audio backend and re-enumerates its devices.
+    /// Devices with the same names as the current ones stay selected,
+    /// otherwise the backend's defaults are picked; a backend without
+    /// devices leaves that side unselected. Processing, if running, is
+    /// restarted on the new backend.
+    pub fn set_host(&mut self, id: HostId) -> Result<()> {
+        if id == self.host.id() {
+            return Ok(());
+        }
+        let was_processing = self.is_processing;
+        if was_processing {
+            self.stop();
+        }
+
+        self.host = cpal::host_from_id(id)?;
+        // The same name on another backend can be a different device
+        for info in self.input_device_info.iter_mut().chain(self.output_device_info.iter_mut()) {
+            info.capabilities = None;
+        }
+        self.refresh_devices()?;
+        info!("Audio host changed to {}", id.name());
+
+        match self.input_device_info.get(self.selected_input_index) {
+            Some(info) => self.events.emit(ProcessorEvent::InputDeviceChanged(info.name.clone())),
+            None => warn!("{} has no input devices", id.name()),
+        }
+        match self.output_device_info.get(self.selected_output_index) {
+            Some(info) => self.events.emit(ProcessorEvent::OutputDeviceChanged(info.name.clone())),
+            None => warn!("{} has no output devices", id.name()),
+        }
+
+        if was_processing {
+            self.start()?;
+        }
+        Ok(())
+    }
+
+    pub fn refresh_devices(&mut self) -> Result<bool> {
+        let input_name = self.input_device_info.get(self.selected_input_index).map(|info| info.name.clone());
+        let output_name = self.output_device_info.get(self.selected_output_index).map(|info| info.name.clone());
+
+        let (input_devices, mut input_device_info) = enumerate_input_devices(&self.host)?;
+        let (output_devices, mut output_device_info) = enumerate_output_devices(&self.host)?;
+        // Capabilities already queried carry over to devices still present
+        for (fresh, old) in [
+            (&mut input_device_info, &self.input_device_info),
+            (&mut output_device_info, &self.output_device_info),
+        ] {
+            for info in fresh.iter_mut() {
+                info.capabilities = old.iter()
+                    .find(|old| old.name == info.name)
+                    .and_then(|old| old.capabilities.clone());
+            }
+        }
+        let names = |infos: &[DeviceInfo]| infos.iter().map(|info| info.name.clone()).collect::<Vec<_>>();
+        let changed = names(&input_device_info) != names(&self.input_device_info)
+            || names(&output_device_info) != names(&self.output_device_info);
+
+        self.input_devices = input_devices;
+        self.input_device_info = input_device_info;
+        self.output_devices = output_devices;
+        self.output_device_info = output_device_info;
+
+        let input_index = find_by_name_or_default(&self.input_device_info, input_name.as_deref());
+        let input_kept = input_name.is_some_and(|name| {
+            input_index.is_some_and(|i| self.input_device_info[i].name == name)
+        });
+        if input_kept || !self.is_processing {
+            self.selected_input_device = input_index.and_then(|i| self.input_devices.get(i).cloned());
+        }
+        self.selected_input_index = input_index.unwrap_or(0);
+
+        let output_index = find_by_name_or_default(&self.output_device_info, output_name.as_deref());
+        let output_kept = output_name.is_some_and(|name| {
+            output_index.is_some_and(|i| self.output_device_info[i].name == name)
+        });
+        if output_kept || !self.is_processing {
+            self.selected_output_device = output_index.and_then(|i| self.output_devices.get(i).cloned());
+        }
+        self.selected_output_index = output_index.unwrap_or(0);
+
+        if changed {
+            info!(
+                "Device lists changed: {} inputs, {} outputs",
+                self.input_device_info.len(), self.output_device_info.len()
+            );
+        }
+        Ok(changed)
+    }
+
+    /// Restarts streams whose device disappeared while processing, on a
+    /// device of the same name if it is back or the system default
+    /// otherwise. Call periodically; returns whether anything was recovered.
+    /// On error no usable device was found and the caller should stop.
+    pub fn recover_lost_devices(&mut self) -> Result<bool> {
+        let input_lost = self.input_lost.swap(false, Ordering::Relaxed);
+        let output_lost = self.output_lost.swap(false, Ordering::Relaxed);
+        if !self.is_processing || !(input_lost || output_lost) {
+            return Ok(false);
+        }
+
+        let input_name = self.input_device_info.get(self.selected_input_index).map(|info| info.name.clone());
+        let output_name = self.output_device_info.get(self.selected_output_index).map(|info| info.name.clone());
+        self.refresh_devices()?;
+
+        if input_lost {
+            self.input_stream.take();
+            let index = find_by_name_or_default(&self.input_device_info, input_name.as_deref())
+                .ok_or(AudioError::NoDevice("input"))?;
+            self.selected_input_index = index;
+            self.selected_input_device = self.input_devices.get(index).cloned();
+            self.restart_input()?;
+            info!("Input recovered on {}", self.input_device_info[index].name);
+            self.events.emit(ProcessorEvent::InputDeviceChanged(self.input_device_info[index].name.clone()));
+        }
+
+        if output_lost {
+            self.loopback_stream.take();
+            let index = find_by_name_or_default(&self.output_device_info, output_name.as_deref())
+                .ok_or(AudioError::NoDevice("output"))?;
+            self.selected_output_index = index;
+            self.selected_output_device = self.output_devices.get(index).cloned();
+            self.start_loopback_output()?;
+            info!("Output recovered on {}", self.output_device_info[index].name);
+            self.events.emit(ProcessorEvent::OutputDeviceChanged(self.output_device_info[index].name.clone()));
+        }
+
+        Ok(true)
+    }
+
+    /// Makes the next attempt at playing streams whose device refused to
+    /// start them, for those that are due. Call periodically, like
+    /// [`Self::recover_lost_devices`]; the retries wait here rather than in
+    /// whatever started the streams. On error the input or output device
+    /// refused every attempt and the caller should stop; the monitor and
+    /// extra outputs or sources that give up are reported and left out.
+    pub fn retry_stream_starts(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let main = [&mut self.input_stream, &mut self.output_stream, &mut self.loopback_stream, &mut self.app_stream];
+        for stream in main.into_iter().flatten() {
+            stream.retry(now)?;
+        }
+        if let Some(Err(e)) = self.monitor_stream.as_mut().map(|stream| stream.retry(now)) {
+            self.monitor_stream = None;
+            self.errors.push(format!("Monitor output failed: {}", e));
+        }
+        let errors = &self.errors;
+        for streams in [&mut self.extra_app_streams, &mut self.extra_output_streams] {
+            streams.retain_mut(|stream| match stream.retry(now) {
+                Ok(()) => true,
+                Err(e) => {
+                    errors.push(e.to_string());
+                    false
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Keeps the input and output on the system default devices: whenever
+    /// the default changes in the OS, the running streams switch to it as
+    /// if it had been selected. Enabling switches to the current defaults
+    /// right away.
+    pub fn set_follow_system_default(&mut self, follow: bool) -> Result<()> {
+        self.follow_system_default = follow;
+        self.last_default_input = None;
+        self.last_default_output = None;
+        info!("Following the system default devices {}", if follow { "enabled" } else { "disabled" });
+        self.apply_system_defaults()?;
+        Ok(())
+    }
+
+    pub fn is_following_system_default(&self) -> bool {
+        self.follow_system_default
+    }
+
+    /// Switches to the system default input and output if either changed
+    /// since the last call, while following them is enabled. Call
+    /// periodically after [`Self::refresh_devices`], which picks up the new
+    /// defaults; returns whether a device was switched.
+    ///
+    /// While the system has no default (e.g. the default device was
+    /// unplugged) the current devices stay; a stream whose device vanished
+    /// is restarted by [`Self::recover_lost_devices`]. A default that fails to
+    /// open returns the error and isn't retried until the default changes
+    /// again. The output isn't switched while the virtual microphone is on,
+    /// which picks its own output device.
+    pub fn apply_system_defaults(&mut self) -> Result<bool> {
+        if !self.follow_system_default {
+            return Ok(false);
+        }
+        let mut switched = false;
+        if let Some(index) = changed_default(&self.input_device_info, &mut self.last_default_input) {
+            if index != self.selected_input_index || self.selected_input_device.is_none() {
+                info!("System default input is now {}", self.input_device_info[index].name);
+                self.set_input_device(index)?;
+                switched = true;
+            }
+        }
+        if !self.virtual_output {
+            if let Some(index) = changed_default(&self.output_device_info, &mut self.last_default_output) {
+                if index != self.selected_output_index || self.selected_output_device.is_none() {
+                    info!("System default output is now {}", self.output_device_info[index].name);
+                    self.set_output_device(index)?;
+                    switched = true;
+                }
+            }
+        }
+        Ok(switched)
+    }
+
+    pub fn set_input_device(&mut self, index: usize) -> Result<()> {
+        if index >= self.input_devices.len() {
+            return Err(AudioError::DeviceIndex { kind: "input", index });
+        }
+        self.selected_input_index = index;
+        self.selected_input_device = self.input_devices.get(index).cloned();
+        
+        if self.is_processing {
+            // Closed before the flag is raised, so no more audio from the
+            // old device arrives once the task has dropped what it holds
+            self.input_stream.take();
+            self.input_switched.store(true, Ordering::Relaxed);
+            self.restart_input()?;
+        }
+        
+        info!("Input device changed to: {}", 
+              self.input_device_info[index].name);
+        self.events.emit(ProcessorEvent::InputDeviceChanged(self.input_device_info[index].name.clone()));
+        Ok(())
+    }
+
+    pub fn set_output_device(&mut self, index: usize) -> Result<()> {
+        if index >= self.output_devices.len() {
+            return Err(AudioError::DeviceIndex { kind: "output", index });
+        }
+        self.selected_output_index = index;
+        self.selected_output_device = self.output_devices.get(index).cloned();
+        if self.get_monitor_device() == Some(index) {
+            info!("Monitor device became the output device, turning the monitor off");
+            self.monitor_device_name = None;
+            self.monitor_stream = None;
+        }
+        if self.get_extra_output_devices().contains(&index) {
+            info!("Extra output became the output device, removing it from the extra outputs");
+            self.remove_output_device(index)?;
+        }
+        
+        self.restart_output()?;
+        
+        info!("Output device changed to: {}", 
+              self.output_device_info[index].name);
+        self.events.emit(ProcessorEvent::OutputDeviceChanged(self.output_device_info[index].name.clone()));
+        Ok(())
+    }
+
+    /// Routes the input device's channels into processing through `map`,
+    /// e.g. `ChannelMap::select(4, &[0])` to process only the first input
+    /// of a four-channel interface as mono. The map's output count becomes
+    /// the processing channel count. A map whose input count doesn't match
+    /// the device is ignored with a warning; None restores the default of
+    /// processing every device channel.
+    pub fn set_input_channel_map(&mut self, map: Option<ChannelMap>) -> Result<()> {
+        self.input_channel_map = map;
+        if self.is_processing {
+            self.restart_input()?;
+        }
+        Ok(())
+    }
+
+    /// Processes only `channel` of the input device, counted from 0, as mono
+    /// and discards the others, e.g. `Some(2)` for a mic on the third input
+    /// of an interface. It takes precedence over a map from
+    /// `set_input_channel_map`. The channel must exist in one of the
+    /// selected device's configurations; a device opened with fewer channels
+    /// processes all of them, with a warning. None processes every channel.
+    pub fn set_input_channel(&mut self, channel: Option<u16>) -> Result<()> {
+        if let Some(channel) = channel {
+            let index = self.selected_input_index;
+            let most = self.input_capabilities(index)
+                .and_then(|capabilities| capabilities.iter().map(|c| c.channels).max());
+            // A device that can't be queried is given the benefit of the doubt
+            if let Some(most) = most.filter(|&most| channel >= most) {
+                return Err(AudioError::InvalidSetting(format!("Input channel {} is beyond the device's {} channels", channel + 1, most)));
+            }
+        }
+        self.input_channel = channel;
+        if self.is_processing {
+            self.restart_input()?;
+        }
+        Ok(())
+    }
+
+    pub fn get_input_channel(&self) -> Option<u16> {
+        self.input_channel
+    }
+
+    /// Routes processed channels to the output device's channels through
+    /// `map`, in place of the default mapping (see [`crate::dsp::channels`]).
+    /// The map must go from the processing channel count to the output
+    /// device's, otherwise it is ignored with a warning.
+    pub fn set_output_channel_map(&mut self, map: Option<ChannelMap>) -> Result<()> {
+        self.output_channel_map = map;
+        self.restart_output()
+    }
+
+    // Reopens the output stream, if running, so a new device or routing
+    // takes effect
+    // Reopens mic capture on the selected device. The processing task, the
+    // reference capture and the output are all set up for the mic's channel
+    // count, so a device with a different one restarts everything.
+    fn restart_input(&mut self) -> Result<()> {
+        let channels = self.channels;
+        self.input_stream.take();
+        self.start_input_capture()?;
+        if self.channels != channels {
+            info!("Input channels changed from {} to {}, restarting processing", channels, self.channels);
+            self.restart()?;
+        }
+        Ok(())
+    }
+
+    fn restart_output(&mut self) -> Result<()> {
+        if self.is_processing {
+            if let Some(stream) = self.loopback_stream.take() {
+                drop(stream);
+            }
+            self.start_loopback_output()?;
+        }
+        Ok(())
+    }
+
+    // Opens the monitor output on the chosen device, if there is one. The
+    // monitor can't be the main output: that would mix the monitor into
+    // what listeners hear.
+    fn start_monitor_output(&mut self) -> Result<()> {
+        self.monitor_stream = None;
+        if let Ok(mut slot) = self.monitor_producer.lock() {
+            slot.take();
+        }
+        let Some(name) = &self.monitor_device_name else {
+            return Ok(());
+        };
+        let Some(index) = self.output_device_info.iter().position(|info| &info.name == name) else {
+            return Err(AudioError::DeviceNotFound { kind: "Monitor", name: name.clone() });
+        };
+        if index == self.selected_output_index {
+            return Err(AudioError::InvalidSetting("The monitor device must differ from the output device".to_string()));
+        }
+        if self.is_echo_reference(name) {
+            // Heard through the echo reference, the monitored voice counts as
+            // echo and is cancelled from the mic
+            warn!("Monitor device {} is also the echo reference; echo cancellation will fight the monitor", name);
+        }
+        let samples_per_ms = self.sample_rate as f32 * self.channels.max(1) as f32 / 1000.0;
+        let capacity = ((MONITOR_BUFFER_MS * samples_per_ms) as usize).max(self.settings().block_size(self.channels) * 2);
+        let volume_db = Arc::clone(&self.monitor_volume_db);
+        let (stream, producer) = self.build_side_output(&self.output_devices[index], "Monitor", capacity, Some(volume_db))?;
+        self.monitor_stream = Some(stream);
+        if let Ok(mut slot) = self.monitor_producer.lock() {
+            *slot = Some(producer);
+        }
+        info!("Monitor output started on {}", name);
+        Ok(())
+    }
+
+    // Whether output played on the device `name` comes back in the main echo
+    // reference
+    fn is_echo_reference(&self, name: &str) -> bool {
+        let reference = self.loopback_device_name.clone()
+            .or_else(|| self.host.default_output_device().and_then(|d| d.name().ok()));
+        reference.is_some_and(|reference| reference == name || reference.strip_suffix(".monitor") == Some(name))
+    }
+
+    // Opens an output beside the main one on `device`, playing what the task
+    // pushes into the returned buffer of `capacity` samples at the processing
+    // format, converted to the device's, and at `volume_db` where given
+    fn build_side_output(
+        &self,
+        device: &Device,
+        label: &'static str,
+        capacity: usize,
+        volume_db: Option<Arc<AtomicF32>>,
+    ) -> Result<(PlayingStream, HeapProducer<f32>)> {
+        let config = choose_stream_config(
+            device.supported_output_configs()?,
+            device.default_output_config()?,
+            self.sample_rate,
+            self.requested_sample_rate,
+            None,
+        );
+        info!("{} config: {:?}", label, config);
+        let mut mapper = ChannelMapper::new(self.channels, config.channels());
+        let mut resampler = (config.sample_rate().0 != self.sample_rate).then(|| {
+            PullResampler::new(self.sample_rate, config.sample_rate().0, self.channels as usize)
+        });
+        let mut gain = Gain::new(config.sample_rate().0, config.channels());
+        let (producer, mut consumer) = HeapRb::<f32>::new(capacity).split();
+        let errors = self.errors.clone();
+
+        let stream = stream::build_output_stream(
+            device,
+            &config.config(),
+            config.sample_format(),
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                mapper.fill(data, |buffer| match resampler.as_mut() {
+                    Some(resampler) => resampler.read(buffer, |input| consumer.pop_slice(input)),
+                    None => consumer.pop_slice(buffer),
+                });
+                if let Some(volume_db) = &volume_db {
+                    gain.set_gain_db(volume_db.load());
+                    gain.process(data);
+                }
+            },
+            move |err| errors.push(describe_stream_error(label, &err)),
+        )?;
+        Ok((stream::play(label, stream)?, producer))
+    }
+
+    // (Re)starts every extra output. One that fails is reported and left
+    // out; the main output and the others still play.
+    fn start_extra_outputs(&mut self) {
+        self.extra_output_streams.clear();
+        let mut started = Vec::new();
+        for name in &self.extra_output_names {
+            if self.is_echo_reference(name) {
+                warn!("Extra output {} is also the echo reference; echo cancellation will remove the voice", name);
+            }
+            let output = self.output_device_info.iter()
+                .position(|info| &info.name == name)
+                .ok_or_else(|| AudioError::DeviceNotFound { kind: "Output", name: name.clone() })
+                .and_then(|index| {
+                    self.build_side_output(&self.output_devices[index], "Extra output", self.buffer_capacity(), None)
+                });
+            match output {
+                Ok((stream, producer)) => {
+                    info!("Extra output started on {}", name);
+                    self.extra_output_streams.push(stream);
+                    started.push(producer);
+                }
+                Err(e) => self.errors.push(format!("Extra output \"{}\" failed: {}", name, e)),
+            }
+        }
+        if let Ok(mut slot) = self.extra_output_producers.lock() {
+            slot.clear = true;
+            slot.added = started;
+        }
+    }
+
+    /// Plays the processed audio on one more output device at the same time
+    /// as the main output, e.g. headphones while the main output feeds a
+    /// virtual microphone. Every extra output has its own buffer and is
+    /// resampled and remixed to its device's format. Fails for the main
+    /// output device; while processing, the extra outputs restart with it.
+    pub fn add_output_device(&mut self, index: usize) -> Result<()> {
+        let info = self.output_device_info.get(index)
+            .ok_or(AudioError::DeviceIndex { kind: "output", index })?;
+        if index == self.selected_output_index {
+            return Err(AudioError::InvalidSetting("The main output device can't also be an extra output".to_string()));
+        }
+        if self.extra_output_names.contains(&info.name) {
+            return Ok(());
+        }
+        self.extra_output_names.push(info.name.clone());
+        if self.is_processing {
+            self.start_extra_outputs();
+        }
+        Ok(())
+    }
+
+    pub fn remove_output_device(&mut self, index: usize) -> Result<()> {
+        let info = self.output_device_info.get(index)
+            .ok_or(AudioError::DeviceIndex { kind: "output", index })?;
+        let count = self.extra_output_names.len();
+        self.extra_output_names.retain(|name| name != &info.name);
+        if self.is_processing && self.extra_output_names.len() != count {
+            self.start_extra_outputs();
+        }
+        Ok(())
+    }
+
+    // Indices of the extra outputs in the current output list, for those
+    // that are there
+    pub fn get_extra_output_devices(&self) -> Vec<usize> {
+        self.extra_output_names.iter()
+            .filter_map(|name| self.output_device_info.iter().position(|info| &info.name == name))
+            .collect()
+    }
+
+    /// Plays a monitor (sidetone) mix on a second output device, typically
+    /// headphones, or stops it with None. Use headphones: a monitor on
+    /// speakers is picked up by the mic and feeds back. Fails for the main
+    /// output device.
+    pub fn set_monitor_device(&mut self, index: Option<usize>) -> Result<()> {
+        let name = match index {
+            Some(index) => {
+                let info = self.output_device_info.get(index)
+                    .ok_or(AudioError::DeviceIndex { kind: "output", index })?;
+                if index == self.selected_output_index {
+                    return Err(AudioError::InvalidSetting("The monitor device must differ from the output device".to_string()));
+                }
+                Some(info.name.clone())
+            }
+            None => None,
+        };
+        self.monitor_device_name = name;
+        if self.is_processing {
+            self.start_monitor_output()?;
+        }
+        Ok(())
+    }
+
+    // Index of the monitor device in the current output list, if it is there
+    pub fn get_monitor_device(&self) -> Option<usize> {
+        let name = self.monitor_device_name.as_ref()?;
+        self.output_device_info.iter().position(|info| &info.name == name)
+    }
+
+    pub fn set_monitor_source(&self, source: MonitorSource) {
+        self.monitor_raw.store(source == MonitorSource::Raw, Ordering::Relaxed);
+    }
+
+    pub fn get_monitor_source(&self) -> MonitorSource {
+        if self.monitor_raw.load(Ordering::Relaxed) { MonitorSource::Raw } else { MonitorSource::Processed }
+    }
+
+    // Level of the monitor, -24 to +24 dB, independent of the output gain
+    pub fn set_monitor_volume(&self, volume_db: f32) {
+        self.monitor_volume_db.store(volume_db.clamp(MIN_GAIN_DB, MAX_GAIN_DB));
+    }
+
+    pub fn get_monitor_volume(&self) -> f32 {
+        self.monitor_volume_db.load()
+    }
+
+    /// Sends the processed audio to a virtual microphone that other
+    /// applications can select as their input, or back to a regular output
+    /// device.
+    ///
+    /// On Linux the microphone is created as a null sink plus remap source in
+    /// PulseAudio/PipeWire and removed again when disabled. On Windows and
+    /// macOS an installed virtual cable (VB-Cable, BlackHole) is selected as
+    /// the output device; without one this returns an error with setup
+    /// instructions.
+    pub fn set_virtual_output(&mut self, enabled: bool) -> Result<()> {
+        if enabled == self.virtual_output {
+            return Ok(());
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            if enabled {
+                self.virtual_microphone = Some(virtual_device::VirtualMicrophone::create()?);
+                // The ALSA pulse plugin plays to $PULSE_SINK when set. Output
+                // streams are only opened from this thread, so nothing reads
+                // the variable while it changes.
+                std::env::set_var("PULSE_SINK", virtual_device::VIRTUAL_SINK_NAME);
+                let sound_server = ["pipewire", "pulse", "default"].iter()
+                    .find_map(|name| self.find_output_device(name));
+                if let Some(index) = sound_server {
+                    self.selected_output_index = index;
+                    self.selected_output_device = self.output_devices.get(index).cloned();
+                }
+            } else {
+                std::env::remove_var("PULSE_SINK");
+                self.virtual_microphone = None;
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let index = if enabled {
+                self.output_device_info.iter()
+                    .position(|info| is_virtual_cable_name(&info.name))
+                    .ok_or_else(|| AudioError::Unavailable(format!("No virtual cable found. {}", virtual_device::setup_hint())))?
+            } else {
+                find_by_name_or_default(&self.output_device_info, None).unwrap_or(0)
+            };
+            self.selected_output_index = index;
+            self.selected_output_device = self.output_devices.get(index).cloned();
+        }
+
+        self.virtual_output = enabled;
+        info!("Virtual output {}", if enabled { "enabled" } else { "disabled" });
+        if let Some(info) = self.output_device_info.get(self.selected_output_index) {
+            self.events.emit(ProcessorEvent::OutputDeviceChanged(info.name.clone()));
+        }
+        self.restart_output()
+    }
+
+    pub fn is_virtual_output_enabled(&self) -> bool {
+        self.virtual_output
+    }
+
+    // Name other applications know the virtual microphone by: ours on Linux
+    // once created, otherwise the recording end of a detected virtual cable
+    pub fn virtual_microphone_name(&self) -> Option<String> {
+        if cfg!(target_os = "linux") {
+            return self.virtual_output.then(|| virtual_device::VIRTUAL_SOURCE_DESCRIPTION.to_string());
+        }
+        self.output_device_info.iter()
+            .find(|info| is_virtual_cable_name(&info.name))
+            .map(|info| info.name.replace("Input", "Output"))
+    }
+}
+
+impl Drop for AudioProcessor {
+    fn drop(&mut self) {
+        self.stop();
+        if let Err(e) = self.stop_recording() {
+            error!("Recording failed: {}", e);
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn input_capture(producer: HeapProducer<f32>, resampler: Option<LinearResampler>) -> InputCapture {
+        InputCapture { mapper: ChannelMapper::new(2, 2), resampler, producer, block_signal: Arc::new(BlockSignal::new(960)) }
+    }
+
+    #[test]
+    fn capture_callback_does_not_wait_for_the_reader() {
+        // Processing holds the consumer for as long as it likes; the callback
+        // only touches its producer, so with the consumer locked here it still
+        // returns every time, dropping what the full buffer can't take. Were it
+        // to wait on the reader, this would deadlock instead.
+        let (producer, consumer) = HeapRb::<f32>::new(4800).split();
+        let mic_consumer: Handoff<HeapConsumer<f32>> = Arc::new(Mutex::new(Some(consumer)));
+        let held = mic_consumer.lock().unwrap();
+        let mut capture = input_capture(producer, None);
+
+        // 10 ms stereo blocks at 48 kHz into a 50 ms buffer
+        let block = vec![0.25; 960];
+        let dropped: Vec<usize> = (0..8).map(|_| capture.push(&block)).collect();
+        assert_eq!(dropped, [0, 0, 0, 0, 0, 960, 960, 960]);
+        drop(held);
+
+        // What was kept is the oldest audio, ready for the reader
+        let mut consumer = mic_consumer.lock().unwrap().take().unwrap();
+        assert_eq!(consumer.len(), 4800);
+        let mut kept = vec![0.0; 4800];
+        assert_eq!(consumer.pop_slice(&mut kept), 4800);
+        assert!(kept.iter().all(|&sample| sample == 0.25));
+
+        // Resampled input is pushed sample by sample and drops the same way
+        let (producer, _consumer) = HeapRb::<f32>::new(4800).split();
+        let mut capture = input_capture(producer, Some(LinearResampler::new(44100, 48000, 2)));
+        let mut reference = LinearResampler::new(44100, 48000, 2);
+        let block = vec![0.25; 882];
+        let (mut emitted, mut dropped) = (0, 0);
+        for _ in 0..8 {
+            reference.process(&block, |_| emitted += 1);
+            dropped += capture.push(&block);
+        }
+        assert!(capture.producer.is_full());
+        assert_eq!(dropped, emitted - 4800);
+    }
+
+    // Polls `condition` until it holds or `limit` passes
+    async fn wait_for(limit: Duration, mut condition: impl FnMut() -> bool) -> bool {
+        let deadline = Instant::now() + limit;
+        while !condition() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        true
+    }
+
+    // The processing task runs without any streams, on silence, so none of
+    // these need audio devices
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn processing_task_exits_after_stop() {
+        let mut processor = AudioProcessor::builder().build().unwrap();
+        processor.start_processing().unwrap();
+        assert!(processor.is_processing_task_running());
+
+        processor.stop();
+        assert!(wait_for(Duration::from_secs(1), || !processor.is_processing_task_running()).await);
+
+        // A restart spawns a fresh task instead of reviving the old one
+        processor.start_processing().unwrap();
+        assert!(processor.is_processing_task_running());
+        processor.stop();
+        assert!(wait_for(Duration::from_secs(1), || !processor.is_processing_task_running()).await);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn idle_processing_task_wakes_once_per_timeout() {
+        let mut processor = AudioProcessor::builder().build().unwrap();
+        processor.start_processing().unwrap();
+        let samples_per_second = processor.get_sample_rate() as f32 * processor.get_channels() as f32;
+        let block = Duration::from_secs_f32(processor.settings().block_size(processor.get_channels()) as f32 / samples_per_second);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // With no mic delivering, the task only wakes for its block timeout
+        // of two block durations; the 10 ms sleep-poll it replaced woke 100
+        // times a second
+        let wakeups = &processor.block_signal.wakeups;
+        let before = wakeups.load(Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let per_second = wakeups.load(Ordering::Relaxed) - before;
+        processor.stop();
+
+        let timeouts = (1.0 / (2.0 * block.as_secs_f32())).ceil() as u64;
+        assert!(per_second > 0, "the task stopped waking for its block timeout");
+        assert!(per_second <= timeouts * 3 / 2 + 1, "{} wakeups in a second for {} timeouts", per_second, timeouts);
+        assert!(per_second < 100, "{} wakeups in a second", per_second);
+    }
+
+    #[test]
+    fn meter_reports_the_rms_of_a_sine() {
+        // 0.5 amplitude, so -9.03 dBFS; 1 kHz fits whole periods in the
+        // 300 ms window, which then holds the analytic RMS exactly
+        let amplitude = 0.5;
+        let sine: Vec<f32> = (0..48000)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 48000.0).sin())
+            .collect();
+        let mut window = RmsWindow::new(48000, 1);
+        let meter = LevelMeter::default();
+        for block in sine.chunks(480) {
+            meter.update(block, window.push(block));
+        }
+
+        let reading = meter.reading();
+        let expected = to_dbfs(amplitude / 2f32.sqrt());
+        assert!((reading.rms_db - expected).abs() < 0.01, "{} dBFS, expected {}", reading.rms_db, expected);
+        assert!((reading.peak_db - to_dbfs(amplitude)).abs() < 0.01);
+    }
+
+    #[test]
+    fn each_channel_gets_its_own_level() {
+        // Constant levels of 0.5, 0.1 and 0 on three interleaved channels,
+        // the last one dead
+        let amplitudes = [0.5, 0.1, 0.0];
+        let block: Vec<f32> = (0..480 * 3).map(|i| amplitudes[i % 3]).collect();
+        let mut windows: Vec<RmsWindow> = (0..3).map(|_| RmsWindow::new(48000, 1)).collect();
+        let levels = ChannelLevels::default();
+        for _ in 0..50 {
+            levels.update(&block, &block, &mut windows);
+        }
+
+        let readings = levels.readings();
+        assert_eq!(readings.len(), 3);
+        for (reading, &amplitude) in readings.iter().zip(&amplitudes) {
+            assert!((reading.rms_db - to_dbfs(amplitude)).abs() < 0.01, "{} dBFS for {}", reading.rms_db, amplitude);
+            assert!((reading.peak_db - to_dbfs(amplitude)).abs() < 0.01);
+        }
+        assert_eq!(readings[2].rms_db, MIN_LEVEL_DB);
+
+        levels.reset();
+        assert!(levels.readings().is_empty());
+    }
+
+    // The test host lists no devices at all, like a headless CI box
+    #[test]
+    fn no_devices_is_an_error_to_start_not_to_build() {
+        let mut processor = AudioProcessor::builder().build().unwrap();
+        assert!(processor.get_input_devices().is_empty() && processor.get_output_devices().is_empty());
+        assert!(!processor.has_input_device() && !processor.has_output_device());
+
+        assert!(matches!(processor.start_input_capture(), Err(AudioError::NoDevice("input"))));
+        assert!(matches!(processor.start_loopback_output(), Err(AudioError::NoDevice("output"))));
+        let error = processor.start().unwrap_err();
+        assert_eq!(error.to_string(), "No input device available");
+        assert!(!processor.is_processing_task_running());
+    }
+
+    #[test]
+    fn reset_and_undo_round_trip_the_settings() {
+        let mut processor = AudioProcessor::builder().build().unwrap();
+        assert!(!processor.can_undo_settings());
+        processor.set_echo_cancellation(false);
+        processor.set_input_gain_db(6.0);
+        processor.set_gate_threshold_db(-35.0);
+        processor.set_fft_size(2048).unwrap();
+        let tuned = processor.settings();
+        assert_ne!(tuned, ProcessorSettings::default());
+
+        processor.reset_to_defaults().unwrap();
+        assert_eq!(processor.settings(), ProcessorSettings::default());
+        assert!(processor.can_undo_settings());
+
+        // Undo brings back every setting at once, and can itself be undone
+        assert!(processor.undo_settings().unwrap());
+        assert_eq!(processor.settings(), tuned);
+        assert!(processor.undo_settings().unwrap());
+        assert_eq!(processor.settings(), ProcessorSettings::default());
+
+        // A captured snapshot applies wholesale
+        processor.apply_settings(tuned.clone()).unwrap();
+        assert_eq!(processor.settings(), tuned);
+    }
+
+    #[test]
+    fn dry_mix_reproduces_the_delayed_input() {
+        let settings = ProcessorSettings { mix: 0.0, ..ProcessorSettings::default() };
+        let mut pipeline = crate::pipeline::OfflinePipeline::new(settings, 48000, 1).unwrap();
+        let mut seed = 1u32;
+        let input: Vec<f32> = (0..48000)
+            .map(|i| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (seed >> 8) as f32 / (1 << 24) as f32 - 0.5;
+                0.3 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48000.0).sin() + 0.2 * noise
+            })
+            .collect();
+        pipeline.push_input_samples(&input);
+        pipeline.flush();
+        let mut output = vec![0.0; pipeline.available()];
+        pipeline.pull_output_samples(&mut output);
+
+        // Noise reduction, high-pass and limiter all run, yet none of them
+        // may leak into a fully dry signal beyond their delay
+        let latency = pipeline.latency();
+        assert!(latency > 0);
+        for (i, (out, expected)) in output[latency..].iter().zip(&input).enumerate() {
+            assert!((out - expected).abs() < 1e-6, "sample {}: {} instead of {}", i, out, expected);
+        }
+    }
+
+    #[test]
+    fn non_finite_input_is_counted_and_replaced() {
+        let settings = ProcessorSettings::default();
+        let mut dsp = DspState::new(settings.fft_size, settings.hop_size, 48000, 1);
+        dsp.apply_settings(&settings);
+        let tone = |n: usize| 0.3 * (2.0 * std::f32::consts::PI * 440.0 * n as f32 / 48000.0).sin();
+
+        let mut blocks: Vec<Vec<f32>> = vec![vec![0.0; 1024]];
+        let mut poisoned: Vec<f32> = (1024..2048).map(tone).collect();
+        poisoned[10] = f32::NAN;
+        poisoned[500] = f32::INFINITY;
+        poisoned[900] = f32::NEG_INFINITY;
+        poisoned[901] = f32::MIN_POSITIVE / 2.0;
+        blocks.push(poisoned);
+        blocks.extend((2..40).map(|block| (block * 1024..(block + 1) * 1024).map(tone).collect()));
+        // A NaN in the reference too, which the echo filter would keep
+        let mut reference = vec![0.0; 1024];
+        reference[3] = f32::NAN;
+
+        let mut output = Vec::new();
+        for block in &blocks {
+            output.extend(AudioProcessor::process_audio_chunk(block, &reference, &settings, &mut dsp));
+        }
+        assert!(output.iter().all(|s| s.is_finite()));
+        assert_eq!(dsp.sanitized_samples(), 3 + blocks.len() as u64);
+        // Nothing was left in the filters to keep the tone from coming back
+        let rms = (output[output.len() - 4800..].iter().map(|s| s * s).sum::<f32>() / 4800.0).sqrt();
+        assert!(rms > 0.05, "the tone came back at an RMS of {}", rms);
+    }
+
+    #[test]
+    fn selected_input_channel_is_processed_alone() {
+        let processor = AudioProcessor::builder().input_channel(2).build().unwrap();
+        // Two frames of an 8-channel interface, each channel holding its index
+        let block: Vec<f32> = (0..16).map(|n| (n % 8) as f32 + (n / 8) as f32 * 10.0).collect();
+        let mut mapper = ChannelMapper::with_map(processor.input_map(8).unwrap());
+        assert_eq!(mapper.map(&block), [2.0, 12.0]);
+
+        // A device without that channel processes all of its own
+        assert!(processor.input_map(2).is_none());
+    }
+
+    #[test]
+    fn extra_outputs_each_get_the_same_samples() {
+        // Two sinks handed to the task, as `add_output_device` does, one of
+        // them short of room for a whole block
+        let handoff = Mutex::new(ExtraBuffers::default());
+        let (roomy, mut heard_roomy) = HeapRb::<f32>::new(64).split();
+        let (cramped, mut heard_cramped) = HeapRb::<f32>::new(7).split();
+        handoff.lock().unwrap().added.extend([roomy, cramped]);
+        let mut outputs = Vec::new();
+        ExtraBuffers::take_into(&handoff, &mut outputs);
+        assert_eq!(outputs.len(), 2);
+
+        let block: Vec<f32> = (0..12).map(|n| n as f32 / 12.0).collect();
+        let pushed: Vec<usize> = outputs.iter_mut().map(|producer| push_frames(producer, &block, 2)).collect();
+        assert_eq!(pushed, [12, 6]);
+        let mut roomy = vec![0.0; 12];
+        let mut cramped = vec![0.0; 6];
+        assert_eq!(heard_roomy.pop_slice(&mut roomy), 12);
+        assert_eq!(heard_cramped.pop_slice(&mut cramped), 6);
+        assert_eq!(roomy, block);
+        // Whole stereo frames only, identical to the other sink's
+        assert_eq!(cramped, roomy[..6]);
+
+        // A clear drops both before anything further is added
+        handoff.lock().unwrap().clear = true;
+        ExtraBuffers::take_into(&handoff, &mut outputs);
+        assert!(outputs.is_empty());
+    }
+
+    #[test]
+    fn session_stats_sum_up_the_blocks() {
+        let tally = SessionTally::default();
+        let block = 480;
+        // A second of quiet room, then a second of voice peaking at -6 dBFS
+        let room = vec![0.001; block];
+        let voice: Vec<f32> = (0..block).map(|n| 0.5 * (2.0 * std::f32::consts::PI * n as f32 / 48.0).sin()).collect();
+        for _ in 0..100 {
+            tally.record_block(&room, 1, false);
+            tally.record_load(0.2);
+        }
+        for _ in 0..100 {
+            tally.record_block(&voice, 1, true);
+            tally.record_load(0.4);
+        }
+        // Clipping across three blocks in a row, then once more later
+        let clipped = vec![1.0; block];
+        for output in [&clipped, &clipped, &clipped, &voice, &clipped] {
+            tally.record_block(output, 1, true);
+        }
+
+        let stats = tally.summary(48000, -23.0, 2);
+        assert_eq!(stats.duration, Duration::from_millis(2050));
+        assert_eq!(stats.peak_db, 0.0);
+        assert_eq!(stats.integrated_lufs, -23.0);
+        assert_eq!(stats.clips, 2);
+        assert_eq!(stats.underruns, 2);
+        assert!((stats.speech_ratio - 105.0 / 205.0).abs() < 1e-6, "speech ratio {}", stats.speech_ratio);
+        assert!((stats.average_load - 0.3).abs() < 1e-4, "average load {}", stats.average_load);
+
+        // A new session starts from nothing
+        tally.reset();
+        let stats = tally.summary(48000, f32::NEG_INFINITY, 0);
+        assert_eq!(stats.duration, Duration::ZERO);
+        assert_eq!((stats.clips, stats.speech_ratio, stats.average_load), (0, 0.0, 0.0));
+        assert_eq!(stats.peak_db, to_dbfs(0.0));
+    }
+
+    #[test]
+    fn integer_wav_files_read_in_full_scale() {
+        let path = std::env::temp_dir().join(format!("cancelcaster-int-{}.wav", std::process::id()));
+        let spec = hound::WavSpec { channels: 2, sample_rate: 44100, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for sample in [i16::MIN, i16::MAX, 0, 16384] {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let (read, samples) = AudioProcessor::read_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((read.sample_rate, read.channels), (44100, 2));
+        assert_eq!(samples, [-1.0, 32767.0 / 32768.0, 0.0, 0.5]);
+
+        // Anything but a WAV file is an error rather than noise
+        std::fs::write(&path, b"RIFF\x04\0\0\0WAVE").unwrap();
+        let result = AudioProcessor::read_wav(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
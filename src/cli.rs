@@ -0,0 +1,88 @@
+// Command-line options. Kept dependency-free since the set of flags is small.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: cancelcaster [OPTIONS]
+
+Options:
+      --headless              Run the processing pipeline without the GUI until Ctrl-C
+      --input-device <NAME>   Capture from the input device with this name
+      --output-device <NAME>  Play processed audio to the output device with this name
+      --input-file <PATH>     Process a WAV file offline instead of live audio
+      --output-file <PATH>    Where to write the processed WAV (with --input-file)
+      --metrics-file <PATH>   Append a JSON line of levels and stats to PATH (\"-\" for stdout)
+      --metrics-interval <MS> Time between metrics records [default: 1000]
+      --log-file <PATH>       Log to PATH instead of cancelcaster.log in the data directory
+  -h, --help                  Print this help";
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub headless: bool,
+    pub input_device: Option<String>,
+    pub output_device: Option<String>,
+    pub input_file: Option<PathBuf>,
+    pub output_file: Option<PathBuf>,
+    // "-" writes the metrics to stdout
+    pub metrics_file: Option<PathBuf>,
+    pub metrics_interval: Option<Duration>,
+    pub log_file: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+
+        while let Some(arg) = args.next() {
+            // Accept both "--flag value" and "--flag=value"
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = |flag: &str| {
+                inline_value.clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{} requires a value", flag))
+            };
+
+            match flag.as_str() {
+                "--headless" => parsed.headless = true,
+                "--input-device" => parsed.input_device = Some(value(&flag)?),
+                "--output-device" => parsed.output_device = Some(value(&flag)?),
+                "--input-file" => parsed.input_file = Some(value(&flag)?.into()),
+                "--output-file" => parsed.output_file = Some(value(&flag)?.into()),
+                "--metrics-file" => parsed.metrics_file = Some(value(&flag)?.into()),
+                "--metrics-interval" => {
+                    let ms = value(&flag)?;
+                    let ms: u64 = ms.parse().map_err(|_| format!("Invalid --metrics-interval: {}", ms))?;
+                    parsed.metrics_interval = Some(Duration::from_millis(ms));
+                }
+                "--log-file" => parsed.log_file = Some(value(&flag)?.into()),
+                "-h" | "--help" => parsed.help = true,
+                other => return Err(format!("Unknown argument: {}", other)),
+            }
+        }
+
+        if parsed.input_file.is_some() != parsed.output_file.is_some() {
+            return Err("--input-file and --output-file must be used together".to_string());
+        }
+
+        if parsed.metrics_interval.is_some() && parsed.metrics_file.is_none() {
+            return Err("--metrics-interval requires --metrics-file".to_string());
+        }
+
+        Ok(parsed)
+    }
+
+    // File --metrics-file names, Some(None) for stdout, None when not given
+    pub fn metrics_path(&self) -> Option<Option<&Path>> {
+        self.metrics_file.as_deref().map(metrics_target)
+    }
+}
+
+// Metrics file a path names; "-" stands for stdout
+pub fn metrics_target(path: &Path) -> Option<&Path> {
+    (path != Path::new("-")).then_some(path)
+}
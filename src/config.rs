@@ -0,0 +1,367 @@
+use crate::audio::{MAX_PROCESSING_SAMPLE_RATE, PROCESSING_SAMPLE_RATE};
+use crate::dsp::backend::ProcessingBackend;
+use crate::dsp::channels::{MonoToStereoMode, DEFAULT_HAAS_DELAY_MS, DEFAULT_STEREO_WIDTH};
+use crate::dsp::noise::NoiseProfile;
+use crate::dsp::DEFAULT_SAFETY_CEILING_DB;
+use crate::dsp::spectral::{DEFAULT_NOISE_REDUCTION_STRENGTH, DEFAULT_SPECTRAL_FLOOR};
+use crate::dsp::weighting::MeterWeighting;
+use crate::replay::{DEFAULT_REPLAY_SECS, MAX_REPLAY_SECS, MIN_REPLAY_SECS};
+use crate::settings::{float, QualityProfile};
+use anyhow::Result;
+use std::path::PathBuf;
+use toml_edit::{table, value, Array, Document};
+use tracing::warn;
+
+// Settings persisted between launches. Devices are stored by name rather than
+// index because enumeration order changes as devices come and go.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub input_device: Option<String>,
+    pub output_device: Option<String>,
+    // Rate the DSP runs at, whatever the devices run at
+    pub processing_sample_rate: u32,
+    // Device stream format chosen by the user; None follows the device
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    // Input device channel processed alone as mono, counted from 0; None
+    // processes every channel
+    pub input_channel: Option<u16>,
+    // Frames per device callback; None leaves it to the device
+    pub buffer_frames: Option<u32>,
+    // Latency/quality trade-off last chosen; None once tuned by hand
+    pub quality_profile: Option<QualityProfile>,
+    // Seconds of processed audio kept for saving after the fact
+    pub replay_secs: u32,
+    // The output is negated, for routing that inverts it later on
+    pub output_polarity_inverted: bool,
+    // Spread of mono processing over a stereo output
+    pub output_stereo_mode: MonoToStereoMode,
+    pub stereo_width: f32,
+    pub haas_delay_ms: f32,
+    // Hard clamp of the output for hearing protection
+    pub safety_ceiling_enabled: bool,
+    pub safety_ceiling_db: f32,
+    // Frequency weighting of the level meters
+    pub meter_weighting: MeterWeighting,
+    // Devices follow the system default instead of the names above
+    pub follow_system_default: bool,
+    pub input_gain_db: f32,
+    pub output_gain_db: f32,
+    pub processing_backend: ProcessingBackend,
+    pub echo_cancellation: bool,
+    pub noise_reduction: bool,
+    pub noise_reduction_strength: f32,
+    pub spectral_floor: f32,
+    // The GUI starts processing on launch with the devices above, for an
+    // always-on setup; the processor never reads it
+    pub autostart: bool,
+    pub shortcuts: Shortcuts,
+}
+
+// Keys of the GUI's shortcuts, by the names egui gives them ("Space", "B",
+// "F5"). Saved in a [shortcuts] table; the processor never reads them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shortcuts {
+    pub start_stop: String,
+    pub bypass: String,
+    pub mute: String,
+}
+
+impl Default for Shortcuts {
+    fn default() -> Self {
+        Self {
+            start_stop: "Space".to_string(),
+            bypass: "B".to_string(),
+            mute: "M".to_string(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            input_device: None,
+            output_device: None,
+            processing_sample_rate: PROCESSING_SAMPLE_RATE,
+            sample_rate: None,
+            channels: None,
+            input_channel: None,
+            buffer_frames: None,
+            quality_profile: None,
+            replay_secs: DEFAULT_REPLAY_SECS,
+            output_polarity_inverted: false,
+            output_stereo_mode: MonoToStereoMode::default(),
+            stereo_width: DEFAULT_STEREO_WIDTH,
+            haas_delay_ms: DEFAULT_HAAS_DELAY_MS,
+            safety_ceiling_enabled: true,
+            safety_ceiling_db: DEFAULT_SAFETY_CEILING_DB,
+            meter_weighting: MeterWeighting::default(),
+            follow_system_default: false,
+            input_gain_db: 0.0,
+            output_gain_db: 0.0,
+            processing_backend: ProcessingBackend::default(),
+            echo_cancellation: true,
+            noise_reduction: true,
+            noise_reduction_strength: DEFAULT_NOISE_REDUCTION_STRENGTH,
+            spectral_floor: DEFAULT_SPECTRAL_FLOOR,
+            autostart: false,
+            shortcuts: Shortcuts::default(),
+        }
+    }
+}
+
+// The user's home directory, where files the user asks for are saved by
+// default
+pub fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")).map(PathBuf::from)
+}
+
+// Per-user configuration directory for this application
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|dir| dir.join("cancelcaster"))
+}
+
+// Per-user directory for data the application writes on its own, such as logs
+pub fn data_dir() -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    base.map(|dir| dir.join("cancelcaster"))
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    // Loads the saved config, falling back to defaults if it is missing or
+    // cannot be parsed. Individual fields with the wrong type also fall back.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).unwrap_or_else(|e| {
+                warn!("Ignoring malformed config {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                warn!("Failed to read config {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let doc = text.parse::<Document>()?;
+        let defaults = Self::default();
+
+        let string = |key: &str| doc.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        let positive = |key: &str| doc.get(key).and_then(|v| v.as_integer()).filter(|&v| v > 0);
+        let boolean = |key: &str, default: bool| doc.get(key).and_then(|v| v.as_bool()).unwrap_or(default);
+        let number = |key: &str, default: f32| {
+            doc.get(key)
+                .and_then(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64)))
+                .map(|v| v as f32)
+                .unwrap_or(default)
+        };
+
+        let shortcut = |key: &str, default: &str| {
+            doc.get("shortcuts")
+                .and_then(|shortcuts| shortcuts.get(key))
+                .and_then(|v| v.as_str())
+                .unwrap_or(default)
+                .to_string()
+        };
+
+        Ok(Self {
+            input_device: string("input_device"),
+            output_device: string("output_device"),
+            processing_sample_rate: positive("processing_sample_rate")
+                .and_then(|v| u32::try_from(v).ok())
+                .filter(|&rate| rate <= MAX_PROCESSING_SAMPLE_RATE)
+                .unwrap_or(defaults.processing_sample_rate),
+            sample_rate: positive("sample_rate").and_then(|v| u32::try_from(v).ok()),
+            channels: positive("channels").and_then(|v| u16::try_from(v).ok()),
+            input_channel: doc.get("input_channel")
+                .and_then(|v| v.as_integer())
+                .and_then(|v| u16::try_from(v).ok()),
+            buffer_frames: positive("buffer_frames").and_then(|v| u32::try_from(v).ok()),
+            quality_profile: doc.get("quality_profile")
+                .and_then(|v| v.as_str())
+                .and_then(QualityProfile::from_name),
+            replay_secs: positive("replay_secs")
+                .and_then(|v| u32::try_from(v).ok())
+                .filter(|secs| (MIN_REPLAY_SECS..=MAX_REPLAY_SECS).contains(secs))
+                .unwrap_or(defaults.replay_secs),
+            output_polarity_inverted: boolean("output_polarity_inverted", defaults.output_polarity_inverted),
+            output_stereo_mode: doc.get("output_stereo_mode")
+                .and_then(|v| v.as_str())
+                .and_then(MonoToStereoMode::from_name)
+                .unwrap_or(defaults.output_stereo_mode),
+            stereo_width: number("stereo_width", defaults.stereo_width),
+            haas_delay_ms: number("haas_delay_ms", defaults.haas_delay_ms),
+            safety_ceiling_enabled: boolean("safety_ceiling_enabled", defaults.safety_ceiling_enabled),
+            safety_ceiling_db: number("safety_ceiling_db", defaults.safety_ceiling_db),
+            meter_weighting: doc.get("meter_weighting")
+                .and_then(|v| v.as_str())
+                .and_then(MeterWeighting::from_name)
+                .unwrap_or(defaults.meter_weighting),
+            follow_system_default: boolean("follow_system_default", defaults.follow_system_default),
+            input_gain_db: number("input_gain_db", defaults.input_gain_db),
+            output_gain_db: number("output_gain_db", defaults.output_gain_db),
+            // A backend this build lacks falls back to the built-in one
+            processing_backend: doc.get("processing_backend")
+                .and_then(|v| v.as_str())
+                .and_then(ProcessingBackend::from_name)
+                .filter(|backend| backend.is_available())
+                .unwrap_or(defaults.processing_backend),
+            echo_cancellation: boolean("echo_cancellation", defaults.echo_cancellation),
+            noise_reduction: boolean("noise_reduction", defaults.noise_reduction),
+            noise_reduction_strength: number("noise_reduction_strength", defaults.noise_reduction_strength),
+            spectral_floor: number("spectral_floor", defaults.spectral_floor),
+            autostart: boolean("autostart", defaults.autostart),
+            shortcuts: Shortcuts {
+                start_stop: shortcut("start_stop", &defaults.shortcuts.start_stop),
+                bypass: shortcut("bypass", &defaults.shortcuts.bypass),
+                mute: shortcut("mute", &defaults.shortcuts.mute),
+            },
+        })
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut doc = Document::new();
+
+        if let Some(name) = &self.input_device {
+            doc["input_device"] = value(name.as_str());
+        }
+        if let Some(name) = &self.output_device {
+            doc["output_device"] = value(name.as_str());
+        }
+        doc["processing_sample_rate"] = value(self.processing_sample_rate as i64);
+        if let Some(rate) = self.sample_rate {
+            doc["sample_rate"] = value(rate as i64);
+        }
+        if let Some(channels) = self.channels {
+            doc["channels"] = value(channels as i64);
+        }
+        if let Some(channel) = self.input_channel {
+            doc["input_channel"] = value(channel as i64);
+        }
+        if let Some(frames) = self.buffer_frames {
+            doc["buffer_frames"] = value(frames as i64);
+        }
+        if let Some(profile) = self.quality_profile {
+            doc["quality_profile"] = value(profile.as_str());
+        }
+        doc["replay_secs"] = value(self.replay_secs as i64);
+        doc["output_polarity_inverted"] = value(self.output_polarity_inverted);
+        doc["output_stereo_mode"] = value(self.output_stereo_mode.as_str());
+        doc["stereo_width"] = value(float(self.stereo_width));
+        doc["haas_delay_ms"] = value(float(self.haas_delay_ms));
+        doc["safety_ceiling_enabled"] = value(self.safety_ceiling_enabled);
+        doc["safety_ceiling_db"] = value(float(self.safety_ceiling_db));
+        doc["meter_weighting"] = value(self.meter_weighting.as_str());
+        doc["follow_system_default"] = value(self.follow_system_default);
+        doc["input_gain_db"] = value(float(self.input_gain_db));
+        doc["output_gain_db"] = value(float(self.output_gain_db));
+        doc["processing_backend"] = value(self.processing_backend.as_str());
+        doc["echo_cancellation"] = value(self.echo_cancellation);
+        doc["noise_reduction"] = value(self.noise_reduction);
+        doc["noise_reduction_strength"] = value(float(self.noise_reduction_strength));
+        doc["spectral_floor"] = value(float(self.spectral_floor));
+        doc["autostart"] = value(self.autostart);
+        doc["shortcuts"] = table();
+        doc["shortcuts"]["start_stop"] = value(self.shortcuts.start_stop.as_str());
+        doc["shortcuts"]["bypass"] = value(self.shortcuts.bypass.as_str());
+        doc["shortcuts"]["mute"] = value(self.shortcuts.mute.as_str());
+
+        doc.to_string()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| anyhow::anyhow!("No configuration directory available"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, self.to_toml())?;
+        Ok(())
+    }
+}
+
+// A learned noise profile is kept in its own file, noise_profile.toml, as it
+// holds one value per FFT bin
+pub fn noise_profile_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("noise_profile.toml"))
+}
+
+// The saved noise profile, if there is one that can be read
+pub fn load_noise_profile() -> Option<NoiseProfile> {
+    let path = noise_profile_path()?;
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("Failed to read noise profile {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let power = text.parse::<Document>()
+        .map_err(|e| warn!("Ignoring malformed noise profile {}: {}", path.display(), e))
+        .ok()?
+        .get("power")
+        .and_then(|v| v.as_array())
+        .map(|array| {
+            array.iter()
+                .map(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64)).map(|v| v as f32))
+                .collect::<Option<Vec<f32>>>()
+        })?;
+    match power {
+        Some(power) if !power.is_empty() => Some(NoiseProfile::new(power)),
+        _ => {
+            warn!("Ignoring malformed noise profile {}", path.display());
+            None
+        }
+    }
+}
+
+pub fn save_noise_profile(profile: &NoiseProfile) -> Result<()> {
+    let path = noise_profile_path()
+        .ok_or_else(|| anyhow::anyhow!("No configuration directory available"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut doc = Document::new();
+    doc["power"] = value(profile.power().iter().map(|&p| float(p)).collect::<Array>());
+    std::fs::write(&path, doc.to_string())?;
+    Ok(())
+}
+
+// Deletes the saved noise profile; a missing one is not an error
+pub fn remove_noise_profile() -> Result<()> {
+    let Some(path) = noise_profile_path() else {
+        return Ok(());
+    };
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
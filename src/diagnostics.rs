@@ -0,0 +1,157 @@
+// Summary of the processor's setup and state for bug reports. It holds device
+// names, formats, settings and measurements only: no audio, no file paths of
+// recordings, metrics or logs, and nothing from the environment. The home
+// directory is masked in error messages, which can mention files.
+
+use crate::audio::LatencyBreakdown;
+use crate::settings::{ProcessorSettings, QualityProfile};
+use std::fmt;
+
+// Format a stream was opened with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub sample_format: cpal::SampleFormat,
+}
+
+impl From<&cpal::SupportedStreamConfig> for StreamFormat {
+    fn from(config: &cpal::SupportedStreamConfig) -> Self {
+        Self {
+            sample_rate: config.sample_rate().0,
+            channels: config.channels(),
+            sample_format: config.sample_format(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    pub version: &'static str,
+    pub os: &'static str,
+    pub host: String,
+    pub input_device: Option<String>,
+    pub output_device: Option<String>,
+    pub loopback_device: Option<String>,
+    pub monitor_device: Option<String>,
+    // Formats of the running streams; None while stopped
+    pub input_format: Option<StreamFormat>,
+    pub output_format: Option<StreamFormat>,
+    // Processing rate and channel counts
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub output_channels: u16,
+    // Device format and sizes as requested; None leaves them to the device
+    pub requested_sample_rate: Option<u32>,
+    pub requested_channels: Option<u16>,
+    // Input channel processed alone, counted from 0
+    pub input_channel: Option<u16>,
+    pub buffer_frames: Option<u32>,
+    pub buffer_ms: f32,
+    pub playout_target_ms: f32,
+    pub quality_profile: Option<QualityProfile>,
+    pub virtual_output: bool,
+    pub settings: ProcessorSettings,
+    pub is_processing: bool,
+    pub bypassed: bool,
+    pub muted: bool,
+    pub latency: LatencyBreakdown,
+    pub processing_load: f32,
+    pub underruns: u64,
+    pub sanitized_samples: u64,
+    // Safety ceiling in dBFS, None when off, and the samples it clamped
+    pub safety_ceiling_db: Option<f32>,
+    pub safety_clamps: u64,
+    // Latest errors, oldest first
+    pub recent_errors: Vec<String>,
+}
+
+// Replaces the home directory in `text` with "~", so paths don't reveal the
+// user name
+pub(crate) fn mask_home(text: &str) -> String {
+    let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+    match home {
+        Ok(home) if home.len() > 1 => text.replace(&home, "~"),
+        _ => text.to_string(),
+    }
+}
+
+fn optional<T: fmt::Display>(value: &Option<T>, none: &str) -> String {
+    value.as_ref().map_or_else(|| none.to_string(), T::to_string)
+}
+
+fn format(format: &Option<StreamFormat>, period_frames: u32) -> String {
+    match format {
+        Some(format) => {
+            let period = if period_frames > 0 { format!(", {} frames per callback", period_frames) } else { String::new() };
+            format!("{} Hz, {} ch, {}{}", format.sample_rate, format.channels, format.sample_format, period)
+        }
+        None => "not running".to_string(),
+    }
+}
+
+// Plain text for pasting into an issue; settings in the TOML of presets
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "CancelCaster {} on {}", self.version, self.os)?;
+        writeln!(f, "Audio backend: {}", self.host)?;
+        writeln!(f, "Input device: {}", optional(&self.input_device, "none"))?;
+        writeln!(f, "  Format: {}", format(&self.input_format, self.latency.input_period_frames))?;
+        writeln!(f, "Output device: {}", optional(&self.output_device, "none"))?;
+        writeln!(f, "  Format: {}", format(&self.output_format, self.latency.output_period_frames))?;
+        writeln!(f, "Loopback device: {}", optional(&self.loopback_device, "default"))?;
+        writeln!(f, "Monitor device: {}", optional(&self.monitor_device, "off"))?;
+        writeln!(f, "Virtual output: {}", self.virtual_output)?;
+        match self.input_channel {
+            Some(channel) => writeln!(f, "Input channel: {}", channel + 1)?,
+            None => writeln!(f, "Input channel: all")?,
+        }
+        writeln!(
+            f,
+            "Processing: {} Hz, {} ch in, {} ch out, FFT size {} per channel",
+            self.sample_rate, self.channels, self.output_channels, self.settings.fft_size,
+        )?;
+        writeln!(
+            f,
+            "Requested: sample rate {}, channels {}, device buffer {}",
+            optional(&self.requested_sample_rate, "device default"),
+            optional(&self.requested_channels, "device default"),
+            optional(&self.buffer_frames, "device default"),
+        )?;
+        writeln!(
+            f,
+            "Buffers: {} ms, output target {} ms, quality profile {}",
+            self.buffer_ms,
+            self.playout_target_ms,
+            self.quality_profile.map_or("custom", |profile| profile.label()),
+        )?;
+        writeln!(f, "Running: {}, bypassed: {}, muted: {}", self.is_processing, self.bypassed, self.muted)?;
+        writeln!(
+            f,
+            "Latency: {:.1} ms (input device {:.1}, input buffer {:.1}, processing {:.1}, output buffer {:.1}, output device {:.1})",
+            self.latency.total_ms(),
+            self.latency.input_device_ms,
+            self.latency.input_buffer_ms,
+            self.latency.processing_ms,
+            self.latency.output_buffer_ms,
+            self.latency.output_device_ms,
+        )?;
+        writeln!(f, "Processing load: {:.3} of realtime", self.processing_load)?;
+        writeln!(f, "Output dropouts: {}", self.underruns)?;
+        writeln!(f, "Non-finite samples replaced: {}", self.sanitized_samples)?;
+        match self.safety_ceiling_db {
+            Some(ceiling_db) => writeln!(f, "Safety ceiling: {} dBFS, {} samples clamped", ceiling_db, self.safety_clamps)?,
+            None => writeln!(f, "Safety ceiling: off")?,
+        }
+        writeln!(f)?;
+        writeln!(f, "Settings:")?;
+        write!(f, "{}", self.settings.to_table())?;
+        writeln!(f)?;
+        if self.recent_errors.is_empty() {
+            writeln!(f, "Recent errors: none")
+        } else {
+            writeln!(f, "Recent errors:")?;
+            self.recent_errors.iter().try_for_each(|error| writeln!(f, "  {}", error))
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// Automatic gain control that steers the output's RMS level toward a target.
+// Loudness is a slow RMS envelope of the signal entering the stage, and the
+// gain follows it smoothly: quickly when it has to come down, slowly when it
+// may go up, so speech is never pumped and a sudden loud passage is reined
+// in fast. Gain is capped, and frozen while the input is near silence, so
+// pauses are not amplified into audible noise.
+
+pub const DEFAULT_AGC_TARGET_DB: f32 = -18.0;
+pub const DEFAULT_AGC_MAX_GAIN_DB: f32 = 20.0;
+
+// Averaging time of the loudness estimate
+const ENVELOPE_MS: f32 = 300.0;
+// Gain smoothing when reducing and when increasing
+const ATTACK_MS: f32 = 50.0;
+const RELEASE_MS: f32 = 1000.0;
+// Input below this level is treated as silence and leaves the gain alone
+const SILENCE_DB: f32 = -55.0;
+
+fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+// One-pole smoothing coefficient reaching ~63% of a step after `ms`
+fn smoothing_coef(ms: f32, samples_per_second: f32) -> f32 {
+    let samples = ms / 1000.0 * samples_per_second;
+    if samples <= 1.0 {
+        1.0
+    } else {
+        1.0 - (-1.0 / samples).exp()
+    }
+}
+
+#[derive(Clone)]
+pub struct AutomaticGainControl {
+    target_power: f32,
+    max_gain: f32,
+    envelope_coef: f32,
+    attack_coef: f32,
+    release_coef: f32,
+    // Mean square of the incoming signal
+    envelope: f32,
+    gain: f32,
+}
+
+impl AutomaticGainControl {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        // Interleaved samples per second, so times apply to the whole stream
+        let samples_per_second = sample_rate.max(1) as f32 * channels.max(1) as f32;
+        let mut agc = Self {
+            target_power: 0.0,
+            max_gain: 1.0,
+            envelope_coef: smoothing_coef(ENVELOPE_MS, samples_per_second),
+            attack_coef: smoothing_coef(ATTACK_MS, samples_per_second),
+            release_coef: smoothing_coef(RELEASE_MS, samples_per_second),
+            envelope: 0.0,
+            gain: 1.0,
+        };
+        agc.set_target_db(DEFAULT_AGC_TARGET_DB);
+        agc.set_max_gain_db(DEFAULT_AGC_MAX_GAIN_DB);
+        agc
+    }
+
+    // RMS level in dBFS the output is steered toward
+    pub fn set_target_db(&mut self, target_db: f32) {
+        self.target_power = 10f32.powf(target_db.min(0.0) / 10.0);
+    }
+
+    // Upper bound on the applied gain
+    pub fn set_max_gain_db(&mut self, max_gain_db: f32) {
+        self.max_gain = db_to_linear(max_gain_db.max(0.0));
+    }
+
+    // Gain applied to the last processed sample, in dB
+    pub fn gain_db(&self) -> f32 {
+        20.0 * self.gain.log10()
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        let silence_power = 10f32.powf(SILENCE_DB / 10.0);
+        for sample in samples {
+            self.envelope += self.envelope_coef * (*sample * *sample - self.envelope);
+
+            if self.envelope > silence_power {
+                let wanted = (self.target_power / self.envelope).sqrt().min(self.max_gain);
+                let coef = if wanted < self.gain { self.attack_coef } else { self.release_coef };
+                self.gain += coef * (wanted - self.gain);
+            }
+            *sample *= self.gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const RATE: u32 = 48000;
+
+    // Seconds of a 440 Hz sine at `rms_db` through a fresh AGC
+    fn run(rms_db: f32, seconds: usize) -> Vec<f32> {
+        let amplitude = db_to_linear(rms_db) * 2f32.sqrt();
+        let mut samples: Vec<f32> = (0..RATE as usize * seconds)
+            .map(|n| amplitude * (2.0 * PI * 440.0 * n as f32 / RATE as f32).sin())
+            .collect();
+        let mut agc = AutomaticGainControl::new(RATE, 1);
+        for block in samples.chunks_mut(480) {
+            agc.process(block);
+        }
+        samples
+    }
+
+    // RMS of each 100 ms window, in dBFS
+    fn window_levels(samples: &[f32]) -> Vec<f32> {
+        samples.chunks(RATE as usize / 10)
+            .map(|window| 10.0 * (window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32).log10())
+            .collect()
+    }
+
+    #[test]
+    fn quiet_signal_is_boosted_to_target_without_overshoot() {
+        let levels = window_levels(&run(-36.0, 10));
+        let settled = levels[levels.len() - 1];
+        assert!((settled - DEFAULT_AGC_TARGET_DB).abs() < 1.0, "settled at {:.1} dBFS", settled);
+        for (window, level) in levels.iter().enumerate() {
+            assert!(*level < DEFAULT_AGC_TARGET_DB + 0.5, "window {} overshot to {:.1} dBFS", window, level);
+        }
+        // Rising steadily rather than jumping
+        for pair in levels.windows(2).skip(1) {
+            assert!(pair[1] - pair[0] < 3.0, "jumped from {:.1} to {:.1} dBFS", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn gain_stops_at_the_cap() {
+        let levels = window_levels(&run(-50.0, 10));
+        let settled = levels[levels.len() - 1];
+        assert!((settled - (-50.0 + DEFAULT_AGC_MAX_GAIN_DB)).abs() < 0.5, "settled at {:.1} dBFS", settled);
+    }
+}
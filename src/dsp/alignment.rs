@@ -0,0 +1,140 @@
+// Bulk delay between the echo reference and its echo in the microphone. The
+// adaptive echo filter only spans a few milliseconds, while device buffers
+// and the speaker-to-mic path can add far more; delaying the reference by
+// the measured lag puts the echo back inside the filter's reach.
+//
+// The lag is found with a generalized cross-correlation with phase transform
+// (GCC-PHAT): the cross-spectrum is whitened before going back to the time
+// domain, which turns the broad correlation of speech and music into a sharp
+// peak at the delay.
+
+use rustfft::{num_complex::Complex, FftPlanner};
+
+// Longest reference delay accepted, in milliseconds
+pub const MAX_REFERENCE_DELAY_MS: f32 = 500.0;
+
+// Audio collected for a calibration, in seconds
+pub const CALIBRATION_SECS: f32 = 2.0;
+
+// Samples the applied delay is kept short of the estimate, so the direct
+// path stays inside the adaptive filter even if the estimate is a little late
+pub const DELAY_MARGIN: usize = 32;
+
+// Normalized correlation at the found lag below which the mic is taken not
+// to contain the reference at all
+const MIN_CORRELATION: f32 = 0.1;
+
+// Lag by which `mic` trails `reference`, both mono at the same rate, searched
+// from 0 to `max_delay` samples. None when the reference is silent or no
+// lag correlates well enough to be an echo.
+pub fn estimate_delay(mic: &[f32], reference: &[f32], max_delay: usize) -> Option<usize> {
+    let len = mic.len().min(reference.len());
+    if len == 0 {
+        return None;
+    }
+    let size = (2 * len).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(size);
+    let ifft = planner.plan_fft_inverse(size);
+
+    let spectrum = |signal: &[f32]| {
+        let mut bins: Vec<Complex<f32>> = signal[..len].iter().map(|&s| Complex::new(s, 0.0)).collect();
+        bins.resize(size, Complex::new(0.0, 0.0));
+        fft.process(&mut bins);
+        bins
+    };
+    let mic_spectrum = spectrum(mic);
+    let mut cross = spectrum(reference);
+    for (bin, mic) in cross.iter_mut().zip(&mic_spectrum) {
+        let product = mic * bin.conj();
+        let magnitude = product.norm();
+        *bin = if magnitude > f32::MIN_POSITIVE { product / magnitude } else { Complex::new(0.0, 0.0) };
+    }
+    ifft.process(&mut cross);
+
+    let max_delay = max_delay.min(len - 1);
+    let delay = (0..=max_delay).max_by(|&a, &b| cross[a].re.total_cmp(&cross[b].re))?;
+
+    // The whitened peak says where, plain correlation says whether
+    let (mic, reference) = (&mic[delay..len], &reference[..len - delay]);
+    let product: f32 = mic.iter().zip(reference).map(|(m, r)| m * r).sum();
+    let energy = mic.iter().map(|m| m * m).sum::<f32>() * reference.iter().map(|r| r * r).sum::<f32>();
+    if energy <= f32::MIN_POSITIVE || product / energy.sqrt() < MIN_CORRELATION {
+        return None;
+    }
+    Some(delay)
+}
+
+// Mono mic and reference audio collected for `estimate_delay` while the
+// processing task runs
+pub struct DelayCalibration {
+    mic: Vec<f32>,
+    reference: Vec<f32>,
+    length: usize,
+}
+
+impl DelayCalibration {
+    // Collects `length` samples of each signal
+    pub fn new(length: usize) -> Self {
+        Self { mic: Vec::with_capacity(length), reference: Vec::with_capacity(length), length }
+    }
+
+    // Adds interleaved blocks of `channels` channels, mixed down to mono
+    pub fn push(&mut self, mic: &[f32], reference: &[f32], channels: u16) {
+        let channels = channels.max(1) as usize;
+        for (signal, block) in [(&mut self.mic, mic), (&mut self.reference, reference)] {
+            let room = self.length - signal.len();
+            signal.extend(block.chunks_exact(channels).take(room).map(|frame| frame.iter().sum::<f32>()));
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.mic.len() >= self.length && self.reference.len() >= self.length
+    }
+
+    pub fn estimate(&self, max_delay: usize) -> Option<usize> {
+        estimate_delay(&self.mic, &self.reference, max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Uniform white noise from a fixed seed
+    fn noise(len: usize, mut seed: u32) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                seed as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_delay_of_a_noisy_echo() {
+        // 120 ms of device and room delay at 48 kHz, the echo at a third of
+        // the level under room noise of its own
+        let delay = 5760;
+        let reference = noise(96000, 1);
+        let room = noise(96000, 2);
+        let mic: Vec<f32> = (0..reference.len())
+            .map(|n| 0.1 * room[n] + if n >= delay { 0.3 * reference[n - delay] } else { 0.0 })
+            .collect();
+
+        let mut calibration = DelayCalibration::new(reference.len());
+        for (mic, reference) in mic.chunks(960).zip(reference.chunks(960)) {
+            calibration.push(mic, reference, 1);
+        }
+        assert!(calibration.is_complete());
+        assert_eq!(calibration.estimate(24000), Some(delay));
+    }
+
+    #[test]
+    fn unrelated_mic_has_no_delay() {
+        let reference = noise(48000, 3);
+        let mic = noise(48000, 4);
+        assert_eq!(estimate_delay(&mic, &reference, 24000), None);
+        assert_eq!(estimate_delay(&mic, &vec![0.0; 48000], 24000), None);
+    }
+}
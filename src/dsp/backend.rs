@@ -0,0 +1,57 @@
+// Which implementation of echo cancellation, noise suppression and gain
+// control processes the microphone. The built-in chain is always there; the
+// WebRTC audio processing module (AEC3, its noise suppressor and AGC2) only
+// in builds with the `webrtc-apm` feature, as it links a native library.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProcessingBackend {
+    #[default]
+    Native,
+    // Hands 10 ms frames to the WebRTC module in place of the built-in echo
+    // canceller, high-pass, spectral stages and AGC. The gate, expander,
+    // compressor and comfort noise don't run; mix, output gain and the
+    // limiter do.
+    WebRtc,
+}
+
+// Rates the WebRTC module processes at
+pub const WEBRTC_SAMPLE_RATES: [u32; 4] = [8000, 16000, 32000, 48000];
+
+impl ProcessingBackend {
+    pub const ALL: [Self; 2] = [Self::Native, Self::WebRtc];
+
+    // Name used in config files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Native => "native",
+            Self::WebRtc => "webrtc",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|backend| backend.as_str() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Native => "Built-in",
+            Self::WebRtc => "WebRTC",
+        }
+    }
+
+    // Whether this build includes the backend
+    pub fn is_available(self) -> bool {
+        match self {
+            Self::Native => true,
+            Self::WebRtc => cfg!(feature = "webrtc-apm"),
+        }
+    }
+
+    // Whether the backend can process audio at `sample_rate`
+    pub fn supports_sample_rate(self, sample_rate: u32) -> bool {
+        match self {
+            Self::Native => true,
+            Self::WebRtc => WEBRTC_SAMPLE_RATES.contains(&sample_rate),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+// Guided microphone calibration. A few seconds of silence give the noise
+// floor (and, through noise learning, the noise profile); a few seconds of
+// normal speech give its peak level. From the two follow an input gain that
+// puts speech peaks near the target and a gate threshold just above the
+// amplified noise floor.
+
+use crate::dsp::gain::{MAX_GAIN_DB, MIN_GAIN_DB};
+use crate::dsp::noise::DEFAULT_NOISE_LEARNING_MS;
+use crate::errors::AudioError;
+
+// Level speech peaks are brought to, in dBFS
+pub const TARGET_SPEECH_PEAK_DB: f32 = -12.0;
+
+// Length of the two steps, in milliseconds. The silence step matches noise
+// learning, which runs alongside it.
+pub const SILENCE_MS: u32 = DEFAULT_NOISE_LEARNING_MS;
+pub const SPEECH_MS: u32 = 5000;
+
+// Windows the levels are measured over
+const LEVEL_WINDOW_MS: usize = 50;
+
+// Share of the speech windows whose peak stays below the one taken as the
+// speech peak; the loudest few are plosives and bumps, not the voice
+const SPEECH_PEAK_PERCENTILE: f32 = 0.95;
+
+// Gap between the amplified noise floor and the gate threshold
+const GATE_MARGIN_DB: f32 = 6.0;
+
+// Speech peaks closer than this to the noise floor mean the user didn't
+// speak, or spoke during the silence step
+const MIN_SPEECH_TO_NOISE_DB: f32 = 20.0;
+
+// Level of a silent window, so logs and thresholds stay finite
+const SILENCE_DB: f32 = -120.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationStep {
+    Silence,
+    Speech,
+}
+
+impl CalibrationStep {
+    // What the user should do during the step
+    pub fn instruction(&self) -> &'static str {
+        match self {
+            CalibrationStep::Silence => "Stay quiet...",
+            CalibrationStep::Speech => "Speak normally...",
+        }
+    }
+}
+
+/// Levels measured by a calibration and the settings derived from them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationResult {
+    /// Median level of the silence, in dBFS before any gain
+    pub noise_floor_db: f32,
+    /// Typical speech peak, in dBFS before any gain
+    pub speech_peak_db: f32,
+    /// Input gain bringing the speech peak to the target
+    pub input_gain_db: f32,
+    /// Gate threshold just above the noise floor after the new gain
+    pub gate_threshold_db: f32,
+}
+
+fn to_db(level: f32) -> f32 {
+    if level > 0.0 {
+        (20.0 * level.log10()).max(SILENCE_DB)
+    } else {
+        SILENCE_DB
+    }
+}
+
+// RMS and peak of each window of mono `samples`, in dBFS
+fn window_levels(samples: &[f32], sample_rate: u32) -> (Vec<f32>, Vec<f32>) {
+    let window = (sample_rate as usize * LEVEL_WINDOW_MS / 1000).max(1);
+    samples
+        .chunks(window)
+        .map(|chunk| {
+            let rms = (chunk.iter().map(|s| s * s).sum::<f32>() / chunk.len() as f32).sqrt();
+            let peak = chunk.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+            (to_db(rms), to_db(peak))
+        })
+        .unzip()
+}
+
+fn percentile(mut values: Vec<f32>, share: f32) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f32::total_cmp);
+    let index = ((values.len() - 1) as f32 * share).round() as usize;
+    Some(values[index])
+}
+
+/// Derives the input gain and gate threshold from mono recordings of the
+/// microphone in silence and during normal speech, both taken before any
+/// input gain. Fails when either is empty or the speech isn't clearly
+/// louder than the silence.
+pub fn run_calibration(silence: &[f32], speech: &[f32], sample_rate: u32) -> Result<CalibrationResult, AudioError> {
+    let (silence_rms, _) = window_levels(silence, sample_rate);
+    let (_, speech_peaks) = window_levels(speech, sample_rate);
+    let (Some(noise_floor_db), Some(speech_peak_db)) =
+        (percentile(silence_rms, 0.5), percentile(speech_peaks, SPEECH_PEAK_PERCENTILE))
+    else {
+        return Err(AudioError::InvalidState("No microphone audio was recorded during calibration".to_string()));
+    };
+
+    if speech_peak_db - noise_floor_db < MIN_SPEECH_TO_NOISE_DB {
+        return Err(AudioError::InvalidSetting(format!(
+            "Speech ({:.0} dBFS) was too close to the background noise ({:.0} dBFS); stay quiet during the first step and speak up during the second",
+            speech_peak_db, noise_floor_db,
+        )));
+    }
+
+    let input_gain_db = (TARGET_SPEECH_PEAK_DB - speech_peak_db).clamp(MIN_GAIN_DB, MAX_GAIN_DB);
+    let gate_threshold_db = (noise_floor_db + input_gain_db + GATE_MARGIN_DB).min(0.0);
+    Ok(CalibrationResult { noise_floor_db, speech_peak_db, input_gain_db, gate_threshold_db })
+}
+
+// Mono microphone audio collected for `run_calibration` while the processing
+// task runs, first the silence, then the speech
+pub struct MicCalibration {
+    silence: Vec<f32>,
+    speech: Vec<f32>,
+    silence_length: usize,
+    speech_length: usize,
+    sample_rate: u32,
+}
+
+impl MicCalibration {
+    pub fn new(sample_rate: u32) -> Self {
+        let length = |ms: u32| sample_rate as usize * ms as usize / 1000;
+        let (silence_length, speech_length) = (length(SILENCE_MS), length(SPEECH_MS));
+        Self {
+            silence: Vec::with_capacity(silence_length),
+            speech: Vec::with_capacity(speech_length),
+            silence_length,
+            speech_length,
+            sample_rate,
+        }
+    }
+
+    // Adds an interleaved block of `channels` channels. Each frame keeps its
+    // loudest channel, so a mic on one channel of several isn't diluted.
+    pub fn push(&mut self, mic: &[f32], channels: u16) {
+        let channels = channels.max(1) as usize;
+        for frame in mic.chunks_exact(channels) {
+            let sample = frame.iter().copied().fold(0.0f32, |loudest, s| if s.abs() > loudest.abs() { s } else { loudest });
+            if self.silence.len() < self.silence_length {
+                self.silence.push(sample);
+            } else if self.speech.len() < self.speech_length {
+                self.speech.push(sample);
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn step(&self) -> CalibrationStep {
+        if self.silence.len() < self.silence_length {
+            CalibrationStep::Silence
+        } else {
+            CalibrationStep::Speech
+        }
+    }
+
+    // Share of the current step done, 0 to 1
+    pub fn progress(&self) -> f32 {
+        match self.step() {
+            CalibrationStep::Silence => self.silence.len() as f32 / self.silence_length.max(1) as f32,
+            CalibrationStep::Speech => self.speech.len() as f32 / self.speech_length.max(1) as f32,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.silence.len() >= self.silence_length && self.speech.len() >= self.speech_length
+    }
+
+    pub fn result(&self) -> Result<CalibrationResult, AudioError> {
+        run_calibration(&self.silence, &self.speech, self.sample_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const RATE: u32 = 48000;
+
+    // Uniform white noise at `rms`
+    fn room(rms: f32, len: usize, mut seed: u32) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed as f32 / u32::MAX as f32 * 2.0 - 1.0) * rms * 3f32.sqrt()
+            })
+            .collect()
+    }
+
+    // Syllables of a 150 Hz voice peaking at `peak`, 200 ms on and 100 ms
+    // off, over the room noise
+    fn speech(peak: f32, len: usize) -> Vec<f32> {
+        let syllable = RATE as usize / 5;
+        room(0.001, len, 2)
+            .iter()
+            .enumerate()
+            .map(|(n, noise)| {
+                let voiced = n % (syllable * 3 / 2) < syllable;
+                let voice = (2.0 * PI * 150.0 * n as f32 / RATE as f32).sin()
+                    + 0.5 * (2.0 * PI * 300.0 * n as f32 / RATE as f32).sin();
+                noise + if voiced { peak / 1.3 * voice } else { 0.0 }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn quiet_room_and_speech_give_gain_and_gate() {
+        let silence_len = (RATE * SILENCE_MS / 1000) as usize;
+        let speech_len = (RATE * SPEECH_MS / 1000) as usize;
+        // A -60 dBFS room, and speech peaking at -24 dBFS
+        let mic: Vec<f32> = [room(0.001, silence_len, 1), speech(0.063, speech_len)].concat();
+
+        // The mic on the right channel of a stereo device
+        let mut calibration = MicCalibration::new(RATE);
+        assert_eq!(calibration.step(), CalibrationStep::Silence);
+        for (n, block) in mic.chunks(480).enumerate() {
+            let stereo: Vec<f32> = block.iter().flat_map(|&s| [0.0, s]).collect();
+            calibration.push(&stereo, 2);
+            let expected = if (n + 1) * 480 < silence_len { CalibrationStep::Silence } else { CalibrationStep::Speech };
+            assert_eq!(calibration.step(), expected, "after {} samples", (n + 1) * 480);
+        }
+        assert!(calibration.is_complete());
+
+        let result = calibration.result().unwrap();
+        assert!((result.noise_floor_db + 60.0).abs() < 1.0, "noise floor {}", result.noise_floor_db);
+        assert!((result.speech_peak_db + 24.0).abs() < 1.0, "speech peak {}", result.speech_peak_db);
+        assert!((result.input_gain_db - 12.0).abs() < 1.0, "input gain {}", result.input_gain_db);
+        let gated_noise_db = result.noise_floor_db + result.input_gain_db;
+        assert!((result.gate_threshold_db - gated_noise_db - GATE_MARGIN_DB).abs() < 1e-4);
+        assert!(result.gate_threshold_db < TARGET_SPEECH_PEAK_DB - 20.0, "gate at {}", result.gate_threshold_db);
+    }
+
+    #[test]
+    fn staying_quiet_throughout_is_refused() {
+        let quiet = room(0.001, RATE as usize, 3);
+        assert!(run_calibration(&quiet, &quiet, RATE).is_err());
+        assert!(run_calibration(&[], &quiet, RATE).is_err());
+    }
+}
@@ -0,0 +1,386 @@
+// Channel mapping between the processing format, which follows the input
+// device, and whatever channel count the output device runs at.
+//
+// The mapping is deliberately simple, since the processed signal is usually
+// a single voice:
+// - mono to N channels copies the mono signal to every channel;
+// - N channels to mono averages the channels, so a signal present on both
+//   sides of a stereo mic keeps its level;
+// - any other pair maps output channel c to input channel c % from, which
+//   repeats the input channels to fill wider layouts and drops the extra
+//   ones for narrower layouts.
+//
+// Anything else, e.g. processing only the first input of a multichannel
+// interface or sending the voice to a specific pair of outputs, is an
+// explicit `ChannelMap`. Mono going to a stereo (or wider) output can instead
+// be widened on its first two channels, see `MonoToStereoMode`.
+
+use anyhow::Result;
+
+pub const DEFAULT_STEREO_WIDTH: f32 = 0.3;
+pub const MAX_STEREO_WIDTH: f32 = 1.0;
+pub const DEFAULT_HAAS_DELAY_MS: f32 = 12.0;
+pub const MIN_HAAS_DELAY_MS: f32 = 1.0;
+pub const MAX_HAAS_DELAY_MS: f32 = 30.0;
+
+// How a mono signal fills a stereo output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MonoToStereoMode {
+    // The same signal on both sides, heard in the middle
+    #[default]
+    Center,
+    // Haas-style spread. A copy delayed by a few milliseconds is added to
+    // the left channel and subtracted from the right, so the sides differ,
+    // which is heard as width, while their sum is exactly the mono signal:
+    // downmixing to mono never cancels anything. Both sides are scaled by
+    // 1 / (1 + width), so neither peaks above the mono signal and the
+    // limiter's ceiling still holds.
+    Wide,
+}
+
+impl MonoToStereoMode {
+    pub const ALL: [Self; 2] = [Self::Center, Self::Wide];
+
+    // Name used in config files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Center => "center",
+            Self::Wide => "wide",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.as_str() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Center => "Center (Dual Mono)",
+            Self::Wide => "Wide",
+        }
+    }
+}
+
+// State of the `Wide` mode: the last `delay` mono samples
+struct Widener {
+    width: f32,
+    history: Vec<f32>,
+    position: usize,
+}
+
+impl Widener {
+    fn new() -> Self {
+        Self { width: 0.0, history: Vec::new(), position: 0 }
+    }
+
+    // A new delay starts the history over from silence
+    fn configure(&mut self, width: f32, delay_frames: usize) {
+        self.width = width.clamp(0.0, MAX_STEREO_WIDTH);
+        let delay_frames = delay_frames.max(1);
+        if self.history.len() != delay_frames {
+            self.history = vec![0.0; delay_frames];
+            self.position = 0;
+        }
+    }
+
+    // Writes mono `input` to `output` with `to` (at least 2) channels: the
+    // first two widened, any others centered. Returns the frames written.
+    fn process(&mut self, input: &[f32], output: &mut [f32], to: usize) -> usize {
+        let frames = input.len().min(output.len() / to);
+        let scale = 1.0 / (1.0 + self.width);
+        for (&sample, out_frame) in input.iter().zip(output.chunks_exact_mut(to)) {
+            let side = self.width * self.history[self.position];
+            self.history[self.position] = sample;
+            self.position = (self.position + 1) % self.history.len();
+            out_frame[0] = (sample + side) * scale;
+            out_frame[1] = (sample - side) * scale;
+            out_frame[2..].fill(sample);
+        }
+        frames
+    }
+}
+
+// Maps interleaved `input` with `from` channels into `output` with `to`
+// channels and returns the number of frames written, which is bounded by
+// whichever buffer holds fewer whole frames
+pub fn remix(input: &[f32], from: usize, output: &mut [f32], to: usize) -> usize {
+    let (from, to) = (from.max(1), to.max(1));
+    let frames = (input.len() / from).min(output.len() / to);
+
+    for (in_frame, out_frame) in input.chunks_exact(from)
+        .zip(output.chunks_exact_mut(to))
+        .take(frames)
+    {
+        if from == to {
+            out_frame.copy_from_slice(in_frame);
+        } else if to == 1 {
+            out_frame[0] = in_frame.iter().sum::<f32>() / from as f32;
+        } else if from == 1 {
+            out_frame.fill(in_frame[0]);
+        } else {
+            for (channel, sample) in out_frame.iter_mut().enumerate() {
+                *sample = in_frame[channel % from];
+            }
+        }
+    }
+    frames
+}
+
+/// Routing matrix from `inputs` interleaved channels to `outputs`: output
+/// channel `o` is the sum of every input channel `i` times `gain(o, i)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelMap {
+    inputs: usize,
+    outputs: usize,
+    // Row per output channel
+    gains: Vec<f32>,
+}
+
+impl ChannelMap {
+    // A map that routes nothing yet; fill it in with `set`
+    pub fn new(inputs: usize, outputs: usize) -> Self {
+        let (inputs, outputs) = (inputs.max(1), outputs.max(1));
+        Self { inputs, outputs, gains: vec![0.0; inputs * outputs] }
+    }
+
+    // The mapping `remix` applies
+    pub fn default_for(inputs: usize, outputs: usize) -> Self {
+        let mut map = Self::new(inputs, outputs);
+        let (inputs, outputs) = (map.inputs, map.outputs);
+        for output in 0..outputs {
+            if outputs == 1 {
+                map.gains[..inputs].fill(1.0 / inputs as f32);
+            } else if inputs == 1 {
+                map.gains[output] = 1.0;
+            } else {
+                map.gains[output * inputs + output % inputs] = 1.0;
+            }
+        }
+        map
+    }
+
+    // Output channel `n` carries input channel `channels[n]`, counted from
+    // 0; e.g. `select(4, &[0])` takes only the first of four inputs, as mono
+    pub fn select(inputs: usize, channels: &[usize]) -> Result<Self> {
+        if channels.is_empty() {
+            anyhow::bail!("A channel map needs at least one output channel");
+        }
+        let mut map = Self::new(inputs, channels.len());
+        for (output, &input) in channels.iter().enumerate() {
+            map.set(output, input, 1.0)?;
+        }
+        Ok(map)
+    }
+
+    pub fn set(&mut self, output: usize, input: usize, gain: f32) -> Result<()> {
+        if output >= self.outputs || input >= self.inputs {
+            anyhow::bail!(
+                "Channel {} -> {} is outside a {}-in, {}-out map",
+                input, output, self.inputs, self.outputs
+            );
+        }
+        self.gains[output * self.inputs + input] = gain;
+        Ok(())
+    }
+
+    pub fn gain(&self, output: usize, input: usize) -> f32 {
+        if output < self.outputs && input < self.inputs {
+            self.gains[output * self.inputs + input]
+        } else {
+            0.0
+        }
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    // Like `remix`, with this map's routing
+    pub fn apply(&self, input: &[f32], output: &mut [f32]) -> usize {
+        let frames = (input.len() / self.inputs).min(output.len() / self.outputs);
+        for (in_frame, out_frame) in input.chunks_exact(self.inputs)
+            .zip(output.chunks_exact_mut(self.outputs))
+            .take(frames)
+        {
+            for (sample, gains) in out_frame.iter_mut().zip(self.gains.chunks_exact(self.inputs)) {
+                *sample = in_frame.iter().zip(gains).map(|(s, g)| s * g).sum();
+            }
+        }
+        frames
+    }
+}
+
+// Remixes inside the stream callbacks, keeping a scratch buffer so a
+// callback doesn't allocate once it has seen its largest buffer
+pub struct ChannelMapper {
+    from: usize,
+    to: usize,
+    // None for the default mapping, which `remix` applies directly
+    map: Option<ChannelMap>,
+    // Upmix of mono to stereo under the default mapping
+    stereo: MonoToStereoMode,
+    widener: Widener,
+    scratch: Vec<f32>,
+}
+
+impl ChannelMapper {
+    pub fn new(from: u16, to: u16) -> Self {
+        Self {
+            from: from.max(1) as usize,
+            to: to.max(1) as usize,
+            map: None,
+            stereo: MonoToStereoMode::default(),
+            widener: Widener::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn with_map(map: ChannelMap) -> Self {
+        Self { from: map.inputs, to: map.outputs, map: Some(map), ..Self::new(1, 1) }
+    }
+
+    // How mono input fills a stereo output. `delay_frames` is the Haas delay
+    // of the `Wide` mode at the output rate. Has no effect with a custom map
+    // or unless going from mono to two or more channels.
+    pub fn set_stereo(&mut self, mode: MonoToStereoMode, width: f32, delay_frames: usize) {
+        self.stereo = mode;
+        self.widener.configure(width, delay_frames);
+    }
+
+    fn remix(&mut self, input: &[f32], output: &mut [f32]) -> usize {
+        match &self.map {
+            Some(map) => map.apply(input, output),
+            None if self.from == 1 && self.to >= 2 && self.stereo == MonoToStereoMode::Wide => {
+                self.widener.process(input, output, self.to)
+            }
+            None => remix(input, self.from, output, self.to),
+        }
+    }
+
+    // Remaps captured `input` and returns the result, valid until the next call
+    pub fn map<'a>(&'a mut self, input: &'a [f32]) -> &'a [f32] {
+        if self.map.is_none() && self.from == self.to {
+            return input;
+        }
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.resize(input.len() / self.from * self.to, 0.0);
+        let frames = self.remix(input, &mut scratch);
+        scratch.truncate(frames * self.to);
+        self.scratch = scratch;
+        &self.scratch
+    }
+
+    // Fills `output` from `read`, which is handed a buffer of input-format
+    // samples and returns how many it filled. Whatever `read` can't supply
+    // is silence.
+    pub fn fill(&mut self, output: &mut [f32], mut read: impl FnMut(&mut [f32]) -> usize) {
+        if self.map.is_none() && self.from == self.to {
+            let filled = read(output);
+            output[filled..].fill(0.0);
+            return;
+        }
+
+        let frames = output.len() / self.to;
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.resize(frames * self.from, 0.0);
+        let filled = read(&mut scratch);
+        let written = self.remix(&scratch[..filled], output);
+        output[written * self.to..].fill(0.0);
+        self.scratch = scratch;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mono_to_stereo_duplicates() {
+        let mono = [0.1, -0.2, 0.3];
+        let mut stereo = [0.0; 6];
+        assert_eq!(remix(&mono, 1, &mut stereo, 2), 3);
+        assert_eq!(stereo, [0.1, 0.1, -0.2, -0.2, 0.3, 0.3]);
+    }
+
+    #[test]
+    fn stereo_to_mono_averages() {
+        let stereo = [0.2, 0.4, -0.5, 0.5, 1.0, 1.0];
+        let mut mono = [0.0; 3];
+        assert_eq!(remix(&stereo, 2, &mut mono, 1), 3);
+        assert_eq!(mono, [0.3, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn default_map_matches_remix() {
+        let input = [0.2, 0.4, -0.5, 0.5];
+        for (from, to) in [(1, 2), (2, 1)] {
+            let mut remixed = [0.0; 4];
+            let mut mapped = [0.0; 4];
+            let frames = remix(&input, from, &mut remixed, to);
+            assert_eq!(ChannelMap::default_for(from, to).apply(&input, &mut mapped), frames);
+            assert_eq!(remixed, mapped);
+        }
+    }
+
+    // Two frames of four channels, each sample naming its frame and channel
+    const QUAD: [f32; 8] = [0.0, 0.1, 0.2, 0.3, 1.0, 1.1, 1.2, 1.3];
+
+    #[test]
+    fn four_channels_round_trip() {
+        let mut copied = [0.0; 8];
+        assert_eq!(remix(&QUAD, 4, &mut copied, 4), 2);
+        assert_eq!(copied, QUAD);
+
+        // Reversing the channels twice restores them
+        let reverse = ChannelMap::select(4, &[3, 2, 1, 0]).unwrap();
+        let (mut reversed, mut restored) = ([0.0; 8], [0.0; 8]);
+        assert_eq!(reverse.apply(&QUAD, &mut reversed), 2);
+        assert_eq!(reversed, [0.3, 0.2, 0.1, 0.0, 1.3, 1.2, 1.1, 1.0]);
+        reverse.apply(&reversed, &mut restored);
+        assert_eq!(restored, QUAD);
+    }
+
+    #[test]
+    fn select_takes_one_channel_of_four() {
+        let mut mono = [0.0; 2];
+        assert_eq!(ChannelMap::select(4, &[1]).unwrap().apply(&QUAD, &mut mono), 2);
+        assert_eq!(mono, [0.1, 1.1]);
+        assert!(ChannelMap::select(4, &[4]).is_err());
+    }
+
+    #[test]
+    fn center_is_dual_mono_and_wide_sums_back_to_mono() {
+        let mut seed = 1u32;
+        let mono: Vec<f32> = (0..4800)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                seed as f32 / u32::MAX as f32 * 2.0 - 1.0
+            })
+            .collect();
+
+        let mut center = ChannelMapper::new(1, 2);
+        let stereo: Vec<f32> = mono.chunks(480).flat_map(|block| center.map(block).to_vec()).collect();
+        for (frame, &sample) in stereo.chunks_exact(2).zip(&mono) {
+            assert_eq!(frame, [sample, sample]);
+        }
+
+        // Blocks carry the delay across, and downmixing gives back the mono
+        // signal at a fixed level, so no frequency is cancelled
+        let width = 0.5;
+        let mut wide = ChannelMapper::new(1, 2);
+        wide.set_stereo(MonoToStereoMode::Wide, width, 576);
+        let stereo: Vec<f32> = mono.chunks(480).flat_map(|block| wide.map(block).to_vec()).collect();
+        assert_eq!(stereo.len(), mono.len() * 2);
+        let scale = 1.0 / (1.0 + width);
+        for (n, (frame, &sample)) in stereo.chunks_exact(2).zip(&mono).enumerate() {
+            assert!((frame[0] + frame[1] - 2.0 * scale * sample).abs() < 1e-6, "downmix differs at {}", n);
+            assert!(frame[0].abs().max(frame[1].abs()) <= 1.0, "peak over full scale at {}", n);
+            let side = if n >= 576 { width * mono[n - 576] } else { 0.0 };
+            assert!((frame[0] - frame[1] - 2.0 * scale * side).abs() < 1e-6, "side differs at {}", n);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+// Suppressor for keyboard and mouse clicks. Like the de-esser it works on the
+// spectral frames of noise reduction. A click is broadband and sudden: within
+// one frame most bins of the upper spectrum jump far above their level in the
+// frames before, while speech onsets rise mainly in the harmonics of the
+// voice. Such a frame has every bin that rose pulled back down to its
+// expected level, which takes out the click and keeps the speech underneath.
+// A jump lasting longer than a click, e.g. a sibilant or a loud onset, stops
+// counting after a few frames and becomes the new level.
+
+use rustfft::num_complex::Complex;
+
+// Clicks are told apart from speech above this frequency, where voiced speech
+// has little energy
+pub const CLICK_LOW_HZ: f32 = 2000.0;
+
+// 0 only catches the sharpest clicks, 1 also softer ones at the risk of
+// dulling consonants
+pub const DEFAULT_CLICK_SENSITIVITY: f32 = 0.5;
+
+// Rise over the expected power, in dB, for a bin to count as jumping
+const JUMP_DB: f32 = 10.0;
+
+// Share of the bins above CLICK_LOW_HZ that must jump for a click, at the
+// lowest and highest sensitivity
+const LEAST_SENSITIVE_SHARE: f32 = 0.7;
+const MOST_SENSITIVE_SHARE: f32 = 0.2;
+
+// Consecutive frames a click may last before the jump counts as real signal
+const MAX_CLICK_FRAMES: usize = 3;
+
+// Share of the distance to a frame's power the expected power moves per frame
+const ADAPTATION: f32 = 0.5;
+
+// Frames quieter than this carry nothing worth detecting
+const SILENCE_POWER: f32 = 1e-10;
+
+#[derive(Clone)]
+struct ChannelClicks {
+    // Power per bin of the lower half, as the recent frames without clicks had it
+    expected: Vec<f32>,
+    // Frames in a row flagged as a click
+    click_frames: usize,
+}
+
+#[derive(Clone)]
+pub struct ClickSuppressor {
+    sample_rate: f32,
+    sensitivity: f32,
+    channels: Vec<ChannelClicks>,
+    // Frames treated as clicks so far
+    clicks: u64,
+}
+
+impl ClickSuppressor {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1) as f32,
+            sensitivity: DEFAULT_CLICK_SENSITIVITY,
+            channels: (0..channels.max(1)).map(|_| ChannelClicks { expected: Vec::new(), click_frames: 0 }).collect(),
+            clicks: 0,
+        }
+    }
+
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity.clamp(0.0, 1.0);
+    }
+
+    // Frames suppressed since the suppressor was created
+    pub fn clicks(&self) -> u64 {
+        self.clicks
+    }
+
+    // Suppresses a click in one FFT frame of `channel` in place
+    pub fn process(&mut self, channel: usize, spectrum: &mut [Complex<f32>]) {
+        let fft_size = spectrum.len();
+        let half = fft_size / 2;
+        let Some(state) = self.channels.get_mut(channel) else {
+            return;
+        };
+        // A new FFT size starts the expectation over from this frame
+        if state.expected.len() != half {
+            state.expected = spectrum[..half].iter().map(|bin| bin.norm_sqr()).collect();
+            state.click_frames = 0;
+            return;
+        }
+
+        let bin_hz = self.sample_rate / fft_size as f32;
+        let low = ((CLICK_LOW_HZ / bin_hz).ceil() as usize).clamp(1, half);
+        let jump = 10f32.powf(JUMP_DB / 10.0);
+        let total: f32 = spectrum[1..half].iter().map(|bin| bin.norm_sqr()).sum();
+        let jumped = spectrum[low..half].iter()
+            .zip(&state.expected[low..])
+            .filter(|(bin, &expected)| bin.norm_sqr() > expected * jump)
+            .count();
+        let required = LEAST_SENSITIVE_SHARE + (MOST_SENSITIVE_SHARE - LEAST_SENSITIVE_SHARE) * self.sensitivity;
+        let is_click = total > SILENCE_POWER
+            && half > low
+            && jumped as f32 >= required * (half - low) as f32
+            && state.click_frames < MAX_CLICK_FRAMES;
+
+        if !is_click {
+            state.click_frames = 0;
+            for (expected, bin) in state.expected.iter_mut().zip(&spectrum[..half]) {
+                *expected += (bin.norm_sqr() - *expected) * ADAPTATION;
+            }
+            return;
+        }
+
+        state.click_frames += 1;
+        if state.click_frames == 1 {
+            self.clicks += 1;
+        }
+        // Bins keep their phase and drop to the expected magnitude; the
+        // upper half mirrors the lower
+        for bin in 1..half {
+            let power = spectrum[bin].norm_sqr();
+            let expected = state.expected[bin];
+            if power > expected {
+                let gain = (expected / power).sqrt();
+                spectrum[bin] *= gain;
+                spectrum[fft_size - bin] *= gain;
+            }
+        }
+        let expected_dc = state.expected[0];
+        if spectrum[0].norm_sqr() > expected_dc {
+            spectrum[0] *= (expected_dc / spectrum[0].norm_sqr()).sqrt();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::spectral::{process_spectral, SpectralState, WindowType};
+    use rustfft::FftPlanner;
+    use std::f32::consts::PI;
+
+    const RATE: f32 = 48000.0;
+
+    // Voiced speech stand-in: a 150 Hz voice with harmonics falling off up
+    // to 1.5 kHz, as vowels have most of their energy there
+    fn voice(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|n| {
+                (1..=10)
+                    .map(|h| 0.1 / h as f32 * (2.0 * PI * 150.0 * h as f32 * n as f32 / RATE).sin())
+                    .sum()
+            })
+            .collect()
+    }
+
+    // A 2 ms burst of noise decaying from `amplitude`, like a key hitting
+    // bottom
+    fn click(amplitude: f32) -> Vec<f32> {
+        let mut seed = 5u32;
+        (0..96)
+            .map(|n| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (seed >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0;
+                amplitude * noise * (-(n as f32) / 24.0).exp()
+            })
+            .collect()
+    }
+
+    // Power below and above CLICK_LOW_HZ of the Hann-windowed 2048 samples
+    // starting at `start`
+    fn split_power(samples: &[f32], start: usize) -> (f32, f32) {
+        let size = 2048;
+        let window = WindowType::Hann.coefficients(size);
+        let mut spectrum: Vec<Complex<f32>> =
+            samples[start..start + size].iter().zip(&window).map(|(&s, &w)| Complex::new(s * w, 0.0)).collect();
+        FftPlanner::new().plan_fft_forward(size).process(&mut spectrum);
+        let low = (CLICK_LOW_HZ / (RATE / size as f32)) as usize;
+        let power = |bins: &[Complex<f32>]| bins.iter().map(|bin| bin.norm_sqr()).sum::<f32>();
+        (power(&spectrum[1..low]), power(&spectrum[low..size / 2]))
+    }
+
+    #[test]
+    fn click_is_removed_from_under_speech() {
+        let speech = voice(48000);
+        let at = 24000;
+        let mut input = speech.clone();
+        for (sample, click) in input[at..].iter_mut().zip(click(0.8)) {
+            *sample += click;
+        }
+
+        let mut state = SpectralState::new(1024, 1);
+        state.set_noise_reduction_enabled(false);
+        let mut suppressor = ClickSuppressor::new(RATE as u32, 1);
+        let mut output: Vec<f32> = input
+            .chunks(1024)
+            .flat_map(|chunk| process_spectral(chunk, &mut state, |channel, spectrum| suppressor.process(channel, spectrum)))
+            .collect();
+        output.drain(..state.latency());
+        assert_eq!(suppressor.clicks(), 1);
+
+        // Around the click its highs are gone...
+        let start = at - 1024;
+        let (_, clicked_highs) = split_power(&input, start);
+        let (output_lows, output_highs) = split_power(&output, start);
+        let removed_db = 10.0 * (clicked_highs / output_highs).log10();
+        assert!(removed_db > 20.0, "click only {:.1} dB down", removed_db);
+        // ...while the voice carries on at its level
+        let (speech_lows, _) = split_power(&speech, start);
+        let voice_db = 10.0 * (output_lows / speech_lows).log10();
+        assert!(voice_db.abs() < 1.0, "voice changed by {:.1} dB", voice_db);
+
+        // Away from the click the speech passes untouched
+        let untouched = |range: std::ops::Range<usize>| {
+            output[range.clone()].iter().zip(&speech[range]).all(|(out, speech)| (out - speech).abs() < 1e-4)
+        };
+        assert!(untouched(2048..at - 2048));
+        assert!(untouched(at + 4096..output.len().min(speech.len()) - 2048));
+    }
+}
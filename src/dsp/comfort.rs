@@ -0,0 +1,202 @@
+// Comfort noise: a faint background shaped like the estimated noise, played
+// where the gate or noise reduction would otherwise leave dead silence, which
+// listeners tend to mistake for a dropped call.
+//
+// Each frame takes the noise estimate's magnitude per bin with a random
+// phase, and the inverse FFTs are overlap-added at 50% under a square-root
+// Hann window, whose squares sum to one, so consecutive frames join without
+// any modulation of the level. The noise is generated per frame and the
+// same sample goes to every channel.
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+// Level relative to the estimated background noise. -20 dB matches what
+// spectral subtraction leaves at its default floor.
+pub const DEFAULT_COMFORT_NOISE_LEVEL_DB: f32 = -20.0;
+pub const MIN_COMFORT_NOISE_LEVEL_DB: f32 = -60.0;
+
+#[derive(Clone)]
+pub struct ComfortNoise {
+    channels: usize,
+    fft_size: usize,
+    hop_size: usize,
+    ifft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    // Turns a bin power measured under the analysis Hann window back into
+    // the time-domain level of the noise it came from
+    scale: f32,
+    level: f32,
+    spectrum: Vec<Complex<f32>>,
+    overlap: Vec<f32>,
+    // Generated samples not handed out yet
+    pending: Vec<f32>,
+    rng: u32,
+    // Share of comfort noise currently faded in by `fill_silence`
+    blend: f32,
+}
+
+impl ComfortNoise {
+    // `fft_size` is the frame length of the noise estimate of one channel
+    pub fn new(fft_size: usize, channels: u16) -> Self {
+        let fft_size = fft_size.max(2);
+        let hop_size = fft_size / 2;
+        let ifft = FftPlanner::new().plan_fft_inverse(fft_size);
+        let analysis: Vec<f32> = apodize::hanning_iter(fft_size + 1)
+            .take(fft_size)
+            .map(|w| w as f32)
+            .collect();
+        let analysis_power = analysis.iter().map(|w| w * w).sum::<f32>().max(f32::MIN_POSITIVE);
+
+        let mut comfort = Self {
+            channels: channels.max(1) as usize,
+            fft_size,
+            hop_size,
+            ifft,
+            window: analysis.iter().map(|w| w.sqrt()).collect(),
+            scale: (fft_size as f32 / analysis_power).sqrt() / fft_size as f32,
+            level: 0.0,
+            spectrum: vec![Complex::new(0.0, 0.0); fft_size],
+            overlap: vec![0.0; fft_size],
+            pending: Vec::new(),
+            rng: 0x2545_f491,
+            blend: 0.0,
+        };
+        comfort.set_level_db(DEFAULT_COMFORT_NOISE_LEVEL_DB);
+        comfort
+    }
+
+    // Level in dB relative to the estimated background noise, 0 or less
+    pub fn set_level_db(&mut self, level_db: f32) {
+        self.level = 10f32.powf(level_db.clamp(MIN_COMFORT_NOISE_LEVEL_DB, 0.0) / 20.0);
+    }
+
+    // Uniform in [0, 1), from a xorshift generator; quality hardly matters
+    // for noise nobody is meant to notice
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    // `length` interleaved samples of noise shaped like `noise_power`, the
+    // per-bin power of a noise estimate over a Hann-windowed FFT frame
+    pub fn generate(&mut self, noise_power: &[f32], length: usize) -> Vec<f32> {
+        // Follow FFT size changes of the estimate
+        if noise_power.len() != self.fft_size {
+            let level = self.level;
+            let blend = self.blend;
+            *self = Self::new(noise_power.len(), self.channels as u16);
+            self.level = level;
+            self.blend = blend;
+        }
+
+        let frames = length.div_ceil(self.channels);
+        while self.pending.len() < frames {
+            let half = self.fft_size / 2;
+            self.spectrum.fill(Complex::new(0.0, 0.0));
+            // DC and Nyquist stay empty; the upper half mirrors the lower so
+            // the result is real
+            for (bin, &power) in noise_power.iter().enumerate().take(half).skip(1) {
+                let magnitude = power.max(0.0).sqrt() * self.level;
+                let value = Complex::from_polar(magnitude, 2.0 * PI * self.random());
+                self.spectrum[bin] = value;
+                self.spectrum[self.fft_size - bin] = value.conj();
+            }
+            self.ifft.process(&mut self.spectrum);
+
+            for ((acc, bin), &w) in self.overlap.iter_mut().zip(&self.spectrum).zip(&self.window) {
+                *acc += bin.re * w * self.scale;
+            }
+            self.pending.extend_from_slice(&self.overlap[..self.hop_size]);
+            self.overlap.copy_within(self.hop_size.., 0);
+            let tail = self.fft_size - self.hop_size;
+            self.overlap[tail..].fill(0.0);
+        }
+
+        self.pending
+            .drain(..frames)
+            .flat_map(|sample| std::iter::repeat_n(sample, self.channels))
+            .take(length)
+            .collect()
+    }
+
+    // Adds comfort noise to `samples` while `silent`, fading it in and out
+    // over the block so it never switches abruptly
+    pub fn fill_silence(&mut self, samples: &mut [f32], noise_power: &[f32], silent: bool) {
+        let target = if silent { 1.0 } else { 0.0 };
+        if self.blend == 0.0 && target == 0.0 {
+            return;
+        }
+        let noise = self.generate(noise_power, samples.len());
+        let start = self.blend;
+        let step = (target - start) / samples.len().max(1) as f32;
+        for (i, (sample, noise)) in samples.iter_mut().zip(&noise).enumerate() {
+            *sample += noise * (start + step * (i + 1) as f32);
+        }
+        self.blend = target;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FFT_SIZE: usize = 1024;
+
+    // Bin powers a Hann-windowed frame of white noise of `rms` has, up to
+    // `cutoff` bins and none above
+    fn noise_power(rms: f32, cutoff: usize) -> Vec<f32> {
+        let window_power: f32 = apodize::hanning_iter(FFT_SIZE + 1).take(FFT_SIZE).map(|w| (w * w) as f32).sum();
+        (0..FFT_SIZE)
+            .map(|bin| if bin.min(FFT_SIZE - bin) <= cutoff { rms * rms * window_power } else { 0.0 })
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn silence_gets_a_floor_at_the_set_level() {
+        let mut comfort = ComfortNoise::new(FFT_SIZE, 1);
+        let mut samples = vec![0.0; 48000];
+        for block in samples.chunks_mut(480) {
+            comfort.fill_silence(block, &noise_power(0.01, FFT_SIZE / 2), true);
+        }
+        // -20 dB under noise of 0.01 RMS
+        let level_db = 20.0 * (rms(&samples[4800..]) / 0.001).log10();
+        assert!(level_db.abs() < 1.0, "{:.2} dB off the set level", level_db);
+        assert!(samples.iter().all(|s| s.abs() < 0.01));
+    }
+
+    #[test]
+    fn floor_follows_the_noise_spectrum() {
+        // Noise below an eighth of Nyquist only, 3 kHz at 48 kHz
+        let cutoff = FFT_SIZE / 16;
+        let mut comfort = ComfortNoise::new(FFT_SIZE, 1);
+        let mut samples = vec![0.0; FFT_SIZE * 32];
+        for block in samples.chunks_mut(480) {
+            comfort.fill_silence(block, &noise_power(0.01, cutoff), true);
+        }
+
+        let window: Vec<f32> = apodize::hanning_iter(FFT_SIZE + 1).take(FFT_SIZE).map(|w| w as f32).collect();
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        let (mut inside, mut outside) = (0.0, 0.0);
+        for frame in samples[FFT_SIZE..].chunks_exact(FFT_SIZE) {
+            let mut spectrum: Vec<Complex<f32>> = frame.iter().zip(&window).map(|(&s, &w)| Complex::new(s * w, 0.0)).collect();
+            fft.process(&mut spectrum);
+            for (bin, value) in spectrum.iter().enumerate().take(FFT_SIZE / 2).skip(1) {
+                if bin <= cutoff {
+                    inside += value.norm_sqr();
+                } else if bin > cutoff + 4 {
+                    outside += value.norm_sqr();
+                }
+            }
+        }
+        let shaping_db = 10.0 * (inside / outside).log10();
+        assert!(shaping_db > 30.0, "only {:.1} dB between the noise band and the rest", shaping_db);
+    }
+}
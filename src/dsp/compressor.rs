@@ -0,0 +1,179 @@
+// Downward compressor for even voice dynamics. Above the threshold every
+// `ratio` dB the level rises becomes 1 dB at the output, so loud words come
+// down towards quiet ones; makeup gain then lifts the whole signal back up.
+// The level is a peak envelope that rises at the attack time and falls at the
+// release time, carried across blocks. A soft knee blends the two slopes over
+// `knee` dB around the threshold.
+
+pub const DEFAULT_COMPRESSOR_THRESHOLD_DB: f32 = -20.0;
+pub const DEFAULT_COMPRESSOR_RATIO: f32 = 3.0;
+pub const MAX_COMPRESSOR_RATIO: f32 = 20.0;
+pub const DEFAULT_COMPRESSOR_ATTACK_MS: f32 = 10.0;
+pub const MAX_COMPRESSOR_ATTACK_MS: f32 = 200.0;
+pub const DEFAULT_COMPRESSOR_RELEASE_MS: f32 = 150.0;
+pub const MAX_COMPRESSOR_RELEASE_MS: f32 = 2000.0;
+pub const DEFAULT_COMPRESSOR_MAKEUP_DB: f32 = 0.0;
+pub const MAX_COMPRESSOR_MAKEUP_DB: f32 = 24.0;
+pub const DEFAULT_COMPRESSOR_KNEE_DB: f32 = 6.0;
+pub const MAX_COMPRESSOR_KNEE_DB: f32 = 24.0;
+
+// Envelope treated as silence, to keep the logarithm finite
+const MIN_ENVELOPE: f32 = 1e-6;
+
+// One-pole smoothing coefficient reaching ~63% of a step after `ms`
+fn smoothing_coef(ms: f32, samples_per_second: f32) -> f32 {
+    let samples = ms / 1000.0 * samples_per_second;
+    if samples <= 1.0 {
+        1.0
+    } else {
+        1.0 - (-1.0 / samples).exp()
+    }
+}
+
+#[derive(Clone)]
+pub struct Compressor {
+    // Interleaved samples per second, so times apply to the whole stream
+    samples_per_second: f32,
+    threshold_db: f32,
+    ratio: f32,
+    knee_db: f32,
+    makeup: f32,
+    attack_coef: f32,
+    release_coef: f32,
+    // Peak level, carried across blocks
+    envelope: f32,
+    // Gain the last sample was turned down by, in dB (0 or less)
+    reduction_db: f32,
+}
+
+impl Compressor {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let mut compressor = Self {
+            samples_per_second: sample_rate.max(1) as f32 * channels.max(1) as f32,
+            threshold_db: DEFAULT_COMPRESSOR_THRESHOLD_DB,
+            ratio: DEFAULT_COMPRESSOR_RATIO,
+            knee_db: DEFAULT_COMPRESSOR_KNEE_DB,
+            makeup: 1.0,
+            attack_coef: 1.0,
+            release_coef: 1.0,
+            envelope: 0.0,
+            reduction_db: 0.0,
+        };
+        compressor.set_attack_ms(DEFAULT_COMPRESSOR_ATTACK_MS);
+        compressor.set_release_ms(DEFAULT_COMPRESSOR_RELEASE_MS);
+        compressor.set_makeup_db(DEFAULT_COMPRESSOR_MAKEUP_DB);
+        compressor
+    }
+
+    // Level in dBFS above which the signal is turned down
+    pub fn set_threshold_db(&mut self, threshold_db: f32) {
+        self.threshold_db = threshold_db.min(0.0);
+    }
+
+    // Input dB per output dB above the threshold; 1 leaves the signal alone
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio.clamp(1.0, MAX_COMPRESSOR_RATIO);
+    }
+
+    // Width of the transition around the threshold; 0 is a hard knee
+    pub fn set_knee_db(&mut self, knee_db: f32) {
+        self.knee_db = knee_db.clamp(0.0, MAX_COMPRESSOR_KNEE_DB);
+    }
+
+    // Time for the envelope to follow a rise in level
+    pub fn set_attack_ms(&mut self, ms: f32) {
+        self.attack_coef = smoothing_coef(ms.clamp(0.0, MAX_COMPRESSOR_ATTACK_MS), self.samples_per_second);
+    }
+
+    // Time for the envelope to follow a fall in level
+    pub fn set_release_ms(&mut self, ms: f32) {
+        self.release_coef = smoothing_coef(ms.clamp(0.0, MAX_COMPRESSOR_RELEASE_MS), self.samples_per_second);
+    }
+
+    // Gain applied after compression, to make up for the level it takes
+    pub fn set_makeup_db(&mut self, makeup_db: f32) {
+        self.makeup = 10f32.powf(makeup_db.clamp(0.0, MAX_COMPRESSOR_MAKEUP_DB) / 20.0);
+    }
+
+    // Static gain curve: gain in dB (0 or less, without makeup) for a level
+    // in dBFS
+    pub fn gain_db(&self, level_db: f32) -> f32 {
+        let above = level_db - self.threshold_db;
+        let half_knee = self.knee_db / 2.0;
+        let slope = 1.0 / self.ratio - 1.0;
+        if above <= -half_knee {
+            0.0
+        } else if above < half_knee {
+            // Quadratic blend, meeting both slopes where the knee ends
+            let distance = above + half_knee;
+            slope * distance * distance / (2.0 * self.knee_db)
+        } else {
+            slope * above
+        }
+    }
+
+    // Gain reduction applied to the last processed sample, in dB (0 or less)
+    pub fn gain_reduction_db(&self) -> f32 {
+        self.reduction_db
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let level = sample.abs();
+            let coef = if level > self.envelope { self.attack_coef } else { self.release_coef };
+            self.envelope += coef * (level - self.envelope);
+            let level_db = 20.0 * self.envelope.max(MIN_ENVELOPE).log10();
+            self.reduction_db = self.gain_db(level_db);
+            *sample *= 10f32.powf(self.reduction_db / 20.0) * self.makeup;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_rises_one_db_per_ratio_db_above_threshold() {
+        let mut compressor = Compressor::new(48000, 1);
+        compressor.set_threshold_db(-20.0);
+        compressor.set_ratio(4.0);
+        compressor.set_knee_db(0.0);
+
+        for level_db in [-40.0, -20.0] {
+            assert_eq!(compressor.gain_db(level_db), 0.0, "turned down at {} dB", level_db);
+        }
+        for level_db in [-16.0, -8.0, 0.0] {
+            let output_db = level_db + compressor.gain_db(level_db);
+            let expected_db = -20.0 + (level_db + 20.0) / 4.0;
+            assert!((output_db - expected_db).abs() < 1e-4, "{} dB in gave {} dB out", level_db, output_db);
+        }
+
+        // A steady level settles on the curve, plus the makeup gain
+        compressor.set_makeup_db(6.0);
+        let mut samples = vec![0.5; 48000];
+        compressor.process(&mut samples);
+        let output_db = 20.0 * samples[samples.len() - 1].log10();
+        let expected_db = -20.0 + (20.0 * 0.5f32.log10() + 20.0) / 4.0 + 6.0;
+        assert!((output_db - expected_db).abs() < 0.05, "settled at {} dB, not {} dB", output_db, expected_db);
+        assert!((compressor.gain_reduction_db() - (expected_db - 6.0 - 20.0 * 0.5f32.log10())).abs() < 0.05);
+    }
+
+    #[test]
+    fn soft_knee_meets_both_slopes() {
+        let mut compressor = Compressor::new(48000, 1);
+        compressor.set_threshold_db(-20.0);
+        compressor.set_ratio(4.0);
+        compressor.set_knee_db(10.0);
+
+        assert_eq!(compressor.gain_db(-25.0), 0.0);
+        assert!((compressor.gain_db(-15.0) - (1.0 / 4.0 - 1.0) * 5.0).abs() < 1e-4);
+        // Inside the knee the gain falls steadily between the two
+        let mut previous = 0.0;
+        for step in 1..100 {
+            let gain = compressor.gain_db(-25.0 + step as f32 * 0.1);
+            assert!(gain <= previous && gain > (1.0 / 4.0 - 1.0) * 5.0);
+            previous = gain;
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// De-esser that tames harsh "S" sounds. It works on the spectral frames of
+// noise reduction rather than running its own FFT: each frame, the share of
+// the energy in the sibilance band is compared against a threshold, and the
+// band's bins are turned down by as much as the share exceeds it, up to the
+// range. Frames without strong sibilance, i.e. almost all of speech, pass
+// untouched, so the midrange keeps its body.
+
+use rustfft::num_complex::Complex;
+
+// Sibilance of most voices sits between these frequencies
+pub const SIBILANCE_LOW_HZ: f32 = 5000.0;
+pub const SIBILANCE_HIGH_HZ: f32 = 9000.0;
+
+// Share of the frame energy in the sibilance band, in dB, above which the
+// band is attenuated. Voiced speech keeps it well below -20 dB, while an
+// "S" puts most of its energy there.
+pub const DEFAULT_DEESSER_THRESHOLD_DB: f32 = -12.0;
+pub const MIN_DEESSER_THRESHOLD_DB: f32 = -40.0;
+
+// Largest attenuation applied to the band
+pub const DEFAULT_DEESSER_RANGE_DB: f32 = 12.0;
+pub const MAX_DEESSER_RANGE_DB: f32 = 24.0;
+
+// Share of the remaining distance the attenuation recovers per frame once the
+// sibilant has passed; it engages at once so the start of an "S" is caught
+const RELEASE: f32 = 0.3;
+
+// Frames quieter than this carry no sibilance worth measuring
+const SILENCE_POWER: f32 = 1e-10;
+
+#[derive(Clone)]
+pub struct DeEsser {
+    sample_rate: f32,
+    threshold_db: f32,
+    range_db: f32,
+    // Attenuation applied to the last frame of each channel, in dB (0 or more)
+    reduction_db: Vec<f32>,
+}
+
+impl DeEsser {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1) as f32,
+            threshold_db: DEFAULT_DEESSER_THRESHOLD_DB,
+            range_db: DEFAULT_DEESSER_RANGE_DB,
+            reduction_db: vec![0.0; channels.max(1) as usize],
+        }
+    }
+
+    pub fn set_threshold_db(&mut self, threshold_db: f32) {
+        self.threshold_db = threshold_db.clamp(MIN_DEESSER_THRESHOLD_DB, 0.0);
+    }
+
+    pub fn set_range_db(&mut self, range_db: f32) {
+        self.range_db = range_db.clamp(0.0, MAX_DEESSER_RANGE_DB);
+    }
+
+    // Attenuation of the sibilance band in the last frame, in dB (0 or
+    // more), the most of any channel
+    pub fn reduction_db(&self) -> f32 {
+        self.reduction_db.iter().copied().fold(0.0, f32::max)
+    }
+
+    // Bins of a `fft_size` frame covering the sibilance band, below Nyquist
+    fn band(&self, fft_size: usize) -> std::ops::Range<usize> {
+        let bin_hz = self.sample_rate / fft_size as f32;
+        let half = fft_size / 2;
+        let low = ((SIBILANCE_LOW_HZ / bin_hz).ceil() as usize).clamp(1, half);
+        let high = ((SIBILANCE_HIGH_HZ / bin_hz).floor() as usize + 1).min(half);
+        low..high.max(low)
+    }
+
+    // Attenuates the sibilance band of one FFT frame of `channel` in place
+    pub fn process(&mut self, channel: usize, spectrum: &mut [Complex<f32>]) {
+        let fft_size = spectrum.len();
+        let band = self.band(fft_size);
+        let half = fft_size / 2;
+
+        // The upper half mirrors the lower, so the lower half suffices
+        let total: f32 = spectrum[1..half].iter().map(|bin| bin.norm_sqr()).sum();
+        let sibilance: f32 = spectrum[band.clone()].iter().map(|bin| bin.norm_sqr()).sum();
+
+        let target_db = if total > SILENCE_POWER && sibilance > 0.0 {
+            let share_db = 10.0 * (sibilance / total).log10();
+            (share_db - self.threshold_db).clamp(0.0, self.range_db)
+        } else {
+            0.0
+        };
+        let Some(reduction_db) = self.reduction_db.get_mut(channel) else {
+            return;
+        };
+        *reduction_db = if target_db > *reduction_db {
+            target_db
+        } else {
+            *reduction_db + (target_db - *reduction_db) * RELEASE
+        };
+        if *reduction_db <= 0.0 {
+            return;
+        }
+
+        let gain = 10f32.powf(-*reduction_db / 20.0);
+        for bin in band {
+            spectrum[bin] *= gain;
+            spectrum[fft_size - bin] *= gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FFT_SIZE: usize = 1024;
+
+    // Spectrum of a frame holding a 7 kHz "S" and a 500 Hz vowel at the given
+    // magnitudes, mirrored so it is that of a real signal
+    fn frame(sibilance: f32, vowel: f32) -> Vec<Complex<f32>> {
+        let bin_hz = 48000.0 / FFT_SIZE as f32;
+        let mut spectrum = vec![Complex::new(0.0, 0.0); FFT_SIZE];
+        for (hz, magnitude) in [(7000.0, sibilance), (500.0, vowel)] {
+            let bin = (hz / bin_hz).round() as usize;
+            spectrum[bin] = Complex::new(magnitude, 0.0);
+            spectrum[FFT_SIZE - bin] = Complex::new(magnitude, 0.0);
+        }
+        spectrum
+    }
+
+    fn magnitude_at(spectrum: &[Complex<f32>], hz: f32) -> f32 {
+        spectrum[(hz / (48000.0 / FFT_SIZE as f32)).round() as usize].norm()
+    }
+
+    #[test]
+    fn sibilant_frame_loses_highs_and_keeps_midrange() {
+        let mut deesser = DeEsser::new(48000, 1);
+        let mut spectrum = frame(1.0, 0.5);
+        deesser.process(0, &mut spectrum);
+
+        // The band holds 1 / 1.25 of the energy, -0.97 dB, so it comes down
+        // by as much as that exceeds the threshold
+        let expected_db = 10.0 * 0.8f32.log10() - DEFAULT_DEESSER_THRESHOLD_DB;
+        let high_db = 20.0 * magnitude_at(&spectrum, 7000.0).log10();
+        assert!((high_db + expected_db).abs() < 0.01, "7 kHz at {:.1} dB, expected -{:.1}", high_db, expected_db);
+        assert!((deesser.reduction_db() - expected_db).abs() < 0.01);
+        assert_eq!(magnitude_at(&spectrum, 500.0), 0.5);
+    }
+
+    #[test]
+    fn voiced_frame_passes_untouched() {
+        let mut deesser = DeEsser::new(48000, 1);
+        // The "S" band 30 dB under the vowel
+        let mut spectrum = frame(0.0316, 1.0);
+        let original = spectrum.clone();
+        deesser.process(0, &mut spectrum);
+        assert_eq!(spectrum, original);
+        assert_eq!(deesser.reduction_db(), 0.0);
+    }
+}
@@ -0,0 +1,30 @@
+// Fixed delay for interleaved samples, used to line a signal up with one
+// that went through a stage with latency
+use std::collections::VecDeque;
+
+#[derive(Clone)]
+pub struct DelayLine {
+    buffer: VecDeque<f32>,
+}
+
+impl DelayLine {
+    pub fn new(delay: usize) -> Self {
+        Self { buffer: VecDeque::from(vec![0.0; delay]) }
+    }
+
+    // Changes the delay, in samples. The delayed history restarts from
+    // silence, so only call this when the delay actually changes.
+    pub fn set_delay(&mut self, delay: usize) {
+        if delay != self.buffer.len() {
+            self.buffer.clear();
+            self.buffer.resize(delay, 0.0);
+        }
+    }
+
+    // Pushes `input` in and returns the same number of samples from
+    // `delay` samples ago
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        self.buffer.extend(input);
+        self.buffer.drain(..input.len()).collect()
+    }
+}
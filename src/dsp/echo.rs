@@ -0,0 +1,147 @@
+// Acoustic echo cancellation with a normalized least-mean-squares (NLMS)
+// adaptive filter per channel. The filter learns the path from the reference
+// (application audio) to the microphone - speaker delay, room response and
+// gain - and subtracts its estimate of the echo from the mic signal.
+
+pub const DEFAULT_ECHO_TAPS: usize = 256;
+pub const DEFAULT_ECHO_STEP_SIZE: f32 = 0.3;
+
+// Geigel double-talk detector: the echo is assumed to be at least 6dB below
+// the loudest recent reference sample, so a louder mic sample means the near
+// end is talking
+const DOUBLE_TALK_THRESHOLD: f32 = 0.5;
+
+// Samples per channel adaptation stays frozen after double talk was last
+// detected, so it does not resume in the gaps between syllables
+const DOUBLE_TALK_HOLD: usize = 1024;
+
+// Keeps the normalization finite while the reference is silent
+const REGULARIZATION: f32 = 1e-6;
+
+#[derive(Clone)]
+struct ChannelFilter {
+    taps: Vec<f32>,
+    // Reference history written twice so the latest `taps.len()` samples are
+    // always contiguous at `history[position..position + taps.len()]`, newest first
+    history: Vec<f32>,
+    position: usize,
+    energy: f32,
+    hold: usize,
+}
+
+impl ChannelFilter {
+    fn new(taps: usize) -> Self {
+        Self {
+            taps: vec![0.0; taps],
+            history: vec![0.0; taps * 2],
+            position: 0,
+            energy: 0.0,
+            hold: 0,
+        }
+    }
+
+    fn process(&mut self, mic: f32, reference: f32, step_size: f32) -> f32 {
+        let len = self.taps.len();
+
+        // The slot being overwritten holds the sample leaving the window
+        self.position = (self.position + len - 1) % len;
+        let oldest = self.history[self.position];
+        self.energy = (self.energy + reference * reference - oldest * oldest).max(0.0);
+        self.history[self.position] = reference;
+        self.history[self.position + len] = reference;
+
+        let window = &self.history[self.position..self.position + len];
+        let estimate: f32 = self.taps.iter().zip(window).map(|(w, x)| w * x).sum();
+        let error = mic - estimate;
+
+        let peak = window.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+        if mic.abs() > DOUBLE_TALK_THRESHOLD * peak {
+            self.hold = DOUBLE_TALK_HOLD;
+        } else if self.hold > 0 {
+            self.hold -= 1;
+        } else {
+            let gain = step_size * error / (self.energy + REGULARIZATION);
+            for (w, x) in self.taps.iter_mut().zip(window) {
+                *w += gain * x;
+            }
+        }
+
+        error
+    }
+}
+
+#[derive(Clone)]
+pub struct EchoCanceller {
+    channels: Vec<ChannelFilter>,
+    // Channel of the next sample, kept across blocks whose length is not a
+    // multiple of the channel count
+    next_channel: usize,
+    step_size: f32,
+}
+
+impl EchoCanceller {
+    pub fn new(taps: usize, channels: u16) -> Self {
+        Self {
+            channels: (0..channels.max(1)).map(|_| ChannelFilter::new(taps.max(1))).collect(),
+            next_channel: 0,
+            step_size: DEFAULT_ECHO_STEP_SIZE,
+        }
+    }
+
+    // NLMS step size in (0, 1]. Larger steps converge faster but leave more
+    // residual echo and react more to noise.
+    pub fn set_step_size(&mut self, step_size: f32) {
+        self.step_size = step_size.clamp(f32::EPSILON, 1.0);
+    }
+
+    // Cancels the echo of interleaved `reference` samples from interleaved
+    // `mic` samples, adapting the filters as it goes. A missing reference is
+    // treated as silence.
+    pub fn process(&mut self, mic: &[f32], reference: &[f32]) -> Vec<f32> {
+        let step_size = self.step_size;
+
+        mic.iter().enumerate()
+            .map(|(i, &sample)| {
+                let reference = reference.get(i).copied().unwrap_or(0.0);
+                let channel = self.next_channel;
+                self.next_channel = (channel + 1) % self.channels.len();
+                self.channels[channel].process(sample, reference, step_size)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn power(samples: &[f32]) -> f32 {
+        samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32
+    }
+
+    #[test]
+    fn cancels_a_delayed_scaled_echo() {
+        // White reference; the mic hears it 40 samples late at half level
+        let mut seed = 1u32;
+        let reference: Vec<f32> = (0..96000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                seed as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect();
+        let delay = 40;
+        let mic: Vec<f32> = (0..reference.len())
+            .map(|n| if n >= delay { 0.5 * reference[n - delay] } else { 0.0 })
+            .collect();
+
+        let mut canceller = EchoCanceller::new(DEFAULT_ECHO_TAPS, 1);
+        let residual: Vec<f32> = mic.chunks(480)
+            .zip(reference.chunks(480))
+            .flat_map(|(mic, reference)| canceller.process(mic, reference))
+            .collect();
+
+        // After a second to converge
+        let cancellation_db = 10.0 * (power(&mic[48000..]) / power(&residual[48000..])).log10();
+        assert!(cancellation_db > 20.0, "only {:.1} dB of cancellation", cancellation_db);
+    }
+}
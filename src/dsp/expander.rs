@@ -0,0 +1,126 @@
+// Downward expander: a gentler alternative to the gate that turns quiet
+// passages down rather than off. Below the threshold every dB the level drops
+// becomes `ratio` dB at the output, so room tone recedes while the pauses
+// stay natural. A soft knee blends the two slopes over `knee` dB around the
+// threshold so the onset of expansion is inaudible.
+
+pub const DEFAULT_EXPANDER_THRESHOLD_DB: f32 = -50.0;
+pub const DEFAULT_EXPANDER_RATIO: f32 = 2.0;
+pub const MAX_EXPANDER_RATIO: f32 = 10.0;
+pub const DEFAULT_EXPANDER_KNEE_DB: f32 = 6.0;
+pub const MAX_EXPANDER_KNEE_DB: f32 = 24.0;
+
+// Averaging time of the RMS envelope the gain is computed from; long enough
+// not to follow individual cycles of low voices
+const ENVELOPE_MS: f32 = 20.0;
+
+// Envelope power treated as silence, to keep the logarithm finite
+const MIN_ENVELOPE_POWER: f32 = 1e-12;
+
+#[derive(Clone)]
+pub struct Expander {
+    envelope_coef: f32,
+    threshold_db: f32,
+    ratio: f32,
+    knee_db: f32,
+    // Mean-square level, carried across blocks
+    envelope: f32,
+}
+
+impl Expander {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let samples_per_second = sample_rate.max(1) as f32 * channels.max(1) as f32;
+        let samples = ENVELOPE_MS / 1000.0 * samples_per_second;
+        Self {
+            envelope_coef: 1.0 - (-1.0 / samples.max(1.0)).exp(),
+            threshold_db: DEFAULT_EXPANDER_THRESHOLD_DB,
+            ratio: DEFAULT_EXPANDER_RATIO,
+            knee_db: DEFAULT_EXPANDER_KNEE_DB,
+            envelope: 0.0,
+        }
+    }
+
+    // Level in dBFS below which the signal is turned down
+    pub fn set_threshold_db(&mut self, threshold_db: f32) {
+        self.threshold_db = threshold_db.min(0.0);
+    }
+
+    // Output dB per input dB below the threshold; 1 leaves the signal alone
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio.clamp(1.0, MAX_EXPANDER_RATIO);
+    }
+
+    // Width of the transition around the threshold; 0 is a hard knee
+    pub fn set_knee_db(&mut self, knee_db: f32) {
+        self.knee_db = knee_db.clamp(0.0, MAX_EXPANDER_KNEE_DB);
+    }
+
+    // Static gain curve: gain in dB (0 or less) for a level in dBFS
+    pub fn gain_db(&self, level_db: f32) -> f32 {
+        let below = level_db - self.threshold_db;
+        let half_knee = self.knee_db / 2.0;
+        if below >= half_knee {
+            0.0
+        } else if below > -half_knee {
+            // Quadratic blend, meeting both slopes where the knee ends
+            let distance = below - half_knee;
+            -(self.ratio - 1.0) * distance * distance / (2.0 * self.knee_db)
+        } else {
+            (self.ratio - 1.0) * below
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            self.envelope += self.envelope_coef * (*sample * *sample - self.envelope);
+            let level_db = 10.0 * self.envelope.max(MIN_ENVELOPE_POWER).log10();
+            *sample *= 10f32.powf(self.gain_db(level_db) / 20.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    // One second of 1 kHz at `rms_db` dBFS RMS
+    fn tone(rms_db: f32) -> Vec<f32> {
+        let amplitude = 10f32.powf(rms_db / 20.0) * 2f32.sqrt();
+        (0..48000).map(|n| amplitude * (2.0 * PI * 1000.0 * n as f32 / 48000.0).sin()).collect()
+    }
+
+    fn rms_db(samples: &[f32]) -> f32 {
+        10.0 * (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).log10()
+    }
+
+    #[test]
+    fn gain_curve_follows_the_ratio_outside_the_knee() {
+        let mut expander = Expander::new(48000, 1);
+        expander.set_threshold_db(-50.0);
+        expander.set_ratio(3.0);
+        expander.set_knee_db(6.0);
+        assert_eq!(expander.gain_db(-20.0), 0.0);
+        assert_eq!(expander.gain_db(-47.0), 0.0);
+        assert!((expander.gain_db(-60.0) + 20.0).abs() < 1e-4);
+        // Inside the knee, between the two slopes
+        let knee = expander.gain_db(-50.0);
+        assert!(knee < 0.0 && knee > -(3.0 - 1.0) * 3.0);
+    }
+
+    #[test]
+    fn loud_tone_passes_and_quiet_tone_drops_by_the_ratio() {
+        let mut expander = Expander::new(48000, 1);
+        let mut loud = tone(-20.0);
+        expander.process(&mut loud);
+        let loud_db = rms_db(&loud[4800..]);
+        assert!((loud_db + 20.0).abs() < 0.1, "loud tone at {:.2} dBFS", loud_db);
+
+        // 20 dB under the -50 dB threshold at the default 2:1, so 20 dB more
+        let mut expander = Expander::new(48000, 1);
+        let mut quiet = tone(-70.0);
+        expander.process(&mut quiet);
+        let quiet_db = rms_db(&quiet[4800..]);
+        assert!((quiet_db + 90.0).abs() < 1.0, "quiet tone at {:.2} dBFS", quiet_db);
+    }
+}
@@ -0,0 +1,128 @@
+// Fixed-size planar frames for processors that take exactly one frame length
+// per call, such as the 10 ms frames of the WebRTC audio processing module.
+// Interleaved blocks of any size go in, complete frames are split into one
+// buffer per channel and handed on, and the result comes back interleaved.
+// Output runs one frame behind the input, so every call returns as many
+// samples as it was given.
+
+use std::collections::VecDeque;
+
+#[derive(Clone)]
+pub struct FrameAdapter {
+    channels: usize,
+    // Samples per channel in a frame
+    frame_len: usize,
+    // Interleaved input and reference not yet making up a whole frame
+    pending: Vec<f32>,
+    pending_reference: Vec<f32>,
+    // Processed interleaved samples, starting with a frame of silence
+    output: VecDeque<f32>,
+    capture: Vec<Vec<f32>>,
+    render: Vec<Vec<f32>>,
+}
+
+impl FrameAdapter {
+    pub fn new(frame_len: usize, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        let frame_len = frame_len.max(1);
+        Self {
+            channels,
+            frame_len,
+            pending: Vec::with_capacity(frame_len * channels),
+            pending_reference: Vec::with_capacity(frame_len * channels),
+            output: VecDeque::from(vec![0.0; frame_len * channels]),
+            capture: vec![vec![0.0; frame_len]; channels],
+            render: vec![vec![0.0; frame_len]; channels],
+        }
+    }
+
+    // Delay of the output behind the input, in interleaved samples
+    pub fn latency(&self) -> usize {
+        self.frame_len * self.channels
+    }
+
+    // Queues interleaved `samples`, with the `reference` block lined up with
+    // them (silence where it is shorter), and runs `frame` on every frame now
+    // complete. `frame` gets the capture channels to process in place and the
+    // reference channels. Returns `samples.len()` processed samples.
+    pub fn process(
+        &mut self,
+        samples: &[f32],
+        reference: &[f32],
+        mut frame: impl FnMut(&mut [Vec<f32>], &[Vec<f32>]),
+    ) -> Vec<f32> {
+        let frame_samples = self.latency();
+        let mut offset = 0;
+        while offset < samples.len() {
+            let take = (frame_samples - self.pending.len()).min(samples.len() - offset);
+            self.pending.extend_from_slice(&samples[offset..offset + take]);
+            let reference = reference.get(offset..).unwrap_or(&[]);
+            self.pending_reference.extend(reference.iter().copied().chain(std::iter::repeat(0.0)).take(take));
+            offset += take;
+            if self.pending.len() < frame_samples {
+                break;
+            }
+
+            for (i, (&sample, &reference)) in self.pending.iter().zip(&self.pending_reference).enumerate() {
+                let (index, channel) = (i / self.channels, i % self.channels);
+                self.capture[channel][index] = sample;
+                self.render[channel][index] = reference;
+            }
+            frame(&mut self.capture, &self.render);
+            for index in 0..self.frame_len {
+                self.output.extend(self.capture.iter().map(|channel| channel[index]));
+            }
+            self.pending.clear();
+            self.pending_reference.clear();
+        }
+        self.output.drain(..samples.len()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_is_the_input_one_frame_late() {
+        let mut adapter = FrameAdapter::new(480, 2);
+        let input: Vec<f32> = (0..4800).map(|n| n as f32).collect();
+        let mut frames = 0;
+        // Blocks that don't line up with the frames
+        let output: Vec<f32> = input
+            .chunks(333)
+            .flat_map(|block| {
+                adapter.process(block, &[], |capture, render| {
+                    assert_eq!(capture.len(), 2);
+                    assert!(capture.iter().all(|channel| channel.len() == 480));
+                    assert!(render.iter().flatten().all(|&r| r == 0.0));
+                    frames += 1;
+                })
+            })
+            .collect();
+
+        assert_eq!(output.len(), input.len());
+        assert_eq!(frames, input.len() / 960);
+        assert!(output[..960].iter().all(|&s| s == 0.0));
+        assert_eq!(&output[960..], &input[..input.len() - 960]);
+    }
+
+    #[test]
+    fn frames_are_split_into_channels() {
+        let mut adapter = FrameAdapter::new(4, 2);
+        // Left counts up, right down; the reference is negated
+        let input: Vec<f32> = (0..4).flat_map(|n| [n as f32, -(n as f32) - 10.0]).collect();
+        let reference: Vec<f32> = input.iter().map(|s| -s).collect();
+        adapter.process(&input, &reference, |capture, render| {
+            assert_eq!(capture[0], [0.0, 1.0, 2.0, 3.0]);
+            assert_eq!(capture[1], [-10.0, -11.0, -12.0, -13.0]);
+            assert_eq!(render[0], [-0.0, -1.0, -2.0, -3.0]);
+            // Processed in place, e.g. halved
+            for sample in capture.iter_mut().flatten() {
+                *sample *= 0.5;
+            }
+        });
+        let output = adapter.process(&[0.0; 8], &[], |_, _| {});
+        assert_eq!(output, [0.0, -5.0, 0.5, -5.5, 1.0, -6.0, 1.5, -6.5]);
+    }
+}
@@ -0,0 +1,76 @@
+// Static gain (trim) with a smoothed ramp, so moving a level slider glides
+// to the new gain instead of stepping, which would be heard as zipper noise.
+
+pub const MIN_GAIN_DB: f32 = -24.0;
+pub const MAX_GAIN_DB: f32 = 24.0;
+
+// Time for the gain to get ~63% of the way to a new setting
+const SMOOTHING_MS: f32 = 10.0;
+
+#[derive(Clone)]
+pub struct Gain {
+    smoothing_coef: f32,
+    target: f32,
+    current: f32,
+}
+
+impl Gain {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let samples = SMOOTHING_MS / 1000.0 * sample_rate.max(1) as f32 * channels.max(1) as f32;
+        Self {
+            smoothing_coef: 1.0 - (-1.0 / samples.max(1.0)).exp(),
+            target: 1.0,
+            current: 1.0,
+        }
+    }
+
+    pub fn set_gain_db(&mut self, gain_db: f32) {
+        self.target = 10f32.powf(gain_db.clamp(MIN_GAIN_DB, MAX_GAIN_DB) / 20.0);
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        // Settled at unity, the common case, leaves the samples untouched
+        if self.current == self.target && self.target == 1.0 {
+            return;
+        }
+        for sample in samples {
+            self.current += self.smoothing_coef * (self.target - self.current);
+            *sample *= self.current;
+        }
+        if (self.current - self.target).abs() < 1e-6 {
+            self.current = self.target;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn plus_six_db_doubles_the_rms() {
+        let input: Vec<f32> = (0..48000).map(|n| 0.1 * (2.0 * std::f32::consts::PI * 1000.0 * n as f32 / 48000.0).sin()).collect();
+        let mut gain = Gain::new(48000, 1);
+        gain.set_gain_db(6.0);
+        let mut output = input.clone();
+        gain.process(&mut output);
+
+        // Once the 10 ms ramp has settled
+        let ratio = rms(&output[4800..]) / rms(&input[4800..]);
+        assert!((ratio - 2.0).abs() < 0.01, "RMS ratio {}", ratio);
+    }
+
+    #[test]
+    fn gain_change_ramps_without_a_step() {
+        let mut gain = Gain::new(48000, 1);
+        gain.set_gain_db(MAX_GAIN_DB);
+        let mut ones = vec![1.0; 480];
+        gain.process(&mut ones);
+        let largest_step = ones.windows(2).map(|pair| pair[1] - pair[0]).fold(0.0f32, f32::max);
+        assert!(ones[0] < 1.5 && largest_step < 0.2, "first sample {}, step {}", ones[0], largest_step);
+    }
+}
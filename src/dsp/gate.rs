@@ -0,0 +1,171 @@
+// Noise gate that silences the signal while its level stays below a
+// threshold. The level is a short RMS envelope and the gain ramps smoothly
+// per sample, so the gate fades rather than clicks. After the level drops, the
+// gate holds open for a while before releasing, which stops it chattering on
+// the short dips inside words.
+
+pub const DEFAULT_GATE_THRESHOLD_DB: f32 = -50.0;
+pub const DEFAULT_GATE_ATTACK_MS: f32 = 5.0;
+pub const DEFAULT_GATE_HOLD_MS: f32 = 100.0;
+pub const DEFAULT_GATE_RELEASE_MS: f32 = 150.0;
+
+// Averaging time of the RMS envelope the threshold is compared against
+const ENVELOPE_MS: f32 = 10.0;
+
+#[derive(Clone)]
+pub struct NoiseGate {
+    // Interleaved samples per second, so times apply to the whole stream
+    samples_per_second: f32,
+    threshold_power: f32,
+    envelope_coef: f32,
+    attack_coef: f32,
+    release_coef: f32,
+    hold_samples: usize,
+    envelope: f32,
+    gain: f32,
+    hold_remaining: usize,
+}
+
+// One-pole smoothing coefficient reaching ~63% of a step after `ms`
+fn smoothing_coef(ms: f32, samples_per_second: f32) -> f32 {
+    let samples = ms / 1000.0 * samples_per_second;
+    if samples <= 1.0 {
+        1.0
+    } else {
+        1.0 - (-1.0 / samples).exp()
+    }
+}
+
+impl NoiseGate {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let samples_per_second = sample_rate.max(1) as f32 * channels.max(1) as f32;
+        let mut gate = Self {
+            samples_per_second,
+            threshold_power: 0.0,
+            envelope_coef: smoothing_coef(ENVELOPE_MS, samples_per_second),
+            attack_coef: 1.0,
+            release_coef: 1.0,
+            hold_samples: 0,
+            envelope: 0.0,
+            gain: 1.0,
+            hold_remaining: 0,
+        };
+        gate.set_threshold_db(DEFAULT_GATE_THRESHOLD_DB);
+        gate.set_attack_ms(DEFAULT_GATE_ATTACK_MS);
+        gate.set_hold_ms(DEFAULT_GATE_HOLD_MS);
+        gate.set_release_ms(DEFAULT_GATE_RELEASE_MS);
+        gate
+    }
+
+    // Level in dBFS below which the gate closes
+    pub fn set_threshold_db(&mut self, threshold_db: f32) {
+        self.threshold_power = 10f32.powf(threshold_db / 10.0);
+    }
+
+    // Time to open once the level rises above the threshold
+    pub fn set_attack_ms(&mut self, ms: f32) {
+        self.attack_coef = smoothing_coef(ms, self.samples_per_second);
+    }
+
+    // Time the gate stays open after the level falls below the threshold
+    pub fn set_hold_ms(&mut self, ms: f32) {
+        self.hold_samples = (ms.max(0.0) / 1000.0 * self.samples_per_second) as usize;
+    }
+
+    // Time to fade to silence once the hold has expired
+    pub fn set_release_ms(&mut self, ms: f32) {
+        self.release_coef = smoothing_coef(ms, self.samples_per_second);
+    }
+
+    // The gate is passing more than it is taking away
+    pub fn is_open(&self) -> bool {
+        self.gain > 0.5
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        self.process_with_fill(samples, &[]);
+    }
+
+    // Like `process`, but whatever the gate takes away is replaced by the
+    // matching sample of `fill` (e.g. comfort noise), so a closed gate plays
+    // `fill` instead of silence. Samples beyond the end of `fill` fade to
+    // silence as usual.
+    pub fn process_with_fill(&mut self, samples: &mut [f32], fill: &[f32]) {
+        for (i, sample) in samples.iter_mut().enumerate() {
+            self.envelope += self.envelope_coef * (*sample * *sample - self.envelope);
+
+            let target = if self.envelope >= self.threshold_power {
+                self.hold_remaining = self.hold_samples;
+                1.0
+            } else if self.hold_remaining > 0 {
+                self.hold_remaining -= 1;
+                1.0
+            } else {
+                0.0
+            };
+
+            let coef = if target > self.gain { self.attack_coef } else { self.release_coef };
+            self.gain += coef * (target - self.gain);
+            let fill = fill.get(i).copied().unwrap_or(0.0);
+            *sample = *sample * self.gain + fill * (1.0 - self.gain);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const RATE: u32 = 48000;
+
+    fn tone(rms_db: f32, len: usize) -> Vec<f32> {
+        let amplitude = 10f32.powf(rms_db / 20.0) * 2f32.sqrt();
+        (0..len).map(|n| amplitude * (2.0 * PI * 440.0 * n as f32 / RATE as f32).sin()).collect()
+    }
+
+    fn rms_db(samples: &[f32]) -> f32 {
+        10.0 * (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).log10()
+    }
+
+    fn gate() -> NoiseGate {
+        let mut gate = NoiseGate::new(RATE, 1);
+        gate.set_threshold_db(-40.0);
+        gate.set_hold_ms(100.0);
+        gate.set_release_ms(50.0);
+        gate
+    }
+
+    #[test]
+    fn sub_threshold_tone_is_attenuated_after_the_hold() {
+        let mut gate = gate();
+        // Speech opens the gate, then the level drops under the threshold
+        let mut opening = tone(-20.0, RATE as usize / 5);
+        gate.process(&mut opening);
+        let input = tone(-60.0, RATE as usize);
+        let mut output = input.clone();
+        for block in output.chunks_mut(480) {
+            gate.process(block);
+        }
+
+        // Still open through the hold
+        let hold_end = RATE as usize / 10;
+        assert!((rms_db(&output[..hold_end]) - rms_db(&input[..hold_end])).abs() < 0.5);
+        // Well down once the release has run its course
+        let closed = hold_end + RATE as usize / 4;
+        assert!(rms_db(&output[closed..]) < rms_db(&input[closed..]) - 40.0);
+        assert!(!gate.is_open());
+    }
+
+    #[test]
+    fn supra_threshold_tone_passes() {
+        let mut gate = gate();
+        let input = tone(-20.0, RATE as usize);
+        let mut output = input.clone();
+        for block in output.chunks_mut(480) {
+            gate.process(block);
+        }
+        assert!((rms_db(&output) - rms_db(&input)).abs() < 0.1);
+        assert!(gate.is_open());
+    }
+}
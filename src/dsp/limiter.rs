@@ -0,0 +1,168 @@
+// Lookahead brickwall limiter that keeps every output sample at or below a
+// ceiling. The signal is delayed by a few milliseconds so gain reduction can
+// ramp down before a peak arrives instead of clipping it:
+//
+// 1. each incoming frame gets the gain that would bring its peak to the
+//    ceiling;
+// 2. that gain is held at the minimum over the lookahead window, so a peak
+//    is known about for its whole approach;
+// 3. the held gain recovers slowly after the peak (release), and drops
+//    instantly;
+// 4. a moving average over the lookahead smooths the ramps. Every value in
+//    the average is at most the gain the delayed frame needs, so the
+//    average is too, and the ceiling holds exactly.
+
+use std::collections::VecDeque;
+
+pub const DEFAULT_LIMITER_CEILING_DB: f32 = -1.0;
+
+const LOOKAHEAD_MS: f32 = 5.0;
+const RELEASE_MS: f32 = 100.0;
+
+#[derive(Clone)]
+pub struct Limiter {
+    channels: usize,
+    lookahead: usize,
+    ceiling: f32,
+    release_coef: f32,
+    // Delayed samples, `lookahead` frames of them
+    delay: VecDeque<f32>,
+    // Sliding-window minimum of the required gain as (frame, gain), with
+    // increasing gains from front to back
+    window: VecDeque<(usize, f32)>,
+    frame: usize,
+    held: f32,
+    // Last `lookahead` held gains and their sum, for the moving average
+    averaged: VecDeque<f32>,
+    sum: f64,
+    gain: f32,
+}
+
+impl Limiter {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        let lookahead = ((LOOKAHEAD_MS / 1000.0 * sample_rate as f32) as usize).max(1);
+        let release_samples = RELEASE_MS / 1000.0 * sample_rate.max(1) as f32;
+        let mut limiter = Self {
+            channels,
+            lookahead,
+            ceiling: 1.0,
+            release_coef: 1.0 - (-1.0 / release_samples.max(1.0)).exp(),
+            delay: VecDeque::from(vec![0.0; lookahead * channels]),
+            window: VecDeque::new(),
+            frame: 0,
+            held: 1.0,
+            averaged: VecDeque::from(vec![1.0; lookahead]),
+            sum: lookahead as f64,
+            gain: 1.0,
+        };
+        limiter.set_ceiling_db(DEFAULT_LIMITER_CEILING_DB);
+        limiter
+    }
+
+    // Highest level in dBFS any output sample may reach
+    pub fn set_ceiling_db(&mut self, ceiling_db: f32) {
+        self.ceiling = 10f32.powf(ceiling_db.min(0.0) / 20.0);
+    }
+
+    // Delay added by the lookahead, in interleaved samples
+    pub fn latency(&self) -> usize {
+        self.lookahead * self.channels
+    }
+
+    // Gain reduction applied to the last processed frame, in dB (0 or less)
+    pub fn gain_reduction_db(&self) -> f32 {
+        20.0 * self.gain.log10()
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(self.channels) {
+            let peak = frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+            let required = if peak > self.ceiling { self.ceiling / peak } else { 1.0 };
+
+            // Minimum over the frames from `lookahead` ago up to this one
+            while self.window.back().is_some_and(|&(_, gain)| gain >= required) {
+                self.window.pop_back();
+            }
+            self.window.push_back((self.frame, required));
+            while self.window.front().is_some_and(|&(frame, _)| frame + self.lookahead < self.frame) {
+                self.window.pop_front();
+            }
+            let minimum = self.window.front().map_or(1.0, |&(_, gain)| gain);
+            self.frame += 1;
+
+            self.held = if minimum < self.held {
+                minimum
+            } else {
+                self.held + self.release_coef * (minimum - self.held)
+            };
+
+            self.sum += self.held as f64;
+            self.averaged.push_back(self.held);
+            self.sum -= self.averaged.pop_front().unwrap_or(0.0) as f64;
+            // The running sum drifts by rounding; never let that lift the
+            // gain above what the window allows
+            self.gain = ((self.sum / self.lookahead as f64) as f32).min(minimum);
+
+            for sample in frame.iter_mut() {
+                self.delay.push_back(*sample);
+                *sample = self.delay.pop_front().unwrap_or(0.0) * self.gain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const RATE: u32 = 48000;
+
+    fn sine(amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len).map(|n| amplitude * (2.0 * PI * 440.0 * n as f32 / RATE as f32).sin()).collect()
+    }
+
+    fn limit(input: &[f32]) -> (Vec<f32>, usize) {
+        let mut limiter = Limiter::new(RATE, 1);
+        let mut output = input.to_vec();
+        for block in output.chunks_mut(480) {
+            limiter.process(block);
+        }
+        (output, limiter.latency())
+    }
+
+    #[test]
+    fn output_never_exceeds_the_ceiling() {
+        let ceiling = 10f32.powf(DEFAULT_LIMITER_CEILING_DB / 20.0);
+        // A tone 6 dB over full scale with a sudden 12 dB spike in the middle
+        let mut input = sine(2.0, RATE as usize);
+        for sample in &mut input[24000..24480] {
+            *sample *= 2.0;
+        }
+        let (output, latency) = limit(&input);
+        let peak = output.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(peak <= ceiling * (1.0 + 1e-5), "peaked at {}", peak);
+
+        // Held steady over the steady part of the tone, the gain leaves it
+        // an undistorted copy at the ceiling
+        let scale = ceiling / 2.0;
+        let (mut error, mut power) = (0.0, 0.0);
+        for n in 12000..20000 {
+            let expected = input[n - latency] * scale;
+            error += (output[n] - expected).powi(2);
+            power += expected * expected;
+        }
+        let distortion_db = 10.0 * (error / power).log10();
+        assert!(distortion_db < -40.0, "distortion at {:.1} dB", distortion_db);
+    }
+
+    #[test]
+    fn signal_under_the_ceiling_passes_delayed() {
+        let input = sine(0.5, RATE as usize / 10);
+        let (output, latency) = limit(&input);
+        for n in latency..output.len() {
+            assert!((output[n] - input[n - latency]).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+// Loudness metering after ITU-R BS.1770 as used by EBU R128, and a slow
+// normalizing gain built on it. The signal is K-weighted (a high shelf for
+// the head's effect plus a high-pass), squared and summed over channels, then
+// averaged over sliding windows: 400 ms for momentary and 3 s for short-term
+// loudness. Integrated loudness averages 400 ms blocks, overlapping by 75%,
+// over the whole measurement, leaving out blocks below -70 LUFS and then
+// those more than 10 LU below the average of the rest, so pauses don't drag
+// it down.
+
+use super::gain::Gain;
+use super::weighting::Biquad;
+use std::collections::VecDeque;
+
+pub const DEFAULT_LOUDNESS_TARGET_LUFS: f32 = -16.0;
+pub const MIN_LOUDNESS_TARGET_LUFS: f32 = -36.0;
+pub const MAX_LOUDNESS_TARGET_LUFS: f32 = -10.0;
+
+// Largest gain the normalizer applies either way
+pub const MAX_NORMALIZATION_GAIN_DB: f32 = 20.0;
+
+// Fastest the normalizing gain moves, so it rides the level rather than
+// compressing it
+const NORMALIZATION_DB_PER_SEC: f32 = 2.0;
+
+// Measurements advance in 100 ms steps: a momentary window is 4 of them and
+// a short-term window 30
+const STEP_MS: usize = 100;
+const MOMENTARY_STEPS: usize = 4;
+const SHORT_TERM_STEPS: usize = 30;
+
+const ABSOLUTE_GATE_LUFS: f32 = -70.0;
+const RELATIVE_GATE_LU: f32 = -10.0;
+
+// Loudness of a mean K-weighted power; -inf for silence
+fn lufs(power: f64) -> f32 {
+    if power > 0.0 {
+        (-0.691 + 10.0 * power.log10()) as f32
+    } else {
+        f32::NEG_INFINITY
+    }
+}
+
+// Mean K-weighted power of a loudness
+fn power(lufs: f32) -> f64 {
+    10f64.powf((lufs as f64 + 0.691) / 10.0)
+}
+
+// Loudness in LUFS; -inf until there is something to measure
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessReading {
+    pub momentary_lufs: f32,
+    pub short_term_lufs: f32,
+    pub integrated_lufs: f32,
+}
+
+impl Default for LoudnessReading {
+    fn default() -> Self {
+        Self {
+            momentary_lufs: f32::NEG_INFINITY,
+            short_term_lufs: f32::NEG_INFINITY,
+            integrated_lufs: f32::NEG_INFINITY,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: [Biquad; 2],
+    // Delay lines of both filter stages, per channel
+    state: Vec<[[f64; 2]; 2]>,
+    step_frames: usize,
+    // K-weighted power summed over channels, and frames, of the current step
+    step_sum: f64,
+    step_count: usize,
+    // Mean power of the latest steps, newest last
+    steps: VecDeque<f64>,
+    // Power of every 400 ms gating block above the absolute gate so far
+    blocks: Vec<f64>,
+    reading: LoudnessReading,
+}
+
+impl LoudnessMeter {
+    // Every channel counts fully, as BS.1770 has it for mono, stereo and
+    // the front channels of surround layouts
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        let sample_rate = sample_rate.max(1);
+        Self {
+            channels,
+            filters: Biquad::k_weighting(sample_rate as f64),
+            state: vec![[[0.0; 2]; 2]; channels],
+            step_frames: (sample_rate as usize * STEP_MS / 1000).max(1),
+            step_sum: 0.0,
+            step_count: 0,
+            steps: VecDeque::with_capacity(SHORT_TERM_STEPS),
+            blocks: Vec::new(),
+            reading: LoudnessReading::default(),
+        }
+    }
+
+    // Loudness as of the last complete 100 ms step
+    pub fn reading(&self) -> LoudnessReading {
+        self.reading
+    }
+
+    // Starts the integrated measurement over; the sliding windows carry on
+    pub fn reset_integrated(&mut self) {
+        self.blocks.clear();
+        self.reading.integrated_lufs = f32::NEG_INFINITY;
+    }
+
+    // Measures a block of interleaved samples without changing it. Returns
+    // true when a step completed, i.e. the reading changed.
+    pub fn process(&mut self, samples: &[f32]) -> bool {
+        let mut stepped = false;
+        for frame in samples.chunks_exact(self.channels) {
+            for (&sample, state) in frame.iter().zip(&mut self.state) {
+                let shelved = self.filters[0].process(sample as f64, &mut state[0]);
+                let weighted = self.filters[1].process(shelved, &mut state[1]);
+                self.step_sum += weighted * weighted;
+            }
+            self.step_count += 1;
+            if self.step_count == self.step_frames {
+                self.complete_step();
+                stepped = true;
+            }
+        }
+        stepped
+    }
+
+    fn complete_step(&mut self) {
+        if self.steps.len() == SHORT_TERM_STEPS {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(self.step_sum / self.step_count as f64);
+        self.step_sum = 0.0;
+        self.step_count = 0;
+
+        let mean = |steps: usize| {
+            let recent = self.steps.iter().rev().take(steps);
+            recent.clone().sum::<f64>() / recent.count() as f64
+        };
+        let momentary = mean(MOMENTARY_STEPS);
+        self.reading.momentary_lufs = lufs(momentary);
+        self.reading.short_term_lufs = lufs(mean(SHORT_TERM_STEPS));
+
+        // Each step completes a gating block: the momentary window
+        if self.steps.len() >= MOMENTARY_STEPS && self.reading.momentary_lufs > ABSOLUTE_GATE_LUFS {
+            self.blocks.push(momentary);
+            let ungated = self.blocks.iter().sum::<f64>() / self.blocks.len() as f64;
+            let threshold = power(lufs(ungated) + RELATIVE_GATE_LU);
+            let (sum, count) = self.blocks.iter()
+                .filter(|&&block| block > threshold)
+                .fold((0.0, 0usize), |(sum, count), block| (sum + block, count + 1));
+            self.reading.integrated_lufs = if count > 0 { lufs(sum / count as f64) } else { f32::NEG_INFINITY };
+        }
+    }
+}
+
+// Gain that slowly brings the short-term loudness of its input to a target.
+// It only moves while the input is active, i.e. its momentary loudness is
+// within 10 LU of the short-term one, so pauses don't make it creep up.
+#[derive(Clone)]
+pub struct LoudnessNormalizer {
+    meter: LoudnessMeter,
+    samples_per_second: f32,
+    target_lufs: f32,
+    gain_db: f32,
+    gain: Gain,
+}
+
+impl LoudnessNormalizer {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            meter: LoudnessMeter::new(sample_rate, channels),
+            samples_per_second: sample_rate.max(1) as f32 * channels.max(1) as f32,
+            target_lufs: DEFAULT_LOUDNESS_TARGET_LUFS,
+            gain_db: 0.0,
+            gain: Gain::new(sample_rate, channels),
+        }
+    }
+
+    pub fn set_target_lufs(&mut self, target_lufs: f32) {
+        self.target_lufs = target_lufs.clamp(MIN_LOUDNESS_TARGET_LUFS, MAX_LOUDNESS_TARGET_LUFS);
+    }
+
+    // Gain currently applied, in dB
+    pub fn gain_db(&self) -> f32 {
+        self.gain_db
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        self.meter.process(samples);
+        let reading = self.meter.reading();
+        let active = reading.short_term_lufs > ABSOLUTE_GATE_LUFS
+            && reading.momentary_lufs > reading.short_term_lufs + RELATIVE_GATE_LU;
+        if active {
+            let wanted = (self.target_lufs - reading.short_term_lufs)
+                .clamp(-MAX_NORMALIZATION_GAIN_DB, MAX_NORMALIZATION_GAIN_DB);
+            let max_step = NORMALIZATION_DB_PER_SEC * samples.len() as f32 / self.samples_per_second;
+            self.gain_db += (wanted - self.gain_db).clamp(-max_step, max_step);
+        }
+        self.gain.set_gain_db(self.gain_db);
+        self.gain.process(samples);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stereo 1 kHz sine peaking at `level_db` dBFS in both channels
+    fn stereo_tone(level_db: f32, seconds: usize) -> Vec<f32> {
+        let amplitude = 10f32.powf(level_db / 20.0);
+        (0..48000 * seconds)
+            .flat_map(|n| {
+                let sample = amplitude * (2.0 * std::f32::consts::PI * 1000.0 * n as f32 / 48000.0).sin();
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    // Cases 1 and 2 of EBU Tech 3341: the tone reads as its level in dBFS,
+    // within the 0.1 LU the spec allows
+    #[test]
+    fn reference_tones_read_at_their_level() {
+        for level in [-23.0, -33.0] {
+            let mut meter = LoudnessMeter::new(48000, 2);
+            for block in stereo_tone(level, 20).chunks(960) {
+                meter.process(block);
+            }
+            let reading = meter.reading();
+            for (name, lufs) in [
+                ("momentary", reading.momentary_lufs),
+                ("short-term", reading.short_term_lufs),
+                ("integrated", reading.integrated_lufs),
+            ] {
+                assert!((lufs - level).abs() < 0.1, "{} loudness {:.2} LUFS for a {} dBFS tone", name, lufs, level);
+            }
+        }
+    }
+
+    #[test]
+    fn silence_is_gated_out_of_the_integrated_loudness() {
+        let mut meter = LoudnessMeter::new(48000, 2);
+        meter.process(&stereo_tone(-23.0, 10));
+        meter.process(&vec![0.0; 48000 * 2 * 10]);
+        let integrated = meter.reading().integrated_lufs;
+        assert!((integrated + 23.0).abs() < 0.1, "integrated {:.2} LUFS", integrated);
+    }
+}
@@ -0,0 +1,476 @@
+pub mod agc;
+pub mod alignment;
+pub mod backend;
+pub mod calibration;
+pub mod channels;
+pub mod click;
+pub mod comfort;
+pub mod compressor;
+pub mod deesser;
+pub mod delay;
+pub mod echo;
+pub mod expander;
+pub mod frames;
+pub mod gain;
+pub mod gate;
+pub mod highpass;
+pub mod limiter;
+pub mod loudness;
+pub mod noise;
+pub mod resample;
+#[cfg(feature = "rnnoise")]
+pub mod rnnoise;
+pub mod spectral;
+pub mod spectrum;
+pub mod tone;
+pub mod vad;
+pub mod weighting;
+#[cfg(feature = "webrtc-apm")]
+pub mod webrtc;
+
+use crate::settings::ProcessorSettings;
+use agc::AutomaticGainControl;
+#[cfg(feature = "webrtc-apm")]
+use backend::ProcessingBackend;
+use click::ClickSuppressor;
+use comfort::ComfortNoise;
+use compressor::Compressor;
+use deesser::DeEsser;
+use delay::DelayLine;
+use echo::{EchoCanceller, DEFAULT_ECHO_TAPS};
+use expander::Expander;
+use gain::Gain;
+use gate::NoiseGate;
+use highpass::HighPassFilter;
+use limiter::Limiter;
+use loudness::LoudnessNormalizer;
+use spectral::{NoiseReductionMode, SpectralState};
+use spectrum::SpectrumAnalyzer;
+use vad::VoiceActivityDetector;
+
+// Silence after which power saving stops transforming the spectral frames
+pub const DEFAULT_POWER_SAVE_HOLD_MS: f32 = 1000.0;
+pub const MAX_POWER_SAVE_HOLD_MS: f32 = 10000.0;
+
+// Length of the cross-fade when echo cancellation or noise reduction is
+// switched on or off, which would otherwise click
+pub const SWITCH_CROSSFADE_MS: f32 = 20.0;
+
+// A switch of echo cancellation or noise reduction in progress. A copy of the
+// state keeps running the chain as it was, and its output fades into that of
+// the switched chain.
+#[derive(Clone)]
+struct Transition {
+    previous: DspState,
+    echo_cancellation: bool,
+    noise_reduction: bool,
+    // Frames before the fade starts, while a chain with more latency than
+    // before fills up, the frames since the switch and the fade length
+    delay: usize,
+    elapsed: usize,
+    length: usize,
+}
+
+// Everything the DSP chain carries from one block to the next. Owned by
+// whoever drives the chain: the processing task or an offline file run.
+#[derive(Clone)]
+pub struct DspState {
+    pub input_gain: Gain,
+    pub output_gain: Gain,
+    // Mic block of the last call after the input gain, as the rest of the
+    // chain saw it
+    pub input: Vec<f32>,
+    pub echo: EchoCanceller,
+    // Shifts the echo reference by the bulk delay of its path into the mic
+    pub reference: DelayLine,
+    pub spectral: SpectralState,
+    pub deesser: DeEsser,
+    pub click: ClickSuppressor,
+    pub vad: VoiceActivityDetector,
+    // Spectra of the input and output for display
+    pub analyzer: SpectrumAnalyzer,
+    pub expander: Expander,
+    pub gate: NoiseGate,
+    pub comfort: ComfortNoise,
+    pub compressor: Compressor,
+    pub highpass: HighPassFilter,
+    pub agc: AutomaticGainControl,
+    pub normalizer: LoudnessNormalizer,
+    pub limiter: Limiter,
+    // Unprocessed mic signal, delayed to line up with the processed one for
+    // the dry/wet mix
+    pub dry: DelayLine,
+    // The WebRTC module while it is the backend
+    #[cfg(feature = "webrtc-apm")]
+    pub webrtc: webrtc::WebRtcSlot,
+    // RNNoise's networks while it is the noise reduction mode, kept while
+    // noise reduction is off so switching back resumes them
+    #[cfg(feature = "rnnoise")]
+    pub rnnoise: Option<rnnoise::RnNoise>,
+    // Non-finite samples replaced with silence so far
+    sanitized: u64,
+    // Interleaved samples since voice was last detected
+    silent_samples: usize,
+    // Echo cancellation and noise reduction as of the last block, and the
+    // cross-fade after one of them was switched
+    switches: Option<(bool, bool)>,
+    transition: Option<Box<Transition>>,
+    sample_rate: u32,
+    channels: usize,
+}
+
+impl DspState {
+    // Spectral frames of `fft_size` advancing by `hop_size`, 0 for half a
+    // frame, both per channel as in `ProcessorSettings`
+    pub fn new(fft_size: usize, hop_size: usize, sample_rate: u32, channels: u16) -> Self {
+        Self {
+            input_gain: Gain::new(sample_rate, channels),
+            output_gain: Gain::new(sample_rate, channels),
+            input: Vec::new(),
+            echo: EchoCanceller::new(DEFAULT_ECHO_TAPS, channels),
+            reference: DelayLine::new(0),
+            spectral: SpectralState::with_hop(fft_size, hop_size, channels),
+            deesser: DeEsser::new(sample_rate, channels),
+            click: ClickSuppressor::new(sample_rate, channels),
+            vad: VoiceActivityDetector::new(),
+            analyzer: SpectrumAnalyzer::new(channels),
+            expander: Expander::new(sample_rate, channels),
+            gate: NoiseGate::new(sample_rate, channels),
+            comfort: ComfortNoise::new(fft_size, channels),
+            compressor: Compressor::new(sample_rate, channels),
+            highpass: HighPassFilter::new(sample_rate, channels),
+            agc: AutomaticGainControl::new(sample_rate, channels),
+            normalizer: LoudnessNormalizer::new(sample_rate, channels),
+            limiter: Limiter::new(sample_rate, channels),
+            dry: DelayLine::new(0),
+            #[cfg(feature = "webrtc-apm")]
+            webrtc: webrtc::WebRtcSlot::default(),
+            #[cfg(feature = "rnnoise")]
+            rnnoise: None,
+            sanitized: 0,
+            silent_samples: 0,
+            switches: None,
+            transition: None,
+            sample_rate,
+            channels: channels.max(1) as usize,
+        }
+    }
+
+    // Forgets everything learned from the signal so far (echo filter, noise
+    // estimate, overlap buffers, envelopes), e.g. for a new input device.
+    // The gain trims keep their current level so nothing jumps; the other
+    // settings need `apply_settings` again.
+    pub fn reset(&mut self, fft_size: usize, hop_size: usize) {
+        let mut fresh = Self::new(fft_size, hop_size, self.sample_rate, self.channels as u16);
+        std::mem::swap(&mut fresh.input_gain, &mut self.input_gain);
+        std::mem::swap(&mut fresh.output_gain, &mut self.output_gain);
+        fresh.sanitized = self.sanitized;
+        *self = fresh;
+    }
+
+    // Replaces NaN and infinite samples with silence and flushes denormals
+    // to zero, counting the former. One NaN reaching a filter or estimator
+    // would stay in its state for good, and infinities play as a full-scale
+    // burst; denormals only cost time, on CPUs that handle them slowly.
+    pub fn sanitize(&mut self, samples: &mut [f32]) {
+        self.sanitized += sanitize(samples) as u64;
+    }
+
+    // Non-finite samples `sanitize` replaced since the state was created
+    pub fn sanitized_samples(&self) -> u64 {
+        self.sanitized
+    }
+
+    // Pushes the tunable parameters down to the individual stages. Structural
+    // settings such as the FFT size need a new state instead.
+    pub fn apply_settings(&mut self, settings: &ProcessorSettings) {
+        self.input_gain.set_gain_db(settings.input_gain_db);
+        self.output_gain.set_gain_db(settings.output_gain_db);
+        self.echo.set_step_size(settings.echo_step_size);
+        self.reference.set_delay(settings.reference_delay_samples * self.channels);
+        self.spectral.set_noise_reduction_enabled(settings.noise_reduction);
+        #[cfg(feature = "rnnoise")]
+        self.configure_rnnoise(settings);
+        // Without the network (a build lacking it, or a rate it can't run
+        // at) the RNNoise mode falls back to spectral subtraction
+        self.spectral.set_mode(match settings.noise_reduction_mode {
+            NoiseReductionMode::RnNoise if !self.rnnoise_ready() => NoiseReductionMode::SpectralSubtraction,
+            mode => mode,
+        });
+        self.spectral.set_window(settings.fft_window);
+        self.spectral.set_strength(settings.noise_reduction_strength);
+        self.spectral.set_band_strengths(&settings.band_strengths);
+        self.spectral.set_floor(settings.spectral_floor);
+        self.spectral.set_noise_adaptation_rate(settings.noise_adaptation_rate);
+        self.deesser.set_threshold_db(settings.deesser_threshold_db);
+        self.deesser.set_range_db(settings.deesser_range_db);
+        self.click.set_sensitivity(settings.click_sensitivity);
+        self.expander.set_threshold_db(settings.expander_threshold_db);
+        self.expander.set_ratio(settings.expander_ratio);
+        self.expander.set_knee_db(settings.expander_knee_db);
+        self.gate.set_threshold_db(settings.gate_threshold_db);
+        self.gate.set_attack_ms(settings.gate_attack_ms);
+        self.gate.set_hold_ms(settings.gate_hold_ms);
+        self.gate.set_release_ms(settings.gate_release_ms);
+        self.comfort.set_level_db(settings.comfort_noise_level_db);
+        self.compressor.set_threshold_db(settings.compressor_threshold_db);
+        self.compressor.set_ratio(settings.compressor_ratio);
+        self.compressor.set_attack_ms(settings.compressor_attack_ms);
+        self.compressor.set_release_ms(settings.compressor_release_ms);
+        self.compressor.set_makeup_db(settings.compressor_makeup_db);
+        self.compressor.set_knee_db(settings.compressor_knee_db);
+        self.highpass.set_cutoff_hz(settings.highpass_cutoff_hz);
+        self.agc.set_target_db(settings.agc_target_db);
+        self.agc.set_max_gain_db(settings.agc_max_gain_db);
+        self.normalizer.set_target_lufs(settings.loudness_target_lufs);
+        self.limiter.set_ceiling_db(settings.limiter_ceiling_db);
+        #[cfg(feature = "webrtc-apm")]
+        self.configure_webrtc(settings);
+    }
+
+    // Creates the WebRTC module once it is picked as the backend and drops
+    // it when it no longer is. At a rate the module doesn't support the
+    // built-in chain keeps running; `AudioProcessor::set_processing_backend`
+    // refuses such a switch up front.
+    #[cfg(feature = "webrtc-apm")]
+    fn configure_webrtc(&mut self, settings: &ProcessorSettings) {
+        if settings.backend != ProcessingBackend::WebRtc {
+            self.webrtc.0 = None;
+            return;
+        }
+        if self.webrtc.0.is_none() {
+            self.webrtc.0 = webrtc::WebRtcApm::new(self.sample_rate, self.channels as u16).ok();
+        }
+        if let Some(apm) = &mut self.webrtc.0 {
+            apm.configure(settings);
+        }
+    }
+
+    // Creates RNNoise's networks once it is picked as the mode and drops
+    // them when it no longer is
+    #[cfg(feature = "rnnoise")]
+    fn configure_rnnoise(&mut self, settings: &ProcessorSettings) {
+        let mode = settings.noise_reduction_mode;
+        if mode != NoiseReductionMode::RnNoise || !mode.supports_sample_rate(self.sample_rate) {
+            self.rnnoise = None;
+        } else if self.rnnoise.is_none() {
+            self.rnnoise = Some(rnnoise::RnNoise::new(self.channels as u16));
+        }
+    }
+
+    // Whether the RNNoise mode has its networks to run
+    #[cfg(feature = "rnnoise")]
+    fn rnnoise_ready(&self) -> bool {
+        self.rnnoise.is_some()
+    }
+
+    #[cfg(not(feature = "rnnoise"))]
+    fn rnnoise_ready(&self) -> bool {
+        false
+    }
+
+    // Delay RNNoise adds under `settings`, in interleaved samples
+    #[cfg(feature = "rnnoise")]
+    pub fn rnnoise_latency(&self, settings: &ProcessorSettings) -> usize {
+        match &self.rnnoise {
+            Some(rnnoise) if settings.noise_reduction => rnnoise.latency(),
+            _ => 0,
+        }
+    }
+
+    #[cfg(not(feature = "rnnoise"))]
+    pub fn rnnoise_latency(&self, _settings: &ProcessorSettings) -> usize {
+        0
+    }
+
+    // Whether the WebRTC module processes blocks under `settings`
+    #[cfg(feature = "webrtc-apm")]
+    pub fn webrtc_active(&self, settings: &ProcessorSettings) -> bool {
+        settings.backend == ProcessingBackend::WebRtc && self.webrtc.0.is_some()
+    }
+
+    #[cfg(not(feature = "webrtc-apm"))]
+    pub fn webrtc_active(&self, _settings: &ProcessorSettings) -> bool {
+        false
+    }
+
+    // Puts the spectral frames to sleep once no voice has been heard for the
+    // power save hold, and wakes them on the first block with voice. A
+    // noise profile being learned keeps them awake.
+    pub fn update_power_save(&mut self, settings: &ProcessorSettings, voice: bool, samples: usize) {
+        self.silent_samples = if voice { 0 } else { self.silent_samples.saturating_add(samples) };
+        let hold = (settings.power_save_hold_ms / 1000.0 * self.sample_rate as f32) as usize * self.channels;
+        let idle = settings.power_save
+            && self.silent_samples >= hold
+            && self.spectral.noise_learning_progress().is_none();
+        self.spectral.set_idle(idle);
+    }
+
+    // Whether the spectral frames currently skip their transforms
+    pub fn is_power_saving(&self) -> bool {
+        self.spectral.is_idle()
+    }
+
+    // Delay the enabled stages add on top of block accumulation, in samples
+    pub fn latency(&self, settings: &ProcessorSettings) -> usize {
+        let limiter = if settings.limiter_enabled { self.limiter.latency() } else { 0 };
+        #[cfg(feature = "webrtc-apm")]
+        if let Some(apm) = self.webrtc.0.as_ref().filter(|_| settings.backend == ProcessingBackend::WebRtc) {
+            return apm.latency() + limiter;
+        }
+        let spectral = if settings.spectral_enabled() { self.spectral.latency() } else { 0 };
+        spectral + self.rnnoise_latency(settings) + limiter
+    }
+
+    // Called before a block runs through the chain with `settings`. When
+    // echo cancellation or noise reduction was switched since the last
+    // block, a copy of the state starts a fade from the chain as it was; for
+    // as long as that lasts, `process` runs the block through it with the
+    // switches as they were and returns what it made. Cloning allocates, but
+    // only once per switch.
+    pub(crate) fn run_previous(
+        &mut self,
+        settings: &ProcessorSettings,
+        process: impl FnOnce(&ProcessorSettings, &mut DspState) -> Vec<f32>,
+    ) -> Option<Vec<f32>> {
+        // The WebRTC module switches its submodules itself, and a copy of the
+        // chain couldn't feed it a second time
+        if self.webrtc_active(settings) {
+            self.switches = None;
+            self.transition = None;
+            return None;
+        }
+        let switches = (settings.echo_cancellation, settings.noise_reduction);
+        if let Some(before) = self.switches.replace(switches).filter(|&before| before != switches) {
+            // Switching again mid-fade starts over from the chain as it is
+            self.transition = None;
+            // Settings reach the stages ahead of the block, so the copy's
+            // noise reduction is already switched and goes back
+            let mut previous = self.clone();
+            previous.switches = Some(before);
+            previous.spectral.set_noise_reduction_enabled(before.1);
+            let mut before_settings = settings.clone();
+            (before_settings.echo_cancellation, before_settings.noise_reduction) = before;
+            let delay = self.latency(settings).saturating_sub(self.latency(&before_settings));
+            self.transition = Some(Box::new(Transition {
+                previous,
+                echo_cancellation: before.0,
+                noise_reduction: before.1,
+                delay: delay / self.channels,
+                elapsed: 0,
+                length: (SWITCH_CROSSFADE_MS / 1000.0 * self.sample_rate as f32).round().max(1.0) as usize,
+            }));
+        }
+
+        let transition = self.transition.as_mut()?;
+        let mut before_settings = settings.clone();
+        before_settings.echo_cancellation = transition.echo_cancellation;
+        before_settings.noise_reduction = transition.noise_reduction;
+        Some(process(&before_settings, &mut transition.previous))
+    }
+
+    // Fades `processed` in from what `run_previous` returned for the same
+    // block, ending the transition once the fade is through
+    pub(crate) fn blend_previous(&mut self, previous: &[f32], processed: &mut [f32]) {
+        let Some(transition) = self.transition.as_mut() else {
+            return;
+        };
+        for (frame, (samples, previous)) in processed.chunks_mut(self.channels)
+            .zip(previous.chunks(self.channels))
+            .enumerate()
+        {
+            let faded = (transition.elapsed + frame).saturating_sub(transition.delay);
+            let t = (faded as f32 / transition.length as f32).min(1.0);
+            for (sample, &previous) in samples.iter_mut().zip(previous) {
+                *sample = previous * (1.0 - t) + *sample * t;
+            }
+        }
+        transition.elapsed += processed.len() / self.channels;
+        if transition.elapsed >= transition.delay + transition.length {
+            self.transition = None;
+        }
+    }
+}
+
+// Zeroes non-finite and denormal samples, returning how many were non-finite
+pub fn sanitize(samples: &mut [f32]) -> usize {
+    let mut non_finite = 0;
+    for sample in samples {
+        if !sample.is_finite() {
+            *sample = 0.0;
+            non_finite += 1;
+        } else if sample.is_subnormal() {
+            *sample = 0.0;
+        }
+    }
+    non_finite
+}
+
+// Fades from `from` into `to` over the first `length` samples of `to`, e.g.
+// when switching between two renderings of the same audio
+pub fn crossfade(from: &[f32], to: &mut [f32], length: usize) {
+    let length = length.min(to.len()).max(1);
+    for (i, (to, &from)) in to.iter_mut().zip(from).take(length).enumerate() {
+        let t = i as f32 / length as f32;
+        *to = from * (1.0 - t) + *to * t;
+    }
+}
+
+// Hard ceiling on the output for hearing protection, e.g. against an echo
+// canceller diverging into full-scale noise while wearing headphones. Unlike
+// the limiter it doesn't shape anything and can't be part of a preset; it
+// only stops what gets past everything else.
+pub const DEFAULT_SAFETY_CEILING_DB: f32 = -3.0;
+pub const MIN_SAFETY_CEILING_DB: f32 = -24.0;
+
+// Clamps every sample to +/- `ceiling_db` dBFS, returning how many were over
+pub fn clamp_to_ceiling(samples: &mut [f32], ceiling_db: f32) -> usize {
+    let ceiling = 10f32.powf(ceiling_db.min(0.0) / 20.0);
+    let mut clamped = 0;
+    for sample in samples {
+        if sample.abs() > ceiling {
+            *sample = sample.clamp(-ceiling, ceiling);
+            clamped += 1;
+        }
+    }
+    clamped
+}
+
+// Adds `source` onto the start of `target`, e.g. one more app audio source
+// onto the echo reference
+pub fn mix_into(target: &mut [f32], source: &[f32]) {
+    for (sample, added) in target.iter_mut().zip(source) {
+        *sample += added;
+    }
+}
+
+// Length of the fade when the output is muted or unmuted
+pub const MUTE_FADE_MS: f32 = 5.0;
+
+// Silences a block while `muted`. On the block where muting starts or ends,
+// the first `length` samples fade out or in instead of cutting, which would
+// click.
+pub fn apply_mute(samples: &mut [f32], muted: bool, was_muted: bool, length: usize) {
+    if !muted && !was_muted {
+        return;
+    }
+    if muted == was_muted {
+        samples.fill(0.0);
+    } else if muted {
+        let length = length.min(samples.len()).max(1);
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample *= 1.0 - (i as f32 / length as f32).min(1.0);
+        }
+    } else {
+        fade_in(samples, length);
+    }
+}
+
+// Ramps the first `length` samples up from silence, e.g. where audio
+// resumes after a gap
+pub fn fade_in(samples: &mut [f32], length: usize) {
+    let length = length.min(samples.len()).max(1);
+    for (i, sample) in samples.iter_mut().take(length).enumerate() {
+        *sample *= i as f32 / length as f32;
+    }
+}
@@ -0,0 +1,162 @@
+// Per-bin noise power estimation by minimum statistics: each bin's power is
+// smoothed over time and the noise floor is the minimum of that smoothed
+// power over a sliding window of recent frames. The minimum drops as soon as
+// a pause reveals the floor, and holds through loud speech until the window
+// slides past the last quiet frame.
+
+pub const DEFAULT_NOISE_ADAPTATION_RATE: f32 = 0.2;
+
+// Quiet stretch a noise profile is learned from by default
+pub const DEFAULT_NOISE_LEARNING_MS: u32 = 2000;
+
+// The sliding window is split into sub-windows so only one minimum per
+// sub-window has to be kept; 8 x 12 frames is roughly one second of 512-sample
+// hops at 48kHz
+const SUBWINDOWS: usize = 8;
+const SUBWINDOW_FRAMES: usize = 12;
+
+// The minimum of a fluctuating power sits below its mean, compensate for that
+const MINIMUM_BIAS: f32 = 1.5;
+
+#[derive(Clone)]
+pub struct NoiseEstimator {
+    smoothed: Vec<f32>,
+    current_min: Vec<f32>,
+    window_mins: Vec<Vec<f32>>,
+    noise: Vec<f32>,
+    frames_in_subwindow: usize,
+    next_subwindow: usize,
+    initialized: bool,
+    adaptation_rate: f32,
+}
+
+impl NoiseEstimator {
+    pub fn new(bins: usize) -> Self {
+        Self {
+            smoothed: vec![0.0; bins],
+            current_min: vec![f32::INFINITY; bins],
+            window_mins: vec![vec![f32::INFINITY; bins]; SUBWINDOWS],
+            noise: vec![0.0; bins],
+            frames_in_subwindow: 0,
+            next_subwindow: 0,
+            initialized: false,
+            adaptation_rate: DEFAULT_NOISE_ADAPTATION_RATE,
+        }
+    }
+
+    // Weight of the newest frame in the smoothed power, in (0, 1]. Higher
+    // values track changing noise faster but make the floor jumpier.
+    pub fn set_adaptation_rate(&mut self, rate: f32) {
+        self.adaptation_rate = rate.clamp(f32::EPSILON, 1.0);
+    }
+
+    // Feeds one frame of bin powers (|X|^2) into the estimate
+    pub fn update(&mut self, power: impl Iterator<Item = f32>) {
+        let rate = self.adaptation_rate;
+        let initialized = self.initialized;
+
+        for ((smoothed, current_min), p) in self.smoothed.iter_mut()
+            .zip(self.current_min.iter_mut())
+            .zip(power)
+        {
+            *smoothed = if initialized {
+                (1.0 - rate) * *smoothed + rate * p
+            } else {
+                p
+            };
+            *current_min = current_min.min(*smoothed);
+        }
+        self.initialized = true;
+
+        for (k, noise) in self.noise.iter_mut().enumerate() {
+            let window_min = self.window_mins.iter()
+                .map(|mins| mins[k])
+                .fold(self.current_min[k], f32::min);
+            *noise = MINIMUM_BIAS * window_min;
+        }
+
+        self.frames_in_subwindow += 1;
+        if self.frames_in_subwindow == SUBWINDOW_FRAMES {
+            self.window_mins[self.next_subwindow].copy_from_slice(&self.current_min);
+            self.current_min.fill(f32::INFINITY);
+            self.next_subwindow = (self.next_subwindow + 1) % SUBWINDOWS;
+            self.frames_in_subwindow = 0;
+        }
+    }
+
+    // Estimated noise power per bin
+    pub fn noise_power(&self) -> &[f32] {
+        &self.noise
+    }
+}
+
+// Noise power per bin learned from a stretch of the user staying quiet. It
+// replaces the running estimate as the reference that noise reduction
+// subtracts, which avoids the bias and lag of minimum statistics for noise
+// that doesn't change, e.g. a fan or hum.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseProfile {
+    power: Vec<f32>,
+}
+
+impl NoiseProfile {
+    // `power` per FFT bin of one channel's frame
+    pub fn new(power: Vec<f32>) -> Self {
+        Self { power }
+    }
+
+    pub fn power(&self) -> &[f32] {
+        &self.power
+    }
+
+    // Frame length the profile was learned with; it only applies to the same
+    pub fn bins(&self) -> usize {
+        self.power.len()
+    }
+
+    // The same noise after an input gain change of `gain_db`
+    pub fn scaled(&self, gain_db: f32) -> Self {
+        let factor = 10f32.powf(gain_db / 10.0);
+        Self { power: self.power.iter().map(|p| p * factor).collect() }
+    }
+}
+
+// Averages the power spectra of `frames` frames into a profile
+#[derive(Clone)]
+pub struct NoiseLearning {
+    sum: Vec<f64>,
+    frames: usize,
+    target_frames: usize,
+}
+
+impl NoiseLearning {
+    pub fn new(bins: usize, frames: usize) -> Self {
+        Self { sum: vec![0.0; bins], frames: 0, target_frames: frames.max(1) }
+    }
+
+    // Adds one frame of bin powers (|X|^2); ignored once complete
+    pub fn push(&mut self, power: impl Iterator<Item = f32>) {
+        if self.is_complete() {
+            return;
+        }
+        for (sum, p) in self.sum.iter_mut().zip(power) {
+            *sum += p as f64;
+        }
+        self.frames += 1;
+    }
+
+    // Share of the frames collected, 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.frames as f32 / self.target_frames as f32
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.frames >= self.target_frames
+    }
+
+    // Mean power of the frames collected so far
+    pub fn profile(&self) -> NoiseProfile {
+        let frames = self.frames.max(1) as f64;
+        NoiseProfile::new(self.sum.iter().map(|&sum| (sum / frames) as f32).collect())
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+// Cutoff of the anti-aliasing low-pass, as a fraction of the lower rate's
+// Nyquist frequency, and the section Q factors of its 8th-order Butterworth
+// response
+const ANTI_ALIAS_CUTOFF: f32 = 0.9;
+const SECTION_Q: [f32; 4] = [0.509_796, 0.601_345, 0.899_976, 2.562_915];
+
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    // Feed-forward (b) and feedback (a) coefficients, normalized by a0
+    b: [f32; 3],
+    a: [f32; 2],
+}
+
+impl Biquad {
+    fn lowpass(cutoff_hz: f32, sample_rate: f32, q: f32) -> Self {
+        let omega = 2.0 * PI * cutoff_hz / sample_rate;
+        let alpha = omega.sin() / (2.0 * q);
+        let cos = omega.cos();
+        let a0 = 1.0 + alpha;
+        Self {
+            b: [(1.0 - cos) / 2.0 / a0, (1.0 - cos) / a0, (1.0 - cos) / 2.0 / a0],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        }
+    }
+
+    // One sample through the section; `state` holds w[n-1] and w[n-2]
+    fn process(&self, x: f32, state: &mut [f32; 2]) -> f32 {
+        let w = x - self.a[0] * state[0] - self.a[1] * state[1];
+        let y = self.b[0] * w + self.b[1] * state[0] + self.b[2] * state[1];
+        *state = [w, state[0]];
+        y
+    }
+}
+
+// Low-pass below the lower of the two rates' Nyquist frequencies, run at the
+// higher rate: on the input when resampling down, where anything above the
+// new Nyquist frequency would fold back into the audio, and on the output
+// when resampling up, where interpolation leaves images of the spectrum above
+// the old one
+struct AntiAliasFilter {
+    sections: [Biquad; 4],
+    // Delay lines of every section, per channel
+    state: Vec<[[f32; 2]; 4]>,
+}
+
+impl AntiAliasFilter {
+    fn new(from_rate: u32, to_rate: u32, channels: usize) -> Self {
+        let cutoff_hz = ANTI_ALIAS_CUTOFF * from_rate.min(to_rate) as f32 / 2.0;
+        let sample_rate = from_rate.max(to_rate) as f32;
+        Self {
+            sections: SECTION_Q.map(|q| Biquad::lowpass(cutoff_hz, sample_rate, q)),
+            state: vec![[[0.0; 2]; 4]; channels],
+        }
+    }
+
+    fn process(&mut self, channel: usize, sample: f32) -> f32 {
+        self.sections.iter()
+            .zip(&mut self.state[channel])
+            .fold(sample, |x, (section, state)| section.process(x, state))
+    }
+}
+
+// Streaming linear-interpolation resampler for interleaved audio. It keeps
+// the last input frame between calls so consecutive blocks join seamlessly,
+// and emits samples through a callback so it never allocates in the realtime
+// path once its buffer has grown to the block size.
+pub struct LinearResampler {
+    channels: usize,
+    step: f64,
+    position: f64,
+    previous: Vec<f32>,
+    filter: Option<AntiAliasFilter>,
+    // The input filtered when resampling down
+    filtered: Vec<f32>,
+    // Channel of the next output sample, when filtering the output
+    output_channel: usize,
+}
+
+impl LinearResampler {
+    pub fn new(from_rate: u32, to_rate: u32, channels: usize) -> Self {
+        Self {
+            channels: channels.max(1),
+            step: from_rate as f64 / to_rate as f64,
+            position: 0.0,
+            previous: vec![0.0; channels.max(1)],
+            filter: (from_rate != to_rate).then(|| AntiAliasFilter::new(from_rate, to_rate, channels.max(1))),
+            filtered: Vec::new(),
+            output_channel: 0,
+        }
+    }
+
+    pub fn process(&mut self, input: &[f32], mut emit: impl FnMut(f32)) {
+        let Some(mut filter) = self.filter.take() else {
+            self.interpolate(input, emit);
+            return;
+        };
+        let channels = self.channels;
+        if self.step > 1.0 {
+            let mut filtered = std::mem::take(&mut self.filtered);
+            filtered.clear();
+            filtered.extend(input.iter().enumerate().map(|(i, &sample)| filter.process(i % channels, sample)));
+            self.interpolate(&filtered, emit);
+            self.filtered = filtered;
+        } else {
+            let mut channel = self.output_channel;
+            self.interpolate(input, |sample| {
+                emit(filter.process(channel, sample));
+                channel = (channel + 1) % channels;
+            });
+            self.output_channel = channel;
+        }
+        self.filter = Some(filter);
+    }
+
+    fn interpolate(&mut self, input: &[f32], mut emit: impl FnMut(f32)) {
+        let channels = self.channels;
+        let input_frames = input.len() / channels;
+        if input_frames == 0 {
+            return;
+        }
+
+        // Frame 0 is the last frame of the previous call, frame n is input frame n - 1
+        let frame = |index: usize, channel: usize| -> f32 {
+            if index == 0 {
+                self.previous[channel]
+            } else {
+                input[(index - 1) * channels + channel]
+            }
+        };
+
+        let total_frames = input_frames + 1;
+        while self.position + 1.0 < total_frames as f64 {
+            let index = self.position as usize;
+            let fraction = (self.position - index as f64) as f32;
+            for channel in 0..channels {
+                let a = frame(index, channel);
+                let b = frame(index + 1, channel);
+                emit(a + (b - a) * fraction);
+            }
+            self.position += self.step;
+        }
+
+        self.position -= input_frames as f64;
+        self.previous
+            .copy_from_slice(&input[(input_frames - 1) * channels..input_frames * channels]);
+    }
+}
+
+// Pull-driven wrapper for output callbacks, which know how many samples they
+// need rather than how many they have. Input is fetched from `source` in
+// whole frames as needed and resampled output beyond the request is kept for
+// the next call.
+pub struct PullResampler {
+    resampler: LinearResampler,
+    step: f64,
+    input: Vec<f32>,
+    pending: VecDeque<f32>,
+}
+
+impl PullResampler {
+    pub fn new(from_rate: u32, to_rate: u32, channels: usize) -> Self {
+        Self {
+            resampler: LinearResampler::new(from_rate, to_rate, channels),
+            step: from_rate as f64 / to_rate as f64,
+            input: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    // Fills `output` with resampled audio and returns how many samples were
+    // written, fewer than requested only once `source` runs dry
+    pub fn read(&mut self, output: &mut [f32], mut source: impl FnMut(&mut [f32]) -> usize) -> usize {
+        let channels = self.resampler.channels;
+        while self.pending.len() < output.len() {
+            let missing_frames = (output.len() - self.pending.len()).div_ceil(channels);
+            // One extra frame covers the fractional read position
+            let frames = (missing_frames as f64 * self.step).ceil() as usize + 1;
+            self.input.resize(frames * channels, 0.0);
+
+            let read = source(&mut self.input) / channels * channels;
+            if read == 0 {
+                break;
+            }
+            let pending = &mut self.pending;
+            self.resampler.process(&self.input[..read], |sample| pending.push_back(sample));
+        }
+
+        let written = output.len().min(self.pending.len());
+        for (out, sample) in output.iter_mut().zip(self.pending.drain(..written)) {
+            *out = sample;
+        }
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    // Frequency from the rising zero crossings, interpolated between samples
+    fn measure_frequency(samples: &[f32], rate: f32) -> f32 {
+        let crossings: Vec<f32> = samples.windows(2).enumerate()
+            .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+            .map(|(n, pair)| n as f32 + pair[0] / (pair[0] - pair[1]))
+            .collect();
+        let periods = (crossings.len() - 1) as f32;
+        rate * periods / (crossings[crossings.len() - 1] - crossings[0])
+    }
+
+    #[test]
+    fn sine_keeps_its_frequency_from_44100_to_48000() {
+        let input: Vec<f32> = (0..44100 * 2).map(|n| 0.5 * (2.0 * PI * 1000.0 * n as f32 / 44100.0).sin()).collect();
+        let mut resampler = LinearResampler::new(44100, 48000, 1);
+        let mut output = Vec::new();
+        // Device-sized blocks that don't divide the ratio evenly
+        for block in input.chunks(441) {
+            resampler.process(block, |sample| output.push(sample));
+        }
+
+        assert!((output.len() as f32 - 96000.0).abs() < 100.0, "{} samples out", output.len());
+        let frequency = measure_frequency(&output[4800..], 48000.0);
+        assert!((frequency - 1000.0).abs() < 0.5, "came out at {} Hz", frequency);
+    }
+
+    #[test]
+    fn voice_band_tone_survives_processing_at_16000() {
+        // Capture at 48 kHz down to the internal rate, and back up in the
+        // output callback's blocks
+        let input: Vec<f32> = (0..48000 * 2).map(|n| 0.5 * (2.0 * PI * 1000.0 * n as f32 / 48000.0).sin()).collect();
+        let mut down = LinearResampler::new(48000, 16000, 1);
+        let mut internal = Vec::new();
+        for block in input.chunks(480) {
+            down.process(block, |sample| internal.push(sample));
+        }
+        let mut up = PullResampler::new(16000, 48000, 1);
+        let mut source = internal.iter().copied();
+        let mut output = Vec::new();
+        let mut block = [0.0; 256];
+        loop {
+            let written = up.read(&mut block, |buffer| {
+                let mut filled = 0;
+                for (slot, sample) in buffer.iter_mut().zip(&mut source) {
+                    *slot = sample;
+                    filled += 1;
+                }
+                filled
+            });
+            output.extend_from_slice(&block[..written]);
+            if written < block.len() {
+                break;
+            }
+        }
+
+        assert!((output.len() as f32 - input.len() as f32).abs() < 10.0, "{} samples out", output.len());
+        let settled = &output[4800..output.len() - 4800];
+        let frequency = measure_frequency(settled, 48000.0);
+        assert!((frequency - 1000.0).abs() < 0.5, "came out at {} Hz", frequency);
+        let peak = settled.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let level_db = 20.0 * (peak / 0.5).log10();
+        assert!(level_db.abs() < 0.5, "came out {:.2} dB off", level_db);
+    }
+}
@@ -0,0 +1,97 @@
+// RNNoise, through its pure-Rust port nnnoiseless, as a noise reduction
+// mode. A small recurrent network turns features of each frame into a gain
+// per band, which follows keyboard clatter or a barking dog that minimum
+// statistics are far too slow for. The network takes 480-sample (10 ms)
+// mono frames at 48 kHz in the 16-bit range, so blocks go through a
+// `FrameAdapter` and each channel gets its own network.
+
+use super::frames::FrameAdapter;
+use nnnoiseless::DenoiseState;
+
+// The network works on samples in the range of 16-bit integers
+const SCALE: f32 = 32768.0;
+
+#[derive(Clone)]
+pub struct RnNoise {
+    frames: FrameAdapter,
+    states: Vec<Box<DenoiseState<'static>>>,
+    output: Vec<f32>,
+}
+
+impl RnNoise {
+    // At `RNNOISE_SAMPLE_RATE`; the network can't run at any other
+    pub fn new(channels: u16) -> Self {
+        let channels = channels.max(1);
+        Self {
+            frames: FrameAdapter::new(DenoiseState::FRAME_SIZE, channels),
+            states: (0..channels).map(|_| DenoiseState::new()).collect(),
+            output: vec![0.0; DenoiseState::FRAME_SIZE],
+        }
+    }
+
+    // Delay of the output behind the input, in interleaved samples: a frame
+    // waiting to fill, and one more in the network's own overlap-add
+    pub fn latency(&self) -> usize {
+        2 * self.frames.latency()
+    }
+
+    // Denoises a block of interleaved samples, returning as many
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let states = &mut self.states;
+        let output = &mut self.output;
+        self.frames.process(samples, &[], |capture, _| {
+            for (channel, state) in capture.iter_mut().zip(states.iter_mut()) {
+                for sample in channel.iter_mut() {
+                    *sample *= SCALE;
+                }
+                state.process_frame(output, channel);
+                for (sample, &denoised) in channel.iter_mut().zip(output.iter()) {
+                    *sample = denoised / SCALE;
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // White noise through a one-pole low-pass, rumbling like a room or a
+    // fan; the network is much less sure what to make of flat hiss
+    fn room_noise(amplitude: f32, len: usize, mut seed: u32) -> Vec<f32> {
+        let mut last = 0.0;
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                last = ((seed >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0) * amplitude + 0.9 * last;
+                last
+            })
+            .collect()
+    }
+
+    fn power(samples: &[f32]) -> f32 {
+        samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32
+    }
+
+    #[test]
+    fn output_is_two_frames_late() {
+        // Too quiet for the network to touch, so it only shows the delay
+        let mut input = vec![0.0; 4800];
+        input[1000] = 1e-6;
+        let mut rnnoise = RnNoise::new(1);
+        let output: Vec<f32> = input.chunks(1024).flat_map(|block| rnnoise.process(block)).collect();
+        let peak = output.iter().enumerate().max_by(|a, b| a.1.abs().total_cmp(&b.1.abs())).unwrap().0;
+        assert_eq!(peak, 1000 + rnnoise.latency());
+    }
+
+    #[test]
+    fn steady_noise_is_reduced_without_speech() {
+        let noise = room_noise(0.004, 48000 * 4, 3);
+        let mut rnnoise = RnNoise::new(1);
+        let output: Vec<f32> = noise.chunks(1024).flat_map(|block| rnnoise.process(block)).collect();
+        // Past the first second, which the network takes to settle
+        let reduction_db = 10.0 * (power(&noise[48000..]) / power(&output[48000..])).log10();
+        assert!(reduction_db > 20.0, "noise only dropped by {:.1} dB", reduction_db);
+    }
+}
@@ -0,0 +1,935 @@
+use super::noise::{NoiseEstimator, NoiseLearning, NoiseProfile};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+// How the per-bin gain is derived from the noise estimate. The spectral modes
+// share the same windowing, noise tracking and overlap-add; RNNoise runs on
+// the time signal ahead of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoiseReductionMode {
+    // Subtracts the noise magnitude from each bin. Strong, but the bins that
+    // randomly poke above the estimate survive as "musical noise".
+    #[default]
+    SpectralSubtraction,
+    // Wiener gain from a decision-directed a-priori SNR estimate, which
+    // smooths the gain over time and leaves a steadier, quieter residual
+    Wiener,
+    // Spectral subtraction with its own strength per frequency band, so hiss
+    // in the highs can be removed harder than noise under the voice
+    MultiBand,
+    // RNNoise's recurrent network picks the gains, following noise that
+    // comes and goes (keys, barking) faster than the estimate can. Only in
+    // builds with the `rnnoise` feature, and only at 48 kHz; elsewhere it
+    // falls back to spectral subtraction.
+    RnNoise,
+}
+
+// The one rate RNNoise's model was trained at
+pub const RNNOISE_SAMPLE_RATE: u32 = 48_000;
+
+impl NoiseReductionMode {
+    pub const ALL: [Self; 4] = [Self::SpectralSubtraction, Self::Wiener, Self::MultiBand, Self::RnNoise];
+
+    // Name used in config files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::SpectralSubtraction => "spectral_subtraction",
+            Self::Wiener => "wiener",
+            Self::MultiBand => "multi_band",
+            Self::RnNoise => "rnnoise",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.as_str() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::SpectralSubtraction => "Spectral Subtraction",
+            Self::Wiener => "Wiener Filter",
+            Self::MultiBand => "Multi-Band",
+            Self::RnNoise => "RNNoise",
+        }
+    }
+
+    // Whether this build includes the mode
+    pub fn is_available(self) -> bool {
+        self != Self::RnNoise || cfg!(feature = "rnnoise")
+    }
+
+    // Whether the mode can process audio at `sample_rate`
+    pub fn supports_sample_rate(self, sample_rate: u32) -> bool {
+        match self {
+            Self::RnNoise => sample_rate == RNNOISE_SAMPLE_RATE,
+            _ => true,
+        }
+    }
+}
+
+// Analysis window of the spectral frames. Hann is the usual compromise;
+// Hamming has a narrower main lobe but leaks more into distant bins, Blackman
+// leaks least but smears each tone over more bins, and rectangular resolves
+// tones most sharply while spreading everything else across the spectrum.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WindowType {
+    #[default]
+    Hann,
+    Hamming,
+    Blackman,
+    Rectangular,
+}
+
+impl WindowType {
+    pub const ALL: [Self; 4] = [Self::Hann, Self::Hamming, Self::Blackman, Self::Rectangular];
+
+    // Name used in config files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Hann => "hann",
+            Self::Hamming => "hamming",
+            Self::Blackman => "blackman",
+            Self::Rectangular => "rectangular",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|window| window.as_str() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Hann => "Hann",
+            Self::Hamming => "Hamming",
+            Self::Blackman => "Blackman",
+            Self::Rectangular => "Rectangular",
+        }
+    }
+
+    // Periodic form of the window over `size` samples, i.e. the symmetric
+    // one of `size + 1` without its last sample, as suits overlap-add
+    pub fn coefficients(self, size: usize) -> Vec<f32> {
+        let symmetric = match self {
+            Self::Hann => apodize::hanning_iter(size + 1),
+            Self::Hamming => apodize::hamming_iter(size + 1),
+            // apodize's own Blackman is the four-term Blackman-Harris
+            Self::Blackman => apodize::cosine_iter(0.42, 0.5, 0.08, 0.0, size + 1),
+            Self::Rectangular => return vec![1.0; size],
+        };
+        symmetric.take(size).map(|w| w as f32).collect()
+    }
+}
+
+// Largest ripple, relative to its peak, that the sum of the overlapping
+// windows may have for a window and hop to count as constant-overlap-add
+pub const COLA_TOLERANCE: f64 = 1e-3;
+
+// Whether frames of `window_type` over `fft_size` samples advancing by
+// `hop_size` overlap-add to a constant (COLA). Untouched frames reconstruct
+// exactly either way, but where the sum varies, frames the noise reduction
+// turns down differently are blended with uneven weights across each hop,
+// which modulates the output at the hop rate.
+pub fn is_cola(window_type: WindowType, fft_size: usize, hop_size: usize) -> bool {
+    if hop_size == 0 || hop_size > fft_size {
+        return false;
+    }
+    let window = window_type.coefficients(fft_size);
+    let sums: Vec<f64> = (0..hop_size)
+        .map(|position| window[position..].iter().step_by(hop_size).map(|&w| w as f64).sum())
+        .collect();
+    let max = sums.iter().copied().fold(0.0, f64::max);
+    let min = sums.iter().copied().fold(f64::INFINITY, f64::min);
+    max > 0.0 && (max - min) / max <= COLA_TOLERANCE
+}
+
+// Analysis window for frames of `fft_size` advancing by `hop_size`, and the
+// factor undoing the overlapping windows at each position of a hop. The window
+// is scaled to the energy of the Hann window, so bin powers, and with them the
+// noise estimate, learned profiles and comfort noise, keep their level
+// whichever window is chosen. The settings only allow pairs that are COLA
+// (see `is_cola`), where the factors are the same at every position.
+fn analysis_window(window_type: WindowType, fft_size: usize, hop_size: usize) -> (Vec<f32>, Vec<f32>) {
+    let energy = |window: &[f32]| window.iter().map(|w| w * w).sum::<f32>();
+    let mut window = window_type.coefficients(fft_size);
+    let scale = (energy(&WindowType::Hann.coefficients(fft_size)) / energy(&window)).sqrt();
+    window.iter_mut().for_each(|w| *w *= scale);
+    let overlap_norm = (0..hop_size)
+        .map(|position| {
+            let sum: f32 = window[position..].iter().step_by(hop_size).sum();
+            1.0 / (fft_size as f32 * sum)
+        })
+        .collect();
+    (window, overlap_norm)
+}
+
+// Weight of the previous frame's clean-speech estimate in the decision-directed
+// a-priori SNR; close to 1 is what suppresses musical noise
+const WIENER_SMOOTHING: f32 = 0.98;
+
+// Over-subtraction factor of spectral subtraction: how many times the noise
+// estimate is subtracted. Higher removes more noise but also more of the
+// speech, and the residual turns into more noticeable musical noise.
+pub const DEFAULT_NOISE_REDUCTION_STRENGTH: f32 = 2.0;
+pub const MIN_NOISE_REDUCTION_STRENGTH: f32 = 1.0;
+pub const MAX_NOISE_REDUCTION_STRENGTH: f32 = 4.0;
+
+// Bands of the multi-band mode, one octave each, counting down from Nyquist:
+// the top band spans half to all of Nyquist, the next a quarter to half, and
+// the lowest takes everything below. At 48kHz that is 0-750Hz, 750Hz-1.5k,
+// 1.5-3k, 3-6k, 6-12k and 12-24k.
+pub const NOISE_BANDS: usize = 6;
+
+// Upper edge of `band` as a fraction of Nyquist
+pub fn band_upper_edge(band: usize) -> f32 {
+    0.5f32.powi((NOISE_BANDS - 1 - band.min(NOISE_BANDS - 1)) as i32)
+}
+
+// Band an FFT bin belongs to. Bins above fft_size / 2 mirror the ones below.
+fn band_of_bin(bin: usize, fft_size: usize) -> usize {
+    let half = fft_size / 2;
+    let position = bin.min(fft_size - bin) as f32 / half.max(1) as f32;
+    (0..NOISE_BANDS).find(|&band| position <= band_upper_edge(band)).unwrap_or(NOISE_BANDS - 1)
+}
+
+// Lowest gain any mode applies, as a fraction of the input magnitude.
+// Removing noise entirely sounds unnatural and makes the remaining artifacts
+// stand out; a higher floor leaves more noise but sounds more natural.
+pub const DEFAULT_SPECTRAL_FLOOR: f32 = 0.1;
+pub const MAX_SPECTRAL_FLOOR: f32 = 0.5;
+
+// The floor as the most a bin is turned down, in dB: the least is that of the
+// highest floor, the most one a slider can still reach
+pub const MIN_MAX_REDUCTION_DB: f32 = 6.0;
+pub const MAX_MAX_REDUCTION_DB: f32 = 60.0;
+
+// Spectral floor reducing bins by at most `db`
+pub fn floor_for_max_reduction_db(db: f32) -> f32 {
+    10f32.powf(-db.clamp(MIN_MAX_REDUCTION_DB, MAX_MAX_REDUCTION_DB) / 20.0).min(MAX_SPECTRAL_FLOOR)
+}
+
+// Most a spectral floor reduces a bin by, in dB; infinite for a floor of 0
+pub fn max_reduction_db(floor: f32) -> f32 {
+    if floor > 0.0 { -20.0 * floor.log10() } else { f32::INFINITY }
+}
+
+// Framing and noise state of one channel
+#[derive(Clone)]
+struct ChannelState {
+    input: Vec<f32>,
+    overlap: Vec<f32>,
+    // Hops synthesized but not yet interleaved into the output
+    output: VecDeque<f32>,
+    noise: NoiseEstimator,
+    // Clean-speech power per bin of the previous frame, for the Wiener mode
+    previous_speech: Vec<f32>,
+}
+
+impl ChannelState {
+    fn new(fft_size: usize, hop_size: usize) -> Self {
+        Self {
+            // Prime with silence so the first hop already yields a full frame
+            input: vec![0.0; fft_size - hop_size],
+            overlap: vec![0.0; fft_size],
+            output: VecDeque::new(),
+            noise: NoiseEstimator::new(fft_size),
+            previous_speech: vec![0.0; fft_size],
+        }
+    }
+}
+
+// State for streaming spectral processing with a Hann window (or another
+// `WindowType`) and overlap-add, at 50% overlap unless a shorter hop is
+// chosen. The processing thread owns one of these so the overlap tail
+// carries across blocks instead of every block being processed in isolation.
+//
+// Interleaved input is split into its channels, each framed, denoised and
+// overlap-added on its own, and interleaved again on the way out, so
+// channels never leak into each other. Frame and hop sizes count the
+// samples of one channel, so a block holds `channels` times as many.
+#[derive(Clone)]
+pub struct SpectralState {
+    // Samples per frame and hop of each channel
+    fft_size: usize,
+    hop_size: usize,
+    fft: Arc<dyn Fft<f32>>,
+    ifft: Arc<dyn Fft<f32>>,
+    window_type: WindowType,
+    window: Vec<f32>,
+    // Inverse FFT scale and inverse sum of the overlapping windows at each
+    // position of a hop, applied after synthesis
+    overlap_norm: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    channels: Vec<ChannelState>,
+    // Channel of the next input and output sample, kept across blocks whose
+    // length is not a multiple of the channel count
+    next_input: usize,
+    next_output: usize,
+    voice_active: bool,
+    // Off when the frames only run for other spectral stages; the noise
+    // estimate keeps tracking either way
+    noise_reduction: bool,
+    mode: NoiseReductionMode,
+    strength: f32,
+    // Over-subtraction factor per band for the multi-band mode, and the band
+    // of every bin
+    band_strengths: [f32; NOISE_BANDS],
+    bin_bands: Vec<usize>,
+    floor: f32,
+    // Mean noise estimate over the channels, for comfort noise
+    noise_power: Vec<f32>,
+    // Learned noise spectrum used in place of the running estimates, and
+    // the learning collecting one
+    profile: Option<NoiseProfile>,
+    learning: Option<NoiseLearning>,
+    // Frames pass through without being transformed, e.g. to save power
+    // during silence; the noise estimate holds until they are again
+    idle: bool,
+    // Frames transformed so far
+    transforms: u64,
+}
+
+impl SpectralState {
+    // Frames of `fft_size` samples per channel for `channels` channels
+    pub fn new(fft_size: usize, channels: u16) -> Self {
+        Self::with_hop(fft_size, 0, channels)
+    }
+
+    // Frames of `fft_size` samples per channel advancing by `hop_size`, e.g.
+    // a 2048 FFT with a hop of 512 for fine frequency resolution at the
+    // latency of 512. A hop of 0 is half the frame, as `new` uses.
+    pub fn with_hop(fft_size: usize, hop_size: usize, channels: u16) -> Self {
+        let channel_count = channels.max(1) as usize;
+        let fft_size = fft_size.max(2);
+        let hop_size = match hop_size {
+            0 => fft_size / 2,
+            hop => hop.clamp(1, fft_size),
+        };
+
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(fft_size);
+        let ifft = planner.plan_fft_inverse(fft_size);
+
+        let (window, overlap_norm) = analysis_window(WindowType::default(), fft_size, hop_size);
+
+        Self {
+            fft_size,
+            hop_size,
+            fft,
+            ifft,
+            window_type: WindowType::default(),
+            window,
+            overlap_norm,
+            spectrum: vec![Complex::new(0.0, 0.0); fft_size],
+            channels: (0..channel_count).map(|_| ChannelState::new(fft_size, hop_size)).collect(),
+            next_input: 0,
+            next_output: 0,
+            voice_active: false,
+            noise_reduction: true,
+            mode: NoiseReductionMode::default(),
+            strength: DEFAULT_NOISE_REDUCTION_STRENGTH,
+            band_strengths: [DEFAULT_NOISE_REDUCTION_STRENGTH; NOISE_BANDS],
+            bin_bands: (0..fft_size).map(|bin| band_of_bin(bin, fft_size)).collect(),
+            floor: DEFAULT_SPECTRAL_FLOOR,
+            noise_power: vec![0.0; fft_size],
+            profile: None,
+            learning: None,
+            idle: false,
+            transforms: 0,
+        }
+    }
+
+    // Delay introduced by the overlap-add framing, in interleaved samples
+    pub fn latency(&self) -> usize {
+        (self.fft_size - self.hop_size) * self.channels.len()
+    }
+
+    // Recomputes the window only when the type changes; a new FFT size
+    // makes a new state anyway
+    pub fn set_window(&mut self, window_type: WindowType) {
+        if window_type != self.window_type {
+            self.window_type = window_type;
+            (self.window, self.overlap_norm) = analysis_window(window_type, self.fft_size, self.hop_size);
+        }
+    }
+
+    pub fn window(&self) -> WindowType {
+        self.window_type
+    }
+
+    pub fn set_noise_reduction_enabled(&mut self, enabled: bool) {
+        self.noise_reduction = enabled;
+    }
+
+    pub fn set_mode(&mut self, mode: NoiseReductionMode) {
+        self.mode = mode;
+    }
+
+    // Over-subtraction factor used while no voice is detected
+    pub fn set_strength(&mut self, strength: f32) {
+        self.strength = strength.clamp(MIN_NOISE_REDUCTION_STRENGTH, MAX_NOISE_REDUCTION_STRENGTH);
+    }
+
+    // Over-subtraction factors of the multi-band mode, lowest band first.
+    // Bands beyond the end of `strengths` keep their current factor.
+    pub fn set_band_strengths(&mut self, strengths: &[f32]) {
+        for (band, &strength) in self.band_strengths.iter_mut().zip(strengths) {
+            *band = strength.clamp(MIN_NOISE_REDUCTION_STRENGTH, MAX_NOISE_REDUCTION_STRENGTH);
+        }
+    }
+
+    pub fn set_floor(&mut self, floor: f32) {
+        self.floor = floor.clamp(0.0, MAX_SPECTRAL_FLOOR);
+    }
+
+    // Current noise estimate, as power per FFT bin of a channel's frame,
+    // averaged over the channels
+    pub fn noise_power(&self) -> &[f32] {
+        &self.noise_power
+    }
+
+    // Frames of each channel advance by this many samples
+    pub fn hop_size(&self) -> usize {
+        self.hop_size
+    }
+
+    // Averages the spectra of the next `frames` frames of every channel, then
+    // uses the result as the noise profile. Frames count whether or not voice
+    // is detected: the user is meant to stay quiet.
+    pub fn start_noise_learning(&mut self, frames: usize) {
+        self.learning = Some(NoiseLearning::new(self.fft_size, frames * self.channels.len()));
+    }
+
+    // Progress of a learning still collecting frames, 0 to 1
+    pub fn noise_learning_progress(&self) -> Option<f32> {
+        self.learning.as_ref().map(NoiseLearning::progress)
+    }
+
+    // Noise reduction subtracts `profile` instead of the running estimate,
+    // or goes back to the estimate with None. Returns false, leaving the
+    // estimate in use, for a profile learned with another frame length.
+    pub fn set_noise_profile(&mut self, profile: Option<NoiseProfile>) -> bool {
+        let fits = profile.as_ref().is_none_or(|profile| profile.bins() == self.fft_size);
+        self.profile = profile.filter(|_| fits);
+        fits
+    }
+
+    pub fn noise_profile(&self) -> Option<&NoiseProfile> {
+        self.profile.as_ref()
+    }
+
+    pub fn set_noise_adaptation_rate(&mut self, rate: f32) {
+        for channel in &mut self.channels {
+            channel.noise.set_adaptation_rate(rate);
+        }
+    }
+
+    // While voice is active the noise estimate is held, so speech cannot leak
+    // into it, and subtraction is gentler to keep speech intact
+    pub fn set_voice_active(&mut self, active: bool) {
+        self.voice_active = active;
+    }
+
+    // Frames go through unchanged and untransformed while idle, at the same
+    // latency, so switching in and out mid-stream is seamless
+    pub fn set_idle(&mut self, idle: bool) {
+        self.idle = idle;
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    // Frames that went through the FFT since the state was created
+    pub fn transforms(&self) -> u64 {
+        self.transforms
+    }
+}
+
+// FFT size per channel covering roughly 20ms of audio, e.g. 1024 at 48kHz.
+// Used where the stream format is only known at runtime.
+pub fn fft_size_for(sample_rate: u32) -> usize {
+    (sample_rate as usize / 50).next_power_of_two().max(64)
+}
+
+// Runs noise reduction in the state's mode over a stream of interleaved
+// samples. Output is delayed by `state.latency()` samples and has the same
+// length as the input whenever the input length is a multiple of the hop size
+// times the channel count.
+pub fn reduce_noise(samples: &[f32], state: &mut SpectralState) -> Vec<f32> {
+    process_spectral(samples, state, |_, _| {})
+}
+
+// Like `reduce_noise`, but hands every frame's spectrum to `stage`, along with
+// its channel, after noise reduction, so further spectral processing shares
+// the same FFTs
+pub fn process_spectral(
+    samples: &[f32],
+    state: &mut SpectralState,
+    mut stage: impl FnMut(usize, &mut [Complex<f32>]),
+) -> Vec<f32> {
+    let fft_size = state.fft_size;
+    let hop_size = state.hop_size;
+    let channel_count = state.channels.len();
+
+    for &sample in samples {
+        state.channels[state.next_input].input.push(sample);
+        state.next_input = (state.next_input + 1) % channel_count;
+    }
+
+    while state.channels.iter().all(|channel| channel.input.len() >= fft_size) {
+        for index in 0..channel_count {
+            let channel = &mut state.channels[index];
+            if state.idle {
+                // What the transforms would give back untouched, without
+                // them: the inverse FFT scales by the frame length
+                let scale = fft_size as f32;
+                for ((acc, &sample), &w) in channel.overlap.iter_mut().zip(&channel.input).zip(&state.window) {
+                    *acc += sample * w * scale;
+                }
+                emit_hop(channel, &state.overlap_norm, fft_size, hop_size);
+                continue;
+            }
+            state.transforms += 1;
+
+            for ((bin, &sample), &w) in state.spectrum.iter_mut()
+                .zip(&channel.input)
+                .zip(&state.window)
+            {
+                *bin = Complex::new(sample * w, 0.0);
+            }
+
+            // Forward FFT
+            state.fft.process(&mut state.spectrum);
+
+            if !state.voice_active {
+                channel.noise.update(state.spectrum.iter().map(|bin| bin.norm_sqr()));
+            }
+            if let Some(learning) = state.learning.as_mut() {
+                learning.push(state.spectrum.iter().map(|bin| bin.norm_sqr()));
+                if learning.is_complete() {
+                    state.profile = Some(learning.profile());
+                    state.learning = None;
+                }
+            }
+            if state.noise_reduction {
+                match state.mode {
+                    NoiseReductionMode::SpectralSubtraction | NoiseReductionMode::MultiBand => {
+                        apply_subtraction(state, index)
+                    }
+                    NoiseReductionMode::Wiener => apply_wiener(state, index),
+                    // The network already ran on the samples
+                    NoiseReductionMode::RnNoise => {}
+                }
+            }
+            stage(index, &mut state.spectrum);
+
+            // Inverse FFT, overlap-added onto the tail of the previous frame
+            state.ifft.process(&mut state.spectrum);
+            let channel = &mut state.channels[index];
+            for (acc, bin) in channel.overlap.iter_mut().zip(&state.spectrum) {
+                *acc += bin.re;
+            }
+            emit_hop(channel, &state.overlap_norm, fft_size, hop_size);
+        }
+    }
+
+    match &state.profile {
+        Some(profile) => state.noise_power.copy_from_slice(profile.power()),
+        None => {
+            for (bin, mean) in state.noise_power.iter_mut().enumerate() {
+                *mean = state.channels.iter().map(|channel| channel.noise.noise_power()[bin]).sum::<f32>()
+                    / channel_count as f32;
+            }
+        }
+    }
+
+    let mut output = Vec::with_capacity(samples.len());
+    while let Some(sample) = state.channels[state.next_output].output.pop_front() {
+        output.push(sample);
+        state.next_output = (state.next_output + 1) % channel_count;
+    }
+    output
+}
+
+// Moves the finished first hop of the overlap into the output and advances
+// the frame by a hop
+fn emit_hop(channel: &mut ChannelState, overlap_norm: &[f32], fft_size: usize, hop_size: usize) {
+    channel.output.extend(channel.overlap[..hop_size].iter().zip(overlap_norm).map(|(s, norm)| s * norm));
+    channel.overlap.copy_within(hop_size.., 0);
+    channel.overlap[fft_size - hop_size..].fill(0.0);
+    channel.input.drain(..hop_size);
+}
+
+fn apply_subtraction(state: &mut SpectralState, channel: usize) {
+    let multi_band = state.mode == NoiseReductionMode::MultiBand;
+
+    let noise = match &state.profile {
+        Some(profile) => profile.power(),
+        None => state.channels[channel].noise.noise_power(),
+    };
+    for ((sample, &noise_power), &band) in state.spectrum.iter_mut()
+        .zip(noise)
+        .zip(&state.bin_bands)
+    {
+        let strength = if multi_band { state.band_strengths[band] } else { state.strength };
+        // Half the over-subtraction during speech keeps it intact
+        let alpha = if state.voice_active {
+            (strength / 2.0).max(MIN_NOISE_REDUCTION_STRENGTH)
+        } else {
+            strength
+        };
+
+        let magnitude = sample.norm();
+        let noise_floor = noise_power.sqrt();
+
+        // Bins at or below the noise estimate are pure noise and drop
+        // straight to the spectral floor. Bins too small to divide by are
+        // inaudible and dropped outright.
+        if magnitude > f32::MIN_POSITIVE {
+            let new_magnitude = magnitude - alpha * noise_floor;
+            let new_magnitude = new_magnitude.max(state.floor * magnitude); // Don't over-subtract
+            *sample *= new_magnitude / magnitude;
+        } else {
+            *sample = Complex::new(0.0, 0.0);
+        }
+    }
+}
+
+fn apply_wiener(state: &mut SpectralState, channel: usize) {
+    let channel = &mut state.channels[channel];
+    let noise = match &state.profile {
+        Some(profile) => profile.power(),
+        None => channel.noise.noise_power(),
+    };
+    for ((sample, &noise_power), previous_speech) in state.spectrum.iter_mut()
+        .zip(noise)
+        .zip(channel.previous_speech.iter_mut())
+    {
+        let power = sample.norm_sqr();
+        let noise_power = noise_power.max(f32::MIN_POSITIVE);
+
+        // A-posteriori SNR of this frame, and the a-priori SNR blending the
+        // previous frame's speech estimate with this frame's evidence
+        let posteriori = power / noise_power;
+        let priori = WIENER_SMOOTHING * *previous_speech / noise_power
+            + (1.0 - WIENER_SMOOTHING) * (posteriori - 1.0).max(0.0);
+
+        // priori / (1 + priori), written so that an infinite SNR over a
+        // zero noise estimate gives a gain of 1 rather than NaN
+        let gain = (1.0 / (1.0 + priori.recip())).max(state.floor);
+        *sample *= gain;
+        *previous_speech = gain * gain * power;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn sine(frequency: f32, amplitude: f32, sample_rate: f32, len: usize) -> Vec<f32> {
+        (0..len).map(|n| amplitude * (2.0 * PI * frequency * n as f32 / sample_rate).sin()).collect()
+    }
+
+    // Uniform white noise of the given RMS from a fixed seed
+    fn white_noise(rms: f32, len: usize, mut seed: u32) -> Vec<f32> {
+        let amplitude = rms * 3f32.sqrt();
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    // Power of `samples` in the FFT bins within `width` of `bin`, and in all
+    // the others, from one Hann-windowed frame
+    fn bin_powers(samples: &[f32], bin: usize, width: usize) -> (f32, f32) {
+        let size = samples.len();
+        let window = WindowType::Hann.coefficients(size);
+        let mut spectrum: Vec<Complex<f32>> =
+            samples.iter().zip(&window).map(|(&s, &w)| Complex::new(s * w, 0.0)).collect();
+        FftPlanner::new().plan_fft_forward(size).process(&mut spectrum);
+        let (mut inside, mut outside) = (0.0, 0.0);
+        for (k, value) in spectrum[1..size / 2].iter().enumerate().map(|(k, v)| (k + 1, v.norm_sqr())) {
+            if k.abs_diff(bin) <= width {
+                inside += value;
+            } else {
+                outside += value;
+            }
+        }
+        (inside, outside)
+    }
+
+    #[test]
+    fn sine_has_no_discontinuities_at_block_boundaries() {
+        let (frequency, amplitude, rate) = (440.0, 0.5, 48000.0);
+        let block = 1024;
+        let input = sine(frequency, amplitude, rate, block * 40);
+        // Largest step a sine makes between two samples
+        let max_step = 2.0 * PI * frequency / rate * amplitude;
+
+        for noise_reduction in [false, true] {
+            let mut state = SpectralState::new(block, 1);
+            state.set_noise_reduction_enabled(noise_reduction);
+            // Held as speech, so the tone isn't learned as noise
+            state.set_voice_active(true);
+            let output: Vec<f32> = input.chunks(block).flat_map(|chunk| reduce_noise(chunk, &mut state)).collect();
+            assert_eq!(output.len(), input.len());
+
+            let latency = state.latency();
+            // Skip the first frames, which fade in from the silent priming
+            for n in (latency + block..output.len()).step_by(block / 2) {
+                let step = (output[n] - output[n - 1]).abs();
+                assert!(step <= max_step + 1e-3, "step of {} at {} with noise reduction {}", step, n, noise_reduction);
+            }
+            if !noise_reduction {
+                for n in latency + block..output.len() {
+                    assert!((output[n] - input[n - latency]).abs() < 1e-4, "sample {} differs", n);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tone_survives_while_broadband_noise_drops() {
+        let (rate, block, frame) = (48000.0, 1024, 8192);
+        let tone_frequency = rate / frame as f32 * 256.0;
+        let mut state = SpectralState::new(block, 1);
+
+        // Two seconds of noise alone for the estimate to settle on, then the
+        // tone over the same noise, held as speech like the VAD would
+        let noise = white_noise(0.01, 48000 * 4, 7);
+        let quiet: Vec<f32> = noise[..96000].chunks(block).flat_map(|chunk| reduce_noise(chunk, &mut state)).collect();
+        let input: Vec<f32> = noise[96000..].iter().zip(sine(tone_frequency, 0.25, rate, 96000)).map(|(n, t)| n + t).collect();
+        state.set_voice_active(true);
+        let output: Vec<f32> = input.chunks(block).flat_map(|chunk| reduce_noise(chunk, &mut state)).collect();
+
+        let (_, noise_before) = bin_powers(&noise[96000 - frame..96000], 256, 8);
+        let (_, noise_after) = bin_powers(&quiet[quiet.len() - frame..], 256, 8);
+        let (tone_before, _) = bin_powers(&input[input.len() - frame..], 256, 8);
+        let (tone_after, _) = bin_powers(&output[output.len() - frame..], 256, 8);
+        let noise_reduction_db = 10.0 * (noise_before / noise_after).log10();
+        let tone_loss_db = 10.0 * (tone_before / tone_after).log10();
+        assert!(noise_reduction_db >= 10.0, "noise only {:.1} dB down", noise_reduction_db);
+        assert!(tone_loss_db.abs() < 1.0, "tone changed by {:.1} dB", tone_loss_db);
+    }
+
+    // Variance of what is left of noise-only input, and the relative spread
+    // of its energy from hop to hop, which is heard as musical noise
+    fn residual_noise(configure: impl FnOnce(&mut SpectralState)) -> (f32, f32) {
+        let noise = white_noise(0.01, 48000 * 6, 11);
+        let mut state = SpectralState::new(1024, 1);
+        configure(&mut state);
+        let output: Vec<f32> = noise.chunks(1024).flat_map(|chunk| reduce_noise(chunk, &mut state)).collect();
+        let settled = &output[48000 * 2..];
+        let variance = settled.iter().map(|s| s * s).sum::<f32>() / settled.len() as f32;
+        let energies: Vec<f32> = settled.chunks(512).map(|hop| hop.iter().map(|s| s * s).sum()).collect();
+        let mean = energies.iter().sum::<f32>() / energies.len() as f32;
+        let spread = (energies.iter().map(|e| (e - mean).powi(2)).sum::<f32>() / energies.len() as f32).sqrt() / mean;
+        (variance, spread)
+    }
+
+    #[test]
+    fn wiener_leaves_less_residual_noise_than_subtraction() {
+        let (subtraction_variance, subtraction_spread) =
+            residual_noise(|state| state.set_mode(NoiseReductionMode::SpectralSubtraction));
+        let (wiener_variance, wiener_spread) = residual_noise(|state| state.set_mode(NoiseReductionMode::Wiener));
+        assert!(wiener_variance < subtraction_variance, "{:e} vs {:e}", wiener_variance, subtraction_variance);
+        assert!(wiener_spread < subtraction_spread, "{} vs {}", wiener_spread, subtraction_spread);
+    }
+
+    #[test]
+    fn higher_strength_leaves_less_noise() {
+        let residuals: Vec<f32> = [MIN_NOISE_REDUCTION_STRENGTH, DEFAULT_NOISE_REDUCTION_STRENGTH, MAX_NOISE_REDUCTION_STRENGTH]
+            .into_iter()
+            .map(|strength| {
+                residual_noise(|state| {
+                    state.set_strength(strength);
+                    state.set_floor(0.0);
+                }).0
+            })
+            .collect();
+        assert!(residuals.windows(2).all(|pair| pair[1] < pair[0]), "{:?}", residuals);
+    }
+
+    // Power of `samples` in each noise band, summed over Hann-windowed frames
+    fn band_powers(samples: &[f32], size: usize) -> [f32; NOISE_BANDS] {
+        let window = WindowType::Hann.coefficients(size);
+        let fft = FftPlanner::new().plan_fft_forward(size);
+        let mut powers = [0.0; NOISE_BANDS];
+        for frame in samples.chunks_exact(size) {
+            let mut spectrum: Vec<Complex<f32>> =
+                frame.iter().zip(&window).map(|(&s, &w)| Complex::new(s * w, 0.0)).collect();
+            fft.process(&mut spectrum);
+            for (k, value) in spectrum[1..size / 2].iter().enumerate() {
+                powers[band_of_bin(k + 1, size)] += value.norm_sqr();
+            }
+        }
+        powers
+    }
+
+    #[test]
+    fn band_strength_only_acts_on_its_band() {
+        let noise = white_noise(0.01, 48000 * 6, 13);
+        let mut state = SpectralState::new(1024, 1);
+        state.set_mode(NoiseReductionMode::MultiBand);
+        state.set_floor(0.0);
+        let mut strengths = [MIN_NOISE_REDUCTION_STRENGTH; NOISE_BANDS];
+        strengths[NOISE_BANDS - 1] = MAX_NOISE_REDUCTION_STRENGTH;
+        state.set_band_strengths(&strengths);
+        let output: Vec<f32> = noise.chunks(1024).flat_map(|chunk| reduce_noise(chunk, &mut state)).collect();
+
+        let latency = state.latency();
+        let before = band_powers(&noise[48000 * 2..noise.len() - latency], 1024);
+        let after = band_powers(&output[48000 * 2 + latency..], 1024);
+        let reduction_db: Vec<f32> = before.iter().zip(&after).map(|(b, a)| 10.0 * (b / a).log10()).collect();
+        // The hiss band is crushed well beyond the 1.5-3 kHz band at the
+        // lowest strength
+        let (top, mid) = (reduction_db[NOISE_BANDS - 1], reduction_db[2]);
+        assert!(top > mid + 20.0, "top band {:.1} dB down, mid band {:.1} dB", top, mid);
+    }
+
+    #[test]
+    fn learned_profile_follows_the_noise_spectrum() {
+        // White noise through y[n] = x[n] + 0.9 y[n-1], whose power falls
+        // by about 25 dB from DC to Nyquist
+        let mut last = 0.0;
+        let noise: Vec<f32> = white_noise(0.01, 48000, 29)
+            .into_iter()
+            .map(|x| {
+                last = x + 0.9 * last;
+                last
+            })
+            .collect();
+        let response = |bin: usize| {
+            let omega = 2.0 * PI * bin as f32 / 1024.0;
+            1.0 / (1.0 - 1.8 * omega.cos() + 0.81)
+        };
+
+        let mut state = SpectralState::new(1024, 1);
+        state.start_noise_learning(80);
+        for chunk in noise.chunks(1024) {
+            reduce_noise(chunk, &mut state);
+        }
+        assert_eq!(state.noise_learning_progress(), None);
+        let profile = state.noise_profile().expect("learning should have finished").power();
+        assert_eq!(profile.len(), 1024);
+
+        // Averaged over groups of bins, the profile has the shape of the
+        // filter's response
+        let group = |powers: &dyn Fn(usize) -> f32, start: usize| (start..start + 16).map(powers).sum::<f32>();
+        let reference = group(&|k| profile[k], 16) / group(&response, 16);
+        for start in (32..496).step_by(16) {
+            let error_db = 10.0 * (group(&|k| profile[k], start) / group(&response, start) / reference).log10();
+            assert!(error_db.abs() < 1.0, "bins {}..{} are off by {:.2} dB", start, start + 16, error_db);
+        }
+    }
+
+    #[test]
+    fn zero_bins_and_a_zero_noise_profile_stay_finite() {
+        // Digital silence, then a tone on a bin centre: every other bin is
+        // (nearly) zero, and so is the noise it is divided by
+        let mut input = vec![0.0; 4096];
+        input.extend(sine(48000.0 / 1024.0 * 40.0, 0.5, 48000.0, 8192));
+        for mode in NoiseReductionMode::ALL {
+            let mut state = SpectralState::new(1024, 1);
+            state.set_mode(mode);
+            assert!(state.set_noise_profile(Some(NoiseProfile::new(vec![0.0; 1024]))));
+            let output: Vec<f32> = input.chunks(1024).flat_map(|chunk| reduce_noise(chunk, &mut state)).collect();
+            assert!(output.iter().all(|s| s.is_finite()), "{:?} produced a non-finite sample", mode);
+            assert!(output[8192..].iter().any(|s| s.abs() > 0.1), "{:?} lost the tone", mode);
+        }
+    }
+
+    #[test]
+    fn windows_match_their_formulas_and_reconstruct_a_constant() {
+        let size = 1536;
+        let cosine = |n: usize, k: f32| (2.0 * PI * k * n as f32 / size as f32).cos();
+        let formulas: [(WindowType, &dyn Fn(usize) -> f32); 4] = [
+            (WindowType::Hann, &|n| 0.5 - 0.5 * cosine(n, 1.0)),
+            (WindowType::Hamming, &|n| 0.54 - 0.46 * cosine(n, 1.0)),
+            (WindowType::Blackman, &|n| 0.42 - 0.5 * cosine(n, 1.0) + 0.08 * cosine(n, 2.0)),
+            (WindowType::Rectangular, &|_| 1.0),
+        ];
+        for (window_type, formula) in formulas {
+            let window = window_type.coefficients(size);
+            assert_eq!(window.len(), size);
+            for (n, &w) in window.iter().enumerate() {
+                assert!((w - formula(n)).abs() < 1e-5, "{:?} is {} at {}, not {}", window_type, w, n, formula(n));
+            }
+
+            // Every window here overlap-adds to a constant at a third of the
+            // frame, so a constant comes back unchanged after the latency
+            assert!(is_cola(window_type, size, size / 3), "{:?} is not COLA", window_type);
+            let mut state = SpectralState::with_hop(size, size / 3, 1);
+            state.set_noise_reduction_enabled(false);
+            state.set_window(window_type);
+            let input = vec![0.5; 48000];
+            let output: Vec<f32> = input.chunks(480).flat_map(|chunk| process_spectral(chunk, &mut state, |_, _| {})).collect();
+            for (n, sample) in output.iter().enumerate().skip(state.latency()) {
+                assert!((sample - 0.5).abs() < 1e-4, "{:?} gave {} at {}", window_type, sample, n);
+            }
+        }
+
+        // Half-overlapped Blackman frames do not sum to a constant
+        assert!(is_cola(WindowType::Hann, size, size / 2));
+        assert!(!is_cola(WindowType::Blackman, size, size / 2));
+    }
+
+    #[test]
+    fn short_hop_reconstructs_with_its_own_latency() {
+        // A 2048 FFT at a hop of 512, fed in blocks of the hop as the
+        // processing task does
+        let mut state = SpectralState::with_hop(2048, 512, 1);
+        state.set_noise_reduction_enabled(false);
+        assert!(is_cola(state.window(), 2048, 512));
+        assert_eq!(state.latency(), 1536);
+
+        let mut input = sine(440.0, 0.5, 48000.0, 49152);
+        for (sample, noise) in input.iter_mut().zip(white_noise(0.05, 49152, 4)) {
+            *sample += noise;
+        }
+        let output: Vec<f32> =
+            input.chunks(512).flat_map(|block| process_spectral(block, &mut state, |_, _| {})).collect();
+        assert_eq!(output.len(), input.len());
+        for (n, (out, expected)) in output[1536..].iter().zip(&input).enumerate() {
+            assert!((out - expected).abs() < 1e-4, "sample {} is {} not {}", n, out, expected);
+        }
+    }
+
+    #[test]
+    fn no_bin_is_reduced_past_the_max_reduction() {
+        let floor = floor_for_max_reduction_db(12.0);
+        assert!((max_reduction_db(floor) - 12.0).abs() < 1e-4);
+        let input: Vec<f32> = white_noise(0.1, 48128, 9);
+        let framed: Vec<f32> = [vec![0.0; 512], input.clone()].concat();
+
+        for mode in [NoiseReductionMode::SpectralSubtraction, NoiseReductionMode::MultiBand, NoiseReductionMode::Wiener] {
+            let mut state = SpectralState::new(1024, 1);
+            state.set_mode(mode);
+            state.set_strength(MAX_NOISE_REDUCTION_STRENGTH);
+            state.set_floor(floor);
+            let window = state.window.clone();
+            let (mut frame, mut at_floor) = (0, 0);
+            process_spectral(&input, &mut state, |_, reduced| {
+                // The frame as it was before noise reduction; the first one
+                // starts with the overlap the framing holds back
+                let mut spectrum: Vec<Complex<f32>> = framed[frame * 512..frame * 512 + 1024]
+                    .iter()
+                    .zip(&window)
+                    .map(|(&s, &w)| Complex::new(s * w, 0.0))
+                    .collect();
+                FftPlanner::new().plan_fft_forward(1024).process(&mut spectrum);
+                for (bin, (before, after)) in spectrum.iter().zip(reduced.iter()).enumerate() {
+                    let gain = after.norm() / before.norm();
+                    assert!(gain >= floor * 0.999, "{:?} frame {} bin {} down to {}", mode, frame, bin, gain);
+                    at_floor += (gain < floor * 1.01) as usize;
+                }
+                frame += 1;
+            });
+            assert!(at_floor > frame * 100, "{:?} only reached the floor in {} bins", mode, at_floor);
+        }
+    }
+}
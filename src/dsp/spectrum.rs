@@ -0,0 +1,69 @@
+// Magnitude spectrum for display. Channels are downmixed first so stereo
+// input shows one spectrum instead of the mirrored image interleaving causes.
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::sync::Arc;
+
+// Floor for bins with no energy, well below anything audible
+pub const MIN_SPECTRUM_DB: f32 = -120.0;
+
+#[derive(Clone)]
+pub struct SpectrumAnalyzer {
+    channels: usize,
+    fft: Option<Arc<dyn Fft<f32>>>,
+    window: Vec<f32>,
+    // Sum of the window, undone so a full-scale sine reads 0 dB
+    window_sum: f32,
+    buffer: Vec<Complex<f32>>,
+    transforms: u64,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(channels: u16) -> Self {
+        Self {
+            channels: channels.max(1) as usize,
+            fft: None,
+            window: Vec::new(),
+            window_sum: 1.0,
+            buffer: Vec::new(),
+            transforms: 0,
+        }
+    }
+
+    // Blocks that went through the FFT since the analyzer was created
+    pub fn transforms(&self) -> u64 {
+        self.transforms
+    }
+
+    // Magnitudes in dBFS of bins 0 (DC) through Nyquist for a block of
+    // interleaved samples
+    pub fn magnitudes_db(&mut self, samples: &[f32]) -> Vec<f32> {
+        let frames = samples.len() / self.channels;
+        if frames < 2 {
+            return Vec::new();
+        }
+        if self.buffer.len() != frames {
+            self.fft = Some(FftPlanner::new().plan_fft_forward(frames));
+            self.window = apodize::hanning_iter(frames).map(|w| w as f32).collect();
+            self.window_sum = self.window.iter().sum::<f32>().max(f32::EPSILON);
+            self.buffer = vec![Complex::new(0.0, 0.0); frames];
+        }
+
+        let scale = 1.0 / self.channels as f32;
+        for ((bin, frame), &w) in self.buffer.iter_mut()
+            .zip(samples.chunks_exact(self.channels))
+            .zip(&self.window)
+        {
+            *bin = Complex::new(frame.iter().sum::<f32>() * scale * w, 0.0);
+        }
+        if let Some(fft) = &self.fft {
+            fft.process(&mut self.buffer);
+            self.transforms += 1;
+        }
+
+        let norm = 2.0 / self.window_sum;
+        self.buffer[..=frames / 2].iter()
+            .map(|bin| (20.0 * (bin.norm() * norm).log10()).max(MIN_SPECTRUM_DB))
+            .collect()
+    }
+}
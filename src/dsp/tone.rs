@@ -0,0 +1,110 @@
+// Sine test tone for checking where the output goes and how loud it arrives.
+// It replaces the processed signal while it plays, on every channel, and
+// fades in and out so neither end clicks.
+
+use std::f32::consts::TAU;
+
+pub const DEFAULT_TEST_TONE_HZ: f32 = 440.0;
+pub const DEFAULT_TEST_TONE_MS: u32 = 1000;
+
+// Peak level of the tone, leaving headroom for the output gain
+pub const TEST_TONE_LEVEL_DB: f32 = -18.0;
+
+const FADE_MS: f32 = 10.0;
+
+pub struct TestTone {
+    channels: usize,
+    phase: f32,
+    // Phase advance per frame, in radians
+    phase_step: f32,
+    amplitude: f32,
+    fade_frames: usize,
+    // Frames played so far and in total
+    position: usize,
+    length: usize,
+}
+
+impl TestTone {
+    // `freq_hz` is kept below Nyquist
+    pub fn new(freq_hz: f32, duration_ms: u32, sample_rate: u32, channels: u16) -> Self {
+        let sample_rate = sample_rate.max(1) as f32;
+        let freq_hz = freq_hz.clamp(0.0, sample_rate / 2.0);
+        Self {
+            channels: channels.max(1) as usize,
+            phase: 0.0,
+            phase_step: TAU * freq_hz / sample_rate,
+            amplitude: 10f32.powf(TEST_TONE_LEVEL_DB / 20.0),
+            fade_frames: (FADE_MS / 1000.0 * sample_rate) as usize,
+            position: 0,
+            length: (duration_ms as f32 / 1000.0 * sample_rate) as usize,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.length
+    }
+
+    // Ends the tone early, after its fade-out
+    pub fn stop(&mut self) {
+        self.length = self.length.min(self.position + self.fade_frames);
+    }
+
+    // Overwrites interleaved `samples` with the next frames of the tone;
+    // frames past its end are silence
+    pub fn fill(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_mut(self.channels) {
+            let sample = if self.is_finished() {
+                0.0
+            } else {
+                // Fade at both ends, never past the middle of a short tone
+                let edge = self.position.min(self.length - self.position);
+                let fade = if self.fade_frames > 0 { (edge as f32 / self.fade_frames as f32).min(1.0) } else { 1.0 };
+                let sample = self.amplitude * fade * self.phase.sin();
+                self.phase = (self.phase + self.phase_step) % TAU;
+                self.position += 1;
+                sample
+            };
+            frame.fill(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tone_has_the_requested_frequency_and_level() {
+        let mut tone = TestTone::new(1000.0, 1000, 48000, 2);
+        let mut samples = vec![0.0; 48000 * 2 + 960];
+        tone.fill(&mut samples);
+        assert!(tone.is_finished());
+        assert!(samples.chunks(2).all(|frame| frame[0] == frame[1]));
+
+        // Rising zero crossings of the left channel over the second: one per
+        // period
+        let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+        let periods = left[..48000].windows(2).filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0).count();
+        assert!(periods.abs_diff(1000) <= 1, "{} periods in a second", periods);
+
+        let peak = left.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((20.0 * peak.log10() - TEST_TONE_LEVEL_DB).abs() < 0.01);
+        assert!(left[48000..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn stop_fades_out_without_a_click() {
+        let mut tone = TestTone::new(440.0, 1000, 48000, 1);
+        let mut samples = vec![0.0; 4800];
+        tone.fill(&mut samples);
+        tone.stop();
+        let mut rest = vec![0.0; 4800];
+        tone.fill(&mut rest);
+        assert!(tone.is_finished());
+        let fade = (FADE_MS / 1000.0 * 48000.0) as usize;
+        assert!(rest[fade..].iter().all(|&s| s == 0.0));
+        let max_step = TAU * 440.0 / 48000.0 * 10f32.powf(TEST_TONE_LEVEL_DB / 20.0) + 1e-4;
+        samples.extend(rest);
+        assert!(samples.windows(2).all(|pair| (pair[1] - pair[0]).abs() <= max_step));
+    }
+}
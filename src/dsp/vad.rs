@@ -0,0 +1,185 @@
+// Voice activity detection from block energy and spectral flatness. Speech is
+// both louder than the background and tonal (harmonics and formants make the
+// spectrum peaky), while steady noise is spread evenly across frequencies. A
+// block counts as voice only when both tests agree.
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::sync::Arc;
+
+// Block energy above the running noise energy needed to count as voice (~6dB)
+const ENERGY_RATIO: f32 = 4.0;
+
+// Geometric over arithmetic mean of the power spectrum; white noise sits
+// around 0.56, voiced speech well below 0.3
+const FLATNESS_THRESHOLD: f32 = 0.3;
+
+// How quickly the noise energy follows the input outside of and during voice.
+// The slow rate lets a permanent rise in background noise be absorbed.
+const NOISE_RATE: f32 = 0.1;
+const NOISE_RATE_DURING_VOICE: f32 = 0.002;
+
+// Energy floor (-80dBFS) so digital silence does not make every sound voice
+const MIN_ENERGY: f32 = 1e-8;
+
+// Blocks voice stays active after the last detection, bridging short pauses
+// between words
+const HANGOVER_BLOCKS: usize = 8;
+
+#[derive(Clone)]
+pub struct VoiceActivityDetector {
+    fft: Option<Arc<dyn Fft<f32>>>,
+    window: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    noise_energy: Option<f32>,
+    hangover: usize,
+    active: bool,
+    transforms: u64,
+}
+
+impl Default for VoiceActivityDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VoiceActivityDetector {
+    pub fn new() -> Self {
+        Self {
+            fft: None,
+            window: Vec::new(),
+            spectrum: Vec::new(),
+            noise_energy: None,
+            hangover: 0,
+            active: false,
+            transforms: 0,
+        }
+    }
+
+    // Classifies one block and returns whether it contains voice
+    pub fn update(&mut self, samples: &[f32]) -> bool {
+        if samples.is_empty() {
+            return self.active;
+        }
+
+        let energy = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).max(MIN_ENERGY);
+        let noise_energy = *self.noise_energy.get_or_insert(energy);
+
+        // The flatness takes an FFT, so it is only measured once the energy
+        // test passes; quiet blocks, such as those power save sleeps
+        // through, cost a sum of squares
+        let voice = energy > ENERGY_RATIO * noise_energy
+            && self.spectral_flatness(samples) < FLATNESS_THRESHOLD;
+
+        if voice {
+            self.hangover = HANGOVER_BLOCKS;
+        } else {
+            self.hangover = self.hangover.saturating_sub(1);
+        }
+        self.active = voice || self.hangover > 0;
+
+        let rate = if self.active { NOISE_RATE_DURING_VOICE } else { NOISE_RATE };
+        self.noise_energy = Some(noise_energy + rate * (energy - noise_energy));
+
+        self.active
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    // Blocks whose flatness was measured since the detector was created
+    pub fn transforms(&self) -> u64 {
+        self.transforms
+    }
+
+    fn spectral_flatness(&mut self, samples: &[f32]) -> f32 {
+        let len = samples.len();
+        if len < 4 {
+            return 1.0;
+        }
+        if self.spectrum.len() != len {
+            self.fft = Some(FftPlanner::new().plan_fft_forward(len));
+            self.window = apodize::hanning_iter(len).map(|w| w as f32).collect();
+            self.spectrum = vec![Complex::new(0.0, 0.0); len];
+        }
+
+        for ((bin, &sample), &w) in self.spectrum.iter_mut().zip(samples).zip(&self.window) {
+            *bin = Complex::new(sample * w, 0.0);
+        }
+        if let Some(fft) = &self.fft {
+            fft.process(&mut self.spectrum);
+            self.transforms += 1;
+        }
+
+        // Positive frequencies without DC
+        let bins = &self.spectrum[1..len / 2];
+        let (log_sum, sum) = bins.iter()
+            .map(|bin| bin.norm_sqr() + f32::MIN_POSITIVE)
+            .fold((0.0, 0.0), |(log_sum, sum), p| (log_sum + p.ln(), sum + p));
+        let count = bins.len() as f32;
+
+        (log_sum / count).exp() / (sum / count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const RATE: f32 = 48000.0;
+    const BLOCK: usize = 1024;
+
+    fn white_noise(rms: f32, len: usize) -> Vec<f32> {
+        let mut seed = 3u32;
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed as f32 / u32::MAX as f32 * 2.0 - 1.0) * rms * 3f32.sqrt()
+            })
+            .collect()
+    }
+
+    // Voiced-speech stand-in: a 150 Hz fundamental with falling harmonics
+    fn voiced(amplitude: f32, n: usize) -> f32 {
+        let t = n as f32 / RATE;
+        (1..=10).map(|h| amplitude / h as f32 * (2.0 * PI * 150.0 * h as f32 * t).sin()).sum()
+    }
+
+    fn decisions(vad: &mut VoiceActivityDetector, samples: &[f32]) -> Vec<bool> {
+        samples.chunks(BLOCK).map(|block| vad.update(block)).collect()
+    }
+
+    #[test]
+    fn steady_noise_is_not_voice() {
+        let mut vad = VoiceActivityDetector::new();
+        let active = decisions(&mut vad, &white_noise(0.01, BLOCK * 200));
+        assert!(active.iter().all(|&active| !active));
+
+        // Nor is noise getting louder, which is loud but flat
+        let active = decisions(&mut vad, &white_noise(0.1, BLOCK * 50));
+        assert!(active.iter().all(|&active| !active));
+    }
+
+    #[test]
+    fn speech_like_bursts_are_voice() {
+        let mut vad = VoiceActivityDetector::new();
+        let noise = white_noise(0.003, BLOCK * 200);
+        decisions(&mut vad, &noise[..BLOCK * 50]);
+
+        // Bursts of 20 blocks with 30 blocks of noise alone between them
+        let signal: Vec<f32> = noise[BLOCK * 50..].iter().enumerate()
+            .map(|(n, &noise)| if (n / BLOCK) % 50 < 20 { noise + voiced(0.1, n) } else { noise })
+            .collect();
+        let active = decisions(&mut vad, &signal);
+        for (block, &active) in active.iter().enumerate() {
+            match block % 50 {
+                // Voice throughout the burst
+                0..20 => assert!(active, "burst block {} missed", block),
+                // Silent again once the hangover has passed
+                n if n >= 20 + HANGOVER_BLOCKS => assert!(!active, "noise block {} taken as voice", block),
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// The WebRTC audio processing module as a processing backend: AEC3 echo
+// cancellation, noise suppression, AGC2 and its high-pass filter, switched by
+// the same settings as their built-in counterparts. The module only takes
+// 10 ms frames, so blocks go through a `FrameAdapter` and come out 10 ms
+// late.
+
+use super::frames::FrameAdapter;
+use crate::errors::AudioError;
+use crate::settings::ProcessorSettings;
+use webrtc_audio_processing::config::{
+    AdaptiveDigital, EchoCanceller, GainController, GainController2, HighPassFilter, NoiseSuppression,
+    NoiseSuppressionLevel, Pipeline,
+};
+use webrtc_audio_processing::{Config, Processor};
+
+pub struct WebRtcApm {
+    processor: Processor,
+    frames: FrameAdapter,
+    config: Config,
+    channels: usize,
+    // Frames the module rejected; they pass through unprocessed
+    errors: u64,
+}
+
+impl WebRtcApm {
+    // Fails for rates other than `WEBRTC_SAMPLE_RATES`
+    pub fn new(sample_rate: u32, channels: u16) -> Result<Self, AudioError> {
+        let processor = Processor::new(sample_rate).map_err(|e| {
+            AudioError::InvalidSetting(format!("WebRTC audio processing can't run at {} Hz: {}", sample_rate, e))
+        })?;
+        Ok(Self {
+            frames: FrameAdapter::new(processor.num_samples_per_frame(), channels),
+            processor,
+            config: Config::default(),
+            channels: channels.max(1) as usize,
+            errors: 0,
+        })
+    }
+
+    // Maps the settings onto the module's submodules. Its own config only
+    // gets pushed when something changed, which reinitializes the parts
+    // concerned.
+    pub fn configure(&mut self, settings: &ProcessorSettings) {
+        let multi_channel = self.channels > 1;
+        let config = Config {
+            pipeline: Pipeline {
+                multi_channel_capture: multi_channel,
+                multi_channel_render: multi_channel,
+                ..Pipeline::default()
+            },
+            // AEC3 tracks the delay itself, on top of the reference delay
+            echo_canceller: settings.echo_cancellation.then_some(EchoCanceller::Full { stream_delay_ms: None }),
+            noise_suppression: settings.noise_reduction.then(|| NoiseSuppression {
+                level: suppression_level(settings.noise_reduction_strength),
+                ..NoiseSuppression::default()
+            }),
+            gain_controller: settings.agc_enabled.then(|| {
+                GainController::GainController2(GainController2 {
+                    adaptive_digital: Some(AdaptiveDigital {
+                        max_gain_db: settings.agc_max_gain_db,
+                        ..AdaptiveDigital::default()
+                    }),
+                    ..GainController2::default()
+                })
+            }),
+            high_pass_filter: settings.highpass_enabled.then(HighPassFilter::default),
+            ..Config::default()
+        };
+        if config != self.config {
+            self.processor.set_config(config);
+            self.config = config;
+        }
+    }
+
+    // Delay of the output behind the input, in interleaved samples
+    pub fn latency(&self) -> usize {
+        self.frames.latency()
+    }
+
+    // Frames the module failed to process since it was created
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    // Processes an interleaved mic block against the echo reference lined up
+    // with it, returning as many samples
+    pub fn process(&mut self, mic: &[f32], reference: &[f32]) -> Vec<f32> {
+        let processor = &self.processor;
+        let errors = &mut self.errors;
+        self.frames.process(mic, reference, |capture, render| {
+            // The module has to see what was played before the capture that
+            // may contain its echo
+            let analyzed = processor.analyze_render_frame(render.iter());
+            if analyzed.and_then(|()| processor.process_capture_frame(capture.iter_mut())).is_err() {
+                *errors += 1;
+            }
+        })
+    }
+}
+
+// Suppression level closest to a strength of the built-in noise reduction,
+// which runs from 1 to 4
+fn suppression_level(strength: f32) -> NoiseSuppressionLevel {
+    if strength < 1.75 {
+        NoiseSuppressionLevel::Low
+    } else if strength < 2.5 {
+        NoiseSuppressionLevel::Moderate
+    } else if strength < 3.25 {
+        NoiseSuppressionLevel::High
+    } else {
+        NoiseSuppressionLevel::VeryHigh
+    }
+}
+
+// Holds the module in `DspState`. A copy of the state starts without one: the
+// module can't be duplicated mid-stream, and the copies made for a switch
+// transition only ever run the built-in chain.
+#[derive(Default)]
+pub struct WebRtcSlot(pub Option<WebRtcApm>);
+
+impl Clone for WebRtcSlot {
+    fn clone(&self) -> Self {
+        Self(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_outside_the_modules_are_refused() {
+        assert!(WebRtcApm::new(44100, 1).is_err());
+        assert!(WebRtcApm::new(48000, 2).is_ok());
+    }
+
+    #[test]
+    fn blocks_of_any_size_come_back_whole() {
+        let mut apm = WebRtcApm::new(48000, 2).unwrap();
+        apm.configure(&ProcessorSettings::default());
+        let mut seed = 1u32;
+        let mut next = || {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        let mic: Vec<f32> = (0..96000).map(|_| 0.1 * next()).collect();
+        let reference: Vec<f32> = (0..96000).map(|_| 0.1 * next()).collect();
+
+        let mut output = Vec::new();
+        for (mic, reference) in mic.chunks(1000).zip(reference.chunks(1000)) {
+            output.extend(apm.process(mic, reference));
+        }
+        assert_eq!(output.len(), mic.len());
+        assert_eq!(apm.latency(), 960);
+        assert_eq!(apm.errors(), 0);
+        assert!(output.iter().all(|s| s.is_finite()));
+    }
+}
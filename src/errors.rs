@@ -0,0 +1,119 @@
+use crate::events::{EventSender, ProcessorEvent};
+use std::sync::{Arc, Mutex};
+use tracing::error;
+
+/// Failures of [`AudioProcessor`](crate::AudioProcessor) methods.
+///
+/// Device and stream problems get their own variants so callers can react to
+/// them (e.g. offer another device); everything else carries a message.
+#[derive(Debug, thiserror::Error)]
+pub enum AudioError {
+    /// No device of the kind ("input", "output") is selected or present
+    #[error("No {0} device available")]
+    NoDevice(&'static str),
+    /// A device looked up by name is gone
+    #[error("{kind} device \"{name}\" not found")]
+    DeviceNotFound { kind: &'static str, name: String },
+    /// A device index beyond the enumerated devices
+    #[error("No {kind} device at index {index}")]
+    DeviceIndex { kind: &'static str, index: usize },
+    /// The audio backend picked with `set_host` isn't available
+    #[error("Failed to open the audio host: {0}")]
+    HostUnavailable(#[from] cpal::HostUnavailable),
+    /// The host failed to list its devices
+    #[error("Failed to list audio devices: {0}")]
+    Enumerate(#[from] cpal::DevicesError),
+    /// The device's formats couldn't be queried or none is usable
+    #[error("Failed to negotiate a stream configuration: {0}")]
+    StreamConfig(String),
+    #[error("Failed to build stream: {0}")]
+    BuildStream(#[from] cpal::BuildStreamError),
+    /// A stream that kept failing to start, typically because another
+    /// application holds the device exclusively
+    #[error("{kind} device is busy: {source}")]
+    DeviceBusy { kind: &'static str, source: cpal::PlayStreamError },
+    /// The device went away as its stream was being started
+    #[error("{0} device was disconnected")]
+    DeviceDisconnected(&'static str),
+    /// A capture or output route the system lacks (monitor source, loopback
+    /// driver, virtual cable), with a hint on setting it up
+    #[error("{0}")]
+    Unavailable(String),
+    /// Delay calibration heard no echo of the system audio
+    #[error("No echo of the system audio found in the microphone; play audio through the speakers while calibrating")]
+    NoEcho,
+    /// A parameter out of range or conflicting with another
+    #[error("{0}")]
+    InvalidSetting(String),
+    /// The call needs the processor in another state (e.g. processing)
+    #[error("{0}")]
+    InvalidState(String),
+    /// Files, presets and other failures outside the audio devices
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl From<cpal::DefaultStreamConfigError> for AudioError {
+    fn from(err: cpal::DefaultStreamConfigError) -> Self {
+        AudioError::StreamConfig(err.to_string())
+    }
+}
+
+impl From<cpal::SupportedStreamConfigsError> for AudioError {
+    fn from(err: cpal::SupportedStreamConfigsError) -> Self {
+        AudioError::StreamConfig(err.to_string())
+    }
+}
+
+pub(crate) type Result<T, E = AudioError> = std::result::Result<T, E>;
+
+// Errors kept for the UI before the oldest are dropped
+const MAX_ERRORS: usize = 20;
+
+// Errors raised off the UI thread (stream callbacks, the processing task)
+// collected for display. Also logged, so headless runs still see them, and
+// sent to event subscribers.
+#[derive(Clone)]
+pub(crate) struct ErrorLog {
+    errors: Arc<Mutex<Vec<String>>>,
+    // The latest errors whether displayed or not, for diagnostics
+    recent: Arc<Mutex<Vec<String>>>,
+    events: EventSender,
+}
+
+impl ErrorLog {
+    pub(crate) fn new(events: EventSender) -> Self {
+        Self { errors: Arc::default(), recent: Arc::default(), events }
+    }
+
+    pub(crate) fn push(&self, message: String) {
+        error!("{}", message);
+        self.events.emit(ProcessorEvent::Error(message.clone()));
+        for log in [&self.errors, &self.recent] {
+            if let Ok(mut errors) = log.lock() {
+                if errors.len() == MAX_ERRORS {
+                    errors.remove(0);
+                }
+                errors.push(message.clone());
+            }
+        }
+    }
+
+    // Errors since the last call, oldest first
+    pub(crate) fn take(&self) -> Vec<String> {
+        self.errors.lock().map(|mut errors| std::mem::take(&mut *errors)).unwrap_or_default()
+    }
+
+    // The last MAX_ERRORS errors, oldest first, including those already taken
+    pub(crate) fn recent(&self) -> Vec<String> {
+        self.recent.lock().map(|errors| errors.clone()).unwrap_or_default()
+    }
+}
+
+// Human-readable message for a cpal stream error, calling out disconnects
+pub(crate) fn describe_stream_error(stream: &str, err: &cpal::StreamError) -> String {
+    match err {
+        cpal::StreamError::DeviceNotAvailable => format!("{} device disconnected", stream),
+        cpal::StreamError::BackendSpecific { err } => format!("{} stream error: {}", stream, err),
+    }
+}
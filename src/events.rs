@@ -0,0 +1,64 @@
+// Notifications about what the processor is doing, for applications that
+// embed it and want to react rather than poll. Events go out on a bounded
+// broadcast channel: sending never waits, and a subscriber that falls more
+// than EVENT_CAPACITY events behind loses the oldest ones (its next `recv`
+// reports how many were skipped).
+
+use tokio::sync::broadcast;
+
+// Events buffered per subscriber before the oldest are dropped
+pub const EVENT_CAPACITY: usize = 256;
+
+/// Which level meter an event refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// The microphone before processing
+    Input,
+    /// The processed audio sent to the output
+    Output,
+}
+
+/// Something that happened inside an [`AudioProcessor`](crate::AudioProcessor).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProcessorEvent {
+    /// Capture, processing and output were started
+    Started,
+    /// All streams and the processing task were stopped
+    Stopped,
+    /// A new input device was selected, by the user or after the previous
+    /// one disconnected
+    InputDeviceChanged(String),
+    /// A new output device was selected
+    OutputDeviceChanged(String),
+    /// A stream reported an error, such as a device disconnecting
+    Error(String),
+    /// A signal reached full scale. Sent once until the clip indicators are
+    /// reset.
+    Clipped(Signal),
+    /// Voice activity detection switched between speech and silence
+    VoiceActivity(bool),
+    /// The limiter started (true) or stopped (false) reducing the gain
+    LimiterActive(bool),
+}
+
+// Sending half shared by the processor, its stream callbacks and the
+// processing task
+#[derive(Clone)]
+pub(crate) struct EventSender(broadcast::Sender<ProcessorEvent>);
+
+impl Default for EventSender {
+    fn default() -> Self {
+        Self(broadcast::channel(EVENT_CAPACITY).0)
+    }
+}
+
+impl EventSender {
+    // Never blocks; without subscribers the event is simply dropped
+    pub(crate) fn emit(&self, event: ProcessorEvent) {
+        let _ = self.0.send(event);
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<ProcessorEvent> {
+        self.0.subscribe()
+    }
+}
@@ -0,0 +1,69 @@
+use crate::cli::Args;
+use anyhow::{anyhow, Result};
+use cancelcaster::metrics::DEFAULT_METRICS_INTERVAL;
+use cancelcaster::AudioProcessor;
+use std::time::Duration;
+use tracing::info;
+
+// Runs capture, processing and output without a window until Ctrl-C
+pub async fn run(args: &Args) -> Result<()> {
+    let mut processor = AudioProcessor::new()?;
+
+    if let Some(name) = &args.input_device {
+        let index = processor.find_input_device(name)
+            .ok_or_else(|| anyhow!("Input device \"{}\" not found", name))?;
+        processor.set_input_device(index)?;
+    }
+    if let Some(name) = &args.output_device {
+        let index = processor.find_output_device(name)
+            .ok_or_else(|| anyhow!("Output device \"{}\" not found", name))?;
+        processor.set_output_device(index)?;
+    }
+
+    if let Some(path) = args.metrics_path() {
+        processor.start_metrics(path, args.metrics_interval.unwrap_or(DEFAULT_METRICS_INTERVAL))?;
+    }
+
+    processor.start()?;
+    info!("Running headless, press Ctrl-C to stop");
+
+    // Check for unplugged devices while waiting for Ctrl-C, and retry
+    // streams a busy device refused to start, without holding up the runtime
+    let mut recovery = tokio::time::interval(Duration::from_millis(500));
+    let mut retries = tokio::time::interval(Duration::from_millis(10));
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let result = loop {
+        tokio::select! {
+            signal = &mut ctrl_c => break signal.map_err(Into::into),
+            _ = recovery.tick() => {
+                if let Err(e) = processor.recover_lost_devices() {
+                    break Err(e.into());
+                }
+                // The export ended by itself on a write error
+                if args.metrics_file.is_some() && !processor.is_exporting_metrics() {
+                    break processor.stop_metrics().map_err(Into::into);
+                }
+            }
+            _ = retries.tick() => {
+                if let Err(e) = processor.retry_stream_starts() {
+                    break Err(e.into());
+                }
+            }
+        }
+    };
+
+    processor.stop();
+    result.and(processor.stop_metrics().map_err(Into::into))
+}
+
+// Processes a WAV file offline with the saved settings
+pub fn run_file(args: &Args) -> Result<()> {
+    let (Some(input), Some(output)) = (&args.input_file, &args.output_file) else {
+        return Ok(());
+    };
+    let processor = AudioProcessor::new()?;
+    processor.process_file(input, output)?;
+    info!("Wrote {}", output.display());
+    Ok(())
+}
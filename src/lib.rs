@@ -0,0 +1,44 @@
+//! Audio capture and DSP behind the CancelCaster GUI.
+//!
+//! [`AudioProcessor`] owns the cpal streams and the processing task. The DSP
+//! itself is plain functions over `&[f32]` slices ([`AudioProcessor::process_audio_chunk`],
+//! [`reduce_noise`]) so it can be driven headless without any device.
+//! [`OfflinePipeline`] runs that chain synchronously on pushed samples, for
+//! checking it against synthetic signals.
+
+pub mod audio;
+pub mod config;
+pub mod diagnostics;
+pub mod dsp;
+mod errors;
+pub mod events;
+pub mod metrics;
+pub mod pipeline;
+pub mod playout;
+pub mod presets;
+mod recording;
+pub mod replay;
+mod scope;
+pub mod settings;
+mod stream;
+pub mod virtual_device;
+
+pub use audio::{
+    AudioProcessor, AudioProcessorBuilder, DeviceCapability, DeviceInfo, LatencyBreakdown, MeterReading, MonitorSource, SessionStats, SpectrumSnapshot,
+    Waveform,
+};
+pub use config::Config;
+pub use diagnostics::Diagnostics;
+pub use errors::AudioError;
+// Audio backend identifiers, for `AudioProcessor::set_host`
+pub use cpal::HostId;
+pub use events::{ProcessorEvent, Signal};
+pub use metrics::MetricsRecord;
+pub use pipeline::OfflinePipeline;
+pub use dsp::spectral::{reduce_noise, NoiseReductionMode, SpectralState, WindowType};
+pub use dsp::channels::{ChannelMap, MonoToStereoMode};
+pub use dsp::weighting::MeterWeighting;
+pub use dsp::backend::ProcessingBackend;
+pub use dsp::DspState;
+pub use presets::{Preset, PresetStore};
+pub use settings::{ProcessorSettings, QualityProfile};
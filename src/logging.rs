@@ -0,0 +1,152 @@
+// Log output: stderr as before, plus a file that survives the session so GUI
+// users, who never see stderr, can attach it to bug reports. The file rotates
+// by size, keeping a couple of older files, so it never grows unbounded.
+
+use cancelcaster::config::data_dir;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use tracing::level_filters::LevelFilter;
+use tracing::warn;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Registry};
+
+// Size at which the log file is rotated, and rotated files kept, for at most
+// 15 MB of logs in total
+const MAX_LOG_BYTES: u64 = 5 * 1024 * 1024;
+const ROTATED_LOGS: usize = 2;
+
+// Default log file, in the platform data directory
+pub fn default_log_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("cancelcaster.log"))
+}
+
+// Path of the `index`th rotated file: cancelcaster.log.1 is the newest
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+struct LogFile {
+    file: File,
+    len: u64,
+}
+
+// Appends to a file, moving it aside once it would exceed MAX_LOG_BYTES.
+// Events are written whole, so a rotation never splits a line.
+struct RotatingLog {
+    path: PathBuf,
+    file: Mutex<LogFile>,
+}
+
+impl RotatingLog {
+    fn open(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { path: path.to_path_buf(), file: Mutex::new(LogFile { file, len }) })
+    }
+
+    fn rotate(&self, log: &mut LogFile) -> io::Result<()> {
+        for index in (1..=ROTATED_LOGS).rev() {
+            let from = if index == 1 { self.path.clone() } else { rotated_path(&self.path, index - 1) };
+            let to = rotated_path(&self.path, index);
+            // Renaming onto an existing file fails on Windows
+            let _ = std::fs::remove_file(&to);
+            if from.exists() {
+                std::fs::rename(&from, &to)?;
+            }
+        }
+        log.file = File::create(&self.path)?;
+        log.len = 0;
+        Ok(())
+    }
+}
+
+impl Write for &RotatingLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A panic while logging mustn't take the log down with it
+        let mut log = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        if log.len > 0 && log.len + buf.len() as u64 > MAX_LOG_BYTES {
+            self.rotate(&mut log)?;
+        }
+        let written = log.file.write(buf)?;
+        log.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.lock().unwrap_or_else(PoisonError::into_inner).file.flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for RotatingLog {
+    type Writer = &'a RotatingLog;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+// Where the log goes and how verbose it is, for the UI
+pub struct LogControl {
+    path: Option<PathBuf>,
+    level: reload::Handle<LevelFilter, Registry>,
+}
+
+impl LogControl {
+    // The log file, None when it couldn't be opened
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // Debug messages on top of the usual ones, e.g. while reproducing a bug
+    pub fn set_verbose(&self, verbose: bool) {
+        let level = if verbose { LevelFilter::DEBUG } else { LevelFilter::INFO };
+        if let Err(e) = self.level.modify(|filter| *filter = level) {
+            warn!("Failed to change the log level: {}", e);
+        }
+    }
+
+    pub fn is_verbose(&self) -> bool {
+        self.level.clone_current().is_some_and(|level| level >= LevelFilter::DEBUG)
+    }
+}
+
+// Installs the global subscriber, logging to stderr (keeping stdout free for
+// metrics) and to `path`, or the default log file when None. Without a
+// usable file only stderr is logged to, with a warning.
+pub fn init(path: Option<&Path>) -> LogControl {
+    let (level, handle) = reload::Layer::new(LevelFilter::INFO);
+    let path = path.map(Path::to_path_buf).or_else(default_log_path);
+    let file = path.as_deref().map(RotatingLog::open);
+    let (file_layer, error) = match file {
+        Some(Ok(log)) => (Some(fmt::layer().with_ansi(false).with_writer(log)), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(level)
+        .with(fmt::layer().with_writer(io::stderr))
+        .with(file_layer)
+        .init();
+
+    let path = match (path, error) {
+        (Some(path), Some(e)) => {
+            warn!("Failed to open log file {}: {}", path.display(), e);
+            None
+        }
+        (path, _) => path,
+    };
+    if let Some(path) = &path {
+        tracing::info!("Logging to {}", path.display());
+    }
+    LogControl { path, level: handle }
+}
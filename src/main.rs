@@ -1,14 +1,39 @@
-mod audio;
+mod cli;
+mod headless;
+mod logging;
+mod plots;
 mod ui;
 
+use cli::Args;
 use eframe::egui;
-use tracing_subscriber;
 use ui::CancelCasterApp;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    let log = logging::init(args.log_file.as_deref());
+
+    if args.input_file.is_some() {
+        headless::run_file(&args)?;
+        return Ok(());
+    }
+
+    if args.headless {
+        headless::run(&args).await?;
+        return Ok(());
+    }
 
     // Configure native options for the GUI
     let options = eframe::NativeOptions {
@@ -23,8 +48,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     eframe::run_native(
         "CancelCaster",
         options,
-        Box::new(|cc| {
-            Box::new(CancelCasterApp::new(cc).unwrap_or_else(|e| {
+        Box::new(move |cc| {
+            Box::new(CancelCasterApp::new(cc, &args, log).unwrap_or_else(|e| {
                 eprintln!("Failed to create application: {}", e);
                 std::process::exit(1);
             }))
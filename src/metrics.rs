@@ -0,0 +1,121 @@
+// Periodic export of the pipeline's meters as JSON lines, one record per
+// interval, for external dashboards. Records are sampled from the atomics the
+// processing task already publishes and written on their own thread, so a
+// slow disk or a blocked stdout never holds up the audio.
+
+use crate::audio::MeterReading;
+use anyhow::Result;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_METRICS_INTERVAL: Duration = Duration::from_secs(1);
+
+// Shortest interval accepted, to keep the writer from spinning
+const MIN_METRICS_INTERVAL: Duration = Duration::from_millis(10);
+
+// Snapshot of the pipeline at one moment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricsRecord {
+    pub timestamp: SystemTime,
+    pub input: MeterReading,
+    pub output: MeterReading,
+    // Share of the block duration spent processing, averaged
+    pub processing_load: f32,
+    pub latency_ms: f32,
+    pub voice_active: bool,
+    // The gate is enabled and currently passing audio
+    pub gate_open: bool,
+    // Gain reduction of the limiter in dB (0 or less)
+    pub limiter_reduction_db: f32,
+}
+
+// JSON has no infinities or NaN; those become null
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+impl MetricsRecord {
+    // One line of JSON, without the trailing newline. The timestamp is in
+    // seconds since the Unix epoch, with millisecond precision.
+    pub fn to_json(&self) -> String {
+        let timestamp = self.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+        format!(
+            concat!(
+                "{{\"timestamp\":{:.3},",
+                "\"input_rms_db\":{},\"input_peak_db\":{},\"input_clipped\":{},",
+                "\"output_rms_db\":{},\"output_peak_db\":{},\"output_clipped\":{},",
+                "\"processing_load\":{},\"latency_ms\":{},\"voice_active\":{},",
+                "\"gate_open\":{},\"limiter_reduction_db\":{}}}",
+            ),
+            timestamp,
+            json_number(self.input.rms_db),
+            json_number(self.input.peak_db),
+            self.input.clipped,
+            json_number(self.output.rms_db),
+            json_number(self.output.peak_db),
+            self.output.clipped,
+            json_number(self.processing_load),
+            json_number(self.latency_ms),
+            self.voice_active,
+            self.gate_open,
+            json_number(self.limiter_reduction_db),
+        )
+    }
+}
+
+// A writer thread emitting a record from `sample` every interval
+pub(crate) struct MetricsExport {
+    // Dropping the sender wakes the thread and ends it
+    stop: Sender<()>,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl MetricsExport {
+    // Appends to the file at `path`, or writes to stdout when there is none
+    pub(crate) fn start(
+        path: Option<&Path>,
+        interval: Duration,
+        mut sample: impl FnMut() -> MetricsRecord + Send + 'static,
+    ) -> Result<Self> {
+        let mut sink: Box<dyn Write + Send> = match path {
+            Some(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+            None => Box::new(std::io::stdout()),
+        };
+        let interval = interval.max(MIN_METRICS_INTERVAL);
+        let (stop, stopped) = channel::<()>();
+
+        let thread = std::thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {
+                    // Flush every line so a dashboard tailing the file sees it at once
+                    writeln!(sink, "{}", sample().to_json())?;
+                    sink.flush()?;
+                }
+                _ => return Ok(()),
+            }
+        });
+
+        Ok(Self { stop, thread })
+    }
+
+    // True once the writer has stopped on its own, i.e. after an error
+    pub(crate) fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    // Ends the writer and returns the error that stopped it early, if any
+    pub(crate) fn stop(self) -> Result<()> {
+        drop(self.stop);
+        self.thread
+            .join()
+            .map_err(|_| anyhow::anyhow!("Metrics thread panicked"))?
+    }
+}
@@ -0,0 +1,811 @@
+// The processing task's DSP loop without devices: samples are pushed in and
+// pulled out by the caller, and every complete block goes through
+// `AudioProcessor::process_audio_chunk` right away. Since nothing runs
+// concurrently, the same input always gives the same output, which makes
+// this the way to check DSP behavior with synthetic signals.
+
+use crate::audio::{AudioProcessor, STREAM_FADE_IN_MS};
+use crate::dsp::resample::LinearResampler;
+use crate::dsp::spectral::SpectralState;
+use crate::dsp::{apply_mute, clamp_to_ceiling, fade_in, mix_into, DspState, DEFAULT_SAFETY_CEILING_DB, MUTE_FADE_MS};
+use crate::settings::{validate_fft_size, validate_framing, ProcessorSettings};
+use anyhow::Result;
+use std::collections::VecDeque;
+
+pub struct OfflinePipeline {
+    settings: ProcessorSettings,
+    sample_rate: u32,
+    channels: u16,
+    dsp: DspState,
+    mic: VecDeque<f32>,
+    reference: VecDeque<f32>,
+    // Brings reference audio pushed at another rate to `sample_rate`, along
+    // with the rate it was set up for
+    reference_resampler: Option<(u32, LinearResampler)>,
+    output: VecDeque<f32>,
+    mic_block: Vec<f32>,
+    reference_block: Vec<f32>,
+    muted: bool,
+    was_muted: bool,
+    mute_fade_samples: usize,
+    fade_in_samples: usize,
+    // The next block fades in, as after a device switch
+    fade_next: bool,
+    polarity_inverted: bool,
+    // Safety ceiling in dBFS, None when off, and the samples it clamped
+    safety_ceiling_db: Option<f32>,
+    safety_clamps: u64,
+    // Spectra are taken of each block, as while the UI shows them, and the
+    // latest input and output spectra
+    spectrum_wanted: bool,
+    spectra: Option<(Vec<f32>, Vec<f32>)>,
+}
+
+impl OfflinePipeline {
+    // Interleaved audio at `sample_rate` with `channels` channels, processed
+    // in blocks of `settings.block_size(channels)` samples
+    pub fn new(settings: ProcessorSettings, sample_rate: u32, channels: u16) -> Result<Self> {
+        validate_fft_size(settings.fft_size)?;
+        validate_framing(settings.fft_window, settings.hop_size, settings.fft_size)?;
+        if sample_rate == 0 || channels == 0 {
+            anyhow::bail!("Sample rate and channel count must be positive, got {} Hz and {} ch", sample_rate, channels);
+        }
+        let mut dsp = DspState::new(settings.fft_size, settings.hop_size, sample_rate, channels);
+        dsp.apply_settings(&settings);
+        Ok(Self {
+            sample_rate,
+            channels,
+            dsp,
+            mic: VecDeque::new(),
+            reference: VecDeque::new(),
+            reference_resampler: None,
+            output: VecDeque::new(),
+            mic_block: vec![0.0; settings.block_size(channels)],
+            reference_block: vec![0.0; settings.block_size(channels)],
+            muted: false,
+            was_muted: false,
+            mute_fade_samples: (MUTE_FADE_MS / 1000.0 * sample_rate as f32) as usize * channels as usize,
+            fade_in_samples: (STREAM_FADE_IN_MS / 1000.0 * sample_rate as f32) as usize * channels as usize,
+            fade_next: false,
+            polarity_inverted: false,
+            safety_ceiling_db: Some(DEFAULT_SAFETY_CEILING_DB),
+            safety_clamps: 0,
+            spectrum_wanted: false,
+            spectra: None,
+            settings,
+        })
+    }
+
+    pub fn settings(&self) -> &ProcessorSettings {
+        &self.settings
+    }
+
+    // Applies from the next block, as a change made while streaming would.
+    // A new FFT or hop size restarts noise reduction with a fresh state.
+    pub fn set_settings(&mut self, settings: ProcessorSettings) -> Result<()> {
+        validate_fft_size(settings.fft_size)?;
+        validate_framing(settings.fft_window, settings.hop_size, settings.fft_size)?;
+        if settings.fft_size != self.settings.fft_size || settings.hop_size != self.settings.hop_size {
+            self.dsp.spectral = SpectralState::with_hop(settings.fft_size, settings.hop_size, self.channels);
+            self.mic_block.resize(settings.block_size(self.channels), 0.0);
+            self.reference_block.resize(settings.block_size(self.channels), 0.0);
+        }
+        self.dsp.apply_settings(&settings);
+        self.settings = settings;
+        Ok(())
+    }
+
+    // Clamps the output to a hard ceiling, as `AudioProcessor::set_safety_ceiling_db`
+    // does; on at its default like there, None turns it off
+    pub fn set_safety_ceiling_db(&mut self, ceiling_db: Option<f32>) {
+        self.safety_ceiling_db = ceiling_db;
+    }
+
+    // Output samples the safety ceiling clamped so far
+    pub fn safety_clamps(&self) -> u64 {
+        self.safety_clamps
+    }
+
+    // Takes the spectra of every block from the next one on, as the
+    // processing task does while `AudioProcessor::get_spectrum` is polled
+    pub fn set_spectrum_wanted(&mut self, wanted: bool) {
+        self.spectrum_wanted = wanted;
+    }
+
+    // Input and output spectra in dBFS of the latest block they were taken
+    // of; power save skips them like the spectral stages
+    pub fn spectra(&self) -> Option<&(Vec<f32>, Vec<f32>)> {
+        self.spectra.as_ref()
+    }
+
+    // Silences blocks from the next one on, as `AudioProcessor::set_muted`
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    // Negates the output from the next block on, as
+    // `AudioProcessor::set_output_polarity_inverted`
+    pub fn set_output_polarity_inverted(&mut self, inverted: bool) {
+        self.polarity_inverted = inverted;
+    }
+
+    // What the processing task does when the input device changes: queued
+    // input and reference not yet processed are dropped, the DSP state starts
+    // over and the next block fades in. Output already processed stays.
+    pub fn switch_input(&mut self) {
+        self.mic.clear();
+        self.reference.clear();
+        self.reference_resampler = None;
+        self.dsp.reset(self.settings.fft_size, self.settings.hop_size);
+        self.dsp.apply_settings(&self.settings);
+        self.fade_next = true;
+    }
+
+    // What `AudioProcessor::reset_dsp_state` has the processing task do: the
+    // DSP state starts over and the next block fades in, while queued input
+    // and reference stay
+    pub fn reset_dsp_state(&mut self) {
+        self.dsp.reset(self.settings.fft_size, self.settings.hop_size);
+        self.dsp.apply_settings(&self.settings);
+        self.fade_next = true;
+    }
+
+    // Stage state after the blocks processed so far, e.g. `dsp().vad`
+    pub fn dsp(&self) -> &DspState {
+        &self.dsp
+    }
+
+    // Delay of the output behind the input, in samples
+    pub fn latency(&self) -> usize {
+        self.dsp.latency(&self.settings)
+    }
+
+    // Queues application audio for echo cancellation. Push it before the
+    // microphone samples it should line up with; blocks processed without
+    // enough reference get silence for the rest.
+    pub fn push_reference_samples(&mut self, samples: &[f32]) {
+        self.reference.extend(samples);
+    }
+
+    // Like `push_reference_samples`, for application audio at `sample_rate`
+    // rather than the pipeline's, as a loopback device often runs (44.1kHz
+    // against a 48kHz mic). Echo cancellation pairs the reference with the
+    // mic sample for sample, so it is resampled first, as the live loopback
+    // capture does. The channel layout must already match. Nothing is pushed
+    // for a rate of 0.
+    pub fn push_reference_samples_at(&mut self, samples: &[f32], sample_rate: u32) {
+        if sample_rate == self.sample_rate {
+            return self.push_reference_samples(samples);
+        }
+        if sample_rate == 0 {
+            return;
+        }
+        if self.reference_resampler.as_ref().is_none_or(|(rate, _)| *rate != sample_rate) {
+            let resampler = LinearResampler::new(sample_rate, self.sample_rate, self.channels as usize);
+            self.reference_resampler = Some((sample_rate, resampler));
+        }
+        if let Some((_, resampler)) = &mut self.reference_resampler {
+            let reference = &mut self.reference;
+            resampler.process(samples, |sample| reference.push_back(sample));
+        }
+    }
+
+    // Like `push_reference_samples`, for application audio from several
+    // sources played at once, e.g. two apps on different endpoints. They are
+    // summed into one reference, as the live path does with the sources of
+    // `AudioProcessor::add_loopback_source`; a shorter source is padded with
+    // silence.
+    pub fn push_reference_sources(&mut self, sources: &[&[f32]]) {
+        let len = sources.iter().map(|source| source.len()).max().unwrap_or(0);
+        let mut mixed = vec![0.0; len];
+        for source in sources {
+            mix_into(&mut mixed, source);
+        }
+        self.push_reference_samples(&mixed);
+    }
+
+    // Queues microphone samples and processes every block now complete
+    pub fn push_input_samples(&mut self, samples: &[f32]) {
+        self.mic.extend(samples);
+        while self.mic.len() >= self.settings.block_size(self.channels) {
+            self.process_block();
+        }
+    }
+
+    // Pads the pending input with silence until every pushed sample, plus
+    // the latency, has come out
+    pub fn flush(&mut self) {
+        let block_size = self.settings.block_size(self.channels);
+        let padded = (self.mic.len() + self.latency()).div_ceil(block_size) * block_size;
+        self.mic.resize(padded, 0.0);
+        while self.mic.len() >= block_size {
+            self.process_block();
+        }
+    }
+
+    // Processed samples ready to be pulled
+    pub fn available(&self) -> usize {
+        self.output.len()
+    }
+
+    // Moves up to `output.len()` processed samples into `output` and
+    // returns how many were written
+    pub fn pull_output_samples(&mut self, output: &mut [f32]) -> usize {
+        let count = output.len().min(self.output.len());
+        for (sample, processed) in output.iter_mut().zip(self.output.drain(..count)) {
+            *sample = processed;
+        }
+        count
+    }
+
+    fn process_block(&mut self) {
+        for (sample, input) in self.mic_block.iter_mut().zip(self.mic.drain(..self.settings.block_size(self.channels))) {
+            *sample = input;
+        }
+        let available = self.reference.len().min(self.reference_block.len());
+        for (sample, reference) in self.reference_block.iter_mut().zip(self.reference.drain(..available)) {
+            *sample = reference;
+        }
+        self.reference_block[available..].fill(0.0);
+
+        let mut processed = AudioProcessor::process_audio_chunk(
+            &self.mic_block,
+            &self.reference_block,
+            &self.settings,
+            &mut self.dsp,
+        );
+        apply_mute(&mut processed, self.muted, self.was_muted, self.mute_fade_samples);
+        self.was_muted = self.muted;
+        if self.fade_next {
+            fade_in(&mut processed, self.fade_in_samples);
+            self.fade_next = false;
+        }
+        if self.polarity_inverted {
+            processed.iter_mut().for_each(|sample| *sample = -*sample);
+        }
+        if let Some(ceiling_db) = self.safety_ceiling_db {
+            self.safety_clamps += clamp_to_ceiling(&mut processed, ceiling_db) as u64;
+        }
+        if self.spectrum_wanted && !self.dsp.is_power_saving() {
+            self.spectra = Some((self.dsp.analyzer.magnitudes_db(&self.mic_block), self.dsp.analyzer.magnitudes_db(&processed)));
+        }
+        self.output.extend(processed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const RATE: u32 = 48000;
+
+    // Every stage off, so each test switches on only the one it checks
+    fn bypass() -> ProcessorSettings {
+        ProcessorSettings {
+            echo_cancellation: false,
+            noise_reduction: false,
+            highpass_enabled: false,
+            limiter_enabled: false,
+            ..ProcessorSettings::default()
+        }
+    }
+
+    fn sine(frequency: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len).map(|n| amplitude * (2.0 * PI * frequency * n as f32 / RATE as f32).sin()).collect()
+    }
+
+    // Uniform white noise in [-amplitude, amplitude) from a fixed seed
+    fn noise(amplitude: f32, len: usize, mut seed: u32) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn power(samples: &[f32]) -> f32 {
+        samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32
+    }
+
+    fn db(power_ratio: f32) -> f32 {
+        10.0 * power_ratio.log10()
+    }
+
+    // Mono `input` through a pipeline with `settings`, flushed and lined up
+    // with the input again
+    fn run(settings: ProcessorSettings, input: &[f32]) -> Vec<f32> {
+        let mut pipeline = OfflinePipeline::new(settings, RATE, 1).unwrap();
+        pipeline.set_safety_ceiling_db(None);
+        pipeline.push_input_samples(input);
+        pipeline.flush();
+        let mut output = vec![0.0; pipeline.available()];
+        pipeline.pull_output_samples(&mut output);
+        output.drain(..pipeline.latency());
+        output.truncate(input.len());
+        output
+    }
+
+    // Amplitude of the `frequency` component of `samples`
+    fn tone_amplitude(samples: &[f32], frequency: f32) -> f32 {
+        let (mut re, mut im) = (0.0, 0.0);
+        for (n, s) in samples.iter().enumerate() {
+            let phase = 2.0 * PI * frequency * n as f32 / RATE as f32;
+            re += s * phase.cos();
+            im += s * phase.sin();
+        }
+        2.0 * (re * re + im * im).sqrt() / samples.len() as f32
+    }
+
+    #[test]
+    fn stereo_channels_stay_separate() {
+        // Every stage at its default, so echo cancellation and the spectral
+        // frames both see interleaved stereo
+        let mut pipeline = OfflinePipeline::new(ProcessorSettings::default(), RATE, 2).unwrap();
+        pipeline.set_safety_ceiling_db(None);
+        let (left, right) = (sine(440.0, 0.3, RATE as usize * 2), sine(1000.0, 0.3, RATE as usize * 2));
+        let input: Vec<f32> = left.iter().zip(&right).flat_map(|(&l, &r)| [l, r]).collect();
+        pipeline.push_input_samples(&input);
+        pipeline.flush();
+        let mut output = vec![0.0; pipeline.available()];
+        pipeline.pull_output_samples(&mut output);
+
+        let settled = &output[RATE as usize * 2..input.len()];
+        let channel = |index: usize| -> Vec<f32> { settled.iter().skip(index).step_by(2).copied().collect() };
+        let (left, right) = (channel(0), channel(1));
+        let (left_own, left_leak) = (tone_amplitude(&left, 440.0), tone_amplitude(&left, 1000.0));
+        let (right_own, right_leak) = (tone_amplitude(&right, 1000.0), tone_amplitude(&right, 440.0));
+        // Noise reduction takes steady tones down to its floor, a tenth
+        assert!(left_own > 0.02 && right_own > 0.02, "tones at {} and {}", left_own, right_own);
+        assert!(left_leak < left_own * 0.01, "1 kHz in the left channel at {}", left_leak);
+        assert!(right_leak < right_own * 0.01, "440 Hz in the right channel at {}", right_leak);
+    }
+
+    #[test]
+    fn output_is_silent_while_muted_and_resumes_after() {
+        let mut pipeline = OfflinePipeline::new(bypass(), RATE, 1).unwrap();
+        let block = pipeline.settings().block_size(1);
+        let input = sine(440.0, 0.5, block * 15);
+        let mut output = vec![0.0; input.len()];
+        for (phase, muted) in [false, true, false].into_iter().enumerate() {
+            pipeline.set_muted(muted);
+            let range = phase * block * 5..(phase + 1) * block * 5;
+            pipeline.push_input_samples(&input[range.clone()]);
+            assert_eq!(pipeline.pull_output_samples(&mut output[range]), block * 5);
+        }
+
+        // The block where muting starts fades out, the rest are silent
+        let fade = pipeline.mute_fade_samples;
+        assert!(output[block * 5 + fade..block * 10].iter().all(|&s| s == 0.0));
+        // Unmuting fades back in, then the input passes as before
+        assert!(output[block * 10..block * 10 + fade].iter().all(|s| s.abs() <= 0.5));
+        assert_eq!(&output[block * 10 + fade..], &input[block * 10 + fade..]);
+        assert_eq!(&output[..block * 5], &input[..block * 5]);
+        // Neither fade steps more than the sine itself does
+        let max_step = 2.0 * PI * 440.0 / RATE as f32 * 0.5 + 1e-3;
+        assert!(output.windows(2).all(|pair| (pair[1] - pair[0]).abs() <= max_step));
+    }
+
+    #[test]
+    fn inverted_output_is_the_exact_negation() {
+        // The whole default chain, with gain enough after it for the
+        // limiter and safety ceiling to act
+        let settings = ProcessorSettings { output_gain_db: 24.0, ..ProcessorSettings::default() };
+        let mut input = sine(440.0, 0.99, RATE as usize);
+        for (sample, noise) in input.iter_mut().zip(noise(0.05, RATE as usize, 8)) {
+            *sample += noise;
+        }
+        let outputs = [false, true].map(|inverted| {
+            let mut pipeline = OfflinePipeline::new(settings.clone(), RATE, 1).unwrap();
+            pipeline.set_output_polarity_inverted(inverted);
+            pipeline.push_input_samples(&input);
+            let mut output = vec![0.0; pipeline.available()];
+            pipeline.pull_output_samples(&mut output);
+            output
+        });
+        let [normal, inverted] = &outputs;
+        let peak = normal.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(peak > 0.5, "peak of {}", peak);
+        assert_eq!(normal.len(), inverted.len());
+        for (i, (normal, inverted)) in normal.iter().zip(inverted).enumerate() {
+            assert_eq!(*inverted, -normal, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn partial_blocks_wait_for_input_and_flush_whole() {
+        let settings = ProcessorSettings { noise_reduction: true, ..bypass() };
+        let mut input = sine(440.0, 0.5, RATE as usize);
+        for (sample, noise) in input.iter_mut().zip(noise(0.05, RATE as usize, 6)) {
+            *sample += noise;
+        }
+
+        // Nothing is processed, and so no silence padded in, until a whole
+        // block has arrived
+        let mut pipeline = OfflinePipeline::new(settings.clone(), RATE, 1).unwrap();
+        let block = settings.block_size(1);
+        pipeline.push_input_samples(&input[..block - 1]);
+        assert_eq!(pipeline.available(), 0);
+        pipeline.push_input_samples(&input[block - 1..block]);
+        assert_eq!(pipeline.available(), block);
+
+        // Odd-sized pushes give the same output as one, so the noise
+        // estimate never sees a gap
+        let mut chunked = OfflinePipeline::new(settings.clone(), RATE, 1).unwrap();
+        for chunk in input.chunks(333) {
+            chunked.push_input_samples(chunk);
+        }
+        chunked.flush();
+        let mut output = vec![0.0; chunked.available()];
+        chunked.pull_output_samples(&mut output);
+        output.drain(..chunked.latency());
+        assert!(output.len() >= input.len(), "tail cut to {} of {} samples", output.len(), input.len());
+        output.truncate(input.len());
+        assert_eq!(output, run(settings, &input));
+    }
+
+    #[test]
+    fn input_switch_drops_stale_audio_and_state() {
+        let settings = ProcessorSettings { noise_reduction: true, ..bypass() };
+        let mut pipeline = OfflinePipeline::new(settings, RATE, 1).unwrap();
+        let block = pipeline.settings().block_size(1);
+        // A second of loud noise from the old device, ending in half a block
+        // not yet processed
+        pipeline.push_input_samples(&noise(0.5, RATE as usize + block / 2, 5));
+        assert!(pipeline.dsp().spectral.noise_power().iter().any(|&p| p > 0.0));
+        let mut before = vec![0.0; pipeline.available()];
+        pipeline.pull_output_samples(&mut before);
+
+        pipeline.switch_input();
+        assert!(pipeline.dsp().spectral.noise_power().iter().all(|&p| p == 0.0));
+        // The new device is silent, so anything heard is left over
+        pipeline.push_input_samples(&vec![0.0; block * 8]);
+        let mut after = vec![0.0; pipeline.available()];
+        assert_eq!(pipeline.pull_output_samples(&mut after), block * 8);
+        assert!(after.iter().all(|&s| s == 0.0), "stale audio after the switch");
+    }
+
+    #[test]
+    fn reset_recovers_from_a_diverged_echo_filter() {
+        let settings = ProcessorSettings { echo_cancellation: true, noise_reduction: true, ..bypass() };
+        let block = settings.block_size(1);
+        let second = RATE as usize / block * block;
+        let reference = noise(0.5, second * 2, 7);
+        // A second of echo to learn the path from, then the echo is gone, as
+        // when headphones are plugged in, while someone talks over it. The
+        // double talk freezes adaptation, so the filter keeps subtracting
+        // the old echo from the voice.
+        let mut mic: Vec<f32> = reference[..second].iter().map(|s| 0.3 * s).collect();
+        mic.extend(sine(300.0, 0.6, second));
+        let run_from = |pipeline: &mut OfflinePipeline, range: std::ops::Range<usize>| {
+            pipeline.push_reference_samples(&reference[range.clone()]);
+            pipeline.push_input_samples(&mic[range]);
+            let mut output = vec![0.0; pipeline.available()];
+            pipeline.pull_output_samples(&mut output);
+            output
+        };
+        let talk = second..second + second / 2;
+        let after = second + second / 2..second * 2;
+
+        let mut pipeline = OfflinePipeline::new(settings.clone(), RATE, 1).unwrap();
+        run_from(&mut pipeline, 0..second);
+        let diverged = run_from(&mut pipeline, talk.clone());
+        let clean = run_from(&mut OfflinePipeline::new(settings.clone(), RATE, 1).unwrap(), talk);
+        let leaked: Vec<f32> = diverged.iter().zip(&clean).map(|(a, b)| a - b).collect();
+        assert!(db(power(&leaked) / power(&clean)) > -20.0, "no divergence to recover from");
+
+        // After the reset the pipeline carries on exactly as a new one
+        // would, once its output has faded in
+        pipeline.reset_dsp_state();
+        assert!(pipeline.dsp().spectral.noise_power().iter().all(|&p| p == 0.0));
+        let recovered = run_from(&mut pipeline, after.clone());
+        let fresh = run_from(&mut OfflinePipeline::new(settings, RATE, 1).unwrap(), after);
+        let faded = (STREAM_FADE_IN_MS / 1000.0 * RATE as f32) as usize;
+        assert_eq!(recovered.len(), fresh.len());
+        assert_eq!(recovered[faded..], fresh[faded..]);
+    }
+
+    #[test]
+    fn input_switch_fades_the_new_device_in() {
+        let mut pipeline = OfflinePipeline::new(bypass(), RATE, 1).unwrap();
+        let block = pipeline.settings().block_size(1);
+        pipeline.push_input_samples(&vec![0.5; block]);
+        pipeline.switch_input();
+        pipeline.push_input_samples(&vec![0.5; block]);
+        let mut output = vec![0.0; block * 2];
+        pipeline.pull_output_samples(&mut output);
+        let fade = pipeline.fade_in_samples;
+        assert_eq!(output[block], 0.0);
+        assert!(output[block..block + fade].windows(2).all(|pair| pair[1] >= pair[0]));
+        assert_eq!(output[block + fade], 0.5);
+    }
+
+    #[test]
+    fn switching_stages_mid_stream_does_not_click() {
+        // A steady tone changes by at most 0.015 a sample, the noise by 0.01
+        let mut input = sine(220.0, 0.5, RATE as usize * 3);
+        for (sample, noise) in input.iter_mut().zip(noise(0.005, RATE as usize * 3, 10)) {
+            *sample += noise;
+        }
+        let reference = noise(0.1, input.len(), 11);
+        let settings = ProcessorSettings { echo_cancellation: true, ..bypass() };
+        let mut pipeline = OfflinePipeline::new(settings.clone(), RATE, 1).unwrap();
+        let block = settings.block_size(1);
+        // Noise reduction on, echo cancellation off, noise reduction off and
+        // echo cancellation on again, half a second apart
+        let switches = [(true, true), (false, true), (false, false), (true, false)];
+        let mut output = Vec::new();
+        for (n, (mic, reference)) in input.chunks(block).zip(reference.chunks(block)).enumerate() {
+            let switch = (n * block * 2 / RATE as usize).checked_sub(1).and_then(|i| switches.get(i));
+            if let Some(&(echo_cancellation, noise_reduction)) = switch {
+                pipeline.set_settings(ProcessorSettings { echo_cancellation, noise_reduction, ..settings.clone() }).unwrap();
+            }
+            pipeline.push_reference_samples(reference);
+            pipeline.push_input_samples(mic);
+            let mut processed = vec![0.0; pipeline.available()];
+            pipeline.pull_output_samples(&mut processed);
+            output.extend(processed);
+        }
+
+        let (at, jump) = output.windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .enumerate()
+            .fold((0, 0.0f32), |worst, (at, jump)| if jump > worst.1 { (at, jump) } else { worst });
+        assert!(jump < 0.04, "jump of {} at sample {}", jump, at);
+    }
+
+    #[test]
+    fn power_save_stops_transforming_during_silence() {
+        let settings = ProcessorSettings { noise_reduction: true, power_save: true, power_save_hold_ms: 200.0, ..bypass() };
+        let mut pipeline = OfflinePipeline::new(settings, RATE, 1).unwrap();
+        // FFTs of the spectral frames, the spectrum display and the VAD's
+        // flatness test
+        let transforms = |pipeline: &OfflinePipeline| {
+            let dsp = pipeline.dsp();
+            (dsp.spectral.transforms(), dsp.analyzer.transforms(), dsp.vad.transforms())
+        };
+        let half_second = RATE as usize / 2;
+
+        // Past the hold, a room too quiet for the VAD stops the transforms,
+        // once its noise has been heard. Nothing asked for the spectrum, as
+        // when headless, so it was never taken.
+        pipeline.push_input_samples(&noise(0.0001, half_second, 12));
+        assert!(pipeline.dsp().is_power_saving());
+        assert!(pipeline.dsp().spectral.noise_power().iter().any(|&p| p > 0.0));
+        assert_eq!(pipeline.dsp().analyzer.transforms(), 0);
+
+        // The voice brings them back at once, the display's with the UI
+        // looking
+        pipeline.set_spectrum_wanted(true);
+        let (spectral, analyzer, vad) = transforms(&pipeline);
+        pipeline.push_input_samples(&sine(220.0, 0.3, half_second));
+        assert!(!pipeline.dsp().is_power_saving());
+        let (spectral_after, analyzer_after, vad_after) = transforms(&pipeline);
+        assert!(spectral_after > spectral);
+        assert!(analyzer_after > analyzer);
+        assert!(vad_after > vad);
+        assert!(pipeline.spectra().is_some());
+
+        // After it, all of them stop again for as long as the silence lasts
+        pipeline.push_input_samples(&noise(0.0001, half_second, 13));
+        assert!(pipeline.dsp().is_power_saving());
+        let idle_from = transforms(&pipeline);
+        pipeline.push_input_samples(&noise(0.0001, RATE as usize * 2, 14));
+        assert_eq!(transforms(&pipeline), idle_from, "transformed during silence");
+    }
+
+    #[test]
+    fn four_channels_pass_through_in_place() {
+        let mut pipeline = OfflinePipeline::new(bypass(), RATE, 4).unwrap();
+        // Each channel holds its own constant, so a swap shows up as a wrong value
+        let input: Vec<f32> = (0..pipeline.settings().block_size(4)).map(|n| (n % 4) as f32 * 0.1).collect();
+        pipeline.push_input_samples(&input);
+        let mut output = vec![0.0; input.len()];
+        assert_eq!(pipeline.pull_output_samples(&mut output), input.len());
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn echo_cancellation_removes_the_reference() {
+        let settings = ProcessorSettings { echo_cancellation: true, ..bypass() };
+        let mut pipeline = OfflinePipeline::new(settings, RATE, 1).unwrap();
+        let block = pipeline.settings().block_size(1);
+        // The mic hears the application 40 samples late at half level
+        let reference = noise(0.5, RATE as usize * 2, 1);
+        let mic: Vec<f32> = (0..reference.len()).map(|n| if n >= 40 { 0.5 * reference[n - 40] } else { 0.0 }).collect();
+        let mut output = Vec::new();
+        for (mic, reference) in mic.chunks(block).zip(reference.chunks(block)) {
+            pipeline.push_reference_samples(reference);
+            pipeline.push_input_samples(mic);
+            let mut processed = vec![0.0; pipeline.available()];
+            pipeline.pull_output_samples(&mut processed);
+            output.extend(processed);
+        }
+
+        let settled = RATE as usize;
+        let cancellation_db = db(power(&mic[settled..output.len()]) / power(&output[settled..]));
+        assert!(cancellation_db > 20.0, "only {:.1} dB of cancellation", cancellation_db);
+    }
+
+    #[test]
+    fn echo_cancellation_resamples_a_reference_at_another_rate() {
+        // The application plays 40 tones spread up to 2kHz, which the
+        // loopback captures at 44.1kHz; the mic hears them at 48kHz, 40
+        // samples late and 10dB down
+        let mut seed = 1u32;
+        let tones: Vec<(f32, f32)> = (0..40)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                let frequency = 100.0 + (seed >> 8) as f32 / (1 << 24) as f32 * 1900.0;
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (frequency, (seed >> 8) as f32 / (1 << 24) as f32 * 2.0 * PI)
+            })
+            .collect();
+        let chord = |t: f32| tones.iter().map(|(f, phase)| 0.03 * (2.0 * PI * f * t + phase).sin()).sum::<f32>();
+        let seconds = 3;
+        let reference: Vec<f32> = (0..44100 * seconds).map(|n| chord(n as f32 / 44100.0)).collect();
+        let mic: Vec<f32> = (0..RATE as usize * seconds)
+            .map(|n| if n >= 40 { 0.3 * chord((n - 40) as f32 / RATE as f32) } else { 0.0 })
+            .collect();
+
+        let cancel = |push_reference: &dyn Fn(&mut OfflinePipeline)| {
+            let settings = ProcessorSettings { echo_cancellation: true, ..bypass() };
+            let mut pipeline = OfflinePipeline::new(settings, RATE, 1).unwrap();
+            pipeline.set_safety_ceiling_db(None);
+            push_reference(&mut pipeline);
+            pipeline.push_input_samples(&mic);
+            let mut output = vec![0.0; pipeline.available()];
+            pipeline.pull_output_samples(&mut output);
+            let settled = 2 * RATE as usize;
+            db(power(&mic[settled..output.len()]) / power(&output[settled..]))
+        };
+
+        let resampled_db = cancel(&|pipeline| pipeline.push_reference_samples_at(&reference, 44100));
+        assert!(resampled_db > 30.0, "only {:.1} dB of cancellation", resampled_db);
+        // Taken as 48kHz, the reference drifts away from the echo
+        let mismatched_db = cancel(&|pipeline| pipeline.push_reference_samples(&reference));
+        assert!(mismatched_db < 3.0, "{:.1} dB of cancellation without resampling", mismatched_db);
+    }
+
+    #[test]
+    fn echo_cancellation_removes_two_summed_sources() {
+        // Two apps on different endpoints; the mic hears both through the
+        // room, 40 samples late and 10dB down
+        let sources = [noise(0.4, RATE as usize * 2, 1), noise(0.4, RATE as usize * 2, 2)];
+        let mic: Vec<f32> = (0..sources[0].len())
+            .map(|n| if n >= 40 { 0.3 * (sources[0][n - 40] + sources[1][n - 40]) } else { 0.0 })
+            .collect();
+
+        let cancel = |sources: &[&[f32]]| {
+            let settings = ProcessorSettings { echo_cancellation: true, ..bypass() };
+            let mut pipeline = OfflinePipeline::new(settings, RATE, 1).unwrap();
+            pipeline.set_safety_ceiling_db(None);
+            pipeline.push_reference_sources(sources);
+            pipeline.push_input_samples(&mic);
+            let mut output = vec![0.0; pipeline.available()];
+            pipeline.pull_output_samples(&mut output);
+            let settled = RATE as usize;
+            db(power(&mic[settled..output.len()]) / power(&output[settled..]))
+        };
+
+        let both_db = cancel(&[&sources[0], &sources[1]]);
+        assert!(both_db > 20.0, "only {:.1} dB of cancellation", both_db);
+        // With one source missing its echo stays, half of the total
+        let one_db = cancel(&[&sources[0]]);
+        assert!(one_db < 4.0, "{:.1} dB of cancellation from one source", one_db);
+    }
+
+    #[test]
+    fn noise_reduction_lowers_steady_noise() {
+        let settings = ProcessorSettings { noise_reduction: true, ..bypass() };
+        let input = noise(0.02, RATE as usize * 4, 2);
+        let output = run(settings, &input);
+        // After two seconds for the noise estimate to settle
+        let settled = RATE as usize * 2;
+        let reduction_db = db(power(&input[settled..]) / power(&output[settled..]));
+        assert!(reduction_db > 10.0, "noise only {:.1} dB down", reduction_db);
+    }
+
+    #[cfg(feature = "rnnoise")]
+    #[test]
+    fn rnnoise_mode_lowers_noise_and_keeps_the_dry_signal_aligned() {
+        let settings = ProcessorSettings {
+            noise_reduction: true,
+            noise_reduction_mode: crate::dsp::spectral::NoiseReductionMode::RnNoise,
+            ..bypass()
+        };
+        // Rumbling room noise; the network is much less sure about flat hiss
+        let mut last = 0.0;
+        let input: Vec<f32> = noise(0.004, RATE as usize * 4, 4)
+            .into_iter()
+            .map(|x| {
+                last = x + 0.9 * last;
+                last
+            })
+            .collect();
+        let output = run(settings.clone(), &input);
+        let settled = RATE as usize;
+        let reduction_db = db(power(&input[settled..]) / power(&output[settled..]));
+        assert!(reduction_db > 20.0, "noise only {:.1} dB down", reduction_db);
+
+        // The reported latency covers the network's frames
+        let output = run(ProcessorSettings { mix: 0.0, ..settings }, &input);
+        for (i, (out, expected)) in output.iter().zip(&input).enumerate() {
+            assert!((out - expected).abs() < 1e-6, "sample {}: {} instead of {}", i, out, expected);
+        }
+    }
+
+    #[test]
+    fn gate_closes_on_a_tone_below_the_threshold() {
+        let settings = ProcessorSettings { gate_enabled: true, gate_threshold_db: -40.0, ..bypass() };
+        // -20 dBFS to open the gate, then -60 dBFS once it has held and
+        // released
+        let mut input = sine(1000.0, 0.1, RATE as usize / 2);
+        input.extend(sine(1000.0, 0.001, RATE as usize));
+        let output = run(settings, &input);
+        let quiet = RATE as usize;
+        let attenuation_db = db(power(&input[quiet..]) / power(&output[quiet..]));
+        assert!(attenuation_db > 20.0, "quiet tone only {:.1} dB down", attenuation_db);
+        let loud = RATE as usize / 4..RATE as usize / 2;
+        assert!(db(power(&input[loud.clone()]) / power(&output[loud])).abs() < 0.5);
+    }
+
+    #[test]
+    fn agc_brings_a_quiet_tone_to_the_target() {
+        let settings = ProcessorSettings { agc_enabled: true, ..bypass() };
+        // -30 dBFS RMS, within reach of the gain cap
+        let input = sine(1000.0, 0.0316 * 2f32.sqrt(), RATE as usize * 4);
+        let output = run(settings.clone(), &input);
+        let level_db = db(power(&output[RATE as usize * 3..]));
+        assert!((level_db - settings.agc_target_db).abs() < 2.0, "settled at {:.1} dBFS", level_db);
+    }
+
+    #[test]
+    fn limiter_holds_the_ceiling() {
+        let settings = ProcessorSettings { limiter_enabled: true, input_gain_db: 12.0, ..bypass() };
+        let input = sine(1000.0, 0.5, RATE as usize);
+        let output = run(settings.clone(), &input);
+        let ceiling = 10f32.powf(settings.limiter_ceiling_db / 20.0);
+        let peak = output.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(peak <= ceiling + 1e-4, "peak {} over the ceiling {}", peak, ceiling);
+    }
+
+    #[test]
+    fn safety_ceiling_holds_full_scale_noise_down() {
+        // As from an echo canceller gone unstable, with no limiter to catch it
+        let settings = ProcessorSettings { output_gain_db: 12.0, ..bypass() };
+        let input = noise(1.0, RATE as usize, 3);
+        let outputs = [None, Some(DEFAULT_SAFETY_CEILING_DB)].map(|ceiling_db| {
+            let mut pipeline = OfflinePipeline::new(settings.clone(), RATE, 1).unwrap();
+            pipeline.set_safety_ceiling_db(ceiling_db);
+            pipeline.push_input_samples(&input);
+            let mut output = vec![0.0; pipeline.available()];
+            pipeline.pull_output_samples(&mut output);
+            (output, pipeline.safety_clamps())
+        });
+        let [(unclamped, none), (clamped, clamps)] = outputs;
+
+        let ceiling = 10f32.powf(DEFAULT_SAFETY_CEILING_DB / 20.0);
+        let over = unclamped.iter().filter(|s| s.abs() > ceiling).count();
+        assert!(over > unclamped.len() / 2, "only {} samples over the ceiling", over);
+        assert_eq!(none, 0);
+        assert_eq!(clamps, over as u64);
+        for (i, (clamped, unclamped)) in clamped.iter().zip(&unclamped).enumerate() {
+            assert!(clamped.abs() <= ceiling, "sample {} at {}", i, clamped);
+            assert_eq!(*clamped, unclamped.clamp(-ceiling, ceiling), "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn highpass_removes_hum_and_keeps_voice() {
+        let settings = ProcessorSettings { highpass_enabled: true, ..bypass() };
+        let len = RATE as usize;
+        let (hum, voice) = (sine(50.0, 0.5, len), sine(1000.0, 0.5, len));
+        let settled = len / 4;
+        let hum_db = db(power(&run(settings.clone(), &hum)[settled..]) / power(&hum[settled..]));
+        let voice_db = db(power(&run(settings, &voice)[settled..]) / power(&voice[settled..]));
+        assert!(hum_db < -20.0, "50 Hz only {:.1} dB down", hum_db);
+        assert!(voice_db.abs() < 0.5, "1 kHz changed by {:.1} dB", voice_db);
+    }
+}
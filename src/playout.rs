@@ -0,0 +1,101 @@
+// Jitter buffer on the output side. The processing task delivers audio a
+// whole FFT block at a time while the output device pulls it in its own,
+// usually smaller, periods, so the processed buffer runs low just before each
+// block arrives. Playback therefore starts only once a target amount is
+// buffered, keeping that much in hand against a late block. When the device
+// still finds the buffer short (an underrun, heard as a pop), it is counted
+// and playback waits for the target again instead of stuttering on whatever
+// trickles in.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Audio kept ahead of the output device by default, on top of one block
+pub const DEFAULT_PLAYOUT_TARGET_MS: f32 = 20.0;
+pub const MAX_PLAYOUT_TARGET_MS: f32 = 500.0;
+
+pub struct Playout {
+    // Samples that must be buffered before playback (re)starts
+    target: usize,
+    priming: bool,
+    underruns: Arc<AtomicU64>,
+}
+
+impl Playout {
+    // Starts out priming; the wait for the first audio is not an underrun
+    pub fn new(target: usize, underruns: Arc<AtomicU64>) -> Self {
+        Self { target, priming: true, underruns }
+    }
+
+    // Reads `wanted` samples through `read` once enough is buffered, or none
+    // while priming. `buffered` is what the buffer holds now. Returns how
+    // many samples `read` produced.
+    pub fn read(&mut self, buffered: usize, wanted: usize, read: impl FnOnce() -> usize) -> usize {
+        if self.priming {
+            if buffered < self.target {
+                return 0;
+            }
+            self.priming = false;
+        }
+        let filled = read();
+        if filled < wanted {
+            self.underruns.fetch_add(1, Ordering::Relaxed);
+            self.priming = true;
+        }
+        filled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs an output pulling 256-sample periods from a processing task that
+    // delivers a 1024-sample block every fourth period, except while stalled
+    // for the periods in `stall`. Returns the underruns counted and the
+    // samples played.
+    fn simulate(target: usize, stall: std::ops::Range<usize>) -> (u64, usize) {
+        let underruns = Arc::new(AtomicU64::new(0));
+        let mut playout = Playout::new(target, Arc::clone(&underruns));
+        let mut buffered = 0;
+        let mut played = 0;
+        for period in 0..400 {
+            if period % 4 == 0 && !stall.contains(&period) {
+                buffered += 1024;
+            }
+            let read = playout.read(buffered, 256, || {
+                let read = buffered.min(256);
+                buffered -= read;
+                read
+            });
+            played += read;
+        }
+        (underruns.load(Ordering::Relaxed), played)
+    }
+
+    #[test]
+    fn on_time_processing_never_underruns() {
+        let (underruns, played) = simulate(1024, 0..0);
+        assert_eq!(underruns, 0);
+        assert_eq!(played, 400 * 256);
+    }
+
+    #[test]
+    fn slow_processing_counts_one_underrun_per_stall() {
+        // Late by five blocks, then caught up
+        let (underruns, _) = simulate(1024, 100..120);
+        assert_eq!(underruns, 1);
+
+        let underruns = Arc::new(AtomicU64::new(0));
+        let mut playout = Playout::new(512, Arc::clone(&underruns));
+        // Priming waits quietly for the target
+        assert_eq!(playout.read(256, 256, || unreachable!()), 0);
+        assert_eq!(underruns.load(Ordering::Relaxed), 0);
+        // A short read once playing is an underrun, and primes again
+        assert_eq!(playout.read(512, 256, || 256), 256);
+        assert_eq!(playout.read(100, 256, || 100), 100);
+        assert_eq!(underruns.load(Ordering::Relaxed), 1);
+        assert_eq!(playout.read(100, 256, || unreachable!()), 0);
+        assert_eq!(underruns.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,325 @@
+use cancelcaster::dsp::spectrum::MIN_SPECTRUM_DB;
+use cancelcaster::{MeterReading, SpectrumSnapshot};
+use eframe::egui::{self, Color32, Pos2, Rect, Shape, Stroke};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const PLOT_HEIGHT: f32 = 120.0;
+
+// Lowest frequency shown on the log axis; below this is mostly DC and rumble
+const LOG_MIN_FREQUENCY: f32 = 20.0;
+
+pub const INPUT_COLOR: Color32 = Color32::GRAY;
+pub const OUTPUT_COLOR: Color32 = Color32::GREEN;
+
+// Input and processed spectra overlaid, with dBFS on the y axis
+pub fn spectrum(ui: &mut egui::Ui, snapshot: &SpectrumSnapshot, log_frequency: bool) {
+    let (response, painter) = ui.allocate_painter(
+        egui::vec2(ui.available_width(), PLOT_HEIGHT),
+        egui::Sense::hover(),
+    );
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, Color32::from_gray(20));
+
+    let nyquist = snapshot.sample_rate as f32 / 2.0;
+    let x_for = |frequency: f32| {
+        let position = if log_frequency {
+            (frequency / LOG_MIN_FREQUENCY).log10() / (nyquist / LOG_MIN_FREQUENCY).log10()
+        } else {
+            frequency / nyquist
+        };
+        rect.left() + position * rect.width()
+    };
+    let y_for = |db: f32| rect.top() + db.clamp(MIN_SPECTRUM_DB, 0.0) / MIN_SPECTRUM_DB * rect.height();
+
+    for (magnitudes, color) in [(&snapshot.input_db, INPUT_COLOR), (&snapshot.output_db, OUTPUT_COLOR)] {
+        let bins = magnitudes.len();
+        if bins < 2 {
+            continue;
+        }
+        let points: Vec<Pos2> = magnitudes.iter().enumerate()
+            .map(|(bin, &db)| (bin as f32 * nyquist / (bins - 1) as f32, db))
+            .filter(|&(frequency, _)| !log_frequency || frequency >= LOG_MIN_FREQUENCY)
+            .map(|(frequency, db)| Pos2::new(x_for(frequency), y_for(db)))
+            .collect();
+        painter.add(Shape::line(points, Stroke::new(1.0, color)));
+    }
+}
+
+// Blocks the spectrogram keeps, one column each: about 5 s at the default
+// FFT size. Older columns are dropped, so memory stays bounded.
+const SPECTROGRAM_COLUMNS: usize = 256;
+const SPECTROGRAM_ROWS: usize = PLOT_HEIGHT as usize;
+
+// Range of the color map; anything quieter is black
+const SPECTROGRAM_MIN_DB: f32 = -100.0;
+
+// Dark to bright through blue, magenta and orange, so level reads at a glance
+const SPECTROGRAM_COLORS: [(f32, Color32); 5] = [
+    (0.0, Color32::BLACK),
+    (0.25, Color32::from_rgb(40, 0, 110)),
+    (0.5, Color32::from_rgb(180, 0, 120)),
+    (0.75, Color32::from_rgb(250, 120, 0)),
+    (1.0, Color32::from_rgb(255, 255, 200)),
+];
+
+fn spectrogram_color(db: f32) -> Color32 {
+    let position = ((db - SPECTROGRAM_MIN_DB) / -SPECTROGRAM_MIN_DB).clamp(0.0, 1.0);
+    let upper = SPECTROGRAM_COLORS.iter().position(|&(stop, _)| stop >= position).unwrap_or(0).max(1);
+    let (from_stop, from) = SPECTROGRAM_COLORS[upper - 1];
+    let (to_stop, to) = SPECTROGRAM_COLORS[upper];
+    let t = (position - from_stop) / (to_stop - from_stop);
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color32::from_rgb(mix(from.r(), to.r()), mix(from.g(), to.g()), mix(from.b(), to.b()))
+}
+
+// Frequency at `position` (0 at the bottom, 1 at the top) of a frequency axis
+fn frequency_at(position: f32, nyquist: f32, log_frequency: bool) -> f32 {
+    if log_frequency {
+        LOG_MIN_FREQUENCY * (nyquist / LOG_MIN_FREQUENCY).powf(position)
+    } else {
+        position * nyquist
+    }
+}
+
+// Scrolling spectrogram: time runs left to right, frequency bottom to top,
+// level as color. Each new spectrum snapshot adds a column, reduced to one
+// value per pixel row (the loudest bin in it), so intermittent noise shows
+// up as a pattern that a single spectrum would only flash.
+pub struct Spectrogram {
+    // dBFS per row, lowest frequency first; newest column last
+    columns: VecDeque<Vec<f32>>,
+    sample_rate: u32,
+    log_frequency: bool,
+    last_sequence: Option<u64>,
+    texture: Option<egui::TextureHandle>,
+    // Columns changed since the texture was last uploaded
+    dirty: bool,
+    // Keeps the current picture for inspection; new snapshots are ignored
+    pub frozen: bool,
+}
+
+impl Default for Spectrogram {
+    fn default() -> Self {
+        Self {
+            columns: VecDeque::with_capacity(SPECTROGRAM_COLUMNS),
+            sample_rate: 0,
+            log_frequency: true,
+            last_sequence: None,
+            texture: None,
+            dirty: false,
+            frozen: false,
+        }
+    }
+}
+
+impl Spectrogram {
+    pub fn clear(&mut self) {
+        self.columns.clear();
+        self.dirty = true;
+    }
+
+    // Adds a column for `magnitudes` (dBFS from DC to Nyquist) unless frozen
+    // or the snapshot was already added. A different axis or sample rate
+    // starts over, since old columns wouldn't line up.
+    pub fn push(&mut self, snapshot: &SpectrumSnapshot, magnitudes: &[f32], log_frequency: bool) {
+        if self.frozen || self.last_sequence == Some(snapshot.sequence) || magnitudes.len() < 2 {
+            return;
+        }
+        self.last_sequence = Some(snapshot.sequence);
+        if log_frequency != self.log_frequency || snapshot.sample_rate != self.sample_rate {
+            self.log_frequency = log_frequency;
+            self.sample_rate = snapshot.sample_rate;
+            self.columns.clear();
+        }
+
+        let nyquist = snapshot.sample_rate as f32 / 2.0;
+        let last_bin = magnitudes.len() - 1;
+        let bin_at = |position: f32| frequency_at(position, nyquist, log_frequency) / nyquist * last_bin as f32;
+        let column = (0..SPECTROGRAM_ROWS)
+            .map(|row| {
+                let low = bin_at(row as f32 / SPECTROGRAM_ROWS as f32).round() as usize;
+                let high = (bin_at((row + 1) as f32 / SPECTROGRAM_ROWS as f32).round() as usize).max(low);
+                magnitudes[low.min(last_bin)..=high.min(last_bin)].iter().copied().fold(MIN_SPECTRUM_DB, f32::max)
+            })
+            .collect();
+
+        if self.columns.len() == SPECTROGRAM_COLUMNS {
+            self.columns.pop_front();
+        }
+        self.columns.push_back(column);
+        self.dirty = true;
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        let (response, painter) = ui.allocate_painter(
+            egui::vec2(ui.available_width(), PLOT_HEIGHT),
+            egui::Sense::hover(),
+        );
+        let rect = response.rect;
+        painter.rect_filled(rect, 2.0, Color32::BLACK);
+
+        if self.dirty || self.texture.is_none() {
+            // Newest column at the right edge, empty ones black
+            let mut image = egui::ColorImage::new([SPECTROGRAM_COLUMNS, SPECTROGRAM_ROWS], Color32::BLACK);
+            let offset = SPECTROGRAM_COLUMNS - self.columns.len();
+            for (x, column) in self.columns.iter().enumerate() {
+                for (row, &db) in column.iter().enumerate() {
+                    image[(offset + x, SPECTROGRAM_ROWS - 1 - row)] = spectrogram_color(db);
+                }
+            }
+            match &mut self.texture {
+                Some(texture) => texture.set(image, egui::TextureOptions::LINEAR),
+                None => self.texture = Some(ui.ctx().load_texture("spectrogram", image, egui::TextureOptions::LINEAR)),
+            }
+            self.dirty = false;
+        }
+        if let Some(texture) = &self.texture {
+            let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+            painter.image(texture.id(), rect, uv, Color32::WHITE);
+        }
+
+        // Frequency and level under the pointer, e.g. to name a hum
+        if let Some(pointer) = response.hover_pos() {
+            let x = ((pointer.x - rect.left()) / rect.width() * SPECTROGRAM_COLUMNS as f32) as usize;
+            let row = ((rect.bottom() - pointer.y) / rect.height() * SPECTROGRAM_ROWS as f32) as usize;
+            let offset = SPECTROGRAM_COLUMNS - self.columns.len();
+            let db = x.checked_sub(offset)
+                .and_then(|column| self.columns.get(column))
+                .and_then(|column| column.get(row.min(SPECTROGRAM_ROWS - 1)));
+            if let Some(&db) = db {
+                let position = (row as f32 + 0.5) / SPECTROGRAM_ROWS as f32;
+                let frequency = frequency_at(position, self.sample_rate as f32 / 2.0, self.log_frequency);
+                response.on_hover_text_at_pointer(format!("{:.0} Hz: {:.0} dBFS", frequency, db));
+            }
+        }
+    }
+}
+
+// Scrolling oscilloscope of one or more traces drawn over each other. Each
+// pixel column shows the min..max range of the samples that fall into it, so
+// the cost follows the plot width rather than the number of samples.
+pub fn waveform(ui: &mut egui::Ui, traces: &[(&[f32], Color32)]) {
+    let (response, painter) = ui.allocate_painter(
+        egui::vec2(ui.available_width(), PLOT_HEIGHT),
+        egui::Sense::hover(),
+    );
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, Color32::from_gray(20));
+    painter.hline(rect.x_range(), rect.center().y, Stroke::new(1.0, Color32::from_gray(50)));
+
+    let columns = rect.width().max(1.0) as usize;
+    let y_for = |sample: f32| rect.center().y - sample.clamp(-1.0, 1.0) * rect.height() / 2.0;
+
+    for &(samples, color) in traces {
+        if samples.is_empty() {
+            continue;
+        }
+        let shapes = (0..columns).filter_map(|column| {
+            let start = column * samples.len() / columns;
+            let end = ((column + 1) * samples.len() / columns).max(start + 1).min(samples.len());
+            let bucket = samples.get(start..end)?;
+            let (min, max) = bucket.iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &s| (min.min(s), max.max(s)));
+            let x = rect.left() + column as f32 + 0.5;
+            // Keep quiet stretches visible as at least a one-pixel line
+            let (top, bottom) = (y_for(max), y_for(min).max(y_for(max) + 1.0));
+            Some(Shape::line_segment([Pos2::new(x, top), Pos2::new(x, bottom)], Stroke::new(1.0, color)))
+        });
+        painter.extend(shapes);
+    }
+}
+
+// Range of the level meters; anything quieter reads as empty
+const METER_MIN_DB: f32 = -60.0;
+const METER_HEIGHT: f32 = 16.0;
+const CLIP_WIDTH: f32 = 36.0;
+
+// Color zones of the meter bar: normal, loud, and close to clipping
+const METER_ZONES: [(f32, Color32); 3] = [
+    (-18.0, Color32::from_rgb(60, 180, 75)),
+    (-6.0, Color32::from_rgb(230, 200, 40)),
+    (0.0, Color32::from_rgb(220, 50, 40)),
+];
+
+// How long the peak marker stays put before falling, and how fast it falls
+const PEAK_HOLD: Duration = Duration::from_millis(1500);
+const PEAK_FALL_DB_PER_SECOND: f32 = 20.0;
+
+// Peak-hold marker of a level meter, kept across frames by the UI
+pub struct PeakHold {
+    db: f32,
+    since: Instant,
+}
+
+impl Default for PeakHold {
+    fn default() -> Self {
+        Self { db: METER_MIN_DB, since: Instant::now() }
+    }
+}
+
+impl PeakHold {
+    // Takes the latest peak and returns where the marker should be drawn
+    pub fn update(&mut self, peak_db: f32) -> f32 {
+        let now = Instant::now();
+        let held = now.duration_since(self.since);
+        let decayed = if held > PEAK_HOLD {
+            self.db - (held - PEAK_HOLD).as_secs_f32() * PEAK_FALL_DB_PER_SECOND
+        } else {
+            self.db
+        };
+
+        if peak_db >= decayed {
+            self.db = peak_db;
+            self.since = now;
+            peak_db
+        } else {
+            decayed
+        }
+    }
+}
+
+// Horizontal dBFS meter with an RMS bar, a peak-hold marker and a clip
+// indicator. Returns true when the clip indicator was clicked to reset it.
+pub fn level_meter(ui: &mut egui::Ui, reading: &MeterReading, hold_db: f32) -> bool {
+    let width = (ui.available_width() - CLIP_WIDTH - ui.spacing().item_spacing.x).max(1.0);
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(width, METER_HEIGHT), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, Color32::from_gray(20));
+
+    let x_for = |db: f32| {
+        rect.left() + (db.clamp(METER_MIN_DB, 0.0) - METER_MIN_DB) / -METER_MIN_DB * rect.width()
+    };
+
+    let level_x = x_for(reading.rms_db);
+    let mut zone_start = METER_MIN_DB;
+    for (zone_end, color) in METER_ZONES {
+        let left = x_for(zone_start);
+        let right = x_for(zone_end).min(level_x);
+        if right > left {
+            painter.rect_filled(Rect::from_x_y_ranges(left..=right, rect.y_range()), 0.0, color);
+        }
+        zone_start = zone_end;
+    }
+
+    if hold_db > METER_MIN_DB {
+        let x = x_for(hold_db);
+        painter.vline(x, rect.y_range(), Stroke::new(2.0, Color32::WHITE));
+    }
+
+    let clip_color = if reading.clipped { Color32::RED } else { Color32::from_gray(40) };
+    let clip = egui::Button::new(egui::RichText::new("CLIP").small().color(Color32::WHITE))
+        .fill(clip_color)
+        .min_size(egui::vec2(CLIP_WIDTH, METER_HEIGHT));
+    ui.add(clip)
+        .on_hover_text("A sample reached full scale; click to reset")
+        .clicked()
+}
+
+// Color key shown under the plots
+pub fn legend(ui: &mut egui::Ui) {
+    ui.horizontal(|ui| {
+        ui.colored_label(INPUT_COLOR, "— Input");
+        ui.colored_label(OUTPUT_COLOR, "— Processed");
+    });
+}
@@ -0,0 +1,154 @@
+use crate::config::config_dir;
+use crate::settings::ProcessorSettings;
+use anyhow::Result;
+use std::path::PathBuf;
+use toml_edit::{Document, Item, Table};
+use tracing::warn;
+
+// A named set of DSP parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    pub settings: ProcessorSettings,
+}
+
+// Presets shipped with the app. A saved preset with the same name replaces
+// the built-in one.
+pub fn builtin_presets() -> Vec<Preset> {
+    let defaults = ProcessorSettings::default();
+    vec![
+        // Speakers in the room, so echo cancellation matters most
+        Preset {
+            name: "Meeting".to_string(),
+            settings: ProcessorSettings {
+                echo_cancellation: true,
+                noise_reduction: true,
+                ..defaults.clone()
+            },
+        },
+        // Headphones and a quiet room; finer frequency resolution and a gate
+        // for clean pauses, latency matters less
+        Preset {
+            name: "Podcast".to_string(),
+            settings: ProcessorSettings {
+                echo_cancellation: false,
+                noise_reduction: true,
+                fft_size: 2048,
+                gate_enabled: true,
+                gate_threshold_db: -45.0,
+                ..defaults.clone()
+            },
+        },
+        // Game audio on speakers and low latency for voice chat
+        Preset {
+            name: "Gaming".to_string(),
+            settings: ProcessorSettings {
+                echo_cancellation: true,
+                noise_reduction: true,
+                fft_size: 512,
+                gate_enabled: true,
+                ..defaults
+            },
+        },
+    ]
+}
+
+// Built-in presets plus the ones the user saved, kept in presets.toml next to
+// the main config as one table per preset:
+//
+//     [presets.Podcast]
+//     noise_reduction = true
+//     fft_size = 2048
+pub struct PresetStore {
+    presets: Vec<Preset>,
+    // Saved presets only; built-ins are never written out
+    saved: Document,
+}
+
+impl PresetStore {
+    pub fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("presets.toml"))
+    }
+
+    // Loads saved presets, falling back to the built-ins alone if the file is
+    // missing or malformed
+    pub fn load() -> Self {
+        let saved = Self::path()
+            .and_then(|path| match std::fs::read_to_string(&path) {
+                Ok(text) => text.parse::<Document>()
+                    .map_err(|e| warn!("Ignoring malformed presets {}: {}", path.display(), e))
+                    .ok(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    warn!("Failed to read presets {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self::from_document(saved)
+    }
+
+    fn from_document(saved: Document) -> Self {
+        let mut store = Self { presets: builtin_presets(), saved };
+
+        let tables: Vec<Preset> = store.saved.get("presets")
+            .and_then(Item::as_table)
+            .map(|presets| {
+                presets.iter()
+                    .filter_map(|(name, item)| {
+                        item.as_table().map(|table| Preset {
+                            name: name.to_string(),
+                            settings: ProcessorSettings::from_table(table),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        for preset in tables {
+            store.insert(preset);
+        }
+        store
+    }
+
+    fn insert(&mut self, preset: Preset) {
+        match self.presets.iter_mut().find(|p| p.name == preset.name) {
+            Some(existing) => *existing = preset,
+            None => self.presets.push(preset),
+        }
+    }
+
+    pub fn presets(&self) -> &[Preset] {
+        &self.presets
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Preset> {
+        self.presets.iter().find(|p| p.name == name)
+    }
+
+    // Stores `settings` under `name`, replacing any preset of that name, and
+    // writes the saved presets to disk
+    pub fn save_preset(&mut self, name: &str, settings: &ProcessorSettings) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            anyhow::bail!("Preset name must not be empty");
+        }
+
+        if !self.saved.contains_table("presets") {
+            let mut presets = Table::new();
+            // Write [presets.Name] headers without an empty [presets] above them
+            presets.set_implicit(true);
+            self.saved["presets"] = Item::Table(presets);
+        }
+        self.saved["presets"][name] = Item::Table(settings.to_table());
+        self.insert(Preset { name: name.to_string(), settings: settings.clone() });
+
+        let path = Self::path()
+            .ok_or_else(|| anyhow::anyhow!("No configuration directory available"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, self.saved.to_string())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::Result;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// Blocks of processed audio queued for the writer thread before blocks are
+// dropped; about a second of audio at 1024-sample blocks
+const QUEUE_BLOCKS: usize = 64;
+
+// Samples a 32-bit float WAV file can hold. hound keeps the data size, and
+// the RIFF size that adds the header to it, in 32-bit fields, and doesn't
+// stop at their limit itself; 1 KiB is left for the header.
+const MAX_WAV_SAMPLES: u64 = (u32::MAX as u64 - 1024) / 4;
+
+pub(crate) type WavWriter = hound::WavWriter<BufWriter<File>>;
+
+// Creates a WAV file for processed audio, in 32-bit float like the pipeline
+pub(crate) fn create_wav(path: &Path, sample_rate: u32, channels: u16) -> Result<WavWriter> {
+    let spec = hound::WavSpec { channels, sample_rate, bits_per_sample: 32, sample_format: hound::SampleFormat::Float };
+    Ok(hound::WavWriter::create(path, spec)?)
+}
+
+// Whether `more` samples still fit after `written`
+fn fits_in_wav(written: u32, more: usize) -> bool {
+    written as u64 + more as u64 <= MAX_WAV_SAMPLES
+}
+
+// Fails without writing anything once the samples would take the file past
+// the 4 GiB a WAV header can describe; what was written before stays valid
+// after `finalize`
+pub(crate) fn write_samples(writer: &mut WavWriter, samples: &[f32]) -> Result<()> {
+    if !fits_in_wav(writer.len(), samples.len()) {
+        anyhow::bail!("Recording reached the 4 GiB size limit of WAV files");
+    }
+    for &sample in samples {
+        writer.write_sample(sample)?;
+    }
+    Ok(())
+}
+
+// Slot the processing thread sends processed blocks through while a
+// recording is active
+pub(crate) type RecordingTap = Arc<Mutex<Option<SyncSender<Vec<f32>>>>>;
+
+// Offers a processed block to the active recording without blocking. A writer
+// that has gone away (e.g. after a write error) is removed from the tap.
+pub(crate) fn tee_to_recording(tap: &RecordingTap, block: &[f32]) {
+    if let Ok(mut tap) = tap.try_lock() {
+        if let Some(sender) = tap.as_ref() {
+            if let Err(TrySendError::Disconnected(_)) = sender.try_send(block.to_vec()) {
+                *tap = None;
+            }
+        }
+    }
+}
+
+// A WAV recording fed from the processing thread and written on its own thread
+pub(crate) struct Recording {
+    thread: JoinHandle<Result<()>>,
+    started: Instant,
+}
+
+impl Recording {
+    pub(crate) fn start(path: &Path, sample_rate: u32, channels: u16, tap: &RecordingTap) -> Result<Self> {
+        let mut writer = create_wav(path, sample_rate, channels)?;
+        let (sender, receiver) = sync_channel::<Vec<f32>>(QUEUE_BLOCKS);
+
+        let thread = std::thread::spawn(move || {
+            // Stop at the first write error (e.g. disk full) but still try to
+            // finalize so everything written so far stays playable
+            let written = receiver.iter().try_for_each(|block| write_samples(&mut writer, &block));
+            let finalized = writer.finalize().map_err(Into::into);
+            written.and(finalized)
+        });
+
+        if let Ok(mut tap) = tap.lock() {
+            *tap = Some(sender);
+        }
+
+        Ok(Self { thread, started: Instant::now() })
+    }
+
+    // True once the writer has stopped on its own, i.e. after an error
+    pub(crate) fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    // Closes the queue, waits for the remaining blocks to be written and
+    // returns the first error the writer hit, if any
+    pub(crate) fn stop(self, tap: &RecordingTap) -> Result<()> {
+        if let Ok(mut tap) = tap.lock() {
+            tap.take();
+        }
+        self.thread
+            .join()
+            .map_err(|_| anyhow::anyhow!("Recording thread panicked"))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_stops_at_the_wav_size_limit() {
+        assert!(fits_in_wav(0, 2));
+        assert!(fits_in_wav((MAX_WAV_SAMPLES - 2) as u32, 2));
+        assert!(!fits_in_wav((MAX_WAV_SAMPLES - 1) as u32, 2));
+        // A full file's sizes, with hound's 68-byte float header, still fit
+        assert!(MAX_WAV_SAMPLES * 4 + 68 - 8 <= u32::MAX as u64);
+    }
+
+    #[test]
+    fn recording_writes_float_samples() {
+        let path = std::env::temp_dir().join(format!("cancelcaster-recording-{}.wav", std::process::id()));
+        let tap = RecordingTap::default();
+        let recording = Recording::start(&path, 48000, 2, &tap).unwrap();
+        let blocks = [[0.5, -0.5, 0.25, -0.25], [1.0, -1.0, 0.0, 0.125]];
+        for block in &blocks {
+            tee_to_recording(&tap, block);
+        }
+        recording.stop(&tap).unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        let samples: Vec<f32> = reader.samples().collect::<Result<_, _>>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((spec.sample_rate, spec.channels, spec.sample_format), (48000, 2, hound::SampleFormat::Float));
+        assert_eq!(samples, blocks.concat());
+    }
+}
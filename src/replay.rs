@@ -0,0 +1,91 @@
+// Instant replay: the last seconds of processed audio, kept in a ring the
+// processing task overwrites as it goes, so a moment worth keeping can be
+// saved after it happened. Saving copies the ring out while the task keeps
+// writing; neither side waits for the other.
+
+use crate::errors::AudioError;
+use crate::scope::ScopeRing;
+use crate::recording::{create_wav, write_samples};
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+
+pub const DEFAULT_REPLAY_SECS: u32 = 30;
+pub const MIN_REPLAY_SECS: u32 = 5;
+// About 46 MB of stereo audio at 48 kHz
+pub const MAX_REPLAY_SECS: u32 = 120;
+
+pub fn validate_replay_secs(secs: u32) -> Result<(), AudioError> {
+    if !(MIN_REPLAY_SECS..=MAX_REPLAY_SECS).contains(&secs) {
+        return Err(AudioError::InvalidSetting(format!(
+            "Replay length must be between {} and {} s, got {} s",
+            MIN_REPLAY_SECS, MAX_REPLAY_SECS, secs
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) struct ReplayBuffer {
+    ring: ScopeRing,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl ReplayBuffer {
+    pub(crate) fn new(secs: u32, sample_rate: u32, channels: u16) -> Self {
+        let channels = channels.max(1);
+        Self {
+            ring: ScopeRing::new(secs as usize * sample_rate as usize * channels as usize),
+            sample_rate,
+            channels,
+        }
+    }
+
+    // Appends a block of interleaved processed audio, overwriting the oldest.
+    // Only the processing task may push.
+    pub(crate) fn push(&self, samples: &[f32]) {
+        self.ring.push(samples);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    // Writes the buffered audio to a WAV file, oldest first, and returns how
+    // long it is
+    pub(crate) fn save(&self, path: &Path) -> Result<Duration> {
+        let samples = self.ring.recent(self.channels as usize);
+        let mut writer = create_wav(path, self.sample_rate, self.channels)?;
+        write_samples(&mut writer, &samples)?;
+        writer.finalize()?;
+        let frames = samples.len() / self.channels as usize;
+        Ok(Duration::from_secs_f64(frames as f64 / self.sample_rate.max(1) as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_replay_is_the_latest_audio_in_order() {
+        // 5 s of stereo at 1 kHz, fed 12 s in uneven blocks, so the ring has
+        // wrapped and the cut falls mid-block
+        let replay = ReplayBuffer::new(5, 1000, 2);
+        assert!(replay.is_empty());
+        let pushed: Vec<f32> = (0..24000).map(|n| n as f32 / 32768.0).collect();
+        for block in pushed.chunks(734) {
+            replay.push(block);
+        }
+
+        let path = std::env::temp_dir().join(format!("cancelcaster-replay-{}.wav", std::process::id()));
+        let duration = replay.save(&path).unwrap();
+        let mut saved = hound::WavReader::open(&path).unwrap();
+        let spec = saved.spec();
+        let samples: Vec<f32> = saved.samples().collect::<Result<_, _>>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(duration, Duration::from_secs(5));
+        assert_eq!((spec.sample_rate, spec.channels), (1000, 2));
+        assert_eq!(samples, pushed[pushed.len() - 10000..]);
+    }
+}
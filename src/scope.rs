@@ -0,0 +1,81 @@
+use std::sync::atomic::{fence, AtomicU32, AtomicUsize, Ordering};
+
+// Fixed-size ring of the most recent samples, overwritten in place by the
+// processing thread and copied out by the UI without either side locking.
+// A snapshot taken mid-write can mix old and new samples, which is harmless
+// for display; `recent` drops those for uses that need the exact audio.
+pub(crate) struct ScopeRing {
+    samples: Vec<AtomicU32>,
+    // Total samples ever written; the next write goes to `written % capacity`
+    written: AtomicUsize,
+    // Where the write under way will end, set before any slot changes
+    claimed: AtomicUsize,
+}
+
+impl ScopeRing {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            samples: (0..capacity.max(1)).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicUsize::new(0),
+            claimed: AtomicUsize::new(0),
+        }
+    }
+
+    // Appends interleaved samples downmixed to mono. Only one thread may push.
+    pub(crate) fn push_frames(&self, samples: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        self.write(samples.chunks_exact(channels).map(|frame| frame.iter().sum::<f32>() / channels as f32));
+    }
+
+    // Appends samples as they are, e.g. whole interleaved frames. Only one
+    // thread may push.
+    pub(crate) fn push(&self, samples: &[f32]) {
+        self.write(samples.iter().copied());
+    }
+
+    fn write(&self, samples: impl ExactSizeIterator<Item = f32>) {
+        let capacity = self.samples.len();
+        let mut written = self.written.load(Ordering::Relaxed);
+        self.claimed.store(written + samples.len(), Ordering::Relaxed);
+        // A reader that sees any of the slots below also sees the claim
+        fence(Ordering::Release);
+        for sample in samples {
+            self.samples[written % capacity].store(sample.to_bits(), Ordering::Relaxed);
+            written += 1;
+        }
+        self.written.store(written, Ordering::Release);
+    }
+
+    // The ring's contents, oldest sample first
+    pub(crate) fn snapshot(&self) -> Vec<f32> {
+        let capacity = self.samples.len();
+        let written = self.written.load(Ordering::Acquire);
+        (0..capacity)
+            .map(|i| f32::from_bits(self.samples[(written + i) % capacity].load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.written.load(Ordering::Acquire) == 0
+    }
+
+    // The samples pushed so far, up to the capacity, oldest first. Any the
+    // writer overwrote while they were being copied are dropped from the
+    // start, in whole frames of `channels`, so the result is always one
+    // unbroken stretch of what was pushed.
+    pub(crate) fn recent(&self, channels: usize) -> Vec<f32> {
+        let capacity = self.samples.len();
+        let written = self.written.load(Ordering::Acquire);
+        let start = written.saturating_sub(capacity);
+        let mut samples: Vec<f32> = (start..written)
+            .map(|i| f32::from_bits(self.samples[i % capacity].load(Ordering::Relaxed)))
+            .collect();
+        fence(Ordering::Acquire);
+        // Samples before this index may share a slot with newer ones by now
+        let valid_from = self.claimed.load(Ordering::Relaxed).saturating_sub(capacity);
+        let channels = channels.max(1);
+        let overwritten = valid_from.saturating_sub(start).div_ceil(channels) * channels;
+        samples.drain(..overwritten.min(samples.len()));
+        samples
+    }
+}
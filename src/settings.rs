@@ -0,0 +1,587 @@
+use crate::dsp::agc::{DEFAULT_AGC_MAX_GAIN_DB, DEFAULT_AGC_TARGET_DB};
+use crate::dsp::backend::ProcessingBackend;
+use crate::dsp::click::DEFAULT_CLICK_SENSITIVITY;
+use crate::dsp::comfort::DEFAULT_COMFORT_NOISE_LEVEL_DB;
+use crate::dsp::compressor::{
+    DEFAULT_COMPRESSOR_ATTACK_MS, DEFAULT_COMPRESSOR_KNEE_DB, DEFAULT_COMPRESSOR_MAKEUP_DB, DEFAULT_COMPRESSOR_RATIO,
+    DEFAULT_COMPRESSOR_RELEASE_MS, DEFAULT_COMPRESSOR_THRESHOLD_DB,
+};
+use crate::dsp::deesser::{DEFAULT_DEESSER_RANGE_DB, DEFAULT_DEESSER_THRESHOLD_DB};
+use crate::dsp::echo::DEFAULT_ECHO_STEP_SIZE;
+use crate::dsp::expander::{DEFAULT_EXPANDER_KNEE_DB, DEFAULT_EXPANDER_RATIO, DEFAULT_EXPANDER_THRESHOLD_DB};
+use crate::dsp::gate::{
+    DEFAULT_GATE_ATTACK_MS, DEFAULT_GATE_HOLD_MS, DEFAULT_GATE_RELEASE_MS, DEFAULT_GATE_THRESHOLD_DB,
+};
+use crate::dsp::highpass::DEFAULT_HIGHPASS_CUTOFF_HZ;
+use crate::dsp::limiter::DEFAULT_LIMITER_CEILING_DB;
+use crate::dsp::loudness::DEFAULT_LOUDNESS_TARGET_LUFS;
+use crate::dsp::noise::DEFAULT_NOISE_ADAPTATION_RATE;
+use crate::dsp::{DEFAULT_POWER_SAVE_HOLD_MS, MAX_POWER_SAVE_HOLD_MS};
+use crate::dsp::spectral::{
+    is_cola, NoiseReductionMode, WindowType, DEFAULT_NOISE_REDUCTION_STRENGTH, DEFAULT_SPECTRAL_FLOOR, NOISE_BANDS,
+};
+use crate::errors::AudioError;
+use crate::playout::DEFAULT_PLAYOUT_TARGET_MS;
+use toml_edit::{value, Array, Table};
+
+pub const DEFAULT_FFT_SIZE: usize = 1024;
+
+// FFT sizes offered in the UI; any power of two in MIN..=MAX is accepted
+pub const FFT_SIZES: &[usize] = &[512, 1024, 2048, 4096];
+pub const MIN_FFT_SIZE: usize = 64;
+pub const MAX_FFT_SIZE: usize = 16384;
+
+// Smallest hop as a fraction of the FFT size. Every hop runs a full FFT per
+// channel, so an eighth already costs four times the default framing.
+pub const MIN_HOP_DIVISOR: usize = 8;
+
+// DSP parameters shared between `AudioProcessor` and its processing thread.
+// The thread takes a snapshot at every block boundary, so changes apply to
+// the next block without restarting streams.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessorSettings {
+    // Trim applied to the mic before any processing and to the output before
+    // the limiter, in dB
+    pub input_gain_db: f32,
+    pub output_gain_db: f32,
+    // What does echo cancellation, noise reduction and gain control; only
+    // backends this build includes are accepted
+    pub backend: ProcessingBackend,
+    pub echo_cancellation: bool,
+    pub echo_step_size: f32,
+    // Delay applied to the echo reference before cancellation, in samples
+    // per channel, to match its path through the speakers into the mic
+    pub reference_delay_samples: usize,
+    pub noise_reduction: bool,
+    pub noise_reduction_mode: NoiseReductionMode,
+    pub noise_reduction_strength: f32,
+    // Strength of each band in the multi-band mode, lowest band first
+    pub band_strengths: [f32; NOISE_BANDS],
+    pub spectral_floor: f32,
+    pub noise_adaptation_rate: f32,
+    // Attenuates the sibilance band while "S" sounds dominate a frame
+    pub deesser_enabled: bool,
+    // Share of the frame energy in the sibilance band above which it is
+    // attenuated, and the most it is attenuated by
+    pub deesser_threshold_db: f32,
+    pub deesser_range_db: f32,
+    // Takes keyboard and mouse clicks out of the frames they land in; the
+    // sensitivity runs from 0 (sharpest clicks only) to 1
+    pub click_suppression: bool,
+    pub click_sensitivity: f32,
+    // Samples of each channel per processing block and FFT frame; a block
+    // of interleaved audio holds this many for every channel. Larger sizes
+    // resolve noise more finely in frequency, smaller ones reduce latency.
+    pub fft_size: usize,
+    // Samples the FFT frames advance by, which then also make a processing
+    // block. A frame still has to fill before it is processed, so the delay
+    // is one FFT size whatever the hop, against 1.5 with the default. 0
+    // keeps blocks of `fft_size` with frames at 50% overlap. Per channel
+    // like `fft_size`; see `validate_framing`.
+    pub hop_size: usize,
+    // Analysis window of the FFT frames; only windows that overlap-add to a
+    // constant at the hop are accepted
+    pub fft_window: WindowType,
+    // Spectral frames pass through untransformed once no voice has been
+    // detected for the hold time, to save CPU, until voice returns
+    pub power_save: bool,
+    pub power_save_hold_ms: f32,
+    // Turns quiet passages down by `expander_ratio` below the threshold
+    // instead of silencing them like the gate
+    pub expander_enabled: bool,
+    pub expander_threshold_db: f32,
+    pub expander_ratio: f32,
+    pub expander_knee_db: f32,
+    pub gate_enabled: bool,
+    pub gate_threshold_db: f32,
+    pub gate_attack_ms: f32,
+    pub gate_hold_ms: f32,
+    pub gate_release_ms: f32,
+    // Faint noise shaped like the background, played instead of silence
+    // while the gate is closed or, without the gate, while no voice is detected
+    pub comfort_noise_enabled: bool,
+    // Relative to the estimated background noise
+    pub comfort_noise_level_db: f32,
+    // Turns the voice down by `compressor_ratio` above the threshold, then
+    // up by the makeup gain, for even dynamics
+    pub compressor_enabled: bool,
+    pub compressor_threshold_db: f32,
+    pub compressor_ratio: f32,
+    pub compressor_attack_ms: f32,
+    pub compressor_release_ms: f32,
+    pub compressor_makeup_db: f32,
+    pub compressor_knee_db: f32,
+    pub highpass_enabled: bool,
+    pub highpass_cutoff_hz: f32,
+    pub agc_enabled: bool,
+    pub agc_target_db: f32,
+    pub agc_max_gain_db: f32,
+    // Slowly steers the loudness toward a target in LUFS (EBU R128)
+    pub loudness_normalization: bool,
+    pub loudness_target_lufs: f32,
+    pub limiter_enabled: bool,
+    pub limiter_ceiling_db: f32,
+    // Share of processed audio in the output, from 0.0 (original mic) to 1.0
+    pub mix: f32,
+}
+
+impl Default for ProcessorSettings {
+    fn default() -> Self {
+        Self {
+            input_gain_db: 0.0,
+            output_gain_db: 0.0,
+            backend: ProcessingBackend::default(),
+            echo_cancellation: true,
+            echo_step_size: DEFAULT_ECHO_STEP_SIZE,
+            reference_delay_samples: 0,
+            noise_reduction: true,
+            noise_reduction_mode: NoiseReductionMode::default(),
+            noise_reduction_strength: DEFAULT_NOISE_REDUCTION_STRENGTH,
+            band_strengths: [DEFAULT_NOISE_REDUCTION_STRENGTH; NOISE_BANDS],
+            spectral_floor: DEFAULT_SPECTRAL_FLOOR,
+            noise_adaptation_rate: DEFAULT_NOISE_ADAPTATION_RATE,
+            deesser_enabled: false,
+            deesser_threshold_db: DEFAULT_DEESSER_THRESHOLD_DB,
+            deesser_range_db: DEFAULT_DEESSER_RANGE_DB,
+            click_suppression: false,
+            click_sensitivity: DEFAULT_CLICK_SENSITIVITY,
+            fft_size: DEFAULT_FFT_SIZE,
+            hop_size: 0,
+            fft_window: WindowType::default(),
+            power_save: false,
+            power_save_hold_ms: DEFAULT_POWER_SAVE_HOLD_MS,
+            expander_enabled: false,
+            expander_threshold_db: DEFAULT_EXPANDER_THRESHOLD_DB,
+            expander_ratio: DEFAULT_EXPANDER_RATIO,
+            expander_knee_db: DEFAULT_EXPANDER_KNEE_DB,
+            gate_enabled: false,
+            gate_threshold_db: DEFAULT_GATE_THRESHOLD_DB,
+            gate_attack_ms: DEFAULT_GATE_ATTACK_MS,
+            gate_hold_ms: DEFAULT_GATE_HOLD_MS,
+            gate_release_ms: DEFAULT_GATE_RELEASE_MS,
+            comfort_noise_enabled: false,
+            comfort_noise_level_db: DEFAULT_COMFORT_NOISE_LEVEL_DB,
+            compressor_enabled: false,
+            compressor_threshold_db: DEFAULT_COMPRESSOR_THRESHOLD_DB,
+            compressor_ratio: DEFAULT_COMPRESSOR_RATIO,
+            compressor_attack_ms: DEFAULT_COMPRESSOR_ATTACK_MS,
+            compressor_release_ms: DEFAULT_COMPRESSOR_RELEASE_MS,
+            compressor_makeup_db: DEFAULT_COMPRESSOR_MAKEUP_DB,
+            compressor_knee_db: DEFAULT_COMPRESSOR_KNEE_DB,
+            highpass_enabled: true,
+            highpass_cutoff_hz: DEFAULT_HIGHPASS_CUTOFF_HZ,
+            agc_enabled: false,
+            agc_target_db: DEFAULT_AGC_TARGET_DB,
+            agc_max_gain_db: DEFAULT_AGC_MAX_GAIN_DB,
+            loudness_normalization: false,
+            loudness_target_lufs: DEFAULT_LOUDNESS_TARGET_LUFS,
+            limiter_enabled: true,
+            limiter_ceiling_db: DEFAULT_LIMITER_CEILING_DB,
+            mix: 1.0,
+        }
+    }
+}
+
+// f32 values go through their shortest decimal form so 0.3 is written as 0.3
+// rather than 0.30000001192092896
+pub(crate) fn float(x: f32) -> f64 {
+    x.to_string().parse().unwrap_or(x as f64)
+}
+
+impl ProcessorSettings {
+    // Whether any stage runs on the spectral frames, which then add their
+    // overlap-add latency
+    pub fn spectral_enabled(&self) -> bool {
+        self.noise_reduction || self.deesser_enabled || self.click_suppression
+    }
+
+    // Interleaved samples each processing block takes from an input of
+    // `channels` channels
+    pub fn block_size(&self, channels: u16) -> usize {
+        let frames = if self.hop_size == 0 { self.fft_size } else { self.hop_size };
+        frames * channels.max(1) as usize
+    }
+
+    // Delay of the framing with the spectral stages on, in samples of one
+    // channel: a block to fill plus the overlap the frames hold back
+    pub fn framing_latency(&self) -> usize {
+        self.block_size(1) + self.fft_size - effective_hop(self.hop_size, self.fft_size)
+    }
+
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        table["input_gain_db"] = value(float(self.input_gain_db));
+        table["output_gain_db"] = value(float(self.output_gain_db));
+        table["backend"] = value(self.backend.as_str());
+        table["echo_cancellation"] = value(self.echo_cancellation);
+        table["echo_step_size"] = value(float(self.echo_step_size));
+        table["reference_delay_samples"] = value(self.reference_delay_samples as i64);
+        table["noise_reduction"] = value(self.noise_reduction);
+        table["noise_reduction_mode"] = value(self.noise_reduction_mode.as_str());
+        table["noise_reduction_strength"] = value(float(self.noise_reduction_strength));
+        table["band_strengths"] = value(self.band_strengths.iter().map(|&s| float(s)).collect::<Array>());
+        table["spectral_floor"] = value(float(self.spectral_floor));
+        table["noise_adaptation_rate"] = value(float(self.noise_adaptation_rate));
+        table["deesser_enabled"] = value(self.deesser_enabled);
+        table["deesser_threshold_db"] = value(float(self.deesser_threshold_db));
+        table["deesser_range_db"] = value(float(self.deesser_range_db));
+        table["click_suppression"] = value(self.click_suppression);
+        table["click_sensitivity"] = value(float(self.click_sensitivity));
+        table["fft_size"] = value(self.fft_size as i64);
+        table["hop_size"] = value(self.hop_size as i64);
+        table["fft_window"] = value(self.fft_window.as_str());
+        table["power_save"] = value(self.power_save);
+        table["power_save_hold_ms"] = value(float(self.power_save_hold_ms));
+        table["expander_enabled"] = value(self.expander_enabled);
+        table["expander_threshold_db"] = value(float(self.expander_threshold_db));
+        table["expander_ratio"] = value(float(self.expander_ratio));
+        table["expander_knee_db"] = value(float(self.expander_knee_db));
+        table["gate_enabled"] = value(self.gate_enabled);
+        table["gate_threshold_db"] = value(float(self.gate_threshold_db));
+        table["gate_attack_ms"] = value(float(self.gate_attack_ms));
+        table["gate_hold_ms"] = value(float(self.gate_hold_ms));
+        table["gate_release_ms"] = value(float(self.gate_release_ms));
+        table["comfort_noise_enabled"] = value(self.comfort_noise_enabled);
+        table["comfort_noise_level_db"] = value(float(self.comfort_noise_level_db));
+        table["compressor_enabled"] = value(self.compressor_enabled);
+        table["compressor_threshold_db"] = value(float(self.compressor_threshold_db));
+        table["compressor_ratio"] = value(float(self.compressor_ratio));
+        table["compressor_attack_ms"] = value(float(self.compressor_attack_ms));
+        table["compressor_release_ms"] = value(float(self.compressor_release_ms));
+        table["compressor_makeup_db"] = value(float(self.compressor_makeup_db));
+        table["compressor_knee_db"] = value(float(self.compressor_knee_db));
+        table["highpass_enabled"] = value(self.highpass_enabled);
+        table["highpass_cutoff_hz"] = value(float(self.highpass_cutoff_hz));
+        table["agc_enabled"] = value(self.agc_enabled);
+        table["agc_target_db"] = value(float(self.agc_target_db));
+        table["agc_max_gain_db"] = value(float(self.agc_max_gain_db));
+        table["loudness_normalization"] = value(self.loudness_normalization);
+        table["loudness_target_lufs"] = value(float(self.loudness_target_lufs));
+        table["limiter_enabled"] = value(self.limiter_enabled);
+        table["limiter_ceiling_db"] = value(float(self.limiter_ceiling_db));
+        table["mix"] = value(float(self.mix));
+        table
+    }
+
+    // Missing fields, fields of the wrong type and invalid FFT sizes fall
+    // back to their defaults
+    pub fn from_table(table: &Table) -> Self {
+        let defaults = Self::default();
+
+        let boolean = |key: &str, default: bool| table.get(key).and_then(|v| v.as_bool()).unwrap_or(default);
+        let number = |key: &str, default: f32| {
+            table.get(key)
+                .and_then(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64)))
+                .map(|v| v as f32)
+                .unwrap_or(default)
+        };
+        // Bands missing from a short array keep their defaults
+        let mut band_strengths = defaults.band_strengths;
+        if let Some(array) = table.get("band_strengths").and_then(|v| v.as_array()) {
+            for (band, v) in band_strengths.iter_mut().zip(array.iter()) {
+                if let Some(v) = v.as_float().or_else(|| v.as_integer().map(|i| i as f64)) {
+                    *band = v as f32;
+                }
+            }
+        }
+        let fft_size = table.get("fft_size")
+            .and_then(|v| v.as_integer())
+            .map(|v| v as usize)
+            .filter(|&size| validate_fft_size(size).is_ok())
+            .unwrap_or(defaults.fft_size);
+        let hop_size = table.get("hop_size")
+            .and_then(|v| v.as_integer())
+            .and_then(|v| usize::try_from(v).ok())
+            .filter(|&hop| validate_hop_size(hop, fft_size).is_ok())
+            .unwrap_or(defaults.hop_size);
+        // A window that doesn't overlap-add at the saved hop falls back too
+        let fft_window = table.get("fft_window")
+            .and_then(|v| v.as_str())
+            .and_then(WindowType::from_name)
+            .filter(|&window| validate_framing(window, hop_size, fft_size).is_ok())
+            .unwrap_or(defaults.fft_window);
+
+        Self {
+            input_gain_db: number("input_gain_db", defaults.input_gain_db),
+            output_gain_db: number("output_gain_db", defaults.output_gain_db),
+            backend: table.get("backend")
+                .and_then(|v| v.as_str())
+                .and_then(ProcessingBackend::from_name)
+                .filter(|backend| backend.is_available())
+                .unwrap_or(defaults.backend),
+            echo_cancellation: boolean("echo_cancellation", defaults.echo_cancellation),
+            echo_step_size: number("echo_step_size", defaults.echo_step_size),
+            reference_delay_samples: table.get("reference_delay_samples")
+                .and_then(|v| v.as_integer())
+                .and_then(|v| usize::try_from(v).ok())
+                .unwrap_or(defaults.reference_delay_samples),
+            noise_reduction: boolean("noise_reduction", defaults.noise_reduction),
+            noise_reduction_mode: table.get("noise_reduction_mode")
+                .and_then(|v| v.as_str())
+                .and_then(NoiseReductionMode::from_name)
+                .filter(|mode| mode.is_available())
+                .unwrap_or(defaults.noise_reduction_mode),
+            noise_reduction_strength: number("noise_reduction_strength", defaults.noise_reduction_strength),
+            band_strengths,
+            spectral_floor: number("spectral_floor", defaults.spectral_floor),
+            noise_adaptation_rate: number("noise_adaptation_rate", defaults.noise_adaptation_rate),
+            deesser_enabled: boolean("deesser_enabled", defaults.deesser_enabled),
+            deesser_threshold_db: number("deesser_threshold_db", defaults.deesser_threshold_db),
+            deesser_range_db: number("deesser_range_db", defaults.deesser_range_db),
+            click_suppression: boolean("click_suppression", defaults.click_suppression),
+            click_sensitivity: number("click_sensitivity", defaults.click_sensitivity),
+            fft_size,
+            hop_size,
+            fft_window,
+            power_save: boolean("power_save", defaults.power_save),
+            power_save_hold_ms: number("power_save_hold_ms", defaults.power_save_hold_ms)
+                .clamp(0.0, MAX_POWER_SAVE_HOLD_MS),
+            expander_enabled: boolean("expander_enabled", defaults.expander_enabled),
+            expander_threshold_db: number("expander_threshold_db", defaults.expander_threshold_db),
+            expander_ratio: number("expander_ratio", defaults.expander_ratio),
+            expander_knee_db: number("expander_knee_db", defaults.expander_knee_db),
+            gate_enabled: boolean("gate_enabled", defaults.gate_enabled),
+            gate_threshold_db: number("gate_threshold_db", defaults.gate_threshold_db),
+            gate_attack_ms: number("gate_attack_ms", defaults.gate_attack_ms),
+            gate_hold_ms: number("gate_hold_ms", defaults.gate_hold_ms),
+            gate_release_ms: number("gate_release_ms", defaults.gate_release_ms),
+            comfort_noise_enabled: boolean("comfort_noise_enabled", defaults.comfort_noise_enabled),
+            comfort_noise_level_db: number("comfort_noise_level_db", defaults.comfort_noise_level_db),
+            compressor_enabled: boolean("compressor_enabled", defaults.compressor_enabled),
+            compressor_threshold_db: number("compressor_threshold_db", defaults.compressor_threshold_db),
+            compressor_ratio: number("compressor_ratio", defaults.compressor_ratio),
+            compressor_attack_ms: number("compressor_attack_ms", defaults.compressor_attack_ms),
+            compressor_release_ms: number("compressor_release_ms", defaults.compressor_release_ms),
+            compressor_makeup_db: number("compressor_makeup_db", defaults.compressor_makeup_db),
+            compressor_knee_db: number("compressor_knee_db", defaults.compressor_knee_db),
+            highpass_enabled: boolean("highpass_enabled", defaults.highpass_enabled),
+            highpass_cutoff_hz: number("highpass_cutoff_hz", defaults.highpass_cutoff_hz),
+            agc_enabled: boolean("agc_enabled", defaults.agc_enabled),
+            agc_target_db: number("agc_target_db", defaults.agc_target_db),
+            agc_max_gain_db: number("agc_max_gain_db", defaults.agc_max_gain_db),
+            loudness_normalization: boolean("loudness_normalization", defaults.loudness_normalization),
+            loudness_target_lufs: number("loudness_target_lufs", defaults.loudness_target_lufs),
+            limiter_enabled: boolean("limiter_enabled", defaults.limiter_enabled),
+            limiter_ceiling_db: number("limiter_ceiling_db", defaults.limiter_ceiling_db),
+            mix: number("mix", defaults.mix).clamp(0.0, 1.0),
+        }
+    }
+}
+
+pub fn validate_fft_size(size: usize) -> Result<(), AudioError> {
+    if !size.is_power_of_two() || !(MIN_FFT_SIZE..=MAX_FFT_SIZE).contains(&size) {
+        return Err(AudioError::InvalidSetting(format!(
+            "FFT size must be a power of two between {} and {}, got {}",
+            MIN_FFT_SIZE, MAX_FFT_SIZE, size
+        )));
+    }
+    Ok(())
+}
+
+// The hop the frames advance by, with 0 standing for half the frame
+fn effective_hop(hop_size: usize, fft_size: usize) -> usize {
+    if hop_size == 0 { fft_size / 2 } else { hop_size }
+}
+
+// Overlap of frames advancing by `hop_size` as a percentage, for messages and labels
+pub fn overlap_percent(hop_size: usize, fft_size: usize) -> f32 {
+    100.0 * (1.0 - effective_hop(hop_size, fft_size) as f32 / fft_size as f32)
+}
+
+// A hop of 0 (the default framing), or a power of two from an eighth to half
+// of `fft_size`. Smaller hops only overlap more; whether the windows then
+// overlap-add to a constant is up to the window, see `validate_framing`.
+pub fn validate_hop_size(hop_size: usize, fft_size: usize) -> Result<(), AudioError> {
+    if hop_size != 0
+        && (!hop_size.is_power_of_two() || !(fft_size / MIN_HOP_DIVISOR..=fft_size / 2).contains(&hop_size))
+    {
+        return Err(AudioError::InvalidSetting(format!(
+            "Hop size must be 0 or a power of two between {} and {} for an FFT size of {}, got {}",
+            fft_size / MIN_HOP_DIVISOR, fft_size / 2, fft_size, hop_size
+        )));
+    }
+    Ok(())
+}
+
+// A valid hop for `fft_size` at which `window` is constant-overlap-add, so
+// noise reduction can't modulate the output's level at the hop rate. The
+// Blackman window, for one, only gets there from 75% overlap.
+pub fn validate_framing(window: WindowType, hop_size: usize, fft_size: usize) -> Result<(), AudioError> {
+    validate_hop_size(hop_size, fft_size)?;
+    if !is_cola(window, fft_size, effective_hop(hop_size, fft_size)) {
+        let allowed: Vec<String> = cola_hops(window, fft_size)
+            .map(|hop| format!("{}%", overlap_percent(hop, fft_size)))
+            .collect();
+        let hint = if allowed.is_empty() {
+            "choose another window".to_string()
+        } else {
+            format!("choose {} overlap or another window", allowed.join(" or "))
+        };
+        return Err(AudioError::InvalidSetting(format!(
+            "The {} window doesn't overlap-add to a constant at {}% overlap and would modulate the audio; {}",
+            window.label(), overlap_percent(hop_size, fft_size), hint
+        )));
+    }
+    Ok(())
+}
+
+// Hops from half to an eighth of `fft_size` at which `window` is COLA
+fn cola_hops(window: WindowType, fft_size: usize) -> impl Iterator<Item = usize> {
+    std::iter::successors(Some(fft_size / 2), |&hop| Some(hop / 2))
+        .take_while(move |&hop| hop >= fft_size / MIN_HOP_DIVISOR && hop > 0)
+        .filter(move |&hop| is_cola(window, fft_size, hop))
+}
+
+// `hop_size` if it frames `fft_size` validly for `window`, otherwise the
+// largest hop, i.e. the least overlap, that does
+pub fn fit_hop_size(window: WindowType, hop_size: usize, fft_size: usize) -> usize {
+    if validate_framing(window, hop_size, fft_size).is_ok() {
+        return hop_size;
+    }
+    cola_hops(window, fft_size).next().unwrap_or(0)
+}
+
+/// One-step trade-off between latency and processing quality, for users who
+/// would rather not tune block and buffer sizes. Applied with
+/// [`AudioProcessor::apply_quality_profile`](crate::AudioProcessor::apply_quality_profile).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QualityProfile {
+    // Short blocks and small device buffers; coarser noise reduction and
+    // less slack before dropouts
+    LowLatency,
+    #[default]
+    Balanced,
+    // Long blocks for finer frequency resolution, with more buffering
+    HighQuality,
+}
+
+impl QualityProfile {
+    pub const ALL: [Self; 3] = [Self::LowLatency, Self::Balanced, Self::HighQuality];
+
+    // Name used in config files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::LowLatency => "low_latency",
+            Self::Balanced => "balanced",
+            Self::HighQuality => "high_quality",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|profile| profile.as_str() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::LowLatency => "Low Latency",
+            Self::Balanced => "Balanced",
+            Self::HighQuality => "High Quality",
+        }
+    }
+
+    // Duration of one processing block, which is also one FFT frame
+    pub fn block_ms(self) -> f32 {
+        match self {
+            Self::LowLatency => 10.0,
+            Self::Balanced => 20.0,
+            Self::HighQuality => 40.0,
+        }
+    }
+
+    // FFT size for the block duration at `sample_rate`: the power of two
+    // nearest above it, so blocks run a little long rather than short
+    pub fn fft_size(self, sample_rate: u32) -> usize {
+        let samples = (self.block_ms() / 1000.0 * sample_rate as f32) as usize;
+        samples.next_power_of_two().clamp(MIN_FFT_SIZE, MAX_FFT_SIZE)
+    }
+
+    // Capacity of the internal ring buffers
+    pub fn buffer_ms(self) -> f32 {
+        match self {
+            Self::LowLatency => 200.0,
+            Self::Balanced | Self::HighQuality => crate::audio::DEFAULT_BUFFER_MS,
+        }
+    }
+
+    // Audio kept ready ahead of the output beyond one block
+    pub fn playout_target_ms(self) -> f32 {
+        match self {
+            Self::LowLatency => 5.0,
+            Self::Balanced => DEFAULT_PLAYOUT_TARGET_MS,
+            Self::HighQuality => 50.0,
+        }
+    }
+
+    // Frames per device callback; None leaves it to the device
+    pub fn buffer_frames(self) -> Option<u32> {
+        match self {
+            Self::LowLatency => Some(128),
+            Self::Balanced | Self::HighQuality => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::spectral::{process_spectral, SpectralState};
+
+    #[test]
+    fn every_allowed_framing_reconstructs() {
+        let mut seed = 3u32;
+        let input: Vec<f32> = (0..16384)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                seed as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect();
+        let mut allowed = 0;
+        for &fft_size in FFT_SIZES {
+            for window in WindowType::ALL {
+                for hop_size in [0, fft_size / 4, fft_size / 8] {
+                    if validate_framing(window, hop_size, fft_size).is_err() {
+                        assert!(!is_cola(window, fft_size, effective_hop(hop_size, fft_size)));
+                        continue;
+                    }
+                    allowed += 1;
+                    // Frames turned down alike come back as the input,
+                    // turned down, after the latency
+                    let mut state = SpectralState::with_hop(fft_size, hop_size, 1);
+                    state.set_window(window);
+                    state.set_noise_reduction_enabled(false);
+                    let output = process_spectral(&input, &mut state, |_, spectrum| {
+                        spectrum.iter_mut().for_each(|bin| *bin *= 0.5)
+                    });
+                    let error = output[state.latency()..].iter()
+                        .zip(&input)
+                        .fold(0.0f32, |error, (out, sample)| error.max((out - 0.5 * sample).abs()));
+                    assert!(error < 1e-4, "{:?} at {}% of {} off by {}", window, overlap_percent(hop_size, fft_size), fft_size, error);
+                }
+            }
+        }
+        // All but Blackman at 50%
+        assert_eq!(allowed, FFT_SIZES.len() * 11);
+
+        // Blackman frames at 50% are refused, pointing at the overlap that works
+        let err = validate_framing(WindowType::Blackman, 0, 1024).unwrap_err().to_string();
+        assert!(err.contains("75%"), "{}", err);
+    }
+
+    #[test]
+    fn stereo_frames_keep_the_full_fft_size() {
+        let settings = ProcessorSettings { fft_size: 1024, hop_size: 256, ..Default::default() };
+        assert_eq!(settings.block_size(1), 256);
+        assert_eq!(settings.block_size(2), 512);
+
+        // A stereo block runs one frame of the full size per channel, and
+        // the framing delays both channels by the same time as mono
+        let mut state = SpectralState::with_hop(settings.fft_size, settings.hop_size, 2);
+        state.set_noise_reduction_enabled(false);
+        process_spectral(&vec![0.1; settings.block_size(2) * 8], &mut state, |_, spectrum| {
+            assert_eq!(spectrum.len(), 1024)
+        });
+        assert_eq!(state.transforms(), 16);
+        assert_eq!(state.latency(), (1024 - 256) * 2);
+        assert_eq!(settings.framing_latency(), 1024);
+    }
+}
@@ -0,0 +1,286 @@
+// Stream builders that hide the device's sample format. Devices may run in
+// i16, u16 or other integer formats; the callbacks here convert to and from
+// f32 so the rest of the pipeline only ever sees f32 samples.
+
+use crate::errors::{AudioError, Result};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{
+    Device, FromSample, InputCallbackInfo, OutputCallbackInfo, Sample, SampleFormat, SizedSample,
+    PlayStreamError, Stream, StreamConfig, StreamError,
+};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+// Attempts at starting a stream, and the wait before the first retry, which
+// doubles after each. Covers another app holding the device in exclusive
+// mode for a moment (e.g. while it switches its own streams).
+const PLAY_ATTEMPTS: u32 = 4;
+const PLAY_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+// A started stream and, while its device refuses to play it, the next
+// attempt. Only the first attempt is made when the stream is started; the
+// later ones are made by `retry` once due, from whatever polls the processor,
+// so no UI or runtime thread sleeps through the backoff.
+pub(crate) struct PlayingStream {
+    stream: Stream,
+    retry: Option<Retry>,
+}
+
+impl PlayingStream {
+    // Makes the next attempt if one is due. An error means the device refused
+    // every attempt, or went away, and the stream should be dropped.
+    pub(crate) fn retry(&mut self, now: Instant) -> Result<()> {
+        if let Some(retry) = self.retry.take() {
+            self.retry = retry.poll(now, || self.stream.play())?;
+        }
+        Ok(())
+    }
+}
+
+// Starts `stream`, scheduling retries with backoff while the device refuses.
+// A device that is gone fails at once, as waiting won't bring it back.
+pub(crate) fn play(label: &'static str, stream: Stream) -> Result<PlayingStream> {
+    let retry = Retry::attempt(label, 1, PLAY_RETRY_DELAY, Instant::now(), || stream.play())?;
+    Ok(PlayingStream { stream, retry })
+}
+
+// The next attempt at starting a stream, and when it is due
+#[derive(Debug, Clone, Copy)]
+struct Retry {
+    label: &'static str,
+    attempt: u32,
+    delay: Duration,
+    due: Instant,
+}
+
+impl Retry {
+    // Makes the attempt once `now` has reached the due time
+    fn poll(self, now: Instant, play: impl FnOnce() -> std::result::Result<(), PlayStreamError>) -> Result<Option<Self>> {
+        if now < self.due {
+            return Ok(Some(self));
+        }
+        Self::attempt(self.label, self.attempt + 1, self.delay * 2, now, play)
+    }
+
+    // Makes attempt number `attempt` at `now`, returning the next one to
+    // make after `delay` if the device refused and attempts remain
+    fn attempt(
+        label: &'static str,
+        attempt: u32,
+        delay: Duration,
+        now: Instant,
+        play: impl FnOnce() -> std::result::Result<(), PlayStreamError>,
+    ) -> Result<Option<Self>> {
+        match play() {
+            Ok(()) => {
+                if attempt > 1 {
+                    info!("{} stream started on attempt {}", label, attempt);
+                }
+                Ok(None)
+            }
+            Err(PlayStreamError::DeviceNotAvailable) => {
+                warn!("{} stream failed to start: the device was disconnected", label);
+                Err(AudioError::DeviceDisconnected(label))
+            }
+            Err(e) if attempt < PLAY_ATTEMPTS => {
+                warn!("{} stream failed to start (attempt {} of {}): {}; retrying in {:?}", label, attempt, PLAY_ATTEMPTS, e, delay);
+                Ok(Some(Self { label, attempt, delay, due: now + delay }))
+            }
+            Err(e) => {
+                warn!("{} stream failed to start (attempt {} of {}): {}", label, attempt, PLAY_ATTEMPTS, e);
+                Err(AudioError::DeviceBusy { kind: label, source: e })
+            }
+        }
+    }
+}
+
+pub(crate) fn build_input_stream<D, E>(
+    device: &Device,
+    config: &StreamConfig,
+    format: SampleFormat,
+    on_data: D,
+    on_error: E,
+) -> Result<Stream>
+where
+    D: FnMut(&[f32], &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    match format {
+        SampleFormat::F32 => Ok(device.build_input_stream(config, on_data, on_error, None)?),
+        SampleFormat::F64 => input_stream::<f64, _, _>(device, config, on_data, on_error),
+        SampleFormat::I8 => input_stream::<i8, _, _>(device, config, on_data, on_error),
+        SampleFormat::I16 => input_stream::<i16, _, _>(device, config, on_data, on_error),
+        SampleFormat::I32 => input_stream::<i32, _, _>(device, config, on_data, on_error),
+        SampleFormat::I64 => input_stream::<i64, _, _>(device, config, on_data, on_error),
+        SampleFormat::U8 => input_stream::<u8, _, _>(device, config, on_data, on_error),
+        SampleFormat::U16 => input_stream::<u16, _, _>(device, config, on_data, on_error),
+        SampleFormat::U32 => input_stream::<u32, _, _>(device, config, on_data, on_error),
+        SampleFormat::U64 => input_stream::<u64, _, _>(device, config, on_data, on_error),
+        other => Err(AudioError::StreamConfig(format!("unsupported sample format {}", other))),
+    }
+}
+
+pub(crate) fn build_output_stream<D, E>(
+    device: &Device,
+    config: &StreamConfig,
+    format: SampleFormat,
+    on_data: D,
+    on_error: E,
+) -> Result<Stream>
+where
+    D: FnMut(&mut [f32], &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    match format {
+        SampleFormat::F32 => Ok(device.build_output_stream(config, on_data, on_error, None)?),
+        SampleFormat::F64 => output_stream::<f64, _, _>(device, config, on_data, on_error),
+        SampleFormat::I8 => output_stream::<i8, _, _>(device, config, on_data, on_error),
+        SampleFormat::I16 => output_stream::<i16, _, _>(device, config, on_data, on_error),
+        SampleFormat::I32 => output_stream::<i32, _, _>(device, config, on_data, on_error),
+        SampleFormat::I64 => output_stream::<i64, _, _>(device, config, on_data, on_error),
+        SampleFormat::U8 => output_stream::<u8, _, _>(device, config, on_data, on_error),
+        SampleFormat::U16 => output_stream::<u16, _, _>(device, config, on_data, on_error),
+        SampleFormat::U32 => output_stream::<u32, _, _>(device, config, on_data, on_error),
+        SampleFormat::U64 => output_stream::<u64, _, _>(device, config, on_data, on_error),
+        other => Err(AudioError::StreamConfig(format!("unsupported sample format {}", other))),
+    }
+}
+
+fn input_stream<T, D, E>(
+    device: &Device,
+    config: &StreamConfig,
+    mut on_data: D,
+    on_error: E,
+) -> Result<Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+    D: FnMut(&[f32], &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    // Reused across callbacks; it only grows if the device hands over a
+    // bigger buffer than before
+    let mut converted: Vec<f32> = Vec::new();
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], info: &InputCallbackInfo| {
+            convert_input(data, &mut converted);
+            on_data(&converted, info);
+        },
+        on_error,
+        None,
+    )?;
+    Ok(stream)
+}
+
+fn output_stream<T, D, E>(
+    device: &Device,
+    config: &StreamConfig,
+    mut on_data: D,
+    on_error: E,
+) -> Result<Stream>
+where
+    T: SizedSample + FromSample<f32>,
+    D: FnMut(&mut [f32], &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let mut rendered: Vec<f32> = Vec::new();
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], info: &OutputCallbackInfo| {
+            rendered.clear();
+            rendered.resize(data.len(), 0.0);
+            on_data(&mut rendered, info);
+            convert_output(&rendered, data);
+        },
+        on_error,
+        None,
+    )?;
+    Ok(stream)
+}
+
+fn convert_input<T>(data: &[T], converted: &mut Vec<f32>)
+where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    converted.clear();
+    converted.extend(data.iter().map(|&sample| f32::from_sample(sample)));
+}
+
+fn convert_output<T: Sample + FromSample<f32>>(rendered: &[f32], data: &mut [T]) {
+    for (out, &sample) in data.iter_mut().zip(rendered) {
+        // The integer conversions assume -1.0..1.0; clamp so an overshoot
+        // saturates at full scale
+        *out = T::from_sample(sample.clamp(-1.0, 1.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::BackendSpecificError;
+
+    fn busy() -> std::result::Result<(), PlayStreamError> {
+        Err(PlayStreamError::BackendSpecific { err: BackendSpecificError { description: "busy".into() } })
+    }
+
+    #[test]
+    fn busy_device_is_retried_with_backoff() {
+        // Fails twice, then starts. Nothing waits: each retry is made by a
+        // poll at or after its due time, and earlier polls don't try at all.
+        let start = Instant::now();
+        let retry = Retry::attempt("Input", 1, PLAY_RETRY_DELAY, start, busy).unwrap().unwrap();
+        assert_eq!(retry.due, start + PLAY_RETRY_DELAY);
+
+        let early = start + PLAY_RETRY_DELAY / 2;
+        let retry = retry.poll(early, || panic!("retried before it was due")).unwrap().unwrap();
+        let second = start + PLAY_RETRY_DELAY;
+        let retry = retry.poll(second, busy).unwrap().unwrap();
+        assert_eq!(retry.due, second + PLAY_RETRY_DELAY * 2);
+        assert!(retry.poll(second + PLAY_RETRY_DELAY * 2, || Ok(())).unwrap().is_none());
+
+        // Gives up after the last attempt
+        let mut retry = Retry::attempt("Input", 1, PLAY_RETRY_DELAY, start, busy).unwrap();
+        let mut attempts = 1;
+        let result = loop {
+            attempts += 1;
+            match retry.map(|retry| retry.poll(retry.due, busy)) {
+                Some(Ok(next)) => retry = next,
+                Some(Err(e)) => break e,
+                None => panic!("started without the device allowing it"),
+            }
+        };
+        assert!(matches!(result, AudioError::DeviceBusy { kind: "Input", .. }));
+        assert_eq!(attempts, PLAY_ATTEMPTS);
+
+        // A device that is gone isn't retried
+        let result = Retry::attempt("Output", 1, PLAY_RETRY_DELAY, start, || Err(PlayStreamError::DeviceNotAvailable));
+        assert!(matches!(result, Err(AudioError::DeviceDisconnected("Output"))));
+    }
+
+    #[test]
+    fn i16_round_trip() {
+        let samples: Vec<f32> = (-100..=100).map(|n| n as f32 / 100.0).collect();
+        let mut device = vec![0i16; samples.len()];
+        convert_output(&samples, &mut device);
+        let mut back = Vec::new();
+        convert_input(&device, &mut back);
+        for (&original, &restored) in samples.iter().zip(&back) {
+            assert!((original - restored).abs() <= 1.0 / 32768.0, "{} came back as {}", original, restored);
+        }
+        assert_eq!(device[0], i16::MIN);
+        assert_eq!(device[samples.len() - 1], i16::MAX);
+    }
+
+    #[test]
+    fn output_overshoot_saturates() {
+        let mut device = [0i16; 4];
+        convert_output(&[1.5, -1.5, f32::MAX, -f32::MAX], &mut device);
+        assert_eq!(device, [i16::MAX, i16::MIN, i16::MAX, i16::MIN]);
+
+        let mut device = [0u16; 3];
+        convert_output(&[2.0, -2.0, 0.0], &mut device);
+        assert_eq!(device, [u16::MAX, 0, 32768]);
+    }
+}
@@ -1,70 +1,586 @@
-use crate::audio::AudioProcessor;
+use cancelcaster::audio::INTERNAL_SAMPLE_RATES;
+use cancelcaster::dsp::gain::{MAX_GAIN_DB, MIN_GAIN_DB};
+use cancelcaster::metrics::DEFAULT_METRICS_INTERVAL;
+use cancelcaster::playout::MAX_PLAYOUT_TARGET_MS;
+use cancelcaster::replay::{MAX_REPLAY_SECS, MIN_REPLAY_SECS};
+use cancelcaster::dsp::tone::{DEFAULT_TEST_TONE_HZ, DEFAULT_TEST_TONE_MS};
+use cancelcaster::dsp::noise::DEFAULT_NOISE_LEARNING_MS;
+use cancelcaster::dsp::calibration::{CalibrationResult, CalibrationStep, TARGET_SPEECH_PEAK_DB};
+use cancelcaster::dsp::{MAX_POWER_SAVE_HOLD_MS, MIN_SAFETY_CEILING_DB};
+use cancelcaster::dsp::loudness::{LoudnessReading, MAX_LOUDNESS_TARGET_LUFS, MIN_LOUDNESS_TARGET_LUFS};
+use cancelcaster::dsp::spectral::{
+    band_upper_edge, floor_for_max_reduction_db, max_reduction_db, MAX_MAX_REDUCTION_DB, MIN_MAX_REDUCTION_DB, NOISE_BANDS,
+};
+use cancelcaster::config::{home_dir, load_noise_profile, remove_noise_profile, save_noise_profile, Shortcuts};
+use cancelcaster::settings::{overlap_percent, validate_framing, QualityProfile, FFT_SIZES, MIN_HOP_DIVISOR};
+use cancelcaster::{
+    AudioError, AudioProcessor, Config, HostId, LatencyBreakdown, MeterReading, MeterWeighting, MonitorSource, MonoToStereoMode, NoiseReductionMode, WindowType,
+    PresetStore, ProcessingBackend, ProcessorEvent, ProcessorSettings, SessionStats,
+};
+use crate::cli::{metrics_target, Args};
+use crate::logging::LogControl;
+use crate::plots;
 use eframe::egui;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+// Errors kept in the banner; older ones are dropped
+const MAX_ERRORS: usize = 5;
+
+fn show_error(errors: &mut Vec<String>, message: String) {
+    if errors.len() == MAX_ERRORS {
+        errors.remove(0);
+    }
+    errors.push(message);
+}
+
+// For failures in the UI itself; processor errors are logged where they occur
+fn report_error(errors: &mut Vec<String>, message: String) {
+    tracing::error!("{}", message);
+    show_error(errors, message);
+}
+
+// Start failure with a hint on what to try for the device problems a user
+// can fix from the device panel
+fn describe_start_error(err: &AudioError) -> String {
+    let hint = match err {
+        AudioError::NoDevice(_) | AudioError::DeviceNotFound { .. } => {
+            "connect a device or pick another one under Audio Devices"
+        }
+        AudioError::StreamConfig(_)
+        | AudioError::BuildStream(cpal::BuildStreamError::StreamConfigNotSupported) => {
+            "try another sample rate, channel count or device buffer size"
+        }
+        AudioError::BuildStream(cpal::BuildStreamError::DeviceNotAvailable) | AudioError::DeviceDisconnected(_) => {
+            "the device was disconnected; click Refresh Devices"
+        }
+        AudioError::DeviceBusy { .. } => {
+            "another application may be using it exclusively; close it or turn off its exclusive mode and try again"
+        }
+        _ => return format!("Failed to start audio processing: {}", err),
+    };
+    format!("Failed to start audio processing: {} ({})", err, hint)
+}
+
+// Device rates offered in the sample rate dropdown, where the input supports them
+const COMMON_SAMPLE_RATES: &[u32] = &[8000, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000];
+
+// Device buffer sizes offered, in frames, and the one to start from when
+// leaving the device default
+const MIN_BUFFER_FRAMES: u32 = 32;
+const MAX_BUFFER_FRAMES: u32 = 4096;
+const INITIAL_BUFFER_FRAMES: u32 = 256;
+
+// Warns as the DSP load approaches realtime, where blocks start coming late
+fn load_color(load: f32) -> egui::Color32 {
+    if load >= 0.9 {
+        egui::Color32::RED
+    } else if load >= 0.6 {
+        egui::Color32::YELLOW
+    } else {
+        egui::Color32::GREEN
+    }
+}
+
+// LUFS with one decimal, or a dash before anything has been measured
+fn format_lufs(lufs: f32) -> String {
+    if lufs.is_finite() {
+        format!("{:.1} LUFS", lufs)
+    } else {
+        "— LUFS".to_string()
+    }
+}
+
+// How often the device lists are re-enumerated to pick up hot-plugged devices
+const DEVICE_REFRESH_INTERVAL: Duration = Duration::from_secs(3);
+
+// Which traces the oscilloscope draws
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScopeMode {
+    Input,
+    Output,
+    Overlay,
+}
 
 pub struct CancelCasterApp {
     audio_processor: Arc<Mutex<AudioProcessor>>,
+    events: broadcast::Receiver<ProcessorEvent>,
     is_running: bool,
-    echo_cancellation: bool,
-    noise_reduction: bool,
-    input_level: f32,
-    output_level: f32,
+    // Both an input and an output device are selected, so Start can work
+    has_devices: bool,
+    // Edited by the controls and pushed to the processor when they change
+    settings: ProcessorSettings,
+    buffer_ms: f32,
+    playout_target_ms: f32,
+    // None once the sizes above were set individually
+    quality_profile: Option<QualityProfile>,
+    presets: PresetStore,
+    selected_preset: Option<String>,
+    // The last preset or reset can be undone
+    can_undo_settings: bool,
+    new_preset_name: String,
+    input_meter: MeterReading,
+    output_meter: MeterReading,
+    meter_weighting: MeterWeighting,
+    input_peak_hold: plots::PeakHold,
+    // One meter per input channel, shown when there is more than one
+    input_channel_meters: Vec<MeterReading>,
+    input_channel_peak_holds: Vec<plots::PeakHold>,
+    output_peak_hold: plots::PeakHold,
+    voice_active: bool,
+    // Power saving is passing silence through untransformed
+    power_saving: bool,
+    agc_gain_db: f32,
+    loudness: LoudnessReading,
+    normalization_gain_db: f32,
+    limiter_reduction_db: f32,
+    compressor_reduction_db: f32,
+    processing_load: f32,
+    underruns: u64,
+    session_stats: Option<SessionStats>,
+    sanitized_samples: u64,
+    safety_clamps: u64,
+    latency: LatencyBreakdown,
+    spectrum_log_frequency: bool,
+    spectrogram: plots::Spectrogram,
+    // The spectrogram shows the input rather than the processed audio
+    spectrogram_input: bool,
+    scope_mode: ScopeMode,
+    is_processing: bool,
+    processing_task_running: bool,
+    loopback_device: Option<String>,
+    selected_loopback_device: Option<String>,
+    // Further sources summed into the echo reference
+    extra_loopback_sources: Vec<String>,
+    // Audio backends offered, found once at startup
+    hosts: Vec<HostId>,
+    host: HostId,
     selected_input_device: usize,
     selected_output_device: usize,
+    // Devices track the OS default; the device lists are then read-only
+    follow_system_default: bool,
+    // Device stream format; None leaves it to the device
+    requested_sample_rate: Option<u32>,
+    requested_channels: Option<u16>,
+    // Single input channel processed as mono, counted from 0
+    input_channel: Option<u16>,
+    buffer_frames: Option<u32>,
+    // Processing rate, to show sample counts as milliseconds
+    sample_rate: u32,
+    calibrating_delay: bool,
+    // Step and progress of a mic calibration under way, and the outcome of
+    // the last one until it is applied or discarded
+    mic_calibration: Option<(CalibrationStep, f32)>,
+    calibration_result: Option<Result<CalibrationResult, String>>,
+    // Progress of a noise learning under way, and whether a learned profile
+    // is in use and kept on disk
+    noise_learning: Option<f32>,
+    has_noise_profile: bool,
+    remember_noise_profile: bool,
+    virtual_output: bool,
+    bypass: bool,
+    muted: bool,
+    output_polarity_inverted: bool,
+    output_stereo_mode: MonoToStereoMode,
+    stereo_width: f32,
+    haas_delay_ms: f32,
+    safety_ceiling_enabled: bool,
+    safety_ceiling_db: f32,
+    // Channel counts of processing and the output device, for the stereo
+    // options
+    channels: u16,
+    output_channels: u16,
+    playing_test_tone: bool,
+    // Sidetone output; the device follows the processor, which drops it when
+    // it becomes the main output
+    monitor_device: Option<usize>,
+    // Further outputs playing the processed audio, followed the same way
+    extra_outputs: Vec<usize>,
+    monitor_source: MonitorSource,
+    monitor_volume_db: f32,
+    // Start processing on launch; kept for the config like the shortcuts
+    autostart: bool,
+    // Keys named in the config, resolved when pressed
+    shortcuts: Shortcuts,
+    // None forces a refresh on the next frame
+    last_device_refresh: Option<Instant>,
+    recording_path: String,
+    recording_elapsed: Option<Duration>,
+    recording_error: Option<String>,
+    replay_secs: u32,
+    // Outcome of the last replay save
+    replay_status: Option<Result<String, String>>,
+    // "-" exports to stdout
+    metrics_path: String,
+    metrics_interval: Duration,
+    exporting_metrics: bool,
+    log: LogControl,
+    metrics_error: Option<String>,
+    // Shown in a dismissible banner at the top of the window
+    errors: Vec<String>,
+}
+
+// Recordings go to the home directory unless the user picks another path
+fn default_recording_path() -> String {
+    home_dir().unwrap_or_default().join("cancelcaster-recording.wav").display().to_string()
+}
+
+// Each replay gets its own file, named by when it was saved
+fn replay_path() -> PathBuf {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    home_dir().unwrap_or_default().join(format!("cancelcaster-replay-{}.wav", timestamp))
+}
+
+fn default_metrics_path() -> String {
+    home_dir().unwrap_or_default().join("cancelcaster-metrics.jsonl").display().to_string()
 }
 
 impl CancelCasterApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Result<Self, Box<dyn std::error::Error>> {
-        let audio_processor = Arc::new(Mutex::new(AudioProcessor::new()?));
+    pub fn new(_cc: &eframe::CreationContext<'_>, args: &Args, log: LogControl) -> Result<Self, Box<dyn std::error::Error>> {
+        let config = Config::load();
+
+        // The processor never leaves the UI thread; the Arc only lets the
+        // Mutex be shared between the update closures
+        #[allow(clippy::arc_with_non_send_sync)]
+        let audio_processor = Arc::new(Mutex::new(AudioProcessor::with_config(&config)?));
         
-        let (selected_input_device, selected_output_device) = if let Ok(processor) = audio_processor.lock() {
-            (processor.get_selected_input_index(), processor.get_selected_output_index())
-        } else {
-            (0, 0)
-        };
+        let mut processor = audio_processor.lock().map_err(|_| "Audio processor lock poisoned")?;
+        let events = processor.subscribe();
+        let selected_input_device = processor.get_selected_input_index();
+        let selected_output_device = processor.get_selected_output_index();
+        let settings = processor.settings();
+        let host = processor.get_host_id();
+        let requested_sample_rate = processor.get_requested_sample_rate();
+        let requested_channels = processor.get_requested_channels();
+        let input_channel = processor.get_input_channel();
+        let buffer_frames = processor.get_buffer_frames();
+        let buffer_ms = processor.get_buffer_ms();
+        let output_polarity_inverted = processor.is_output_polarity_inverted();
+        let output_stereo_mode = processor.get_output_stereo_mode();
+        let stereo_width = processor.get_stereo_width();
+        let haas_delay_ms = processor.get_haas_delay_ms();
+        let safety_ceiling_enabled = processor.is_safety_ceiling_enabled();
+        let safety_ceiling_db = processor.get_safety_ceiling_db();
+        let meter_weighting = processor.get_meter_weighting();
+        let follow_system_default = processor.is_following_system_default();
+        let playout_target_ms = processor.get_playout_target_ms();
+        let replay_secs = processor.get_replay_secs();
+        let quality_profile = processor.get_quality_profile();
+        let saved_noise_profile = load_noise_profile();
+        let remember_noise_profile = saved_noise_profile.is_some();
+        processor.set_noise_profile(saved_noise_profile);
+        let sample_rate = processor.get_sample_rate();
+        let metrics_interval = args.metrics_interval.unwrap_or(DEFAULT_METRICS_INTERVAL);
+        if let Some(path) = args.metrics_path() {
+            processor.start_metrics(path, metrics_interval)?;
+        }
+        let exporting_metrics = processor.is_exporting_metrics();
+        drop(processor);
         
-        Ok(Self {
+        let mut app = Self {
             audio_processor,
+            events,
             is_running: false,
-            echo_cancellation: true,
-            noise_reduction: true,
-            input_level: 0.0,
-            output_level: 0.0,
+            has_devices: false,
+            settings,
+            buffer_ms,
+            playout_target_ms,
+            quality_profile,
+            presets: PresetStore::load(),
+            selected_preset: None,
+            can_undo_settings: false,
+            new_preset_name: String::new(),
+            input_meter: MeterReading::default(),
+            output_meter: MeterReading::default(),
+            meter_weighting,
+            input_peak_hold: plots::PeakHold::default(),
+            input_channel_meters: Vec::new(),
+            input_channel_peak_holds: Vec::new(),
+            output_peak_hold: plots::PeakHold::default(),
+            voice_active: false,
+            power_saving: false,
+            agc_gain_db: 0.0,
+            loudness: LoudnessReading::default(),
+            normalization_gain_db: 0.0,
+            limiter_reduction_db: 0.0,
+            compressor_reduction_db: 0.0,
+            processing_load: 0.0,
+            underruns: 0,
+            session_stats: None,
+            sanitized_samples: 0,
+            safety_clamps: 0,
+            latency: LatencyBreakdown::default(),
+            spectrum_log_frequency: true,
+            spectrogram: plots::Spectrogram::default(),
+            spectrogram_input: false,
+            scope_mode: ScopeMode::Overlay,
+            is_processing: false,
+            processing_task_running: false,
+            loopback_device: None,
+            selected_loopback_device: None,
+            extra_loopback_sources: Vec::new(),
+            hosts: AudioProcessor::available_hosts(),
+            host,
             selected_input_device,
             selected_output_device,
-        })
+            follow_system_default,
+            requested_sample_rate,
+            requested_channels,
+            input_channel,
+            buffer_frames,
+            sample_rate,
+            calibrating_delay: false,
+            mic_calibration: None,
+            calibration_result: None,
+            noise_learning: None,
+            has_noise_profile: remember_noise_profile,
+            remember_noise_profile,
+            virtual_output: false,
+            bypass: false,
+            muted: false,
+            output_polarity_inverted,
+            output_stereo_mode,
+            stereo_width,
+            haas_delay_ms,
+            safety_ceiling_enabled,
+            safety_ceiling_db,
+            channels: 1,
+            output_channels: 2,
+            playing_test_tone: false,
+            monitor_device: None,
+            extra_outputs: Vec::new(),
+            monitor_source: MonitorSource::default(),
+            monitor_volume_db: 0.0,
+            autostart: config.autostart,
+            shortcuts: config.shortcuts,
+            last_device_refresh: Some(Instant::now()),
+            recording_path: default_recording_path(),
+            recording_elapsed: None,
+            recording_error: None,
+            replay_secs,
+            replay_status: None,
+            metrics_path: args.metrics_file.as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(default_metrics_path),
+            metrics_interval,
+            exporting_metrics,
+            log,
+            metrics_error: None,
+            errors: Vec::new(),
+        };
+        // With the devices restored from the config; a failure leaves the
+        // app stopped with the error shown
+        if app.autostart {
+            app.toggle_processing();
+        }
+        Ok(app)
     }
 }
 
 impl eframe::App for CancelCasterApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Update audio levels
-        if let Ok(processor) = self.audio_processor.lock() {
-            self.input_level = processor.get_input_level();
-            self.output_level = processor.get_output_level();
+        if let Ok(mut processor) = self.audio_processor.lock() {
+            // A recording that ended by itself hit a write error; collect it
+            if processor.recording_elapsed().is_some() && !processor.is_recording() {
+                if let Err(e) = processor.stop_recording() {
+                    self.recording_error = Some(format!("Recording stopped: {}", e));
+                }
+            }
+            self.recording_elapsed = processor.recording_elapsed();
+
+            // Likewise for a metrics export that stopped on a write error
+            if self.exporting_metrics && !processor.is_exporting_metrics() {
+                if let Err(e) = processor.stop_metrics() {
+                    self.metrics_error = Some(format!("Metrics export stopped: {}", e));
+                }
+            }
+            self.exporting_metrics = processor.is_exporting_metrics();
+
+            loop {
+                match self.events.try_recv() {
+                    Ok(ProcessorEvent::Error(error)) => show_error(&mut self.errors, error),
+                    Ok(_) | Err(TryRecvError::Lagged(_)) => {}
+                    Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                }
+            }
+
+            if self.last_device_refresh.is_none_or(|last| last.elapsed() >= DEVICE_REFRESH_INTERVAL) {
+                self.last_device_refresh = Some(Instant::now());
+                match processor.refresh_devices() {
+                    Ok(_) => {
+                        if let Err(e) = processor.apply_system_defaults() {
+                            report_error(&mut self.errors, format!("Failed to switch to the system default device: {}", e));
+                        }
+                        self.selected_input_device = processor.get_selected_input_index();
+                        self.selected_output_device = processor.get_selected_output_index();
+                    }
+                    Err(e) => report_error(&mut self.errors, format!("Failed to list devices: {}", e)),
+                }
+            }
+
+            match processor.recover_lost_devices() {
+                Ok(true) => {
+                    self.selected_input_device = processor.get_selected_input_index();
+                    self.selected_output_device = processor.get_selected_output_index();
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    processor.stop();
+                    self.is_running = false;
+                    report_error(&mut self.errors, format!("Audio stopped: {}", e));
+                }
+            }
+
+            // A device that refused to start its stream is tried again here,
+            // once per retry, instead of the start button waiting it out
+            if let Err(e) = processor.retry_stream_starts() {
+                processor.stop();
+                self.is_running = false;
+                report_error(&mut self.errors, describe_start_error(&e));
+            }
+
+            self.input_meter = processor.get_input_meter();
+            self.input_channel_meters = processor.get_input_channel_meters();
+            self.can_undo_settings = processor.can_undo_settings();
+            self.channels = processor.get_channels();
+            self.output_channels = processor.get_output_channels();
+            self.output_meter = processor.get_output_meter();
+            self.voice_active = processor.is_voice_active();
+            self.power_saving = processor.is_power_saving();
+            self.agc_gain_db = processor.get_agc_gain_db();
+            self.loudness = processor.get_loudness();
+            self.normalization_gain_db = processor.get_loudness_normalization_gain_db();
+            self.limiter_reduction_db = processor.get_limiter_reduction_db();
+            self.compressor_reduction_db = processor.get_compressor_reduction_db();
+            self.processing_load = processor.get_processing_load();
+            self.underruns = processor.get_underrun_count();
+            self.session_stats = processor.get_session_stats();
+            self.sanitized_samples = processor.get_sanitized_sample_count();
+            self.safety_clamps = processor.get_safety_clamp_count();
+            match processor.poll_delay_calibration() {
+                Some(Ok(_)) => self.settings.reference_delay_samples = processor.settings().reference_delay_samples,
+                Some(Err(e)) => report_error(&mut self.errors, format!("Echo delay calibration failed: {}", e)),
+                None => {}
+            }
+            self.calibrating_delay = processor.is_calibrating_delay();
+            if let Some(result) = processor.poll_mic_calibration() {
+                self.calibration_result = Some(result.map_err(|e| e.to_string()));
+            }
+            self.mic_calibration = processor.mic_calibration_progress();
+            let noise_learning = processor.noise_learning_progress();
+            let noise_profile = processor.get_noise_profile();
+            if self.noise_learning.is_some() && noise_learning.is_none() && self.remember_noise_profile {
+                if let Some(profile) = &noise_profile {
+                    if let Err(e) = save_noise_profile(profile) {
+                        report_error(&mut self.errors, format!("Failed to save noise profile: {}", e));
+                    }
+                }
+            }
+            self.noise_learning = noise_learning;
+            self.has_noise_profile = noise_profile.is_some();
+            self.latency = processor.get_latency_breakdown();
+            self.is_processing = processor.is_processing();
+            self.playing_test_tone = processor.is_playing_test_tone();
+            self.monitor_device = processor.get_monitor_device();
+            self.extra_outputs = processor.get_extra_output_devices();
+            self.has_devices = processor.has_input_device() && processor.has_output_device();
+            self.processing_task_running = processor.is_processing_task_running();
+            self.loopback_device = processor.get_loopback_device_name();
+        }
+
+        // Keyboard shortcuts, except while typing in a text field. The keys
+        // are consumed so a focused button doesn't act on them as well.
+        if !ctx.wants_keyboard_input() {
+            let pressed = |name: &str| {
+                egui::Key::from_name(name)
+                    .is_some_and(|key| ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, key)))
+            };
+            if pressed(&self.shortcuts.start_stop) && (self.is_running || self.has_devices) {
+                self.toggle_processing();
+            }
+            if pressed(&self.shortcuts.bypass) {
+                self.toggle_bypass();
+            }
+            if pressed(&self.shortcuts.mute) {
+                self.toggle_mute();
+            }
         }
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("CancelCaster - Audio Noise Cancellation");
             ui.separator();
 
-            // Control Panel
-            ui.horizontal(|ui| {
-                if ui.button(if self.is_running { "Stop" } else { "Start" }).clicked() {
-                    if self.is_running {
-                        if let Ok(mut processor) = self.audio_processor.lock() {
-                            processor.stop();
-                        }
-                        self.is_running = false;
-                    } else {
-                        if let Ok(mut processor) = self.audio_processor.lock() {
-                            match self.start_audio_processing(&mut processor) {
-                                Ok(()) => self.is_running = true,
-                                Err(e) => {
-                                    eprintln!("Failed to start audio processing: {}", e);
+            // Error banner
+            if !self.errors.is_empty() {
+                let mut dismissed = None;
+                egui::Frame::none()
+                    .fill(egui::Color32::from_rgb(90, 20, 20))
+                    .inner_margin(6.0)
+                    .rounding(4.0)
+                    .show(ui, |ui| {
+                        for (i, error) in self.errors.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                if ui.small_button("✕").clicked() {
+                                    dismissed = Some(i);
                                 }
-                            }
+                                ui.colored_label(egui::Color32::WHITE, error);
+                            });
                         }
+                        if self.errors.len() > 1 && ui.small_button("Dismiss all").clicked() {
+                            dismissed = Some(usize::MAX);
+                        }
+                    });
+                match dismissed {
+                    Some(usize::MAX) => self.errors.clear(),
+                    Some(i) => {
+                        self.errors.remove(i);
+                    }
+                    None => {}
+                }
+                ui.separator();
+            }
+
+            // Control Panel
+            ui.horizontal(|ui| {
+                let start_stop = ui
+                    .add_enabled(self.is_running || self.has_devices, egui::Button::new(if self.is_running { "Stop" } else { "Start" }))
+                    .on_disabled_hover_text("No input or output device available");
+                if start_stop.clicked() {
+                    self.toggle_processing();
+                }
+
+                let bypass = egui::Button::new(egui::RichText::new("Bypass").strong())
+                    .selected(self.bypass);
+                if ui.add(bypass)
+                    .on_hover_text("Send the unprocessed microphone to the output to compare")
+                    .clicked()
+                {
+                    self.toggle_bypass();
+                }
+
+                // Red while muted, so a muted output is never mistaken for silence
+                let mute = if self.muted {
+                    egui::Button::new(egui::RichText::new("🔇 Muted").strong().color(egui::Color32::WHITE))
+                        .fill(egui::Color32::from_rgb(200, 40, 40))
+                } else {
+                    egui::Button::new(egui::RichText::new("Mute").strong())
+                };
+                if ui.add(mute)
+                    .on_hover_text("Silence the output without stopping audio")
+                    .clicked()
+                {
+                    self.toggle_mute();
+                }
+
+                if ui.add_enabled(self.is_running, egui::Button::new("Reset DSP"))
+                    .on_hover_text("Start echo cancellation, noise reduction and the dynamics over, e.g. when the output squeals or distorts after a glitch; settings are kept")
+                    .clicked()
+                {
+                    if let Ok(processor) = self.audio_processor.lock() {
+                        processor.reset_dsp_state();
                     }
                 }
 
@@ -75,20 +591,63 @@ impl eframe::App for CancelCasterApp {
                     if self.is_running { egui::Color32::GREEN } else { egui::Color32::RED },
                     if self.is_running { "Running" } else { "Stopped" }
                 );
+
+                if ui.checkbox(&mut self.autostart, "Start on Launch")
+                    .on_hover_text("Start processing with the saved devices when CancelCaster opens, e.g. as a login item")
+                    .changed()
+                {
+                    self.save_config();
+                }
+
+                ui.label("⌨").on_hover_text(format!(
+                    "Keyboard shortcuts:\n{}: Start/Stop\n{}: Bypass\n{}: Mute\n\nChange them in the [shortcuts] table of config.toml",
+                    self.shortcuts.start_stop, self.shortcuts.bypass, self.shortcuts.mute,
+                ));
             });
 
             ui.separator();
 
             // Device Selection
-            ui.heading("Audio Devices");
+            ui.horizontal(|ui| {
+                ui.heading("Audio Devices");
+                if ui.button("Refresh Devices").clicked() {
+                    self.last_device_refresh = None;
+                }
+            });
+            let mut follow_changed = ui.checkbox(&mut self.follow_system_default, "Follow System Default")
+                .on_hover_text("Switch to the default input and output whenever they change in the system settings")
+                .changed();
             
             // Get device info (clone to avoid borrowing issues)
-            let (input_devices, output_devices) = if let Ok(processor) = self.audio_processor.lock() {
-                (processor.get_input_devices().clone(), processor.get_output_devices().clone())
+            let (input_devices, output_devices, virtual_microphone) = if let Ok(mut processor) = self.audio_processor.lock() {
+                // Only the selected devices are queried, and only once each
+                processor.input_capabilities(self.selected_input_device);
+                processor.output_capabilities(self.selected_output_device);
+                (
+                    processor.get_input_devices().clone(),
+                    processor.get_output_devices().clone(),
+                    processor.virtual_microphone_name(),
+                )
             } else {
-                (Vec::new(), Vec::new())
+                (Vec::new(), Vec::new(), None)
             };
             
+            let mut host_changed = false;
+            if self.hosts.len() > 1 {
+                ui.horizontal(|ui| {
+                    ui.label("Audio Backend:");
+                    egui::ComboBox::from_id_source("audio_host")
+                        .selected_text(self.host.name())
+                        .show_ui(ui, |ui| {
+                            for &host in &self.hosts {
+                                host_changed |= ui.selectable_value(&mut self.host, host, host.name()).changed();
+                            }
+                        })
+                        .response
+                        .on_hover_text("JACK and ASIO offer lower latency where available");
+                });
+            }
+
             let mut input_device_changed = None;
             let mut output_device_changed = None;
             
@@ -100,15 +659,27 @@ impl eframe::App for CancelCasterApp {
                     egui::ComboBox::from_id_source("input_device")
                         .selected_text(&input_devices[self.selected_input_device].name)
                         .show_ui(ui, |ui| {
-                            for (i, device_info) in input_devices.iter().enumerate() {
-                                let text = if device_info.is_default {
-                                    format!("{} (Default)", device_info.name)
-                                } else {
-                                    device_info.name.clone()
-                                };
-                                
-                                if ui.selectable_value(&mut self.selected_input_device, i, text).changed() {
-                                    input_device_changed = Some(i);
+                            // Microphones first, then system-audio monitor sources
+                            for loopback in [false, true] {
+                                if loopback && input_devices.iter().any(|d| d.is_loopback) {
+                                    ui.separator();
+                                    ui.label("System Audio Sources");
+                                }
+
+                                for (i, device_info) in input_devices.iter().enumerate() {
+                                    if device_info.is_loopback != loopback {
+                                        continue;
+                                    }
+
+                                    let text = if device_info.is_default {
+                                        format!("{} (Default)", device_info.name)
+                                    } else {
+                                        device_info.name.clone()
+                                    };
+
+                                    if ui.selectable_value(&mut self.selected_input_device, i, text).changed() {
+                                        input_device_changed = Some(i);
+                                    }
                                 }
                             }
                         });
@@ -136,13 +707,328 @@ impl eframe::App for CancelCasterApp {
                             }
                         });
                 }
+
+                let label = if self.playing_test_tone { "Stop Tone" } else { "Test Tone" };
+                let test_tone = ui.add_enabled(self.is_processing, egui::Button::new(label))
+                    .on_hover_text(format!(
+                        "Play a {} Hz tone to the output for {} s to check routing and levels",
+                        DEFAULT_TEST_TONE_HZ, DEFAULT_TEST_TONE_MS / 1000,
+                    ))
+                    .on_disabled_hover_text("Start processing first");
+                if test_tone.clicked() {
+                    if let Ok(mut processor) = self.audio_processor.lock() {
+                        if self.playing_test_tone {
+                            processor.stop_test_tone();
+                        } else if let Err(e) = processor.play_test_tone(DEFAULT_TEST_TONE_HZ, DEFAULT_TEST_TONE_MS) {
+                            report_error(&mut self.errors, format!("Failed to play test tone: {}", e));
+                        }
+                    }
+                }
+            });
+
+            // Monitoring on a second output, to hear yourself
+            let mut monitor_device_changed = false;
+            ui.horizontal(|ui| {
+                ui.label("Monitor:");
+                let selected = self.monitor_device
+                    .and_then(|i| output_devices.get(i))
+                    .map_or("Off", |d| d.name.as_str());
+                egui::ComboBox::from_id_source("monitor_device")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        monitor_device_changed |= ui.selectable_value(&mut self.monitor_device, None, "Off").changed();
+                        for (i, device_info) in output_devices.iter().enumerate() {
+                            if i != self.selected_output_device {
+                                monitor_device_changed |= ui
+                                    .selectable_value(&mut self.monitor_device, Some(i), &device_info.name)
+                                    .changed();
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("Play your voice on headphones; on speakers the mic picks it up and it feeds back");
+
+                let source_changed = ui.selectable_value(&mut self.monitor_source, MonitorSource::Processed, "Processed")
+                    .on_hover_text("Hear what listeners hear")
+                    .changed()
+                    | ui.selectable_value(&mut self.monitor_source, MonitorSource::Raw, "Raw")
+                        .on_hover_text("Hear the microphone unprocessed, with less delay")
+                        .changed();
+                let volume_changed = ui.add(
+                    egui::Slider::new(&mut self.monitor_volume_db, MIN_GAIN_DB..=MAX_GAIN_DB).suffix(" dB"),
+                ).changed();
+
+                if source_changed || volume_changed {
+                    if let Ok(processor) = self.audio_processor.lock() {
+                        processor.set_monitor_source(self.monitor_source);
+                        processor.set_monitor_volume(self.monitor_volume_db);
+                    }
+                }
+            });
+
+            // The processed audio on more outputs at once, e.g. headphones
+            // next to a virtual microphone
+            let mut extra_output_changed = None;
+            if output_devices.len() > 1 {
+                ui.collapsing(format!("Also Play On ({})", self.extra_outputs.len()), |ui| {
+                    for (i, device_info) in output_devices.iter().enumerate() {
+                        if i == self.selected_output_device {
+                            continue;
+                        }
+                        let mut selected = self.extra_outputs.contains(&i);
+                        if ui.checkbox(&mut selected, &device_info.name)
+                            .on_hover_text("Plays the processed audio on this device too")
+                            .changed()
+                        {
+                            extra_output_changed = Some((i, selected));
+                        }
+                    }
+                });
+            }
+
+            // What the selected devices support, to explain format errors
+            for (label, device) in [
+                ("Input Capabilities", input_devices.get(self.selected_input_device)),
+                ("Output Capabilities", output_devices.get(self.selected_output_device)),
+            ] {
+                if let Some(capabilities) = device.and_then(|d| d.capabilities.as_ref()) {
+                    ui.collapsing(label, |ui| {
+                        if capabilities.is_empty() {
+                            ui.weak("Could not be queried");
+                        }
+                        for capability in capabilities {
+                            ui.weak(capability.summary());
+                        }
+                    });
+                }
+            }
+
+            // Stream format of the devices, from what the selected input supports
+            let input_capabilities = input_devices
+                .get(self.selected_input_device)
+                .and_then(|d| d.capabilities.clone())
+                .unwrap_or_default();
+            let mut sample_rates: Vec<u32> = COMMON_SAMPLE_RATES
+                .iter()
+                .copied()
+                .filter(|&rate| input_capabilities.iter().any(|c| (c.min_sample_rate..=c.max_sample_rate).contains(&rate)))
+                .collect();
+            let mut channel_counts: Vec<u16> = input_capabilities.iter().map(|c| c.channels).collect();
+            channel_counts.sort_unstable();
+            channel_counts.dedup();
+            // Keep a saved choice selectable after switching devices
+            sample_rates.extend(self.requested_sample_rate.filter(|rate| !sample_rates.contains(rate)));
+            channel_counts.extend(self.requested_channels.filter(|c| !channel_counts.contains(c)));
+
+            let mut sample_rate_changed = false;
+            let mut channels_changed = false;
+            let mut input_channel_changed = false;
+            ui.horizontal(|ui| {
+                ui.label("Device Sample Rate:");
+                egui::ComboBox::from_id_source("device_sample_rate")
+                    .selected_text(self.requested_sample_rate.map_or("Automatic".to_string(), |rate| format!("{} Hz", rate)))
+                    .show_ui(ui, |ui| {
+                        sample_rate_changed |= ui.selectable_value(&mut self.requested_sample_rate, None, "Automatic").changed();
+                        for &rate in &sample_rates {
+                            sample_rate_changed |= ui
+                                .selectable_value(&mut self.requested_sample_rate, Some(rate), format!("{} Hz", rate))
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text("Rate the devices are opened at; audio is resampled for processing");
+
+                ui.label("Channels:");
+                egui::ComboBox::from_id_source("device_channels")
+                    .selected_text(self.requested_channels.map_or("Automatic".to_string(), |c| c.to_string()))
+                    .show_ui(ui, |ui| {
+                        channels_changed |= ui.selectable_value(&mut self.requested_channels, None, "Automatic").changed();
+                        for &channels in &channel_counts {
+                            channels_changed |= ui
+                                .selectable_value(&mut self.requested_channels, Some(channels), channels.to_string())
+                                .changed();
+                        }
+                    });
+
+                // Only offered for devices with more than one channel
+                let most_channels = channel_counts.iter().copied().max().unwrap_or(1);
+                if most_channels > 1 || self.input_channel.is_some() {
+                    ui.label("Input Channel:");
+                    egui::ComboBox::from_id_source("input_channel")
+                        .selected_text(self.input_channel.map_or("All".to_string(), |c| (c + 1).to_string()))
+                        .show_ui(ui, |ui| {
+                            input_channel_changed |= ui.selectable_value(&mut self.input_channel, None, "All").changed();
+                            for channel in 0..most_channels {
+                                input_channel_changed |= ui
+                                    .selectable_value(&mut self.input_channel, Some(channel), (channel + 1).to_string())
+                                    .changed();
+                            }
+                        })
+                        .response
+                        .on_hover_text("Process only the input your mic is plugged into, as mono");
+                }
+            });
+
+            let mut processing_rate = self.sample_rate;
+            let processing_rate_changed = ui.horizontal(|ui| {
+                ui.label("Processing Rate:");
+                let mut changed = false;
+                egui::ComboBox::from_id_source("processing_rate")
+                    .selected_text(format!("{} kHz", processing_rate as f32 / 1000.0))
+                    .show_ui(ui, |ui| {
+                        let current = (!INTERNAL_SAMPLE_RATES.contains(&self.sample_rate)).then_some(self.sample_rate);
+                        for rate in INTERNAL_SAMPLE_RATES.iter().copied().chain(current) {
+                            changed |= ui
+                                .selectable_value(&mut processing_rate, rate, format!("{} kHz", rate as f32 / 1000.0))
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text("Rate the processing runs at; 16 kHz covers the voice band for about a third of the CPU of 48 kHz, 48 kHz keeps music intact");
+                changed
+            }).inner;
+
+            // Restarts the streams, so only once a drag ends
+            let mut buffer_frames_changed = false;
+            ui.horizontal(|ui| {
+                ui.label("Device Buffer:");
+                let mut automatic = self.buffer_frames.is_none();
+                if ui.checkbox(&mut automatic, "Automatic").changed() {
+                    self.buffer_frames = (!automatic).then_some(INITIAL_BUFFER_FRAMES);
+                    buffer_frames_changed = true;
+                }
+                if let Some(frames) = self.buffer_frames.as_mut() {
+                    let response = ui.add(
+                        egui::Slider::new(frames, MIN_BUFFER_FRAMES..=MAX_BUFFER_FRAMES)
+                            .logarithmic(true)
+                            .suffix(" frames"),
+                    ).on_hover_text("Audio the devices exchange per callback; smaller is lower latency, larger is more stable under load");
+                    buffer_frames_changed |= response.drag_stopped() || (response.changed() && !response.dragged());
+                }
+                if self.is_processing {
+                    ui.weak(format!(
+                        "In use: {} in, {} out",
+                        self.latency.input_period_frames, self.latency.output_period_frames,
+                    ));
+                }
+            });
+
+            // Route the output to a virtual microphone for other applications
+            let virtual_output_changed = ui.horizontal(|ui| {
+                let changed = ui.checkbox(&mut self.virtual_output, "Virtual Microphone")
+                    .on_hover_text("Send the processed audio to a microphone other apps (Discord, Zoom, OBS) can select")
+                    .changed();
+                match &virtual_microphone {
+                    Some(name) if self.virtual_output => ui.label(format!("Select \"{}\" as the microphone in other apps", name)),
+                    Some(name) => ui.label(format!("Detected: {}", name)),
+                    None => ui.weak(cancelcaster::virtual_device::setup_hint()),
+                };
+                changed
+            }).inner;
+
+            // System audio (echo cancellation reference) source selection.
+            // WASAPI captures render endpoints directly; elsewhere the source
+            // is a monitor or virtual device listed among the inputs.
+            let loopback_candidates: Vec<String> = if cfg!(target_os = "windows") {
+                output_devices.iter().map(|d| d.name.clone()).collect()
+            } else {
+                input_devices.iter().filter(|d| d.is_loopback).map(|d| d.name.clone()).collect()
+            };
+            let mut loopback_changed = false;
+
+            ui.horizontal(|ui| {
+                ui.label("System Audio:");
+
+                egui::ComboBox::from_id_source("loopback_device")
+                    .selected_text(self.selected_loopback_device.as_deref().unwrap_or("Automatic"))
+                    .show_ui(ui, |ui| {
+                        loopback_changed |= ui
+                            .selectable_value(&mut self.selected_loopback_device, None, "Automatic")
+                            .changed();
+                        for name in &loopback_candidates {
+                            loopback_changed |= ui
+                                .selectable_value(&mut self.selected_loopback_device, Some(name.clone()), name)
+                                .changed();
+                        }
+                    });
             });
+
+            // Sources besides the main one, for apps playing elsewhere
+            let mut extra_loopback_changed = false;
+            let extra_candidates: Vec<&String> = loopback_candidates.iter()
+                .filter(|name| self.selected_loopback_device.as_ref() != Some(*name))
+                .collect();
+            if !extra_candidates.is_empty() {
+                ui.collapsing(format!("Also Cancel ({})", self.extra_loopback_sources.len()), |ui| {
+                    for name in extra_candidates {
+                        let mut selected = self.extra_loopback_sources.contains(name);
+                        if ui.checkbox(&mut selected, name.as_str())
+                            .on_hover_text("Mixes this source's audio into the echo reference, for apps that play to it")
+                            .changed()
+                        {
+                            if selected {
+                                self.extra_loopback_sources.push(name.clone());
+                            } else {
+                                self.extra_loopback_sources.retain(|source| source != name);
+                            }
+                            extra_loopback_changed = true;
+                        }
+                    }
+                });
+            }
             
             // Apply device changes
+            if host_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.set_host(self.host) {
+                        report_error(&mut self.errors, format!("Failed to switch audio backend: {}", e));
+                    }
+                    self.host = processor.get_host_id();
+                    self.is_running = processor.is_processing();
+                    self.selected_input_device = processor.get_selected_input_index();
+                    self.selected_output_device = processor.get_selected_output_index();
+                }
+            }
+
+            if loopback_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_loopback_device_name(self.selected_loopback_device.clone());
+                }
+            }
+
+            if extra_loopback_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.clear_loopback_sources();
+                    for name in &self.extra_loopback_sources {
+                        if let Err(e) = processor.add_loopback_source(name.clone()) {
+                            report_error(&mut self.errors, format!("Failed to add loopback source: {}", e));
+                        }
+                    }
+                    self.extra_loopback_sources = processor.get_loopback_sources().to_vec();
+                }
+            }
+
+            // Picking a device by hand stops following the default
+            if self.follow_system_default && (input_device_changed.is_some() || output_device_changed.is_some()) {
+                self.follow_system_default = false;
+                follow_changed = true;
+            }
+            if follow_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.set_follow_system_default(self.follow_system_default) {
+                        report_error(&mut self.errors, format!("Failed to switch to the system default device: {}", e));
+                    }
+                    if self.follow_system_default {
+                        self.selected_input_device = processor.get_selected_input_index();
+                        self.selected_output_device = processor.get_selected_output_index();
+                    }
+                }
+            }
+
             if let Some(index) = input_device_changed {
                 if let Ok(mut processor) = self.audio_processor.lock() {
                     if let Err(e) = processor.set_input_device(index) {
-                        eprintln!("Failed to set input device: {}", e);
+                        report_error(&mut self.errors, format!("Failed to set input device: {}", e));
                     }
                 }
             }
@@ -150,80 +1036,1531 @@ impl eframe::App for CancelCasterApp {
             if let Some(index) = output_device_changed {
                 if let Ok(mut processor) = self.audio_processor.lock() {
                     if let Err(e) = processor.set_output_device(index) {
-                        eprintln!("Failed to set output device: {}", e);
+                        report_error(&mut self.errors, format!("Failed to set output device: {}", e));
                     }
                 }
             }
 
-            ui.separator();
+            if monitor_device_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.set_monitor_device(self.monitor_device) {
+                        report_error(&mut self.errors, format!("Failed to start monitor: {}", e));
+                    }
+                    self.monitor_device = processor.get_monitor_device();
+                }
+            }
 
-            // Settings
-            ui.heading("Settings");
-            
-            let mut noise_changed = false;
-            
-            ui.checkbox(&mut self.echo_cancellation, "Echo Cancellation")
-                .on_hover_text("Removes application audio from microphone input using phase inversion");
-            
-            if ui.checkbox(&mut self.noise_reduction, "Noise Reduction").changed() {
-                noise_changed = true;
+            if let Some((index, selected)) = extra_output_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    let result = if selected {
+                        processor.add_output_device(index)
+                    } else {
+                        processor.remove_output_device(index)
+                    };
+                    if let Err(e) = result {
+                        report_error(&mut self.errors, format!("Failed to change extra outputs: {}", e));
+                    }
+                    self.extra_outputs = processor.get_extra_output_devices();
+                }
             }
-            ui.label("Reduces background noise using spectral subtraction");
 
-            // Apply setting changes
-            if noise_changed {
+            if virtual_output_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.set_virtual_output(self.virtual_output) {
+                        report_error(&mut self.errors, format!("Failed to set up virtual microphone: {}", e));
+                    }
+                    self.virtual_output = processor.is_virtual_output_enabled();
+                    self.selected_output_device = processor.get_selected_output_index();
+                }
+            }
+
+            if sample_rate_changed || channels_changed || input_channel_changed || buffer_frames_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    let result = if sample_rate_changed {
+                        processor.set_requested_sample_rate(self.requested_sample_rate)
+                    } else if channels_changed {
+                        processor.set_requested_channels(self.requested_channels)
+                    } else if input_channel_changed {
+                        let result = processor.set_input_channel(self.input_channel);
+                        self.input_channel = processor.get_input_channel();
+                        result
+                    } else {
+                        processor.set_buffer_frames(self.buffer_frames)
+                    };
+                    if let Err(e) = result {
+                        report_error(&mut self.errors, format!("Failed to change device format: {}", e));
+                    }
+                    self.quality_profile = processor.get_quality_profile();
+                    self.is_running = processor.is_processing();
+                }
+            }
+
+            if processing_rate_changed {
                 if let Ok(mut processor) = self.audio_processor.lock() {
-                    processor.set_echo_cancellation(self.echo_cancellation);
-                    processor.set_noise_reduction(self.noise_reduction);
+                    match processor.set_internal_sample_rate(processing_rate) {
+                        // A remembered noise profile was learned at the old rate
+                        Ok(()) if self.remember_noise_profile => {
+                            if let Err(e) = remove_noise_profile() {
+                                report_error(&mut self.errors, format!("Failed to remove saved noise profile: {}", e));
+                            }
+                        }
+                        Ok(()) => {}
+                        Err(e) => report_error(&mut self.errors, format!("Failed to change processing rate: {}", e)),
+                    }
+                    self.sample_rate = processor.get_sample_rate();
+                    self.settings = processor.settings();
+                    self.buffer_ms = processor.get_buffer_ms();
+                    self.quality_profile = processor.get_quality_profile();
+                    self.is_running = processor.is_processing();
                 }
             }
 
+            if follow_changed
+                || input_device_changed.is_some()
+                || output_device_changed.is_some()
+                || processing_rate_changed
+                || sample_rate_changed
+                || channels_changed
+                || input_channel_changed
+                || buffer_frames_changed
+            {
+                self.save_config();
+            }
+
             ui.separator();
 
-            // Audio Levels
-            ui.heading("Audio Levels");
-            
+            // Settings
+            let mut reset_settings = false;
+            let mut undo_settings = false;
             ui.horizontal(|ui| {
-                ui.label("Input:");
-                ui.add(egui::ProgressBar::new(self.input_level * 10.0).show_percentage());
+                ui.heading("Settings");
+                reset_settings = ui.button("Reset to Defaults")
+                    .on_hover_text("Restore every processing setting and the default devices")
+                    .clicked();
+                undo_settings = ui.add_enabled(self.can_undo_settings, egui::Button::new("Undo"))
+                    .on_hover_text("Go back to the settings before the last reset or preset")
+                    .clicked();
+            });
+            if reset_settings || undo_settings {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    let result = if reset_settings {
+                        processor.reset_to_defaults()
+                    } else {
+                        processor.undo_settings().map(|_| ())
+                    };
+                    if let Err(e) = result {
+                        report_error(&mut self.errors, format!("Failed to restore settings: {}", e));
+                    }
+                    self.settings = processor.settings();
+                    self.quality_profile = processor.get_quality_profile();
+                    self.selected_input_device = processor.get_selected_input_index();
+                    self.selected_output_device = processor.get_selected_output_index();
+                    self.can_undo_settings = processor.can_undo_settings();
+                }
+                self.selected_preset = None;
+                self.save_config();
+            }
+
+            // Presets
+            let mut apply_preset = false;
+            let mut save_preset = false;
+            ui.horizontal(|ui| {
+                ui.label("Preset:");
+                egui::ComboBox::from_id_source("preset")
+                    .selected_text(self.selected_preset.as_deref().unwrap_or("Choose..."))
+                    .show_ui(ui, |ui| {
+                        for preset in self.presets.presets() {
+                            ui.selectable_value(&mut self.selected_preset, Some(preset.name.clone()), &preset.name);
+                        }
+                    });
+                apply_preset = ui.add_enabled(self.selected_preset.is_some(), egui::Button::new("Apply")).clicked();
             });
+            ui.horizontal(|ui| {
+                ui.label("Save as:");
+                ui.text_edit_singleline(&mut self.new_preset_name);
+                save_preset = ui
+                    .add_enabled(!self.new_preset_name.trim().is_empty(), egui::Button::new("Save"))
+                    .clicked();
+            });
+
+            if apply_preset {
+                if let Some(preset) = self.selected_preset.as_deref().and_then(|name| self.presets.get(name)) {
+                    self.settings = preset.settings.clone();
+                    if let Ok(mut processor) = self.audio_processor.lock() {
+                        if let Err(e) = processor.apply_settings(self.settings.clone()) {
+                            report_error(&mut self.errors, format!("Failed to apply preset: {}", e));
+                        }
+                    }
+                    self.save_config();
+                }
+            }
+            if save_preset {
+                let name = self.new_preset_name.trim().to_string();
+                match self.presets.save_preset(&name, &self.settings) {
+                    Ok(()) => {
+                        self.selected_preset = Some(name);
+                        self.new_preset_name.clear();
+                    }
+                    Err(e) => report_error(&mut self.errors, format!("Failed to save preset: {}", e)),
+                }
+            }
             
+            // Guided setup of the input gain, gate threshold and noise profile
+            let mut calibrate_mic = false;
+            let mut apply_calibration = false;
+            let mut discard_calibration = false;
             ui.horizontal(|ui| {
-                ui.label("Output:");
-                ui.add(egui::ProgressBar::new(self.output_level * 10.0).show_percentage());
+                if let Some((step, progress)) = self.mic_calibration {
+                    let number = if step == CalibrationStep::Silence { 1 } else { 2 };
+                    ui.label(format!("Step {} of 2:", number));
+                    ui.add(egui::ProgressBar::new(progress).desired_width(160.0).text(step.instruction()));
+                } else {
+                    calibrate_mic = ui.add_enabled(self.is_processing, egui::Button::new("Calibrate Mic"))
+                        .on_hover_text(format!(
+                            "Stay quiet for a moment, then speak normally: sets the input gain so speech peaks near {:.0} dBFS, the gate just above the noise and learns the noise profile",
+                            TARGET_SPEECH_PEAK_DB,
+                        ))
+                        .on_disabled_hover_text("Start processing first")
+                        .clicked();
+                }
             });
+            match &self.calibration_result {
+                Some(Ok(result)) => {
+                    egui::Grid::new("calibration_result").num_columns(2).show(ui, |ui| {
+                        ui.label("Noise Floor:");
+                        ui.label(format!("{:.1} dBFS", result.noise_floor_db));
+                        ui.end_row();
+                        ui.label("Speech Peaks:");
+                        ui.label(format!("{:.1} dBFS", result.speech_peak_db));
+                        ui.end_row();
+                        ui.label("Input Gain:");
+                        ui.label(format!("{:+.1} dB", result.input_gain_db));
+                        ui.end_row();
+                        ui.label("Gate Threshold:");
+                        ui.label(format!("{:.1} dBFS", result.gate_threshold_db));
+                        ui.end_row();
+                    });
+                    ui.horizontal(|ui| {
+                        apply_calibration = ui.button("Apply").clicked();
+                        discard_calibration = ui.button("Discard")
+                            .on_hover_text("Keep the current gain and gate; the learned noise profile stays in use")
+                            .clicked();
+                    });
+                }
+                Some(Err(message)) => {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(egui::Color32::RED, message);
+                        discard_calibration = ui.small_button("Dismiss").clicked();
+                    });
+                }
+                None => {}
+            }
 
-            ui.separator();
+            // Levels are persisted once a drag ends
+            let mut gain_changed = false;
+            let mut gain_committed = false;
+            for (label, value, hint) in [
+                ("Input Gain:", &mut self.settings.input_gain_db, "Trim for the microphone before any processing"),
+                ("Output Gain:", &mut self.settings.output_gain_db, "Trim for the processed audio, ahead of the limiter"),
+            ] {
+                let response = ui.horizontal(|ui| {
+                    ui.label(label);
+                    ui.add(egui::Slider::new(value, -24.0..=24.0).suffix(" dB")).on_hover_text(hint)
+                }).inner;
+                gain_changed |= response.changed();
+                gain_committed |= response.drag_stopped() || (response.changed() && !response.dragged());
+            }
 
-            // Information
-            ui.heading("Information");
-            ui.label("• This application captures microphone input and system audio");
-            ui.label("• It applies phase inversion to cancel echo from applications");
-            ui.label("• Noise reduction is applied using spectral subtraction");
-            ui.label("• Processed audio is sent to loopback for use in other applications");
-            
-            ui.separator();
-            
-            // Debug Info
-            if ui.collapsing("Debug Information", |ui| {
-                ui.label(format!("Echo Cancellation: {}", self.echo_cancellation));
-                ui.label(format!("Noise Reduction: {}", self.noise_reduction));
-                ui.label(format!("Input Level: {:.3}", self.input_level));
-                ui.label(format!("Output Level: {:.3}", self.output_level));
-            }).header_response.clicked() {}
-        });
+            let polarity_changed = ui.checkbox(&mut self.output_polarity_inverted, "Invert Output Polarity")
+                .on_hover_text("Flips the output upside down, to undo an inversion later in the routing")
+                .changed();
+
+            let mut stereo_changed = false;
+            let mut stereo_committed = false;
+            if self.output_channels >= 2 {
+                ui.horizontal(|ui| {
+                    ui.label("Stereo Output:");
+                    egui::ComboBox::from_id_source("output_stereo_mode")
+                        .selected_text(self.output_stereo_mode.label())
+                        .show_ui(ui, |ui| {
+                            for mode in MonoToStereoMode::ALL {
+                                stereo_changed |= ui
+                                    .selectable_value(&mut self.output_stereo_mode, mode, mode.label())
+                                    .changed();
+                            }
+                        })
+                        .response
+                        .on_hover_text(if self.channels == 1 {
+                            "How the mono voice fills the stereo output; both modes sum back to the voice unchanged in mono"
+                        } else {
+                            "Only applies while processing is mono; the stereo input goes out as it is"
+                        });
+                });
+                stereo_committed = stereo_changed;
+                if self.output_stereo_mode == MonoToStereoMode::Wide {
+                    for (label, value, range, suffix, hint) in [
+                        ("Width:", &mut self.stereo_width, 0.0..=1.0, "", "How far the sides differ"),
+                        ("Delay:", &mut self.haas_delay_ms, 1.0..=30.0, " ms", "Longer sounds wider until it turns into an echo"),
+                    ] {
+                        let response = ui.horizontal(|ui| {
+                            ui.label(label);
+                            ui.add(egui::Slider::new(value, range).suffix(suffix)).on_hover_text(hint)
+                        }).inner;
+                        stereo_changed |= response.changed();
+                        stereo_committed |= response.drag_stopped() || (response.changed() && !response.dragged());
+                    }
+                }
+            }
+
+            let mut safety_changed = ui.checkbox(&mut self.safety_ceiling_enabled, "Safety Ceiling")
+                .on_hover_text("Hard-clips the output at the ceiling as the very last step, whatever else is enabled, to protect your ears")
+                .changed();
+            let mut safety_committed = safety_changed;
+            if self.safety_ceiling_enabled {
+                let response = ui.horizontal(|ui| {
+                    ui.label("Ceiling:");
+                    ui.add(egui::Slider::new(&mut self.safety_ceiling_db, MIN_SAFETY_CEILING_DB..=0.0).suffix(" dBFS"))
+                        .on_hover_text("Keep it above the limiter's ceiling, so it only catches what the limiter misses")
+                }).inner;
+                safety_changed |= response.changed();
+                safety_committed |= response.drag_stopped() || (response.changed() && !response.dragged());
+            }
+
+            let previous_backend = self.settings.backend;
+            ui.horizontal(|ui| {
+                ui.label("Backend:");
+                for backend in ProcessingBackend::ALL {
+                    let clicked = ui
+                        .add_enabled(
+                            backend.is_available(),
+                            egui::RadioButton::new(self.settings.backend == backend, backend.label()),
+                        )
+                        .on_hover_text(match backend {
+                            ProcessingBackend::Native => "The echo canceller, noise reduction and AGC below",
+                            ProcessingBackend::WebRtc => "WebRTC's AEC3, noise suppressor and AGC2, switched by the same options; 10 ms more latency",
+                        })
+                        .on_disabled_hover_text("Build with the webrtc-apm feature to use it")
+                        .clicked();
+                    if clicked {
+                        self.settings.backend = backend;
+                    }
+                }
+            });
+            let backend_changed = self.settings.backend != previous_backend;
+
+            let echo_changed = ui.checkbox(&mut self.settings.echo_cancellation, "Echo Cancellation")
+                .on_hover_text("Removes application audio from microphone input with an adaptive filter")
+                .changed();
+
+            let echo_step_changed = ui.horizontal(|ui| {
+                ui.label("Echo Adaptation:");
+                ui.add(egui::Slider::new(&mut self.settings.echo_step_size, 0.01..=1.0))
+                    .on_hover_text("How quickly the echo filter adapts; higher converges faster but leaves more residual echo")
+                    .changed()
+            }).inner;
+
+            let samples_per_ms = self.sample_rate as f32 / 1000.0;
+            let mut reference_delay_ms = self.settings.reference_delay_samples as f32 / samples_per_ms;
+            let mut calibrate_delay = false;
+            let reference_delay_changed = ui.horizontal(|ui| {
+                ui.label("Echo Delay:");
+                let changed = ui.add(egui::Slider::new(&mut reference_delay_ms, 0.0..=500.0).suffix(" ms"))
+                    .on_hover_text("How long application audio takes to come back through the microphone")
+                    .changed();
+                if self.calibrating_delay {
+                    ui.spinner();
+                    ui.label("Play audio through the speakers...");
+                } else {
+                    calibrate_delay = ui.add_enabled(self.is_processing, egui::Button::new("Calibrate Echo Delay"))
+                        .on_hover_text("Measures the delay from two seconds of audio playing through the speakers")
+                        .on_disabled_hover_text("Start processing first")
+                        .clicked();
+                }
+                changed
+            }).inner;
+            
+            let noise_changed = ui.checkbox(&mut self.settings.noise_reduction, "Noise Reduction").changed();
+            ui.label("Reduces background noise using spectral subtraction, a Wiener filter or per-band subtraction");
+
+            let mut learn_noise = false;
+            let mut clear_noise_profile = false;
+            let mut remember_changed = false;
+            ui.horizontal(|ui| {
+                if let Some(progress) = self.noise_learning {
+                    ui.add(egui::ProgressBar::new(progress).desired_width(120.0).text("Stay quiet..."));
+                } else {
+                    learn_noise = ui.add_enabled(self.is_processing, egui::Button::new("Learn Noise (stay quiet)"))
+                        .on_hover_text(format!(
+                            "Measures the background noise over {} s of silence and subtracts exactly that from then on",
+                            DEFAULT_NOISE_LEARNING_MS / 1000,
+                        ))
+                        .on_disabled_hover_text("Start processing first")
+                        .clicked();
+                }
+                if self.has_noise_profile {
+                    ui.label("Using learned profile");
+                    clear_noise_profile = ui.button("Clear")
+                        .on_hover_text("Go back to estimating the noise continuously")
+                        .clicked();
+                }
+                remember_changed = ui.checkbox(&mut self.remember_noise_profile, "Remember")
+                    .on_hover_text("Keep the learned profile for the next launch")
+                    .changed();
+            });
+
+            let previous_mode = self.settings.noise_reduction_mode;
+            ui.horizontal(|ui| {
+                ui.label("Method:");
+                for mode in NoiseReductionMode::ALL {
+                    let clicked = ui
+                        .add_enabled(
+                            mode.is_available(),
+                            egui::RadioButton::new(self.settings.noise_reduction_mode == mode, mode.label()),
+                        )
+                        .on_disabled_hover_text("Build with the rnnoise feature to use it")
+                        .clicked();
+                    if clicked {
+                        self.settings.noise_reduction_mode = mode;
+                    }
+                }
+            });
+            let mode_changed = self.settings.noise_reduction_mode != previous_mode;
+
+            // Strength and floor are persisted, but only once a drag ends
+            // The floor is shown as the most any frequency is turned down
+            let mut strength_changed = false;
+            let mut strength_committed = false;
+            let shown_reduction = max_reduction_db(self.settings.spectral_floor).min(MAX_MAX_REDUCTION_DB);
+            let mut max_reduction = shown_reduction;
+            for (label, value, range, suffix, hint) in [
+                (
+                    "Strength:",
+                    &mut self.settings.noise_reduction_strength,
+                    1.0..=4.0,
+                    "",
+                    "Over-subtraction factor; higher removes more noise but causes more artifacts",
+                ),
+                (
+                    "Max Reduction:",
+                    &mut max_reduction,
+                    MIN_MAX_REDUCTION_DB..=MAX_MAX_REDUCTION_DB,
+                    " dB",
+                    "Most any frequency is turned down; lower leaves more noise but sounds more natural",
+                ),
+            ] {
+                let response = ui.horizontal(|ui| {
+                    ui.label(label);
+                    ui.add(egui::Slider::new(value, range).suffix(suffix)).on_hover_text(hint)
+                }).inner;
+                strength_changed |= response.changed();
+                strength_committed |= response.drag_stopped() || (response.changed() && !response.dragged());
+            }
+            if max_reduction != shown_reduction {
+                self.settings.spectral_floor = floor_for_max_reduction_db(max_reduction);
+            }
+
+            // Per-band strengths replace the single strength in multi-band mode
+            let mut bands_changed = false;
+            if self.settings.noise_reduction_mode == NoiseReductionMode::MultiBand {
+                let nyquist = self.sample_rate as f32 / 2.0;
+                for band in 0..NOISE_BANDS {
+                    let low = if band == 0 { 0.0 } else { band_upper_edge(band - 1) * nyquist };
+                    let high = band_upper_edge(band) * nyquist;
+                    bands_changed |= ui.horizontal(|ui| {
+                        ui.label(format!("{:.0}-{:.0} Hz:", low, high));
+                        ui.add(egui::Slider::new(&mut self.settings.band_strengths[band], 1.0..=4.0))
+                            .on_hover_text("Over-subtraction factor for this frequency band")
+                            .changed()
+                    }).inner;
+                }
+            }
+
+            let adaptation_changed = ui.horizontal(|ui| {
+                ui.label("Noise Tracking:");
+                ui.add(egui::Slider::new(&mut self.settings.noise_adaptation_rate, 0.01..=1.0))
+                    .on_hover_text("How quickly the noise estimate follows changes in background noise")
+                    .changed()
+            }).inner;
+
+            let mut power_save_changed = ui.checkbox(&mut self.settings.power_save, "Power Save")
+                .on_hover_text("Skips the FFT processing while you're not talking, to save CPU")
+                .changed();
+            if self.settings.power_save {
+                power_save_changed |= ui.horizontal(|ui| {
+                    ui.label("After Silence:");
+                    ui.add(egui::Slider::new(&mut self.settings.power_save_hold_ms, 0.0..=MAX_POWER_SAVE_HOLD_MS).suffix(" ms"))
+                        .on_hover_text("How long no voice must be heard before processing is skipped")
+                        .changed()
+                }).inner;
+            }
+
+            let mut highpass_changed = ui.checkbox(&mut self.settings.highpass_enabled, "High-Pass Filter")
+                .on_hover_text("Removes low-frequency rumble such as desk bumps and hum")
+                .changed();
+            if self.settings.highpass_enabled {
+                highpass_changed |= ui.horizontal(|ui| {
+                    ui.label("Cutoff:");
+                    ui.add(egui::Slider::new(&mut self.settings.highpass_cutoff_hz, 20.0..=300.0)
+                        .suffix(" Hz")
+                        .logarithmic(true))
+                        .changed()
+                }).inner;
+            }
+
+            let mut quality_changed = false;
+            ui.horizontal(|ui| {
+                ui.label("Quality:");
+                egui::ComboBox::from_id_source("quality_profile")
+                    .selected_text(self.quality_profile.map_or("Custom", |profile| profile.label()))
+                    .show_ui(ui, |ui| {
+                        for profile in QualityProfile::ALL {
+                            quality_changed |= ui
+                                .selectable_value(&mut self.quality_profile, Some(profile), profile.label())
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text("Sets the FFT size, buffers and device buffer together; lower latency costs noise reduction precision and dropout headroom");
+            });
+
+            let mut fft_size_changed = false;
+            ui.horizontal(|ui| {
+                ui.label("FFT Size (per channel):");
+                egui::ComboBox::from_id_source("fft_size")
+                    .selected_text(self.settings.fft_size.to_string())
+                    .show_ui(ui, |ui| {
+                        for &size in FFT_SIZES {
+                            fft_size_changed |= ui
+                                .selectable_value(&mut self.settings.fft_size, size, size.to_string())
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text("Larger sizes separate noise more precisely, smaller sizes add less latency");
+            });
+
+            // Hops of powers of two from an eighth to half the FFT size; 0 is
+            // the default framing. Overlaps the window doesn't overlap-add
+            // to a constant at are offered but disabled.
+            let mut hop_size_changed = false;
+            let fft_size = self.settings.fft_size;
+            let hop_label = |hop: usize| {
+                if hop == 0 {
+                    "50% (Default)".to_string()
+                } else {
+                    format!("{}% (hop {})", overlap_percent(hop, fft_size), hop)
+                }
+            };
+            ui.horizontal(|ui| {
+                ui.label("Overlap:");
+                egui::ComboBox::from_id_source("hop_size")
+                    .selected_text(hop_label(self.settings.hop_size))
+                    .show_ui(ui, |ui| {
+                        let hops = std::iter::successors(Some(fft_size / 2), |&hop| Some(hop / 2))
+                            .take_while(|&hop| hop >= fft_size / MIN_HOP_DIVISOR);
+                        for hop in std::iter::once(0).chain(hops) {
+                            let framing = validate_framing(self.settings.fft_window, hop, fft_size);
+                            let response = ui.add_enabled(
+                                framing.is_ok(),
+                                egui::SelectableLabel::new(self.settings.hop_size == hop, hop_label(hop)),
+                            );
+                            if let Err(e) = framing {
+                                response.on_disabled_hover_text(e.to_string());
+                            } else if response.clicked() && self.settings.hop_size != hop {
+                                self.settings.hop_size = hop;
+                                hop_size_changed = true;
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("How much each FFT frame overlaps the next; any explicit hop cuts the delay from 1.5 to one FFT size, more overlap costs more CPU");
+                // What the spectral stages add to the latency at this framing
+                let samples_per_ms = self.sample_rate as f32 / 1000.0;
+                ui.weak(format!("Delay: {:.1} ms", self.settings.framing_latency() as f32 / samples_per_ms));
+            });
+
+            let mut window_changed = false;
+            ui.horizontal(|ui| {
+                ui.label("FFT Window:");
+                egui::ComboBox::from_id_source("fft_window")
+                    .selected_text(self.settings.fft_window.label())
+                    .show_ui(ui, |ui| {
+                        for window in WindowType::ALL {
+                            let framing = validate_framing(window, self.settings.hop_size, self.settings.fft_size);
+                            let response = ui.add_enabled(
+                                framing.is_ok(),
+                                egui::SelectableLabel::new(self.settings.fft_window == window, window.label()),
+                            );
+                            if let Err(e) = framing {
+                                response.on_disabled_hover_text(e.to_string());
+                            } else if response.clicked() && self.settings.fft_window != window {
+                                self.settings.fft_window = window;
+                                window_changed = true;
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("Hann suits most noise; Blackman keeps loud noise from leaking into quiet bins, Hamming and rectangular resolve tones more sharply");
+            });
+
+            // Changing the buffer restarts the streams, so only once a drag ends
+            let buffer_response = ui.horizontal(|ui| {
+                ui.label("Buffer:");
+                ui.add(egui::Slider::new(&mut self.buffer_ms, 50.0..=2000.0).suffix(" ms").logarithmic(true))
+                    .on_hover_text("Audio each internal buffer can hold; smaller limits latency, larger tolerates stalls")
+            }).inner;
+            let buffer_changed = buffer_response.drag_stopped()
+                || (buffer_response.changed() && !buffer_response.dragged());
+
+            // Also restarts the output, so likewise once a drag ends
+            let playout_response = ui.horizontal(|ui| {
+                ui.label("Output Target:");
+                ui.add(egui::Slider::new(&mut self.playout_target_ms, 0.0..=MAX_PLAYOUT_TARGET_MS).suffix(" ms"))
+                    .on_hover_text("Audio kept ready ahead of the output; more survives stalls without dropouts but adds latency")
+            }).inner;
+            let playout_changed = playout_response.drag_stopped()
+                || (playout_response.changed() && !playout_response.dragged());
+
+            let mut expander_changed = ui.checkbox(&mut self.settings.expander_enabled, "Expander")
+                .on_hover_text("Turns room tone down between sentences instead of silencing it like the gate")
+                .changed();
+            if self.settings.expander_enabled {
+                for (label, value, range, suffix) in [
+                    ("Threshold:", &mut self.settings.expander_threshold_db, -80.0..=0.0, " dB"),
+                    ("Ratio:", &mut self.settings.expander_ratio, 1.0..=10.0, ":1"),
+                    ("Knee:", &mut self.settings.expander_knee_db, 0.0..=24.0, " dB"),
+                ] {
+                    expander_changed |= ui.horizontal(|ui| {
+                        ui.label(label);
+                        ui.add(egui::Slider::new(value, range).suffix(suffix)).changed()
+                    }).inner;
+                }
+            }
+
+            let mut gate_changed = ui.checkbox(&mut self.settings.gate_enabled, "Noise Gate")
+                .on_hover_text("Silences the output between sentences when the level drops below the threshold")
+                .changed();
+            if self.settings.gate_enabled {
+                for (label, value, range, suffix) in [
+                    ("Threshold:", &mut self.settings.gate_threshold_db, -80.0..=0.0, " dB"),
+                    ("Attack:", &mut self.settings.gate_attack_ms, 0.0..=50.0, " ms"),
+                    ("Hold:", &mut self.settings.gate_hold_ms, 0.0..=1000.0, " ms"),
+                    ("Release:", &mut self.settings.gate_release_ms, 10.0..=1000.0, " ms"),
+                ] {
+                    gate_changed |= ui.horizontal(|ui| {
+                        ui.label(label);
+                        ui.add(egui::Slider::new(value, range).suffix(suffix)).changed()
+                    }).inner;
+                }
+            }
+
+            let mut comfort_changed = ui.checkbox(&mut self.settings.comfort_noise_enabled, "Comfort Noise")
+                .on_hover_text("Plays faint background noise instead of dead silence while the gate is closed or nobody speaks")
+                .changed();
+            if self.settings.comfort_noise_enabled {
+                comfort_changed |= ui.horizontal(|ui| {
+                    ui.label("Level:");
+                    ui.add(egui::Slider::new(&mut self.settings.comfort_noise_level_db, -60.0..=0.0).suffix(" dB"))
+                        .on_hover_text("Relative to the estimated background noise")
+                        .changed()
+                }).inner;
+            }
+
+            let mut compressor_changed = ui.checkbox(&mut self.settings.compressor_enabled, "Compressor")
+                .on_hover_text("Turns loud words down towards quiet ones for an even voice, then makes up the level")
+                .changed();
+            if self.settings.compressor_enabled {
+                for (label, value, range, suffix) in [
+                    ("Threshold:", &mut self.settings.compressor_threshold_db, -60.0..=0.0, " dB"),
+                    ("Ratio:", &mut self.settings.compressor_ratio, 1.0..=20.0, ":1"),
+                    ("Attack:", &mut self.settings.compressor_attack_ms, 0.0..=200.0, " ms"),
+                    ("Release:", &mut self.settings.compressor_release_ms, 10.0..=2000.0, " ms"),
+                    ("Makeup:", &mut self.settings.compressor_makeup_db, 0.0..=24.0, " dB"),
+                    ("Knee:", &mut self.settings.compressor_knee_db, 0.0..=24.0, " dB"),
+                ] {
+                    compressor_changed |= ui.horizontal(|ui| {
+                        ui.label(label);
+                        ui.add(egui::Slider::new(value, range).suffix(suffix)).changed()
+                    }).inner;
+                }
+                ui.label(format!("Gain Reduction: {:.1} dB", self.compressor_reduction_db));
+            }
+
+            let mut deesser_changed = ui.checkbox(&mut self.settings.deesser_enabled, "De-esser")
+                .on_hover_text("Tames harsh \"S\" sounds by turning down 5-9 kHz while they dominate")
+                .changed();
+            if self.settings.deesser_enabled {
+                for (label, value, range) in [
+                    ("Threshold:", &mut self.settings.deesser_threshold_db, -40.0..=0.0),
+                    ("Range:", &mut self.settings.deesser_range_db, 0.0..=24.0),
+                ] {
+                    deesser_changed |= ui.horizontal(|ui| {
+                        ui.label(label);
+                        ui.add(egui::Slider::new(value, range).suffix(" dB")).changed()
+                    }).inner;
+                }
+            }
+
+            let mut click_changed = ui.checkbox(&mut self.settings.click_suppression, "Click Suppression")
+                .on_hover_text("Takes keyboard and mouse clicks out, also while you speak")
+                .changed();
+            if self.settings.click_suppression {
+                click_changed |= ui.horizontal(|ui| {
+                    ui.label("Sensitivity:");
+                    ui.add(egui::Slider::new(&mut self.settings.click_sensitivity, 0.0..=1.0))
+                        .on_hover_text("Higher catches softer clicks but may dull consonants like \"t\" and \"k\"")
+                        .changed()
+                }).inner;
+            }
+
+            let mut agc_changed = ui.checkbox(&mut self.settings.agc_enabled, "Automatic Gain Control")
+                .on_hover_text("Evens out the output level so listeners hear a steady volume")
+                .changed();
+            if self.settings.agc_enabled {
+                for (label, value, range) in [
+                    ("Target Level:", &mut self.settings.agc_target_db, -40.0..=-6.0),
+                    ("Max Gain:", &mut self.settings.agc_max_gain_db, 0.0..=40.0),
+                ] {
+                    agc_changed |= ui.horizontal(|ui| {
+                        ui.label(label);
+                        ui.add(egui::Slider::new(value, range).suffix(" dB")).changed()
+                    }).inner;
+                }
+            }
+
+            let mut loudness_changed = ui.checkbox(&mut self.settings.loudness_normalization, "Loudness Normalization")
+                .on_hover_text("Slowly brings the output to a target loudness, e.g. -16 LUFS for podcasts or -23 LUFS for broadcast")
+                .changed();
+            if self.settings.loudness_normalization {
+                ui.horizontal(|ui| {
+                    ui.label("Target:");
+                    loudness_changed |= ui.add(
+                        egui::Slider::new(&mut self.settings.loudness_target_lufs, MIN_LOUDNESS_TARGET_LUFS..=MAX_LOUDNESS_TARGET_LUFS)
+                            .suffix(" LUFS"),
+                    ).changed();
+                    ui.label(format!("Gain: {:+.1} dB", self.normalization_gain_db));
+                });
+            }
+
+            let mix_changed = ui.horizontal(|ui| {
+                ui.label("Dry/Wet Mix:");
+                ui.add(egui::Slider::new(&mut self.settings.mix, 0.0..=1.0))
+                    .on_hover_text("Blend of original (0) and processed (1) audio; lower sounds more natural")
+                    .changed()
+            }).inner;
+
+            let mut limiter_changed = ui.checkbox(&mut self.settings.limiter_enabled, "Limiter")
+                .on_hover_text("Keeps the output below the ceiling so it never clips")
+                .changed();
+            if self.settings.limiter_enabled {
+                limiter_changed |= ui.horizontal(|ui| {
+                    ui.label("Ceiling:");
+                    ui.add(egui::Slider::new(&mut self.settings.limiter_ceiling_db, -12.0..=0.0).suffix(" dB"))
+                        .changed()
+                }).inner;
+            }
+
+            // Apply setting changes
+            if backend_changed {
+                let result = match self.audio_processor.lock() {
+                    Ok(mut processor) => processor.set_processing_backend(self.settings.backend),
+                    Err(_) => Ok(()),
+                };
+                if let Err(e) = result {
+                    report_error(&mut self.errors, format!("Failed to switch the processing backend: {}", e));
+                    self.settings.backend = previous_backend;
+                }
+                self.save_config();
+            }
+            if echo_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_echo_cancellation(self.settings.echo_cancellation);
+                }
+            }
+            if noise_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_noise_reduction(self.settings.noise_reduction);
+                }
+            }
+            if echo_changed || noise_changed {
+                self.save_config();
+            }
+
+            if calibrate_mic {
+                self.calibration_result = None;
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.start_mic_calibration() {
+                        report_error(&mut self.errors, format!("Failed to calibrate the microphone: {}", e));
+                    }
+                }
+            }
+
+            if apply_calibration {
+                if let Some(Ok(result)) = self.calibration_result.take() {
+                    let mut profile = None;
+                    if let Ok(mut processor) = self.audio_processor.lock() {
+                        processor.apply_mic_calibration(&result);
+                        let settings = processor.settings();
+                        self.settings.input_gain_db = settings.input_gain_db;
+                        self.settings.gate_threshold_db = settings.gate_threshold_db;
+                        profile = processor.get_noise_profile();
+                    }
+                    // The remembered profile was saved before the gain changed
+                    if let Some(profile) = profile.filter(|_| self.remember_noise_profile) {
+                        if let Err(e) = save_noise_profile(&profile) {
+                            report_error(&mut self.errors, format!("Failed to save noise profile: {}", e));
+                        }
+                    }
+                    self.save_config();
+                }
+            }
+            if discard_calibration {
+                self.calibration_result = None;
+            }
+
+            if gain_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_input_gain_db(self.settings.input_gain_db);
+                    processor.set_output_gain_db(self.settings.output_gain_db);
+                }
+            }
+            if gain_committed {
+                self.save_config();
+            }
+
+            if polarity_changed {
+                if let Ok(processor) = self.audio_processor.lock() {
+                    processor.set_output_polarity_inverted(self.output_polarity_inverted);
+                }
+                self.save_config();
+            }
+
+            if stereo_changed {
+                if let Ok(processor) = self.audio_processor.lock() {
+                    processor.set_output_stereo_mode(self.output_stereo_mode);
+                    processor.set_stereo_width(self.stereo_width);
+                    processor.set_haas_delay_ms(self.haas_delay_ms);
+                }
+            }
+            if stereo_committed {
+                self.save_config();
+            }
+
+            if safety_changed {
+                if let Ok(processor) = self.audio_processor.lock() {
+                    processor.set_safety_ceiling_enabled(self.safety_ceiling_enabled);
+                    processor.set_safety_ceiling_db(self.safety_ceiling_db);
+                }
+            }
+            if safety_committed {
+                self.save_config();
+            }
+
+            if reference_delay_changed {
+                self.settings.reference_delay_samples = (reference_delay_ms * samples_per_ms).round() as usize;
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_reference_delay_samples(self.settings.reference_delay_samples);
+                }
+            }
+
+            if learn_noise {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.start_noise_learning(DEFAULT_NOISE_LEARNING_MS) {
+                        report_error(&mut self.errors, format!("Failed to learn noise: {}", e));
+                    }
+                }
+            }
+
+            if clear_noise_profile {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_noise_profile(None);
+                }
+                self.has_noise_profile = false;
+            }
+
+            // The saved profile follows the checkbox and the profile in use
+            if clear_noise_profile || remember_changed {
+                let profile = self.audio_processor.lock().ok().and_then(|p| p.get_noise_profile());
+                let result = match profile.filter(|_| self.remember_noise_profile) {
+                    Some(profile) => save_noise_profile(&profile),
+                    None => remove_noise_profile(),
+                };
+                if let Err(e) = result {
+                    report_error(&mut self.errors, format!("Failed to update saved noise profile: {}", e));
+                }
+            }
+
+            if calibrate_delay {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.start_delay_calibration() {
+                        report_error(&mut self.errors, format!("Failed to calibrate echo delay: {}", e));
+                    }
+                }
+            }
+
+            if echo_step_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_echo_step_size(self.settings.echo_step_size);
+                }
+            }
+
+            if mode_changed {
+                let result = match self.audio_processor.lock() {
+                    Ok(mut processor) => processor.set_noise_reduction_mode(self.settings.noise_reduction_mode),
+                    Err(_) => Ok(()),
+                };
+                if let Err(e) = result {
+                    report_error(&mut self.errors, format!("Failed to switch the noise reduction method: {}", e));
+                    self.settings.noise_reduction_mode = previous_mode;
+                }
+            }
+
+            if strength_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_noise_reduction_strength(self.settings.noise_reduction_strength);
+                    processor.set_spectral_floor(self.settings.spectral_floor);
+                }
+            }
+            if strength_committed {
+                self.save_config();
+            }
+
+            if bands_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_band_strengths(&self.settings.band_strengths);
+                }
+            }
+
+            if adaptation_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_noise_adaptation_rate(self.settings.noise_adaptation_rate);
+                }
+            }
+
+            if power_save_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_power_save_enabled(self.settings.power_save);
+                    processor.set_power_save_hold_ms(self.settings.power_save_hold_ms);
+                }
+            }
+
+            if expander_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_expander_enabled(self.settings.expander_enabled);
+                    processor.set_expander_threshold_db(self.settings.expander_threshold_db);
+                    processor.set_expander_ratio(self.settings.expander_ratio);
+                    processor.set_expander_knee_db(self.settings.expander_knee_db);
+                }
+            }
+
+            if gate_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_gate_enabled(self.settings.gate_enabled);
+                    processor.set_gate_threshold_db(self.settings.gate_threshold_db);
+                    processor.set_gate_attack_ms(self.settings.gate_attack_ms);
+                    processor.set_gate_hold_ms(self.settings.gate_hold_ms);
+                    processor.set_gate_release_ms(self.settings.gate_release_ms);
+                }
+            }
+
+            if comfort_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_comfort_noise_enabled(self.settings.comfort_noise_enabled);
+                    processor.set_comfort_noise_level_db(self.settings.comfort_noise_level_db);
+                }
+            }
+
+            if compressor_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_compressor_enabled(self.settings.compressor_enabled);
+                    processor.set_compressor_threshold_db(self.settings.compressor_threshold_db);
+                    processor.set_compressor_ratio(self.settings.compressor_ratio);
+                    processor.set_compressor_attack_ms(self.settings.compressor_attack_ms);
+                    processor.set_compressor_release_ms(self.settings.compressor_release_ms);
+                    processor.set_compressor_makeup_db(self.settings.compressor_makeup_db);
+                    processor.set_compressor_knee_db(self.settings.compressor_knee_db);
+                }
+            }
+
+            if deesser_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_deesser_enabled(self.settings.deesser_enabled);
+                    processor.set_deesser_threshold_db(self.settings.deesser_threshold_db);
+                    processor.set_deesser_range_db(self.settings.deesser_range_db);
+                }
+            }
+
+            if click_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_click_suppression_enabled(self.settings.click_suppression);
+                    processor.set_click_sensitivity(self.settings.click_sensitivity);
+                }
+            }
+
+            if agc_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_agc_enabled(self.settings.agc_enabled);
+                    processor.set_agc_target_db(self.settings.agc_target_db);
+                    processor.set_agc_max_gain_db(self.settings.agc_max_gain_db);
+                }
+            }
+
+            if loudness_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_loudness_normalization(self.settings.loudness_normalization);
+                    processor.set_loudness_target_lufs(self.settings.loudness_target_lufs);
+                }
+            }
+
+            if mix_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_mix(self.settings.mix);
+                }
+            }
+
+            if limiter_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_limiter_enabled(self.settings.limiter_enabled);
+                    processor.set_limiter_ceiling_db(self.settings.limiter_ceiling_db);
+                }
+            }
+
+            if highpass_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    processor.set_highpass_enabled(self.settings.highpass_enabled);
+                    processor.set_highpass_cutoff_hz(self.settings.highpass_cutoff_hz);
+                }
+            }
+
+            if quality_changed {
+                if let (Some(profile), Ok(mut processor)) = (self.quality_profile, self.audio_processor.lock()) {
+                    if let Err(e) = processor.apply_quality_profile(profile) {
+                        report_error(&mut self.errors, format!("Failed to apply quality profile: {}", e));
+                    }
+                    self.quality_profile = processor.get_quality_profile();
+                    self.settings.fft_size = processor.settings().fft_size;
+                    self.settings.hop_size = processor.settings().hop_size;
+                    self.buffer_ms = processor.get_buffer_ms();
+                    self.playout_target_ms = processor.get_playout_target_ms();
+                    self.buffer_frames = processor.get_buffer_frames();
+                    self.is_running = processor.is_processing();
+                }
+            }
+
+            // Tuning one of the sizes by hand leaves the profile
+            let leaves_profile = fft_size_changed || hop_size_changed || buffer_changed || playout_changed;
+            if leaves_profile {
+                self.quality_profile = None;
+            }
+
+            if fft_size_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.set_fft_size(self.settings.fft_size) {
+                        report_error(&mut self.errors, format!("Failed to set FFT size: {}", e));
+                    }
+                    self.settings.hop_size = processor.settings().hop_size;
+                }
+            }
+
+            if hop_size_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.set_hop_size(self.settings.hop_size) {
+                        report_error(&mut self.errors, format!("Failed to set hop size: {}", e));
+                        self.settings.hop_size = processor.settings().hop_size;
+                    }
+                }
+            }
+
+            if window_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.set_window(self.settings.fft_window) {
+                        report_error(&mut self.errors, format!("Failed to set FFT window: {}", e));
+                        self.settings.fft_window = processor.settings().fft_window;
+                    }
+                }
+            }
+
+            if buffer_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.set_buffer_ms(self.buffer_ms) {
+                        report_error(&mut self.errors, format!("Failed to set buffer size: {}", e));
+                        self.buffer_ms = processor.get_buffer_ms();
+                    }
+                }
+            }
+
+            if playout_changed {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    if let Err(e) = processor.set_playout_target_ms(self.playout_target_ms) {
+                        report_error(&mut self.errors, format!("Failed to set output target: {}", e));
+                        self.playout_target_ms = processor.get_playout_target_ms();
+                    }
+                }
+            }
+
+            // The profile is remembered, or forgotten once left
+            if quality_changed || leaves_profile {
+                self.save_config();
+            }
+
+            ui.separator();
+
+            // Audio Levels
+            let mut weighting_changed = false;
+            ui.horizontal(|ui| {
+                ui.heading("Audio Levels");
+                egui::ComboBox::from_id_source("meter_weighting")
+                    .selected_text(self.meter_weighting.label())
+                    .show_ui(ui, |ui| {
+                        for weighting in MeterWeighting::ALL {
+                            weighting_changed |= ui.selectable_value(&mut self.meter_weighting, weighting, weighting.label()).changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text("Weight the RMS levels by frequency to follow how loud the audio sounds: A-weighting discounts rumble and hum as the ear does. Peaks stay unweighted.");
+            });
+            if weighting_changed {
+                if let Ok(processor) = self.audio_processor.lock() {
+                    processor.set_meter_weighting(self.meter_weighting);
+                }
+                self.save_config();
+            }
+            
+            let input_hold = self.input_peak_hold.update(self.input_meter.peak_db);
+            let output_hold = self.output_peak_hold.update(self.output_meter.peak_db);
+            let mut reset_clip = false;
+            egui::Grid::new("level_meters").num_columns(2).show(ui, |ui| {
+                ui.label("Input:");
+                ui.horizontal(|ui| reset_clip |= plots::level_meter(ui, &self.input_meter, input_hold));
+                ui.end_row();
+                if self.input_channel_meters.len() > 1 {
+                    self.input_channel_peak_holds.resize_with(self.input_channel_meters.len(), Default::default);
+                    for (channel, (meter, peak_hold)) in
+                        self.input_channel_meters.iter().zip(&mut self.input_channel_peak_holds).enumerate()
+                    {
+                        let hold = peak_hold.update(meter.peak_db);
+                        ui.label(format!("  In {}:", channel + 1));
+                        ui.horizontal(|ui| reset_clip |= plots::level_meter(ui, meter, hold));
+                        ui.end_row();
+                    }
+                }
+                ui.label("Output:");
+                ui.horizontal(|ui| reset_clip |= plots::level_meter(ui, &self.output_meter, output_hold));
+                ui.end_row();
+            });
+            if reset_clip {
+                if let Ok(processor) = self.audio_processor.lock() {
+                    processor.reset_clip_indicators();
+                }
+            }
+
+            ui.horizontal(|ui| {
+                if self.is_processing && self.voice_active {
+                    ui.colored_label(egui::Color32::GREEN, "● Speaking");
+                } else {
+                    ui.colored_label(egui::Color32::GRAY, "○ Silent");
+                }
+                if self.is_processing {
+                    ui.label(format!("Latency: {:.0} ms", self.latency.total_ms()));
+                    ui.colored_label(load_color(self.processing_load), format!("DSP Load: {:.0}%", self.processing_load * 100.0))
+                        .on_hover_text("Processing time per block of audio; near 100% the output starts to glitch");
+                    let underruns = format!("Dropouts: {}", self.underruns);
+                    let underruns = if self.underruns > 0 {
+                        ui.colored_label(egui::Color32::YELLOW, underruns)
+                    } else {
+                        ui.label(underruns)
+                    };
+                    underruns.on_hover_text("Times the output ran out of processed audio; raise the output target if this keeps rising");
+                    if self.safety_clamps > 0 {
+                        ui.colored_label(egui::Color32::YELLOW, format!("⚠ Safety Clamps: {}", self.safety_clamps))
+                            .on_hover_text("Output samples cut off at the safety ceiling; turn the gain down or enable the limiter");
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label(format!("Momentary: {}", format_lufs(self.loudness.momentary_lufs)))
+                    .on_hover_text("Output loudness over the last 400 ms (EBU R128)");
+                ui.label(format!("Integrated: {}", format_lufs(self.loudness.integrated_lufs)))
+                    .on_hover_text("Output loudness since processing started, ignoring pauses");
+                if ui.small_button("Reset").on_hover_text("Start the integrated measurement over").clicked() {
+                    if let Ok(processor) = self.audio_processor.lock() {
+                        processor.reset_integrated_loudness();
+                    }
+                }
+            });
+
+            if let Some(stats) = self.session_stats {
+                egui::CollapsingHeader::new("Last Session").default_open(true).show(ui, |ui| {
+                    egui::Grid::new("session_stats").num_columns(2).show(ui, |ui| {
+                        let seconds = stats.duration.as_secs();
+                        ui.label("Duration:");
+                        ui.label(format!("{}:{:02}", seconds / 60, seconds % 60));
+                        ui.end_row();
+                        ui.label("Peak:");
+                        ui.label(format!("{:.1} dBFS", stats.peak_db));
+                        ui.end_row();
+                        ui.label("Integrated:");
+                        ui.label(format_lufs(stats.integrated_lufs));
+                        ui.end_row();
+                        ui.label("Clips:");
+                        ui.label(stats.clips.to_string());
+                        ui.end_row();
+                        ui.label("Dropouts:");
+                        ui.label(stats.underruns.to_string());
+                        ui.end_row();
+                        ui.label("Speaking:");
+                        ui.label(format!("{:.0}%", stats.speech_ratio * 100.0));
+                        ui.end_row();
+                        ui.label("Average DSP Load:");
+                        ui.label(format!("{:.0}%", stats.average_load * 100.0));
+                        ui.end_row();
+                    });
+                });
+            }
+
+            ui.collapsing("Spectrum", |ui| {
+                ui.checkbox(&mut self.spectrum_log_frequency, "Log frequency axis");
+                let spectrum = self.audio_processor.lock().ok().and_then(|p| p.get_spectrum());
+                match spectrum {
+                    Some(spectrum) => {
+                        plots::spectrum(ui, &spectrum, self.spectrum_log_frequency);
+                        plots::legend(ui);
+                    }
+                    None => {
+                        ui.label("Start processing to see the spectrum");
+                    }
+                }
+            });
+
+            ui.collapsing("Spectrogram", |ui| {
+                ui.horizontal(|ui| {
+                    let input = ui.selectable_value(&mut self.spectrogram_input, true, "Input").changed();
+                    let processed = ui.selectable_value(&mut self.spectrogram_input, false, "Processed").changed();
+                    if input || processed {
+                        self.spectrogram.clear();
+                    }
+                    let freeze = if self.spectrogram.frozen { "Resume" } else { "Freeze" };
+                    if ui.button(freeze).on_hover_text("Holds the picture to inspect a moment").clicked() {
+                        self.spectrogram.frozen = !self.spectrogram.frozen;
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.spectrogram.clear();
+                    }
+                });
+                let spectrum = self.audio_processor.lock().ok().and_then(|p| p.get_spectrum());
+                if let Some(spectrum) = &spectrum {
+                    let magnitudes = if self.spectrogram_input { &spectrum.input_db } else { &spectrum.output_db };
+                    self.spectrogram.push(spectrum, magnitudes, self.spectrum_log_frequency);
+                }
+                self.spectrogram.show(ui);
+                ui.label("Frequency axis as set under Spectrum; hover for frequency and level");
+            });
+
+            ui.collapsing("Oscilloscope", |ui| {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.scope_mode, ScopeMode::Input, "Input");
+                    ui.selectable_value(&mut self.scope_mode, ScopeMode::Output, "Output");
+                    ui.selectable_value(&mut self.scope_mode, ScopeMode::Overlay, "Overlay");
+                });
+                let waveform = self.audio_processor.lock().ok().map(|p| p.get_waveform()).unwrap_or_default();
+                let input = (waveform.input.as_slice(), plots::INPUT_COLOR);
+                let output = (waveform.output.as_slice(), plots::OUTPUT_COLOR);
+                match self.scope_mode {
+                    ScopeMode::Input => plots::waveform(ui, &[input]),
+                    ScopeMode::Output => plots::waveform(ui, &[output]),
+                    ScopeMode::Overlay => plots::waveform(ui, &[input, output]),
+                }
+                plots::legend(ui);
+            });
+
+            ui.separator();
+
+            // Recording
+            ui.heading("Recording");
+
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.add_enabled(
+                    self.recording_elapsed.is_none(),
+                    egui::TextEdit::singleline(&mut self.recording_path),
+                );
+            });
+
+            ui.horizontal(|ui| {
+                match self.recording_elapsed {
+                    Some(elapsed) => {
+                        if ui.button("Stop Recording").clicked() {
+                            if let Ok(mut processor) = self.audio_processor.lock() {
+                                if let Err(e) = processor.stop_recording() {
+                                    self.recording_error = Some(format!("Recording stopped: {}", e));
+                                }
+                            }
+                        }
+                        let secs = elapsed.as_secs();
+                        ui.colored_label(
+                            egui::Color32::RED,
+                            format!("● {:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60),
+                        );
+                    }
+                    None => {
+                        if ui.button("Record").clicked() {
+                            if let Ok(mut processor) = self.audio_processor.lock() {
+                                match processor.start_recording(Path::new(&self.recording_path)) {
+                                    Ok(()) => self.recording_error = None,
+                                    Err(e) => self.recording_error = Some(format!("Failed to start recording: {}", e)),
+                                }
+                            }
+                        }
+                    }
+                }
+            });
+
+            if let Some(error) = &self.recording_error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button(format!("Save Last {}s", self.replay_secs))
+                    .on_hover_text("Save the most recent processed audio to a new WAV file in the home directory")
+                    .clicked()
+                {
+                    if let Ok(processor) = self.audio_processor.lock() {
+                        let path = replay_path();
+                        self.replay_status = Some(match processor.save_replay(&path) {
+                            Ok(duration) => Ok(format!("Saved {:.0} s to {}", duration.as_secs_f32(), path.display())),
+                            Err(e) => Err(format!("Failed to save replay: {}", e)),
+                        });
+                    }
+                }
+                // Resizing empties the buffer, so only once a drag ends
+                ui.label("Keep:");
+                let response = ui.add(
+                    egui::DragValue::new(&mut self.replay_secs)
+                        .clamp_range(MIN_REPLAY_SECS..=MAX_REPLAY_SECS)
+                        .suffix(" s"),
+                ).on_hover_text("Processed audio held for saving; changing it discards what is held");
+                if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                    let result = self.audio_processor.lock().ok().map(|mut p| p.set_replay_secs(self.replay_secs));
+                    if let Some(Err(e)) = result {
+                        report_error(&mut self.errors, format!("Failed to resize replay buffer: {}", e));
+                    }
+                    self.save_config();
+                }
+            });
+            match &self.replay_status {
+                Some(Ok(saved)) => {
+                    ui.label(saved);
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                None => {}
+            }
+
+            ui.separator();
+
+            // Metrics export
+            ui.heading("Metrics Export");
+
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.add_enabled(
+                    !self.exporting_metrics,
+                    egui::TextEdit::singleline(&mut self.metrics_path),
+                ).on_hover_text("\"-\" writes to stdout");
+            });
+
+            let mut exporting_metrics = self.exporting_metrics;
+            if ui.checkbox(&mut exporting_metrics, "Export Metrics")
+                .on_hover_text("Append a JSON line of levels, load, latency and gate/limiter state every interval")
+                .changed()
+            {
+                if let Ok(mut processor) = self.audio_processor.lock() {
+                    let result = if exporting_metrics {
+                        let path = metrics_target(Path::new(&self.metrics_path));
+                        processor.start_metrics(path, self.metrics_interval)
+                            .map_err(|e| format!("Failed to start metrics export: {}", e))
+                    } else {
+                        processor.stop_metrics()
+                            .map_err(|e| format!("Metrics export stopped: {}", e))
+                    };
+                    self.metrics_error = result.err();
+                    self.exporting_metrics = processor.is_exporting_metrics();
+                }
+            }
+
+            if let Some(error) = &self.metrics_error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+
+            ui.separator();
+
+            // Information
+            ui.heading("Information");
+            ui.label("• This application captures microphone input and system audio");
+            ui.label("• An adaptive filter cancels the echo of application audio");
+            ui.label("• Noise reduction is applied using spectral subtraction");
+            ui.label("• Processed audio can be sent to a virtual microphone for use in other applications");
+            
+            ui.separator();
+
+            if ui.button("Copy Diagnostics")
+                .on_hover_text("Copy devices, formats, settings, measurements and recent errors as text, for a bug report")
+                .clicked()
+            {
+                if let Ok(processor) = self.audio_processor.lock() {
+                    let diagnostics = processor.diagnostics().to_string();
+                    ui.output_mut(|output| output.copied_text = diagnostics);
+                }
+            }
+            
+            // Debug Info
+            if ui.collapsing("Debug Information", |ui| {
+                ui.label(format!("Echo Cancellation: {}", self.settings.echo_cancellation));
+                ui.label(format!(
+                    "Echo Reference Delay: {} samples ({:.1} ms)",
+                    self.settings.reference_delay_samples,
+                    self.settings.reference_delay_samples as f32 * 1000.0 / self.sample_rate as f32,
+                ));
+                ui.label(format!("Noise Reduction: {}", self.settings.noise_reduction));
+                ui.label(format!(
+                    "Input Level: {:.1} dBFS RMS, {:.1} dBFS peak",
+                    self.input_meter.rms_db, self.input_meter.peak_db
+                ));
+                ui.label(format!(
+                    "Output Level: {:.1} dBFS RMS, {:.1} dBFS peak",
+                    self.output_meter.rms_db, self.output_meter.peak_db
+                ));
+                ui.label(format!("Voice Active: {}", self.voice_active));
+                let processing_mode = if !self.settings.power_save {
+                    "full (power save off)"
+                } else if self.power_saving {
+                    "power save (passthrough)"
+                } else {
+                    "full"
+                };
+                ui.label(format!("Processing Mode: {}", processing_mode));
+                ui.label(format!("AGC Gain: {:+.1} dB", self.agc_gain_db));
+                ui.label(format!(
+                    "Loudness: momentary {}, short-term {}, integrated {}, normalization gain {:+.1} dB",
+                    format_lufs(self.loudness.momentary_lufs),
+                    format_lufs(self.loudness.short_term_lufs),
+                    format_lufs(self.loudness.integrated_lufs),
+                    self.normalization_gain_db,
+                ));
+                ui.label(format!("Compressor Gain Reduction: {:.1} dB", self.compressor_reduction_db));
+                ui.label(format!("Limiter Gain Reduction: {:.1} dB", self.limiter_reduction_db));
+                ui.label(format!("Non-Finite Samples Replaced: {}", self.sanitized_samples));
+                ui.colored_label(
+                    load_color(self.processing_load),
+                    format!("Processing Load: {:.3} of realtime", self.processing_load),
+                );
+                ui.label(format!(
+                    "Latency: {:.1} ms (input device {:.1}, input buffer {:.1}, processing {:.1}, output buffer {:.1}, output device {:.1})",
+                    self.latency.total_ms(),
+                    self.latency.input_device_ms,
+                    self.latency.input_buffer_ms,
+                    self.latency.processing_ms,
+                    self.latency.output_buffer_ms,
+                    self.latency.output_device_ms,
+                ));
+                ui.label(format!("Processing: {}", self.is_processing));
+                ui.label(format!("Processing Task Running: {}", self.processing_task_running));
+                ui.horizontal(|ui| {
+                    match self.log.path() {
+                        Some(path) => {
+                            ui.label(format!("Log File: {}", path.display()));
+                            if ui.small_button("Copy").on_hover_text("Copy the path, e.g. to attach the log to a bug report").clicked() {
+                                ui.output_mut(|output| output.copied_text = path.display().to_string());
+                            }
+                        }
+                        None => {
+                            ui.label("Log File: none (logging to stderr only)");
+                        }
+                    }
+                });
+                let mut verbose = self.log.is_verbose();
+                if ui.checkbox(&mut verbose, "Verbose Logging")
+                    .on_hover_text("Also log debug messages, e.g. while reproducing a problem")
+                    .changed()
+                {
+                    self.log.set_verbose(verbose);
+                }
+                ui.label(format!(
+                    "Loopback Device: {}",
+                    self.loopback_device.as_deref().unwrap_or("None")
+                ));
+                for (label, device) in [
+                    ("Input", input_devices.get(self.selected_input_device)),
+                    ("Output", output_devices.get(self.selected_output_device)),
+                ] {
+                    let summary = device
+                        .and_then(|d| d.capabilities.as_ref())
+                        .map(|capabilities| {
+                            capabilities.iter().map(|c| c.summary()).collect::<Vec<_>>().join("; ")
+                        })
+                        .unwrap_or_default();
+                    ui.label(format!("{} Device Formats: {}", label, summary));
+                }
+            }).header_response.clicked() {}
+        });
 
         // Request repaint for real-time updates
         ctx.request_repaint();
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_config();
+    }
 }
 
 impl CancelCasterApp {
-    fn start_audio_processing(&self, processor: &mut AudioProcessor) -> Result<(), Box<dyn std::error::Error>> {
-        processor.start_input_capture()?;
-        processor.start_loopback_capture()?;
-        processor.start_processing()?;
-        processor.start_loopback_output()?;
+    fn toggle_processing(&mut self) {
+        let Ok(mut processor) = self.audio_processor.lock() else {
+            return;
+        };
+        if self.is_running {
+            processor.stop();
+            self.is_running = false;
+        } else {
+            match self.start_audio_processing(&mut processor) {
+                Ok(()) => self.is_running = true,
+                Err(e) => report_error(&mut self.errors, describe_start_error(&e)),
+            }
+        }
+    }
+
+    fn toggle_bypass(&mut self) {
+        self.bypass = !self.bypass;
+        if let Ok(processor) = self.audio_processor.lock() {
+            processor.set_bypass(self.bypass);
+        }
+    }
+
+    fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+        if let Ok(processor) = self.audio_processor.lock() {
+            processor.set_muted(self.muted);
+        }
+    }
+
+    fn save_config(&mut self) {
+        if let Ok(processor) = self.audio_processor.lock() {
+            // The processor doesn't know these; keep the UI's
+            let config = Config {
+                autostart: self.autostart,
+                shortcuts: self.shortcuts.clone(),
+                ..processor.config()
+            };
+            if let Err(e) = config.save() {
+                report_error(&mut self.errors, format!("Failed to save config: {}", e));
+            }
+        }
+    }
+
+    fn start_audio_processing(&self, processor: &mut AudioProcessor) -> Result<(), AudioError> {
+        processor.start()?;
         Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+// Virtual microphone that other applications (Discord, Zoom, OBS, ...) can
+// select as their input to receive the processed audio.
+//
+// - Linux: a PulseAudio/PipeWire null sink receives our output, and a remap
+//   source on its monitor turns that into a microphone. Both are created
+//   through `pactl` and unloaded again when dropped.
+// - Windows and macOS: creating devices needs a driver, so a third-party
+//   virtual cable (VB-Cable, BlackHole) is detected among the output devices
+//   and used as the output. Its other end is the microphone other apps see.
+
+#[cfg(target_os = "linux")]
+use anyhow::Result;
+#[cfg(target_os = "linux")]
+use tracing::{info, warn};
+
+// Null sink the processed audio is played into
+pub const VIRTUAL_SINK_NAME: &str = "cancelcaster";
+// Microphone other applications select, fed from the sink's monitor
+pub const VIRTUAL_SOURCE_NAME: &str = "cancelcaster_mic";
+pub const VIRTUAL_SOURCE_DESCRIPTION: &str = "CancelCaster Microphone";
+
+// Playback ends of virtual cables whose recording end appears as a microphone
+const VIRTUAL_CABLE_NAMES: &[&str] = &["CABLE Input", "VB-Audio", "VoiceMeeter Input", "BlackHole"];
+
+pub fn is_virtual_cable_name(name: &str) -> bool {
+    VIRTUAL_CABLE_NAMES.iter().any(|cable| name.contains(cable))
+}
+
+// Whether `name` is the monitor of our own sink, which must never be used as
+// the echo reference since it carries our output rather than system audio
+pub fn is_own_monitor(name: &str) -> bool {
+    name == format!("{}.monitor", VIRTUAL_SINK_NAME)
+}
+
+// What to tell the user when no virtual cable is available
+pub fn setup_hint() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "Install VB-Cable; other apps then use \"CABLE Output\" as their microphone"
+    } else if cfg!(target_os = "macos") {
+        "Install BlackHole; other apps then use it as their microphone"
+    } else {
+        "Requires PulseAudio or PipeWire with pipewire-pulse and the pactl tool"
+    }
+}
+
+// Sound server modules making up the virtual microphone. Dropping it unloads
+// them, which removes the devices again.
+#[cfg(target_os = "linux")]
+pub struct VirtualMicrophone {
+    modules: Vec<u32>,
+}
+
+#[cfg(target_os = "linux")]
+impl VirtualMicrophone {
+    pub fn create() -> Result<Self> {
+        let mut microphone = Self { modules: Vec::new() };
+        // Pushed one at a time so a failure unloads what was already loaded
+        microphone.modules.push(load_module(&[
+            "module-null-sink",
+            &format!("sink_name={}", VIRTUAL_SINK_NAME),
+            "sink_properties=device.description=CancelCaster",
+        ])?);
+        microphone.modules.push(load_module(&[
+            "module-remap-source",
+            &format!("master={}.monitor", VIRTUAL_SINK_NAME),
+            &format!("source_name={}", VIRTUAL_SOURCE_NAME),
+            &format!("source_properties=device.description=\"{}\"", VIRTUAL_SOURCE_DESCRIPTION),
+        ])?);
+        info!("Created virtual microphone \"{}\"", VIRTUAL_SOURCE_DESCRIPTION);
+        Ok(microphone)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn load_module(args: &[&str]) -> Result<u32> {
+    let output = std::process::Command::new("pactl")
+        .arg("load-module")
+        .args(args)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run pactl ({}); is PulseAudio or pipewire-pulse installed?", e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "pactl load-module {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let index = String::from_utf8_lossy(&output.stdout).trim().parse()?;
+    Ok(index)
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for VirtualMicrophone {
+    fn drop(&mut self) {
+        for module in self.modules.iter().rev() {
+            let status = std::process::Command::new("pactl")
+                .args(["unload-module", &module.to_string()])
+                .status();
+            if !matches!(status, Ok(status) if status.success()) {
+                warn!("Failed to unload sound server module {}", module);
+            }
+        }
+        info!("Removed virtual microphone");
+    }
+}